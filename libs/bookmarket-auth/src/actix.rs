@@ -0,0 +1,103 @@
+//! `Claims` as an actix-web extractor, and [`RequireAuth`] for scopes where
+//! every route needs a caller. Register the verifier with
+//! `App::app_data(web::Data::new(verifier))`.
+
+use actix_web::{
+    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header,
+    http::StatusCode,
+    web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
+};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use std::rc::Rc;
+
+use crate::{claims::Claims, error::AuthError, verifier::Verifier};
+
+impl FromRequest for Claims {
+    type Error = AuthError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Already verified by `RequireAuth`
+        if let Some(claims) = req.extensions().get::<Claims>().cloned() {
+            return Box::pin(ready(Ok(claims)));
+        }
+
+        let verifier = req.app_data::<web::Data<Verifier>>().cloned();
+        let token = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(crate::bearer_token)
+            .map(str::to_string);
+
+        Box::pin(async move {
+            let verifier = verifier.ok_or_else(|| {
+                AuthError::Unavailable("Verifier is not registered as app data".to_string())
+            })?;
+            let token = token.ok_or(AuthError::MissingToken)?;
+
+            verifier.verify(&token).await
+        })
+    }
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::from_u16(self.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.body())
+    }
+}
+
+/// Middleware that turns away requests without a valid access token before
+/// any handler runs, including ones that don't take `Claims`. The verified
+/// claims are kept on the request, so extracting them again is free.
+pub struct RequireAuth;
+
+impl<S, B> Transform<S, ServiceRequest> for RequireAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequireAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireAuthMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequireAuthMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequireAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let claims = Claims::extract(req.request()).await?;
+            req.extensions_mut().insert(claims);
+
+            service.call(req).await
+        })
+    }
+}
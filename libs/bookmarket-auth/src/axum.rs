@@ -0,0 +1,42 @@
+//! `Claims` as an axum extractor. The router state must provide an
+//! `Arc<Verifier>` through `FromRef`.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::sync::Arc;
+
+use crate::{claims::Claims, error::AuthError, verifier::Verifier};
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Claims
+where
+    Arc<Verifier>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(crate::bearer_token)
+            .ok_or(AuthError::MissingToken)?;
+
+        Arc::<Verifier>::from_ref(state).verify(token).await
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        (status, Json(self.body())).into_response()
+    }
+}
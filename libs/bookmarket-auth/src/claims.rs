@@ -0,0 +1,104 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The claims auth-service puts in its access and refresh tokens.
+///
+/// This is the wire format: fields auth-service adds later must be optional
+/// here so services built against an older version keep accepting tokens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    /// User ID.
+    pub sub: String,
+    pub email: String,
+    pub role: Role,
+    pub iat: i64,
+    pub exp: i64,
+    /// Session ID; revoking the session revokes every token issued for it.
+    pub jti: String,
+    /// Set when an admin is impersonating `sub`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// Absent on tokens issued before it was added, which were all access
+    /// tokens as far as resource servers are concerned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_use: Option<TokenUse>,
+    /// Vendor organisation the token acts for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_role: Option<OrgRole>,
+    /// When the user last proved who they are, as a Unix timestamp.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<AuthMethod>,
+}
+
+impl Claims {
+    /// The authenticated user. `None` only for a malformed subject, which
+    /// the verifier already rejects.
+    pub fn user_id(&self) -> Option<Uuid> {
+        Uuid::parse_str(&self.sub).ok()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    pub fn is_impersonated(&self) -> bool {
+        self.act.is_some()
+    }
+
+    /// Whether the token may be presented as a bearer credential.
+    pub fn is_access_token(&self) -> bool {
+        self.token_use != Some(TokenUse::Refresh)
+    }
+
+    /// Whether the user authenticated within the last `max_age` seconds, for
+    /// operations that should not ride on a long-lived session.
+    pub fn authenticated_within(&self, max_age: i64) -> bool {
+        matches!(self.auth_time, Some(auth_time) if Utc::now().timestamp() - auth_time <= max_age)
+    }
+}
+
+/// Serialised as auth-service's `UserRole` is, in PascalCase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Role {
+    Customer,
+    Vendor,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Owner,
+    CatalogManager,
+    Fulfilment,
+    Accountant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenUse {
+    Access,
+    Refresh,
+}
+
+/// How the user proved who they are, per RFC 8176 where it has a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    Pwd,
+    Otp,
+    Email,
+    Fed,
+}
+
+/// RFC 8693 actor claim naming the admin acting on behalf of the subject.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+    pub email: String,
+}
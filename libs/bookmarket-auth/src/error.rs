@@ -0,0 +1,61 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Missing bearer token")]
+    MissingToken,
+
+    #[error("Invalid token: {0}")]
+    InvalidToken(#[from] jsonwebtoken::errors::Error),
+
+    /// A refresh token, or a token whose subject is not a user ID.
+    #[error("Token cannot be used as a bearer credential")]
+    WrongToken,
+
+    #[error("Token has been revoked")]
+    Revoked,
+
+    /// Signing keys or the revocation check could not be reached. Requests
+    /// are refused rather than let through unchecked.
+    #[error("Token verification unavailable: {0}")]
+    Unavailable(String),
+}
+
+impl AuthError {
+    /// Stable UPPER_SNAKE_CASE code, matching auth-service's error bodies.
+    pub fn code(&self) -> &'static str {
+        match self {
+            AuthError::MissingToken => "UNAUTHORIZED",
+            AuthError::InvalidToken(_) | AuthError::WrongToken => "INVALID_TOKEN",
+            AuthError::Revoked => "TOKEN_REVOKED",
+            AuthError::Unavailable(_) => "AUTH_UNAVAILABLE",
+        }
+    }
+
+    /// HTTP status the error should be answered with.
+    pub fn status(&self) -> u16 {
+        match self {
+            AuthError::Unavailable(_) => 503,
+            _ => 401,
+        }
+    }
+
+    /// Body in auth-service's `{error: {code, message, timestamp}}` shape.
+    /// Verification details stay in the logs.
+    pub fn body(&self) -> serde_json::Value {
+        let message = match self {
+            AuthError::MissingToken => "Authentication required",
+            AuthError::InvalidToken(_) | AuthError::WrongToken => "Invalid token",
+            AuthError::Revoked => "Session has ended",
+            AuthError::Unavailable(_) => "Authentication is temporarily unavailable",
+        };
+
+        serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "message": message,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            }
+        })
+    }
+}
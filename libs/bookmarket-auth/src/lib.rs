@@ -0,0 +1,33 @@
+//! Token verification for services that sit behind auth-service.
+//!
+//! Build a [`Verifier`] once at startup, optionally with a
+//! [`RevocationCheck`], and take [`Claims`] as a handler argument with the
+//! `axum` or `actix-web` feature enabled. A handler that takes `Claims` only
+//! runs for a valid, unrevoked access token.
+
+pub mod claims;
+pub mod error;
+pub mod revocation;
+pub mod verifier;
+
+#[cfg(feature = "actix-web")]
+mod actix;
+#[cfg(feature = "axum")]
+mod axum;
+
+#[cfg(feature = "actix-web")]
+pub use actix::{RequireAuth, RequireAuthMiddleware};
+pub use claims::{Actor, AuthMethod, Claims, OrgRole, Role, TokenUse};
+pub use error::AuthError;
+#[cfg(feature = "redis")]
+pub use revocation::RedisCheck;
+pub use revocation::{IntrospectionCheck, RevocationCheck, REVOKED_KEY_PREFIX};
+pub use verifier::{secret_key_id, Verifier};
+
+/// The token from an `Authorization: Bearer <token>` header value.
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.split_once(' ')?;
+    let token = token.trim();
+
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then_some(token)
+}
@@ -0,0 +1,221 @@
+use jsonwebtoken::{
+    decode, decode_header,
+    errors::ErrorKind,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+
+use crate::{claims::Claims, error::AuthError, revocation::RevocationCheck};
+
+/// How long fetched signing keys are trusted before they are fetched again.
+const JWKS_TTL: Duration = Duration::from_secs(3600);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Checks bearer tokens issued by auth-service. Build one at startup and share
+/// it; it caches signing keys and holds the revocation check.
+pub struct Verifier {
+    keys: KeySource,
+    revocation: Option<Arc<dyn RevocationCheck>>,
+}
+
+enum KeySource {
+    /// The HS256 secrets auth-service signs with, current first.
+    Secrets(Vec<SecretKey>),
+    Jwks(JwksCache),
+}
+
+struct SecretKey {
+    kid: String,
+    key: DecodingKey,
+}
+
+struct JwksCache {
+    url: String,
+    http: reqwest::Client,
+    cached: RwLock<Option<FetchedKeys>>,
+}
+
+struct FetchedKeys {
+    jwks: Arc<JwkSet>,
+    fetched_at: Instant,
+}
+
+impl Verifier {
+    /// Verifies tokens signed with auth-service's shared secret.
+    pub fn with_secret(secret: &str) -> Self {
+        Self::with_secrets(&[secret])
+    }
+
+    /// Verifies tokens signed with any of these secrets, picked by the key id
+    /// in the token header. While a rotation is under way, pass the new
+    /// secret and the one it replaced.
+    pub fn with_secrets(secrets: &[&str]) -> Self {
+        let keys = secrets
+            .iter()
+            .map(|secret| SecretKey {
+                kid: secret_key_id(secret),
+                key: DecodingKey::from_secret(secret.as_bytes()),
+            })
+            .collect();
+
+        Self {
+            keys: KeySource::Secrets(keys),
+            revocation: None,
+        }
+    }
+
+    /// Verifies tokens against the keys published at `url`, fetched on first
+    /// use and again whenever a token names a key the cached set lacks.
+    pub fn with_jwks(url: impl Into<String>) -> Result<Self, AuthError> {
+        let http = reqwest::Client::builder()
+            .timeout(JWKS_TIMEOUT)
+            .build()
+            .map_err(|e| AuthError::Unavailable(e.to_string()))?;
+
+        Ok(Self {
+            keys: KeySource::Jwks(JwksCache {
+                url: url.into(),
+                http,
+                cached: RwLock::new(None),
+            }),
+            revocation: None,
+        })
+    }
+
+    /// Also asks `check` whether each token's session has ended. Without one,
+    /// a token stays valid until it expires even after logout.
+    pub fn with_revocation(mut self, check: impl RevocationCheck + 'static) -> Self {
+        self.revocation = Some(Arc::new(check));
+        self
+    }
+
+    /// Verifies the token and returns its claims. Refresh tokens are rejected:
+    /// only auth-service accepts them.
+    pub async fn verify(&self, token: &str) -> Result<Claims, AuthError> {
+        let claims = match &self.keys {
+            KeySource::Secrets(keys) => verify_with_secrets(token, keys)?,
+            KeySource::Jwks(jwks) => jwks.verify(token).await?,
+        };
+
+        if !claims.is_access_token() || claims.user_id().is_none() {
+            return Err(AuthError::WrongToken);
+        }
+
+        if let Some(revocation) = &self.revocation {
+            match revocation.is_revoked(token, &claims).await {
+                Ok(false) => {}
+                Ok(true) => return Err(AuthError::Revoked),
+                Err(e) => {
+                    tracing::error!(error = %e, "Revocation check failed");
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(claims)
+    }
+}
+
+/// The key id auth-service puts in the header of tokens signed with
+/// `secret`: the first 16 hex characters of its SHA-256 digest, which names
+/// the key without revealing it.
+pub fn secret_key_id(secret: &str) -> String {
+    let digest = Sha256::digest(secret.as_bytes());
+    digest[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn verify_with_secrets(token: &str, keys: &[SecretKey]) -> Result<Claims, AuthError> {
+    let kid = decode_header(token)?.kid;
+    // Tokens from before key ids were added name no key, so each is tried
+    let candidates = keys
+        .iter()
+        .filter(|key| kid.as_ref().is_none_or(|kid| *kid == key.kid));
+
+    let mut error = None;
+    for key in candidates {
+        match decode::<Claims>(token, &key.key, &Validation::default()) {
+            Ok(data) => return Ok(data.claims),
+            // A signature mismatch says least about why the token failed
+            Err(e) if error.is_none() || *e.kind() != ErrorKind::InvalidSignature => {
+                error = Some(e)
+            }
+            Err(_) => {}
+        }
+    }
+
+    Err(error
+        .unwrap_or_else(|| ErrorKind::InvalidSignature.into())
+        .into())
+}
+
+impl JwksCache {
+    async fn verify(&self, token: &str) -> Result<Claims, AuthError> {
+        let header = decode_header(token)?;
+        // Asymmetric keys only, so a public key can't be replayed as an HMAC secret
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(AuthError::WrongToken);
+        }
+
+        let mut jwks = self.keys(false).await?;
+        let jwk = match find_key(&jwks, header.kid.as_deref()) {
+            Some(jwk) => jwk,
+            None => {
+                jwks = self.keys(true).await?;
+                find_key(&jwks, header.kid.as_deref()).ok_or(AuthError::WrongToken)?
+            }
+        };
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        Ok(decode::<Claims>(token, &key, &Validation::new(header.alg))?.claims)
+    }
+
+    async fn keys(&self, force_refresh: bool) -> Result<Arc<JwkSet>, AuthError> {
+        if !force_refresh {
+            if let Some(cached) = self.cached.read().await.as_ref() {
+                if cached.fetched_at.elapsed() < JWKS_TTL {
+                    return Ok(cached.jwks.clone());
+                }
+            }
+        }
+
+        let jwks: JwkSet = self
+            .http
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AuthError::Unavailable(format!("JWKS request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| AuthError::Unavailable(format!("Invalid JWKS: {}", e)))?;
+        let jwks = Arc::new(jwks);
+
+        *self.cached.write().await = Some(FetchedKeys {
+            jwks: jwks.clone(),
+            fetched_at: Instant::now(),
+        });
+
+        Ok(jwks)
+    }
+}
+
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        // Without a key id the only unambiguous choice is a single-key set
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+}
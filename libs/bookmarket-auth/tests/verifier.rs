@@ -0,0 +1,277 @@
+//! Verification rules and both framework extractors, against tokens signed
+//! the way auth-service signs them.
+
+use async_trait::async_trait;
+use chrono::Utc;
+use jsonwebtoken::{encode, EncodingKey, Header};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use bookmarket_auth::{
+    secret_key_id, AuthError, Claims, RequireAuth, RevocationCheck, Role, TokenUse, Verifier,
+};
+
+const SECRET: &str = "bookmarket-auth-test-secret";
+const REVOKED_SESSION: &str = "00000000-0000-0000-0000-00000000dead";
+
+fn claims(token_use: Option<TokenUse>) -> Claims {
+    let now = Utc::now().timestamp();
+
+    Claims {
+        sub: Uuid::new_v4().to_string(),
+        email: "reader@bookmarket.test".to_string(),
+        role: Role::Customer,
+        iat: now,
+        exp: now + 900,
+        jti: Uuid::new_v4().to_string(),
+        act: None,
+        token_use,
+        org_id: None,
+        org_role: None,
+        auth_time: Some(now),
+        amr: vec![],
+    }
+}
+
+fn sign(claims: &Claims) -> String {
+    encode(
+        &Header::default(),
+        claims,
+        &EncodingKey::from_secret(SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+/// Treats one fixed session as ended.
+struct RevokedSessions;
+
+#[async_trait]
+impl RevocationCheck for RevokedSessions {
+    async fn is_revoked(&self, _token: &str, claims: &Claims) -> Result<bool, AuthError> {
+        Ok(claims.jti == REVOKED_SESSION)
+    }
+}
+
+struct Unreachable;
+
+#[async_trait]
+impl RevocationCheck for Unreachable {
+    async fn is_revoked(&self, _token: &str, _claims: &Claims) -> Result<bool, AuthError> {
+        Err(AuthError::Unavailable("connection refused".to_string()))
+    }
+}
+
+#[tokio::test]
+async fn accepts_access_tokens() {
+    let issued = claims(Some(TokenUse::Access));
+    let verified = Verifier::with_secret(SECRET)
+        .verify(&sign(&issued))
+        .await
+        .unwrap();
+
+    assert_eq!(verified, issued);
+}
+
+#[tokio::test]
+async fn rejects_refresh_tokens_and_other_secrets() {
+    let verifier = Verifier::with_secret(SECRET);
+
+    let refresh = sign(&claims(Some(TokenUse::Refresh)));
+    assert!(matches!(
+        verifier.verify(&refresh).await,
+        Err(AuthError::WrongToken)
+    ));
+
+    let forged = Verifier::with_secret("another-secret")
+        .verify(&sign(&claims(None)))
+        .await;
+    assert!(matches!(forged, Err(AuthError::InvalidToken(_))));
+}
+
+#[tokio::test]
+async fn previous_secret_verifies_during_a_rotation() {
+    const NEW_SECRET: &str = "bookmarket-auth-rotated-secret";
+
+    let with_kid = |claims: &Claims, secret: &str| {
+        let header = Header {
+            kid: Some(secret_key_id(secret)),
+            ..Header::default()
+        };
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    };
+    let verifier = Verifier::with_secrets(&[NEW_SECRET, SECRET]);
+
+    let issued = claims(None);
+    for token in [
+        with_kid(&issued, NEW_SECRET),
+        with_kid(&issued, SECRET),
+        sign(&issued),
+    ] {
+        assert_eq!(verifier.verify(&token).await.unwrap(), issued);
+    }
+
+    // Once the previous secret is dropped, its tokens stop verifying
+    let after = Verifier::with_secret(NEW_SECRET);
+    assert!(matches!(
+        after.verify(&with_kid(&issued, SECRET)).await,
+        Err(AuthError::InvalidToken(_))
+    ));
+}
+
+#[tokio::test]
+async fn revocation_check_is_enforced_and_fails_closed() {
+    let mut revoked = claims(None);
+    revoked.jti = REVOKED_SESSION.to_string();
+
+    let verifier = Verifier::with_secret(SECRET).with_revocation(RevokedSessions);
+    assert!(verifier.verify(&sign(&claims(None))).await.is_ok());
+    assert!(matches!(
+        verifier.verify(&sign(&revoked)).await,
+        Err(AuthError::Revoked)
+    ));
+
+    let verifier = Verifier::with_secret(SECRET).with_revocation(Unreachable);
+    let error = verifier.verify(&sign(&claims(None))).await.unwrap_err();
+    assert_eq!(error.status(), 503);
+}
+
+#[tokio::test]
+async fn axum_extractor() {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    async fn whoami(claims: Claims) -> String {
+        claims.email
+    }
+
+    let app = Router::new()
+        .route("/me", get(whoami))
+        .with_state(Arc::new(Verifier::with_secret(SECRET)));
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get("/me")
+                .header(
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", sign(&claims(None))),
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = app
+        .oneshot(Request::get("/me").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["error"]["code"], "UNAUTHORIZED");
+}
+
+#[actix_web::test]
+async fn actix_extractor() {
+    use actix_web::{http::header, http::StatusCode, test, web, App};
+
+    async fn whoami(claims: Claims) -> String {
+        claims.email
+    }
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Verifier::with_secret(SECRET)))
+            .route("/me", web::get().to(whoami)),
+    )
+    .await;
+
+    let request = test::TestRequest::get()
+        .uri("/me")
+        .insert_header((
+            header::AUTHORIZATION,
+            format!("Bearer {}", sign(&claims(None))),
+        ))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, request).await.status(),
+        StatusCode::OK
+    );
+
+    let refresh = sign(&claims(Some(TokenUse::Refresh)));
+    let request = test::TestRequest::get()
+        .uri("/me")
+        .insert_header((header::AUTHORIZATION, format!("Bearer {}", refresh)))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, request).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+/// Routes behind `RequireAuth` need a token even when the handler never asks
+/// for the caller.
+#[actix_web::test]
+async fn actix_require_auth() {
+    use actix_web::{http::header, http::StatusCode, test, web, App, HttpResponse};
+
+    async fn listing() -> HttpResponse {
+        HttpResponse::Ok().finish()
+    }
+
+    async fn whoami(claims: Claims) -> String {
+        claims.email
+    }
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Verifier::with_secret(SECRET)))
+            .route("/health", web::get().to(listing))
+            .service(
+                web::scope("/api")
+                    .wrap(RequireAuth)
+                    .route("/books", web::get().to(listing))
+                    .route("/me", web::get().to(whoami)),
+            ),
+    )
+    .await;
+
+    for uri in ["/api/books", "/api/me"] {
+        let request = test::TestRequest::get().uri(uri).to_request();
+        let error = test::try_call_service(&app, request).await.unwrap_err();
+        assert_eq!(
+            error.as_response_error().status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+
+        let request = test::TestRequest::get()
+            .uri(uri)
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Bearer {}", sign(&claims(None))),
+            ))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, request).await.status(),
+            StatusCode::OK
+        );
+    }
+
+    let request = test::TestRequest::get().uri("/health").to_request();
+    assert_eq!(
+        test::call_service(&app, request).await.status(),
+        StatusCode::OK
+    );
+}
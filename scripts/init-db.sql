@@ -11,49 +11,11 @@ CREATE EXTENSION IF NOT EXISTS "uuid-ossp";
 CREATE EXTENSION IF NOT EXISTS "pg_trgm";
 CREATE EXTENSION IF NOT EXISTS "btree_gin";
 
--- =============================================
--- AUTH SCHEMA
--- =============================================
-
--- Users table
-CREATE TABLE auth.users (
-    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-    email VARCHAR(255) UNIQUE NOT NULL,
-    password_hash VARCHAR(255) NOT NULL,
-    first_name VARCHAR(100),
-    last_name VARCHAR(100),
-    phone VARCHAR(20),
-    role VARCHAR(20) DEFAULT 'customer' CHECK (role IN ('customer', 'vendor', 'admin')),
-    status VARCHAR(20) DEFAULT 'active' CHECK (status IN ('active', 'inactive', 'suspended')),
-    email_verified BOOLEAN DEFAULT FALSE,
-    phone_verified BOOLEAN DEFAULT FALSE,
-    last_login TIMESTAMP,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-);
-
--- Sessions table
-CREATE TABLE auth.sessions (
-    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-    user_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
-    token_hash VARCHAR(255) NOT NULL,
-    expires_at TIMESTAMP NOT NULL,
-    user_agent TEXT,
-    ip_address INET,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-);
-
--- API keys for vendors
-CREATE TABLE auth.api_keys (
-    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-    user_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
-    key_hash VARCHAR(255) NOT NULL,
-    name VARCHAR(100) NOT NULL,
-    scopes TEXT[] DEFAULT '{}',
-    last_used TIMESTAMP,
-    expires_at TIMESTAMP,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-);
+-- The auth schema is owned by auth-service, which creates and upgrades it
+-- with its own migrations (services/auth-service/migrations) at startup.
+-- Marketplace columns holding user IDs get their foreign keys to auth.users
+-- from whichever runs second: the block at the end of this script, or the
+-- service's 0004_marketplace_user_references migration.
 
 -- =============================================
 -- MARKETPLACE SCHEMA
@@ -62,7 +24,7 @@ CREATE TABLE auth.api_keys (
 -- Vendors table
 CREATE TABLE marketplace.vendors (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-    user_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
+    user_id UUID,
     company_name VARCHAR(255) NOT NULL,
     business_type VARCHAR(50) CHECK (business_type IN ('individual', 'company', 'cooperative')),
     tax_id VARCHAR(50),
@@ -154,7 +116,7 @@ CREATE TABLE marketplace.price_history (
     old_price DECIMAL(10,2),
     new_price DECIMAL(10,2) NOT NULL,
     reason VARCHAR(100),
-    changed_by UUID REFERENCES auth.users(id),
+    changed_by UUID,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -162,7 +124,7 @@ CREATE TABLE marketplace.price_history (
 CREATE TABLE marketplace.orders (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     order_number VARCHAR(20) UNIQUE NOT NULL,
-    customer_id UUID REFERENCES auth.users(id),
+    customer_id UUID,
     status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'confirmed', 'processing', 'shipped', 'delivered', 'cancelled', 'refunded')),
     subtotal DECIMAL(10,2) NOT NULL,
     tax_amount DECIMAL(10,2) DEFAULT 0,
@@ -218,7 +180,7 @@ CREATE TABLE marketplace.payments (
 CREATE TABLE marketplace.reviews (
     id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     product_id UUID NOT NULL,
-    customer_id UUID REFERENCES auth.users(id),
+    customer_id UUID,
     order_item_id UUID REFERENCES marketplace.order_items(id),
     rating INTEGER NOT NULL CHECK (rating >= 1 AND rating <= 5),
     title VARCHAR(255),
@@ -280,13 +242,6 @@ CREATE TABLE analytics.vendor_metrics (
 -- INDEXES FOR PERFORMANCE
 -- =============================================
 
--- Auth indexes
-CREATE INDEX idx_users_email ON auth.users(email);
-CREATE INDEX idx_users_role ON auth.users(role);
-CREATE INDEX idx_sessions_user_id ON auth.sessions(user_id);
-CREATE INDEX idx_sessions_expires_at ON auth.sessions(expires_at);
-CREATE INDEX idx_api_keys_user_id ON auth.api_keys(user_id);
-
 -- Vendor indexes
 CREATE INDEX idx_vendors_user_id ON marketplace.vendors(user_id);
 CREATE INDEX idx_vendors_status ON marketplace.vendors(status);
@@ -336,12 +291,29 @@ END;
 $$ language 'plpgsql';
 
 -- Apply triggers
-CREATE TRIGGER update_users_updated_at BEFORE UPDATE ON auth.users FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 CREATE TRIGGER update_vendors_updated_at BEFORE UPDATE ON marketplace.vendors FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 CREATE TRIGGER update_products_updated_at BEFORE UPDATE ON marketplace.products FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 CREATE TRIGGER update_orders_updated_at BEFORE UPDATE ON marketplace.orders FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 CREATE TRIGGER update_reviews_updated_at BEFORE UPDATE ON marketplace.reviews FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
 
+-- =============================================
+-- REFERENCES TO AUTH USERS
+-- =============================================
+
+DO $$
+BEGIN
+    IF to_regclass('auth.users') IS NOT NULL THEN
+        ALTER TABLE marketplace.vendors ADD CONSTRAINT vendors_user_id_fkey
+            FOREIGN KEY (user_id) REFERENCES auth.users(id) ON DELETE CASCADE;
+        ALTER TABLE marketplace.price_history ADD CONSTRAINT price_history_changed_by_fkey
+            FOREIGN KEY (changed_by) REFERENCES auth.users(id);
+        ALTER TABLE marketplace.orders ADD CONSTRAINT orders_customer_id_fkey
+            FOREIGN KEY (customer_id) REFERENCES auth.users(id);
+        ALTER TABLE marketplace.reviews ADD CONSTRAINT reviews_customer_id_fkey
+            FOREIGN KEY (customer_id) REFERENCES auth.users(id);
+    END IF;
+END $$;
+
 -- =============================================
 -- SAMPLE DATA
 -- =============================================
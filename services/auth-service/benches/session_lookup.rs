@@ -0,0 +1,98 @@
+//! Per-request session lookup latency: Postgres only vs. the Redis session cache.
+//!
+//! Needs a migrated database and a Redis instance:
+//!
+//! ```sh
+//! DATABASE_URL=postgres://... REDIS_URL=redis://localhost:6379 JWT_SECRET=bench \
+//!     MFA_ENCRYPTION_KEY=$(openssl rand -hex 32) cargo bench --bench session_lookup
+//! ```
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tokio::runtime::Runtime;
+
+use auth_service::{
+    config::Config,
+    database,
+    models::UserRole,
+    repositories::{
+        postgres::{PgSessionRepository, PgUserRepository},
+        NewSession, SessionRepository, UserRepository,
+    },
+    services::{audit::ClientContext, session_cache::SessionCache},
+};
+
+fn session_lookup(c: &mut Criterion) {
+    let rt = Runtime::new().expect("Failed to start Tokio runtime");
+    let config = Config::from_env().expect("Failed to load configuration");
+
+    let (db, cache, sessions, user, session) = rt.block_on(async {
+        let db = database::connect(&config.database_url)
+            .await
+            .expect("Failed to connect to database");
+        let redis_client =
+            redis::Client::open(config.redis_url.as_str()).expect("Invalid REDIS_URL");
+        let cache = SessionCache::new(
+            &redis_client,
+            config.session_cache_ttl,
+            config.refresh_token_expiration as u64,
+        );
+
+        let users = PgUserRepository::new(db.clone());
+        let sessions = PgSessionRepository::new(db.clone(), cache.clone());
+
+        let email = format!("bench-{}@bookmarket.test", uuid::Uuid::new_v4());
+        let user = users
+            .create(
+                &email,
+                "not-a-real-hash",
+                "Bench",
+                "User",
+                None,
+                UserRole::Customer,
+            )
+            .await
+            .expect("Failed to create bench user");
+        let session = sessions
+            .create(NewSession::login(
+                user.id,
+                &ClientContext::default(),
+                true,
+                &config,
+            ))
+            .await
+            .expect("Failed to create bench session");
+
+        (db, cache, sessions, user, session)
+    });
+
+    let session_id = session.id.to_string();
+    let mut group = c.benchmark_group("session_lookup");
+
+    group.bench_function("postgres", |b| {
+        b.to_async(&rt)
+            .iter(|| async { sessions.get(&session_id).await.unwrap() })
+    });
+
+    // Prime the cache so every iteration measures a hit
+    rt.block_on(cache.put(&session))
+        .expect("Failed to prime cache");
+
+    group.bench_function("redis_cache_hit", |b| {
+        b.to_async(&rt)
+            .iter(|| async { sessions.get_active(&session_id).await.unwrap() })
+    });
+
+    group.finish();
+
+    rt.block_on(async {
+        sessions.delete_for_user(user.id).await.ok();
+        sqlx::query("DELETE FROM auth.users WHERE id = $1")
+            .bind(user.id)
+            .execute(&db)
+            .await
+            .ok();
+    });
+}
+
+criterion_group!(benches, session_lookup);
+criterion_main!(benches);
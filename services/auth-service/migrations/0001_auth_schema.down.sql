@@ -0,0 +1,27 @@
+-- Drops every auth table along with foreign keys other schemas hold on them.
+-- The schema itself stays: it holds this service's migration history.
+
+DROP TABLE IF EXISTS
+    auth.audit_events,
+    auth.outbox_events,
+    auth.webhook_deliveries,
+    auth.webhook_subscriptions,
+    auth.job_runs,
+    auth.account_deletions,
+    auth.organization_invitations,
+    auth.organization_members,
+    auth.organizations,
+    auth.user_identities,
+    auth.magic_links,
+    auth.login_alerts,
+    auth.totp_factors,
+    auth.email_change_requests,
+    auth.email_verification_tokens,
+    auth.password_reset_tokens,
+    auth.api_keys,
+    auth.sessions,
+    auth.users
+CASCADE;
+
+DROP FUNCTION IF EXISTS auth.reject_audit_mutation();
+DROP FUNCTION IF EXISTS auth.update_updated_at_column();
@@ -0,0 +1,297 @@
+-- The auth schema for new databases. Every statement tolerates existing
+-- objects, so databases set up by scripts/init-db.sql before auth-service
+-- owned its migrations can apply it too, but the tables that script created
+-- are left as they were: 0002 fixes the column types it got wrong and 0005
+-- adds the columns and status value the service has gained since.
+
+CREATE SCHEMA IF NOT EXISTS auth;
+CREATE EXTENSION IF NOT EXISTS pg_trgm WITH SCHEMA public;
+
+-- Users table
+CREATE TABLE IF NOT EXISTS auth.users (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email VARCHAR(255) UNIQUE NOT NULL,
+    password_hash VARCHAR(255) NOT NULL,
+    first_name VARCHAR(100),
+    last_name VARCHAR(100),
+    phone VARCHAR(20),
+    role VARCHAR(20) DEFAULT 'customer' CHECK (role IN ('customer', 'vendor', 'admin')),
+    status VARCHAR(20) DEFAULT 'active' CHECK (status IN ('active', 'inactive', 'suspended', 'deleted')),
+    email_verified BOOLEAN DEFAULT FALSE,
+    phone_verified BOOLEAN DEFAULT FALSE,
+    password_reset_required BOOLEAN NOT NULL DEFAULT FALSE,
+    verification_reminder_sent_at TIMESTAMPTZ,
+    last_login TIMESTAMP,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Sessions table
+CREATE TABLE IF NOT EXISTS auth.sessions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(255) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    user_agent TEXT,
+    ip_address INET,
+    impersonator_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- API keys for vendors
+CREATE TABLE IF NOT EXISTS auth.api_keys (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
+    key_hash VARCHAR(255) NOT NULL,
+    name VARCHAR(100) NOT NULL,
+    scopes TEXT[] DEFAULT '{}',
+    last_used TIMESTAMP,
+    expires_at TIMESTAMP,
+    disabled_at TIMESTAMPTZ,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Single-use password reset links
+CREATE TABLE IF NOT EXISTS auth.password_reset_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Single-use email address verification links
+CREATE TABLE IF NOT EXISTS auth.email_verification_tokens (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Pending email address changes: confirmed from the new address, cancellable from the old one
+CREATE TABLE IF NOT EXISTS auth.email_change_requests (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    session_id UUID,
+    old_email VARCHAR(255) NOT NULL,
+    new_email VARCHAR(255) NOT NULL,
+    confirm_token_hash VARCHAR(64) UNIQUE NOT NULL,
+    cancel_token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    confirmed_at TIMESTAMPTZ,
+    cancelled_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Authenticator app (TOTP) second factors. last_used_step stops a code being
+-- accepted twice.
+CREATE TABLE IF NOT EXISTS auth.totp_factors (
+    user_id UUID PRIMARY KEY REFERENCES auth.users(id) ON DELETE CASCADE,
+    secret VARCHAR(64) NOT NULL,
+    confirmed_at TIMESTAMPTZ,
+    last_used_step BIGINT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- New-device / dormant-account sign-in notices and their "this wasn't me" links
+CREATE TABLE IF NOT EXISTS auth.login_alerts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    session_id UUID NOT NULL,
+    reason VARCHAR(20) NOT NULL CHECK (reason IN ('new_device', 'dormant_account')),
+    ip_address TEXT,
+    user_agent TEXT,
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    denied_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Passwordless sign-in links, bound to the browser that requested them
+CREATE TABLE IF NOT EXISTS auth.magic_links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    nonce_hash VARCHAR(64) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Accounts at external OpenID Connect providers linked to local users
+CREATE TABLE IF NOT EXISTS auth.user_identities (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    provider VARCHAR(50) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    email VARCHAR(255),
+    last_used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE(provider, subject),
+    UNIQUE(user_id, provider)
+);
+
+-- Vendor organisations: a shop and the staff accounts that run it
+CREATE TABLE IF NOT EXISTS auth.organizations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name VARCHAR(200) NOT NULL,
+    created_by UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS auth.organization_members (
+    org_id UUID NOT NULL REFERENCES auth.organizations(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'catalog_manager', 'fulfilment', 'accountant')),
+    last_selected_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (org_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS auth.organization_invitations (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    org_id UUID NOT NULL REFERENCES auth.organizations(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'catalog_manager', 'fulfilment', 'accountant')),
+    token_hash VARCHAR(64) UNIQUE NOT NULL,
+    invited_by UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    responded_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Account deletion requests; the user row is anonymised once the cooling-off period ends
+CREATE TABLE IF NOT EXISTS auth.account_deletions (
+    user_id UUID PRIMARY KEY REFERENCES auth.users(id) ON DELETE CASCADE,
+    requested_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    scheduled_for TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ
+);
+
+-- Last run of each background job, shared by all replicas
+CREATE TABLE IF NOT EXISTS auth.job_runs (
+    job_name VARCHAR(100) PRIMARY KEY,
+    last_started_at TIMESTAMPTZ NOT NULL,
+    last_finished_at TIMESTAMPTZ,
+    last_outcome VARCHAR(20) CHECK (last_outcome IN ('success', 'failure')),
+    last_error TEXT,
+    items_processed BIGINT NOT NULL DEFAULT 0
+);
+
+-- Outbound webhooks for a user's or an organisation's own systems. The secret
+-- signs deliveries, so it is kept as issued rather than hashed.
+CREATE TABLE IF NOT EXISTS auth.webhook_subscriptions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID REFERENCES auth.users(id) ON DELETE CASCADE,
+    org_id UUID REFERENCES auth.organizations(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret VARCHAR(100) NOT NULL,
+    events TEXT[] NOT NULL,
+    created_by UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((user_id IS NULL) <> (org_id IS NULL))
+);
+
+-- One row per event per subscription. event_id is shared by every delivery
+-- of the same event, so receivers can deduplicate retries and redeliveries.
+CREATE TABLE IF NOT EXISTS auth.webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    subscription_id UUID NOT NULL REFERENCES auth.webhook_subscriptions(id) ON DELETE CASCADE,
+    event_id UUID NOT NULL,
+    event_type VARCHAR(50) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'dead')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_attempt_at TIMESTAMPTZ,
+    response_status INTEGER,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- User lifecycle events for other services, written in the same transaction
+-- as the change they describe and published to Redis Streams by a relay job.
+-- The id is the idempotency key consumers deduplicate on; sequence keeps
+-- events from one transaction in order.
+CREATE TABLE IF NOT EXISTS auth.outbox_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    sequence BIGSERIAL NOT NULL UNIQUE,
+    event_type VARCHAR(50) NOT NULL,
+    user_id UUID NOT NULL,
+    payload JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    published_at TIMESTAMPTZ,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT
+);
+
+-- Append-only security audit trail (no FKs so events outlive the users they mention)
+CREATE TABLE IF NOT EXISTS auth.audit_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event_type VARCHAR(50) NOT NULL,
+    outcome VARCHAR(20) NOT NULL CHECK (outcome IN ('success', 'failure')),
+    actor_id UUID,
+    target_id UUID,
+    ip_address TEXT,
+    user_agent TEXT,
+    details JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- Indexes
+CREATE INDEX IF NOT EXISTS idx_users_email ON auth.users(email);
+CREATE INDEX IF NOT EXISTS idx_users_role ON auth.users(role);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_verified_phone ON auth.users(phone) WHERE phone_verified;
+CREATE INDEX IF NOT EXISTS idx_users_created_at_id ON auth.users(created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_users_search_trgm ON auth.users USING GIN ((lower(coalesce(first_name, '') || ' ' || coalesce(last_name, '') || ' ' || email)) gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON auth.sessions(user_id);
+CREATE INDEX IF NOT EXISTS idx_sessions_expires_at ON auth.sessions(expires_at);
+CREATE INDEX IF NOT EXISTS idx_api_keys_user_id ON auth.api_keys(user_id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_api_keys_key_hash ON auth.api_keys(key_hash);
+CREATE INDEX IF NOT EXISTS idx_sessions_user_device ON auth.sessions(user_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_password_reset_tokens_user_id ON auth.password_reset_tokens(user_id);
+CREATE INDEX IF NOT EXISTS idx_email_change_requests_user_id ON auth.email_change_requests(user_id);
+CREATE INDEX IF NOT EXISTS idx_email_verification_tokens_user_id ON auth.email_verification_tokens(user_id);
+CREATE INDEX IF NOT EXISTS idx_organization_members_user_id ON auth.organization_members(user_id);
+CREATE INDEX IF NOT EXISTS idx_organization_invitations_org_id ON auth.organization_invitations(org_id);
+CREATE INDEX IF NOT EXISTS idx_account_deletions_due ON auth.account_deletions(scheduled_for) WHERE completed_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_users_unverified ON auth.users(created_at) WHERE NOT email_verified;
+CREATE INDEX IF NOT EXISTS idx_login_alerts_user_id ON auth.login_alerts(user_id);
+CREATE INDEX IF NOT EXISTS idx_magic_links_user_id ON auth.magic_links(user_id);
+CREATE INDEX IF NOT EXISTS idx_webhook_subscriptions_user_id ON auth.webhook_subscriptions(user_id);
+CREATE INDEX IF NOT EXISTS idx_webhook_subscriptions_org_id ON auth.webhook_subscriptions(org_id);
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due ON auth.webhook_deliveries(next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_subscription ON auth.webhook_deliveries(subscription_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_outbox_events_unpublished ON auth.outbox_events(sequence) WHERE published_at IS NULL;
+CREATE INDEX IF NOT EXISTS idx_outbox_events_published_at ON auth.outbox_events(published_at) WHERE published_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_audit_events_created_at ON auth.audit_events(created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_events_actor_id ON auth.audit_events(actor_id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_target_id ON auth.audit_events(target_id);
+CREATE INDEX IF NOT EXISTS idx_audit_events_type ON auth.audit_events(event_type, created_at DESC);
+
+CREATE OR REPLACE FUNCTION auth.update_updated_at_column()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.updated_at = CURRENT_TIMESTAMP;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS update_users_updated_at ON auth.users;
+CREATE TRIGGER update_users_updated_at BEFORE UPDATE ON auth.users FOR EACH ROW EXECUTE FUNCTION auth.update_updated_at_column();
+
+-- Audit events can be inserted but never rewritten
+CREATE OR REPLACE FUNCTION auth.reject_audit_mutation()
+RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'auth.audit_events is append-only';
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS audit_events_append_only ON auth.audit_events;
+DROP TRIGGER IF EXISTS audit_events_no_truncate ON auth.audit_events;
+CREATE TRIGGER audit_events_append_only BEFORE UPDATE OR DELETE ON auth.audit_events FOR EACH ROW EXECUTE FUNCTION auth.reject_audit_mutation();
+CREATE TRIGGER audit_events_no_truncate BEFORE TRUNCATE ON auth.audit_events FOR EACH STATEMENT EXECUTE FUNCTION auth.reject_audit_mutation();
@@ -0,0 +1,35 @@
+-- Restores the original column types and nullability. Rows removed or filled
+-- in on the way up are not restored, and an address that is not valid INET
+-- input makes this fail rather than be dropped.
+
+ALTER TABLE auth.api_keys
+    ALTER COLUMN user_id DROP NOT NULL,
+    ALTER COLUMN scopes DROP NOT NULL,
+    ALTER COLUMN created_at DROP NOT NULL;
+
+ALTER TABLE auth.sessions
+    ALTER COLUMN user_id DROP NOT NULL,
+    ALTER COLUMN created_at DROP NOT NULL;
+
+ALTER TABLE auth.users
+    ALTER COLUMN role DROP NOT NULL,
+    ALTER COLUMN status DROP NOT NULL,
+    ALTER COLUMN email_verified DROP NOT NULL,
+    ALTER COLUMN phone_verified DROP NOT NULL,
+    ALTER COLUMN created_at DROP NOT NULL,
+    ALTER COLUMN updated_at DROP NOT NULL;
+
+ALTER TABLE auth.api_keys
+    ALTER COLUMN last_used TYPE TIMESTAMP USING last_used AT TIME ZONE 'UTC',
+    ALTER COLUMN expires_at TYPE TIMESTAMP USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC';
+
+ALTER TABLE auth.sessions
+    ALTER COLUMN expires_at TYPE TIMESTAMP USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN ip_address TYPE INET USING ip_address::inet;
+
+ALTER TABLE auth.users
+    ALTER COLUMN last_login TYPE TIMESTAMP USING last_login AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMP USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN updated_at TYPE TIMESTAMP USING updated_at AT TIME ZONE 'UTC';
@@ -0,0 +1,57 @@
+-- Brings the columns the service reads into line with models.rs: addresses
+-- are stored as the text clients sent, timestamps carry a time zone, and
+-- columns decoded into non-optional fields are NOT NULL.
+
+-- Timestamps without a zone were written as UTC, so that is how they are read
+ALTER TABLE auth.users
+    ALTER COLUMN last_login TYPE TIMESTAMPTZ USING last_login AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at AT TIME ZONE 'UTC';
+
+ALTER TABLE auth.sessions
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN ip_address TYPE TEXT USING host(ip_address);
+
+ALTER TABLE auth.api_keys
+    ALTER COLUMN last_used TYPE TIMESTAMPTZ USING last_used AT TIME ZONE 'UTC',
+    ALTER COLUMN expires_at TYPE TIMESTAMPTZ USING expires_at AT TIME ZONE 'UTC',
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC';
+
+-- Fill the gaps the old defaults allowed before forbidding them. Sessions and
+-- keys without an owner could never be used.
+UPDATE auth.users
+SET role = COALESCE(role, 'customer'),
+    status = COALESCE(status, 'active'),
+    email_verified = COALESCE(email_verified, FALSE),
+    phone_verified = COALESCE(phone_verified, FALSE),
+    created_at = COALESCE(created_at, CURRENT_TIMESTAMP),
+    updated_at = COALESCE(updated_at, created_at, CURRENT_TIMESTAMP)
+WHERE role IS NULL OR status IS NULL OR email_verified IS NULL OR phone_verified IS NULL
+   OR created_at IS NULL OR updated_at IS NULL;
+
+DELETE FROM auth.sessions WHERE user_id IS NULL;
+UPDATE auth.sessions SET created_at = CURRENT_TIMESTAMP WHERE created_at IS NULL;
+
+DELETE FROM auth.api_keys WHERE user_id IS NULL;
+UPDATE auth.api_keys
+SET scopes = COALESCE(scopes, '{}'),
+    created_at = COALESCE(created_at, CURRENT_TIMESTAMP)
+WHERE scopes IS NULL OR created_at IS NULL;
+
+ALTER TABLE auth.users
+    ALTER COLUMN role SET NOT NULL,
+    ALTER COLUMN status SET NOT NULL,
+    ALTER COLUMN email_verified SET NOT NULL,
+    ALTER COLUMN phone_verified SET NOT NULL,
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at SET NOT NULL;
+
+ALTER TABLE auth.sessions
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN created_at SET NOT NULL;
+
+ALTER TABLE auth.api_keys
+    ALTER COLUMN user_id SET NOT NULL,
+    ALTER COLUMN scopes SET NOT NULL,
+    ALTER COLUMN created_at SET NOT NULL;
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS auth.pending_session_revocations;
@@ -0,0 +1,7 @@
+-- Sessions deleted while Redis was unreachable. Their cache entries and
+-- revocation markers are written by a retry job; until then the service
+-- reads sessions from Postgres only.
+CREATE TABLE auth.pending_session_revocations (
+    session_id UUID PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,18 @@
+-- Drops the marketplace foreign keys to auth.users, whether this migration or
+-- an older init-db.sql created them.
+
+DO $$
+BEGIN
+    IF to_regclass('marketplace.vendors') IS NOT NULL THEN
+        ALTER TABLE marketplace.vendors DROP CONSTRAINT IF EXISTS vendors_user_id_fkey;
+    END IF;
+    IF to_regclass('marketplace.price_history') IS NOT NULL THEN
+        ALTER TABLE marketplace.price_history DROP CONSTRAINT IF EXISTS price_history_changed_by_fkey;
+    END IF;
+    IF to_regclass('marketplace.orders') IS NOT NULL THEN
+        ALTER TABLE marketplace.orders DROP CONSTRAINT IF EXISTS orders_customer_id_fkey;
+    END IF;
+    IF to_regclass('marketplace.reviews') IS NOT NULL THEN
+        ALTER TABLE marketplace.reviews DROP CONSTRAINT IF EXISTS reviews_customer_id_fkey;
+    END IF;
+END $$;
@@ -0,0 +1,31 @@
+-- Restores the marketplace foreign keys to auth.users that init-db.sql can't
+-- create, since it runs before this service has migrated. Each is added only
+-- when its table exists and doesn't have it yet; scripts/init-db.sql does the
+-- same for databases where the auth schema came first.
+
+DO $$
+DECLARE
+    reference RECORD;
+BEGIN
+    FOR reference IN
+        SELECT * FROM (VALUES
+            ('vendors', 'user_id', 'vendors_user_id_fkey', 'ON DELETE CASCADE'),
+            ('price_history', 'changed_by', 'price_history_changed_by_fkey', ''),
+            ('orders', 'customer_id', 'orders_customer_id_fkey', ''),
+            ('reviews', 'customer_id', 'reviews_customer_id_fkey', '')
+        ) AS r (table_name, column_name, constraint_name, on_delete)
+    LOOP
+        IF to_regclass('marketplace.' || reference.table_name) IS NOT NULL
+           AND NOT EXISTS (
+               SELECT 1 FROM pg_constraint
+               WHERE conname = reference.constraint_name
+                 AND conrelid = to_regclass('marketplace.' || reference.table_name)
+           )
+        THEN
+            EXECUTE format(
+                'ALTER TABLE marketplace.%I ADD CONSTRAINT %I FOREIGN KEY (%I) REFERENCES auth.users(id) %s',
+                reference.table_name, reference.constraint_name, reference.column_name, reference.on_delete
+            );
+        END IF;
+    END LOOP;
+END $$;
@@ -0,0 +1,5 @@
+-- Nothing is undone. The columns and the status check are part of 0001 on
+-- databases it created, so dropping them here would leave those behind it;
+-- reverting 0001 removes them along with the tables.
+
+SELECT 1;
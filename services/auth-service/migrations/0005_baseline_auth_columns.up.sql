@@ -0,0 +1,19 @@
+-- Adds what 0001 declares on the users, sessions and api_keys tables to
+-- databases where scripts/init-db.sql created those tables first, since
+-- 0001 leaves existing tables as they are. On databases 0001 created, every
+-- statement finds its column already there.
+
+ALTER TABLE auth.users
+    ADD COLUMN IF NOT EXISTS password_reset_required BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN IF NOT EXISTS verification_reminder_sent_at TIMESTAMPTZ;
+
+ALTER TABLE auth.sessions
+    ADD COLUMN IF NOT EXISTS impersonator_id UUID REFERENCES auth.users(id) ON DELETE CASCADE;
+
+ALTER TABLE auth.api_keys
+    ADD COLUMN IF NOT EXISTS disabled_at TIMESTAMPTZ;
+
+-- The old check predates account deletion
+ALTER TABLE auth.users DROP CONSTRAINT IF EXISTS users_status_check;
+ALTER TABLE auth.users ADD CONSTRAINT users_status_check
+    CHECK (status IN ('active', 'inactive', 'suspended', 'deleted'));
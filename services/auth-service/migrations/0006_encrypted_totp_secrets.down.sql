@@ -0,0 +1,3 @@
+-- Fails while encrypted secrets are stored: they don't fit the old column,
+-- and SQL can't decrypt them. Disable MFA for those users first.
+ALTER TABLE auth.totp_factors ALTER COLUMN secret TYPE VARCHAR(64);
@@ -0,0 +1,5 @@
+-- Authenticator secrets are now stored encrypted ("v1:" and the base64 of
+-- nonce, ciphertext and tag), which no longer fits in 64 characters. Rows
+-- written before this are encrypted by the service the next time they are
+-- read.
+ALTER TABLE auth.totp_factors ALTER COLUMN secret TYPE TEXT;
@@ -0,0 +1,34 @@
+-- Devices each user has signed in from, for new-device login alerts. Kept
+-- apart from auth.sessions so signing out doesn't make a device unfamiliar.
+-- A device is its user agent and the /24 (IPv4) or /64 (IPv6) network it
+-- connected from; an unknown value is stored as ''.
+CREATE TABLE auth.known_devices (
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    user_agent TEXT NOT NULL,
+    network TEXT NOT NULL,
+    first_seen TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id, user_agent, network)
+);
+
+CREATE INDEX idx_known_devices_last_seen ON auth.known_devices(last_seen);
+
+-- Start from the sessions still on record so existing users aren't alerted
+-- about every device after the upgrade
+INSERT INTO auth.known_devices (user_id, user_agent, network, first_seen, last_seen)
+SELECT
+    user_id,
+    COALESCE(user_agent, ''),
+    COALESCE(
+        network(set_masklen(
+            ip_address::inet,
+            CASE family(ip_address::inet) WHEN 4 THEN 24 ELSE 64 END
+        ))::text,
+        ''
+    ),
+    MIN(created_at),
+    MAX(created_at)
+FROM auth.sessions
+WHERE user_id IS NOT NULL AND impersonator_id IS NULL
+GROUP BY 1, 2, 3
+ON CONFLICT DO NOTHING;
@@ -1,24 +1,119 @@
-use bcrypt::{hash, verify, DEFAULT_COST};
+use bookmarket_auth::secret_key_id;
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{
+    decode, decode_header, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 use crate::{
     errors::AppError,
-    models::{Claims, Session, User},
+    models::{
+        ActorClaim, AuthMethod, Authentication, Claims, MagicLinkClaims, MfaChallengeClaims,
+        OrgContext, Session, TokenUse, User,
+    },
 };
 
-pub fn hash_password(password: &str, cost: u32) -> Result<String, AppError> {
-    hash(password, cost).map_err(AppError::from)
+const MAGIC_LINK_AUDIENCE: &str = "magic_link";
+const MFA_CHALLENGE_AUDIENCE: &str = "mfa_challenge";
+
+/// Issues an access token, acting for `org` when one is given. `authn`
+/// records when and how the user last proved who they are.
+pub fn create_jwt_token(
+    user: &User,
+    session: &Session,
+    org: Option<OrgContext>,
+    authn: Option<&Authentication>,
+    expiration_seconds: i64,
+    secret: &str,
+) -> Result<String, AppError> {
+    encode_claims(
+        user,
+        session,
+        None,
+        org,
+        authn,
+        TokenUse::Access,
+        None,
+        expiration_seconds,
+        secret,
+    )
 }
 
-pub fn verify_password(password: &str, hash: &str) -> Result<bool, AppError> {
-    verify(password, hash).map_err(AppError::from)
+/// The hash a session must still hold for a refresh token to be unspent: its
+/// generation's, or for tokens issued before rotation, the id the session was
+/// created with. `None` when the token can never be current again.
+pub fn refresh_generation_hash(claims: &Claims, session: &Session) -> Option<String> {
+    match &claims.rti {
+        Some(rti) => Some(hash_token(rti)),
+        None if Uuid::parse_str(&session.token_hash).is_ok() => Some(session.token_hash.clone()),
+        None => None,
+    }
 }
 
-pub fn create_jwt_token(
+/// Issues a refresh token for the session's `generation`, as returned by
+/// `SessionRepository::rotate_refresh_token`. It carries the same
+/// organisation and authentication as the access token so a refresh keeps
+/// both.
+#[allow(clippy::too_many_arguments)]
+pub fn create_refresh_token(
+    user: &User,
+    session: &Session,
+    generation: &str,
+    org: Option<OrgContext>,
+    authn: Option<&Authentication>,
+    expiration_seconds: i64,
+    secret: &str,
+) -> Result<String, AppError> {
+    encode_claims(
+        user,
+        session,
+        None,
+        org,
+        authn,
+        TokenUse::Refresh,
+        Some(generation.to_string()),
+        expiration_seconds,
+        secret,
+    )
+}
+
+/// Issues an access token for `user` that carries `impersonator` as the actor.
+pub fn create_impersonation_token(
     user: &User,
     session: &Session,
+    impersonator: &User,
+    expiration_seconds: i64,
+    secret: &str,
+) -> Result<String, AppError> {
+    let act = ActorClaim {
+        sub: impersonator.id.to_string(),
+        email: impersonator.email.clone(),
+    };
+
+    encode_claims(
+        user,
+        session,
+        Some(act),
+        None,
+        None,
+        TokenUse::Access,
+        None,
+        expiration_seconds,
+        secret,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn encode_claims(
+    user: &User,
+    session: &Session,
+    act: Option<ActorClaim>,
+    org: Option<OrgContext>,
+    authn: Option<&Authentication>,
+    token_use: TokenUse,
+    rti: Option<String>,
     expiration_seconds: i64,
     secret: &str,
 ) -> Result<String, AppError> {
@@ -32,24 +127,136 @@ pub fn create_jwt_token(
         iat: now.timestamp(),
         exp: exp.timestamp(),
         jti: session.id.to_string(),
+        act,
+        token_use: Some(token_use),
+        rti,
+        org_id: org.map(|org| org.org_id),
+        org_role: org.map(|org| org.role),
+        auth_time: authn.map(|authn| authn.auth_time),
+        amr: authn.map(|authn| authn.amr.clone()).unwrap_or_default(),
     };
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
-    )
-    .map_err(AppError::from)
+    sign(&claims, secret)
+}
+
+pub fn decode_jwt_token(token: &str, secrets: &[&str]) -> Result<Claims, AppError> {
+    verify(token, secrets, &Validation::default())
 }
 
-pub fn decode_jwt_token(token: &str, secret: &str) -> Result<Claims, AppError> {
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    )?;
+/// Checks the signature but not the expiry, for revocation: a client must be
+/// able to end a session with a refresh token that has already lapsed.
+pub fn decode_jwt_token_allow_expired(token: &str, secrets: &[&str]) -> Result<Claims, AppError> {
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
 
-    Ok(token_data.claims)
+    verify(token, secrets, &validation)
+}
+
+/// Signs the token carried by a magic sign-in link.
+pub fn create_magic_link_token(
+    user_id: Uuid,
+    link_id: Uuid,
+    expiration_seconds: i64,
+    secret: &str,
+) -> Result<String, AppError> {
+    let now = Utc::now();
+    let claims = MagicLinkClaims {
+        sub: user_id.to_string(),
+        aud: MAGIC_LINK_AUDIENCE.to_string(),
+        iat: now.timestamp(),
+        exp: (now + Duration::seconds(expiration_seconds)).timestamp(),
+        jti: link_id.to_string(),
+    };
+
+    sign(&claims, secret)
+}
+
+pub fn decode_magic_link_token(token: &str, secrets: &[&str]) -> Result<MagicLinkClaims, AppError> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[MAGIC_LINK_AUDIENCE]);
+    validation.leeway = 0;
+
+    verify(token, secrets, &validation)
+}
+
+/// Signs the token a sign-in hands back while it waits for the user's
+/// authenticator code.
+pub fn create_mfa_challenge_token(
+    user_id: Uuid,
+    method: &str,
+    amr: AuthMethod,
+    remember_me: bool,
+    expiration_seconds: i64,
+    secret: &str,
+) -> Result<String, AppError> {
+    let now = Utc::now();
+    let claims = MfaChallengeClaims {
+        sub: user_id.to_string(),
+        aud: MFA_CHALLENGE_AUDIENCE.to_string(),
+        iat: now.timestamp(),
+        exp: (now + Duration::seconds(expiration_seconds)).timestamp(),
+        jti: Uuid::new_v4().to_string(),
+        method: method.to_string(),
+        amr,
+        remember_me,
+    };
+
+    sign(&claims, secret)
+}
+
+pub fn decode_mfa_challenge_token(
+    token: &str,
+    secrets: &[&str],
+) -> Result<MfaChallengeClaims, AppError> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[MFA_CHALLENGE_AUDIENCE]);
+    validation.leeway = 0;
+
+    verify(token, secrets, &validation)
+}
+
+/// Signs with the current secret, naming it in the header so verifiers can
+/// tell it from the previous one during a rotation.
+fn sign(claims: &impl Serialize, secret: &str) -> Result<String, AppError> {
+    let header = Header {
+        kid: Some(secret_key_id(secret)),
+        ..Header::default()
+    };
+
+    encode(&header, claims, &EncodingKey::from_secret(secret.as_ref())).map_err(AppError::from)
+}
+
+/// Verifies with the secret the header names. Tokens from before key ids
+/// were added name none, so each secret is tried.
+fn verify<T: DeserializeOwned>(
+    token: &str,
+    secrets: &[&str],
+    validation: &Validation,
+) -> Result<T, AppError> {
+    let kid = decode_header(token)?.kid;
+    let candidates = secrets
+        .iter()
+        .filter(|secret| kid.as_ref().is_none_or(|kid| *kid == secret_key_id(secret)));
+
+    let mut error = None;
+    for secret in candidates {
+        match decode::<T>(
+            token,
+            &DecodingKey::from_secret(secret.as_ref()),
+            validation,
+        ) {
+            Ok(data) => return Ok(data.claims),
+            // A signature mismatch says least about why the token failed
+            Err(e) if error.is_none() || *e.kind() != ErrorKind::InvalidSignature => {
+                error = Some(e)
+            }
+            Err(_) => {}
+        }
+    }
+
+    Err(error
+        .unwrap_or_else(|| ErrorKind::InvalidSignature.into())
+        .into())
 }
 
 pub fn generate_api_key() -> String {
@@ -70,6 +277,22 @@ pub fn generate_api_key() -> String {
     format!("bm_{}", key)
 }
 
-pub fn hash_api_key(key: &str) -> Result<String, AppError> {
-    hash(key, DEFAULT_COST).map_err(AppError::from)
+/// API keys are random and long, so like link tokens they are stored as a
+/// plain digest that can be looked up directly.
+pub fn hash_api_key(key: &str) -> String {
+    hash_token(key)
+}
+
+/// Generates an opaque single-use token for links sent by email.
+pub fn generate_token() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hashes a single-use token for storage. Unlike passwords these tokens are
+/// high-entropy, so a fast digest is enough and allows lookup by hash.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
@@ -0,0 +1,421 @@
+//! Operator commands for auth-service, run against the same database and
+//! Redis as the service and configured the same way (`DATABASE_URL`,
+//! `REDIS_URL`, `JWT_SECRET`, `JWT_PREVIOUS_SECRET`, `MFA_ENCRYPTION_KEY`
+//! and `AUTH_*`).
+//!
+//! Every command prints one JSON object on stdout. Failures print
+//! `{"error": {...}}` on stderr and exit with status 1. Changes are recorded
+//! in the audit log without an actor and with `"via": "cli"`.
+//!
+//! The `migrate` commands only need the database, so they can run before the
+//! service is deployed.
+
+use bookmarket_auth::secret_key_id;
+use clap::{Parser, Subcommand};
+use serde_json::{json, Value};
+use std::io::BufRead;
+use std::process::ExitCode;
+use uuid::Uuid;
+
+use auth_service::{
+    auth::generate_token,
+    config::Config,
+    database,
+    errors::AppError,
+    models::{AuditEventType, AuditOutcome, User, UserRole},
+    repositories::{
+        postgres::{PgApiKeyRepository, PgAuditRepository, PgSessionRepository, PgUserRepository},
+        ApiKeyRepository, AuditRepository, SessionRepository, UserRepository,
+    },
+    services::{
+        accounts,
+        audit::{ClientContext, NewAuditEvent},
+        maintenance,
+        password_hasher::PasswordHasher,
+        password_policy::{PasswordContext, PasswordPolicy},
+        session_cache::SessionCache,
+    },
+};
+
+#[derive(Parser)]
+#[command(
+    name = "bm-auth-admin",
+    about = "BookMarket auth-service administration"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates an admin account with a verified email address.
+    CreateAdmin {
+        #[arg(long)]
+        email: String,
+        #[arg(long)]
+        first_name: String,
+        #[arg(long)]
+        last_name: String,
+        /// Reads the password from the first line of stdin instead of
+        /// generating one.
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Sets a new password and signs the user out everywhere.
+    ResetPassword {
+        /// User ID or email address.
+        user: String,
+        /// Reads the password from the first line of stdin instead of
+        /// generating one.
+        #[arg(long)]
+        password_stdin: bool,
+    },
+    /// Suspends a user and signs them out everywhere.
+    Suspend {
+        /// User ID or email address.
+        user: String,
+    },
+    /// Lets a suspended user sign in again.
+    Activate {
+        /// User ID or email address.
+        user: String,
+    },
+    /// Signs a user out of every session.
+    RevokeSessions {
+        /// User ID or email address.
+        user: String,
+    },
+    /// Deletes expired sessions now rather than at the next scheduled run.
+    PurgeSessions,
+    /// Lists a user's API keys.
+    ListKeys {
+        /// User ID or email address.
+        user: String,
+    },
+    /// Revokes one of a user's API keys.
+    RevokeKey {
+        /// User ID or email address.
+        user: String,
+        key_id: Uuid,
+    },
+    /// Generates a new token signing secret.
+    ///
+    /// Deploy it as `JWT_SECRET`, and the secret it replaces as
+    /// `JWT_PREVIOUS_SECRET`, to auth-service and every service that
+    /// verifies its tokens. Tokens signed with either keep working, so nobody
+    /// is signed out; drop `JWT_PREVIOUS_SECRET` once a refresh token
+    /// lifetime has passed. Pending phone codes must be requested again.
+    RotateSigningKey,
+    /// Applies pending schema migrations.
+    Migrate,
+    /// Lists the schema migrations and whether each has been applied.
+    MigrateStatus,
+    /// Reverts the most recently applied schema migration.
+    MigrateRevert,
+}
+
+struct Context {
+    config: Config,
+    db: sqlx::PgPool,
+    users: PgUserRepository,
+    sessions: PgSessionRepository,
+    api_keys: PgApiKeyRepository,
+    audit: PgAuditRepository,
+}
+
+impl Context {
+    async fn connect(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let db = database::connect(&config.database_url).await?;
+        let redis = redis::Client::open(config.redis_url.as_str())?;
+
+        // Revocations must reach the cache and leave markers, as the service's do
+        let session_cache = SessionCache::new(
+            &redis,
+            config.session_cache_ttl,
+            config.refresh_token_expiration as u64,
+        );
+
+        Ok(Self {
+            users: PgUserRepository::new(db.clone()),
+            sessions: PgSessionRepository::new(db.clone(), session_cache),
+            api_keys: PgApiKeyRepository::new(db.clone()),
+            audit: PgAuditRepository::new(db.clone()),
+            db,
+            config,
+        })
+    }
+
+    /// Looks a user up by ID, or by email address when `user` isn't a UUID.
+    async fn user(&self, user: &str) -> Result<User, AppError> {
+        match Uuid::parse_str(user) {
+            Ok(id) => self.users.get_by_id(&id.to_string()).await,
+            Err(_) => self.users.get_by_email(user).await,
+        }
+    }
+
+    async fn record(&self, event: NewAuditEvent) -> Result<(), AppError> {
+        self.audit.record(&ClientContext::default(), event).await
+    }
+}
+
+fn cli_event(event_type: AuditEventType, target: Uuid, details: Value) -> NewAuditEvent {
+    let mut details = details;
+    details["via"] = json!("cli");
+
+    NewAuditEvent::new(event_type, AuditOutcome::Success)
+        .target(target)
+        .details(details)
+}
+
+/// The password to set, and whether it was generated and so must be shown.
+fn read_password(from_stdin: bool) -> Result<(String, bool), AppError> {
+    if !from_stdin {
+        return Ok((generate_token(), true));
+    }
+
+    let mut line = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .map_err(|e| AppError::BadRequest(format!("Could not read password: {}", e)))?;
+
+    Ok((line.trim_end_matches(['\r', '\n']).to_string(), false))
+}
+
+async fn run(command: Command, ctx: &Context) -> Result<Value, AppError> {
+    let hasher = PasswordHasher::from_config(&ctx.config)?;
+
+    match command {
+        Command::CreateAdmin {
+            email,
+            first_name,
+            last_name,
+            password_stdin,
+        } => {
+            if ctx.users.email_taken(&email).await? {
+                return Err(AppError::Conflict("User already exists".to_string()));
+            }
+
+            let (password, generated) = read_password(password_stdin)?;
+            PasswordPolicy::from_config(&ctx.config)
+                .enforce(
+                    &password,
+                    PasswordContext {
+                        email: Some(&email),
+                        first_name: Some(&first_name),
+                        last_name: Some(&last_name),
+                    },
+                )
+                .await?;
+
+            let password_hash = hasher.hash(&password).await?;
+            let user = ctx
+                .users
+                .create(
+                    &email,
+                    &password_hash,
+                    &first_name,
+                    &last_name,
+                    None,
+                    UserRole::Admin,
+                )
+                .await?;
+            accounts::mark_email_verified(&ctx.db, user.id, &user.email).await?;
+
+            ctx.record(cli_event(
+                AuditEventType::AdminCreated,
+                user.id,
+                json!({ "email": user.email }),
+            ))
+            .await?;
+
+            Ok(json!({
+                "user_id": user.id,
+                "email": user.email,
+                "password": generated.then_some(password),
+            }))
+        }
+        Command::ResetPassword {
+            user,
+            password_stdin,
+        } => {
+            let user = ctx.user(&user).await?;
+
+            let (password, generated) = read_password(password_stdin)?;
+            PasswordPolicy::from_config(&ctx.config)
+                .enforce(&password, PasswordContext::for_user(&user))
+                .await?;
+
+            let password_hash = hasher.hash(&password).await?;
+            let revoked =
+                accounts::set_password(&ctx.db, &ctx.sessions, user.id, &password_hash).await?;
+
+            ctx.record(cli_event(
+                AuditEventType::PasswordReset,
+                user.id,
+                json!({ "sessions_revoked": revoked }),
+            ))
+            .await?;
+
+            Ok(json!({
+                "user_id": user.id,
+                "sessions_revoked": revoked,
+                "password": generated.then_some(password),
+            }))
+        }
+        Command::Suspend { user } => {
+            let user = ctx.user(&user).await?;
+            let revoked = accounts::suspend(&ctx.db, &ctx.sessions, user.id).await?;
+
+            ctx.record(cli_event(
+                AuditEventType::UserSuspended,
+                user.id,
+                json!({ "sessions_revoked": revoked }),
+            ))
+            .await?;
+
+            Ok(json!({ "user_id": user.id, "status": "suspended", "sessions_revoked": revoked }))
+        }
+        Command::Activate { user } => {
+            let user = ctx.user(&user).await?;
+            accounts::activate(&ctx.db, user.id).await?;
+
+            ctx.record(cli_event(AuditEventType::UserActivated, user.id, json!({})))
+                .await?;
+
+            Ok(json!({ "user_id": user.id, "status": "active" }))
+        }
+        Command::RevokeSessions { user } => {
+            let user = ctx.user(&user).await?;
+            let revoked = ctx.sessions.delete_for_user(user.id).await?;
+
+            ctx.record(cli_event(
+                AuditEventType::SessionsRevoked,
+                user.id,
+                json!({ "sessions_revoked": revoked }),
+            ))
+            .await?;
+
+            Ok(json!({ "user_id": user.id, "sessions_revoked": revoked }))
+        }
+        Command::PurgeSessions => {
+            let purged =
+                maintenance::purge_expired_sessions(&ctx.db, &ctx.sessions, &ctx.audit).await?;
+
+            Ok(json!({ "sessions_purged": purged }))
+        }
+        Command::ListKeys { user } => {
+            let user = ctx.user(&user).await?;
+            let keys = ctx.api_keys.list_for_user(user.id).await?;
+
+            Ok(json!({ "user_id": user.id, "keys": keys }))
+        }
+        Command::RevokeKey { user, key_id } => {
+            let user = ctx.user(&user).await?;
+            if !ctx.api_keys.delete(key_id, user.id).await? {
+                return Err(AppError::NotFound);
+            }
+
+            ctx.record(cli_event(
+                AuditEventType::ApiKeyRevoked,
+                user.id,
+                json!({ "key_id": key_id }),
+            ))
+            .await?;
+
+            Ok(json!({ "user_id": user.id, "key_id": key_id, "revoked": true }))
+        }
+        Command::RotateSigningKey => Ok(rotate_signing_key()),
+        Command::Migrate | Command::MigrateStatus | Command::MigrateRevert => {
+            run_migrations(command, &ctx.db).await
+        }
+    }
+}
+
+async fn run_migrations(command: Command, db: &sqlx::PgPool) -> Result<Value, AppError> {
+    let migration_error =
+        |e: sqlx::migrate::MigrateError| AppError::InternalServerError(e.to_string());
+
+    match command {
+        Command::Migrate => {
+            database::migrate(db).await.map_err(migration_error)?;
+            Ok(json!({ "migrations": database::status(db).await? }))
+        }
+        Command::MigrateRevert => {
+            let reverted = database::revert_latest(db).await.map_err(migration_error)?;
+            Ok(json!({ "reverted": reverted }))
+        }
+        _ => Ok(json!({ "migrations": database::status(db).await? })),
+    }
+}
+
+/// Secrets are read at startup, so rotating one is a deployment; this only
+/// produces the new value and the key id tokens signed with it will carry.
+fn rotate_signing_key() -> Value {
+    let secret = generate_token();
+
+    json!({
+        "key_id": secret_key_id(&secret),
+        "jwt_secret": secret,
+        "deploy_as": "JWT_SECRET",
+        "replaced_secret_deploy_as": "JWT_PREVIOUS_SECRET",
+    })
+}
+
+fn fail(message: impl std::fmt::Display, details: Option<Value>) -> ExitCode {
+    eprintln!(
+        "{}",
+        json!({ "error": { "message": message.to_string(), "details": details } })
+    );
+    ExitCode::FAILURE
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // Needs neither the database nor a valid configuration
+    if let Command::RotateSigningKey = cli.command {
+        println!("{}", rotate_signing_key());
+        return ExitCode::SUCCESS;
+    }
+
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => return fail(format!("Invalid configuration: {}", e), None),
+    };
+
+    // Schema changes need only the database, which may not be usable by the
+    // rest of the commands yet
+    if let Command::Migrate | Command::MigrateStatus | Command::MigrateRevert = cli.command {
+        let db = match database::connect(&config.database_url).await {
+            Ok(db) => db,
+            Err(e) => return fail(format!("Could not connect: {}", e), None),
+        };
+        return match run_migrations(cli.command, &db).await {
+            Ok(output) => {
+                println!("{}", output);
+                ExitCode::SUCCESS
+            }
+            Err(e) => fail(e, None),
+        };
+    }
+
+    let ctx = match Context::connect(config).await {
+        Ok(ctx) => ctx,
+        Err(e) => return fail(format!("Could not connect: {}", e), None),
+    };
+
+    match run(cli.command, &ctx).await {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(AppError::WeakPassword(feedback)) => fail(
+            "Password does not meet the password policy",
+            serde_json::to_value(feedback).ok(),
+        ),
+        Err(e) => fail(e, None),
+    }
+}
@@ -1,5 +1,7 @@
+use ipnet::IpNet;
 use serde::Deserialize;
 use std::env;
+use std::net::IpAddr;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -7,12 +9,138 @@ pub struct Config {
     pub database_url: String,
     pub redis_url: String,
     pub jwt_secret: String,
+    /// The secret `jwt_secret` replaced, still accepted for verification
+    /// until tokens signed with it have expired. Unset outside a rotation.
+    pub jwt_previous_secret: Option<String>,
     pub jwt_expiration: i64,
     pub refresh_token_expiration: i64,
+    pub password_hash_algorithm: String,
+    pub argon2_memory_kib: u32,
+    pub argon2_iterations: u32,
+    pub argon2_parallelism: u32,
     pub bcrypt_cost: u32,
+    pub session_cache_ttl: u64,
     pub rate_limit_requests: u64,
     pub rate_limit_window: u64,
     pub cors_origins: Vec<String>,
+    pub public_url: String,
+    pub frontend_url: String,
+    pub smtp_url: Option<String>,
+    pub mail_from: String,
+    pub password_reset_ttl: i64,
+    pub email_verification_ttl: i64,
+    pub email_change_ttl: i64,
+    pub login_alert_ttl: i64,
+    pub known_device_window_days: i64,
+    pub dormant_account_days: i64,
+    pub impersonation_ttl: i64,
+    pub reauthentication_max_age: i64,
+    /// AES-256 key for authenticator secrets at rest, as 64 hex characters.
+    pub mfa_encryption_key: String,
+    /// How long a sign-in waits for the authenticator code.
+    pub mfa_challenge_ttl: i64,
+    /// Codes that may be tried against one account per challenge lifetime.
+    pub mfa_challenge_max_attempts: u64,
+    pub oidc_state_ttl: u64,
+    pub magic_link_ttl: i64,
+    pub magic_link_email_hourly_limit: u64,
+    pub magic_link_ip_hourly_limit: u64,
+    pub sms_gateway_url: Option<String>,
+    pub sms_gateway_api_key: Option<String>,
+    pub sms_sender_id: String,
+    pub phone_otp_ttl: u64,
+    pub phone_otp_cooldown: u64,
+    pub phone_otp_hourly_limit: u64,
+    pub phone_otp_max_attempts: u64,
+    pub password_min_length: usize,
+    pub password_max_length: usize,
+    pub password_require_lowercase: bool,
+    pub password_require_uppercase: bool,
+    pub password_require_digit: bool,
+    pub password_require_symbol: bool,
+    pub password_min_score: u8,
+    /// Directory of Have I Been Pwned range files; screening is off when unset.
+    pub breached_passwords_dir: Option<String>,
+    /// Runs the maintenance jobs in this process; replicas coordinate through
+    /// Postgres, so this only needs turning off for one-off tooling.
+    pub jobs_enabled: bool,
+    /// Applies pending schema migrations at startup. When off, the service
+    /// still refuses to start on a schema that is behind.
+    pub run_migrations: bool,
+    pub session_cleanup_interval: u64,
+    pub api_key_expiry_interval: u64,
+    pub token_cleanup_interval: u64,
+    /// How long used or expired single-use tokens are kept for investigation.
+    pub token_retention: i64,
+    pub verification_reminder_interval: u64,
+    pub verification_reminder_delay: i64,
+    /// Cooling-off period between a deletion request and anonymisation.
+    pub account_deletion_grace_days: i64,
+    pub account_deletion_interval: u64,
+    pub session_gauge_interval: u64,
+    /// How often revocations that failed to reach Redis are retried.
+    pub session_revocation_retry_interval: u64,
+    /// How often unpublished user lifecycle events are relayed to Redis.
+    pub outbox_relay_interval: u64,
+    /// Redis stream the events are published to.
+    pub outbox_stream: String,
+    /// Approximate cap on the stream's length.
+    pub outbox_stream_max_len: usize,
+    /// How long published events stay in the outbox table.
+    pub outbox_retention: i64,
+    /// How often due webhook deliveries are sent.
+    pub webhook_delivery_interval: u64,
+    /// Attempts before a delivery is given up on.
+    pub webhook_max_attempts: u32,
+    /// Wait after the first failed attempt; doubles with each further failure.
+    pub webhook_retry_base: u64,
+    /// Per-request timeout for webhook receivers.
+    pub webhook_timeout: u64,
+    pub org_invitation_ttl: i64,
+    /// OTLP gRPC collector that spans are exported to; tracing stays local
+    /// when unset.
+    #[serde(default)]
+    pub otel_exporter_endpoint: Option<String>,
+    #[serde(default)]
+    pub oidc_providers: Vec<OidcProviderConfig>,
+    #[serde(default)]
+    pub oauth_clients: Vec<OAuthClientConfig>,
+    /// Reverse proxies whose `X-Forwarded-For` is believed, as addresses or
+    /// CIDR ranges. Forwarding headers from anyone else are ignored.
+    #[serde(skip)]
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+/// A backend service allowed to introspect tokens.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAuthClientConfig {
+    pub client_id: String,
+    pub client_secret: String,
+    /// Whether the client acts for BookMarket's own apps, which every session
+    /// and API key is issued to. Only these clients may revoke tokens.
+    #[serde(default)]
+    pub first_party: bool,
+}
+
+/// An external OpenID Connect provider users can sign in with.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcProviderConfig {
+    /// Short identifier used in URLs and stored with linked identities,
+    /// e.g. "google".
+    pub name: String,
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "email".to_string(),
+        "profile".to_string(),
+    ]
 }
 
 impl Config {
@@ -26,13 +154,68 @@ impl Config {
             .set_default("server_address", "0.0.0.0:8001")?
             .set_default("jwt_expiration", 3600)? // 1 hour
             .set_default("refresh_token_expiration", 2592000)? // 30 days
+            .set_default("password_hash_algorithm", "argon2id")? // or "bcrypt"
+            .set_default("argon2_memory_kib", 19456)? // 19 MiB, OWASP baseline
+            .set_default("argon2_iterations", 2)?
+            .set_default("argon2_parallelism", 1)?
             .set_default("bcrypt_cost", 12)?
+            .set_default("session_cache_ttl", 300)? // 5 minutes
             .set_default("rate_limit_requests", 100)?
             .set_default("rate_limit_window", 60)? // 1 minute
             .set_default(
                 "cors_origins",
                 vec!["http://localhost:3000", "http://localhost:3001"],
-            )?;
+            )?
+            .set_default("public_url", "http://localhost:8001")?
+            .set_default("frontend_url", "http://localhost:3000")?
+            .set_default("mail_from", "BookMarket <no-reply@bookmarket.ma>")?
+            .set_default("password_reset_ttl", 3600)? // 1 hour
+            .set_default("email_verification_ttl", 172800)? // 48 hours
+            .set_default("email_change_ttl", 86400)? // 24 hours
+            .set_default("login_alert_ttl", 604800)? // 7 days
+            .set_default("known_device_window_days", 90)?
+            .set_default("dormant_account_days", 180)?
+            .set_default("impersonation_ttl", 900)? // 15 minutes
+            .set_default("reauthentication_max_age", 300)? // 5 minutes
+            .set_default("mfa_challenge_ttl", 300)? // 5 minutes
+            .set_default("mfa_challenge_max_attempts", 5)?
+            .set_default("oidc_state_ttl", 600)? // 10 minutes
+            .set_default("magic_link_ttl", 600)? // 10 minutes
+            .set_default("magic_link_email_hourly_limit", 5)?
+            .set_default("magic_link_ip_hourly_limit", 20)?
+            .set_default("sms_sender_id", "BookMarket")?
+            .set_default("phone_otp_ttl", 300)? // 5 minutes
+            .set_default("phone_otp_cooldown", 60)? // 1 minute between codes per number
+            .set_default("phone_otp_hourly_limit", 5)?
+            .set_default("phone_otp_max_attempts", 5)?
+            .set_default("password_min_length", 10)?
+            .set_default("password_max_length", 128)?
+            .set_default("password_require_lowercase", true)?
+            .set_default("password_require_uppercase", false)?
+            .set_default("password_require_digit", false)?
+            .set_default("password_require_symbol", false)?
+            .set_default("password_min_score", 3)? // zxcvbn scale, 0-4
+            .set_default("jobs_enabled", true)?
+            .set_default("run_migrations", true)?
+            .set_default("session_cleanup_interval", 3600)? // 1 hour
+            .set_default("api_key_expiry_interval", 3600)? // 1 hour
+            .set_default("token_cleanup_interval", 21600)? // 6 hours
+            .set_default("token_retention", 2592000)? // 30 days
+            .set_default("verification_reminder_interval", 3600)? // 1 hour
+            .set_default("verification_reminder_delay", 86400)? // 1 day after registering
+            .set_default("account_deletion_grace_days", 30)?
+            .set_default("account_deletion_interval", 3600)? // 1 hour
+            .set_default("session_gauge_interval", 60)?
+            .set_default("session_revocation_retry_interval", 5)?
+            .set_default("outbox_relay_interval", 5)?
+            .set_default("outbox_stream", "bookmarket:events:users")?
+            .set_default("outbox_stream_max_len", 100000)?
+            .set_default("outbox_retention", 604800)? // 7 days
+            .set_default("webhook_delivery_interval", 10)?
+            .set_default("webhook_max_attempts", 8)?
+            .set_default("webhook_retry_base", 30)?
+            .set_default("webhook_timeout", 10)?
+            .set_default("org_invitation_ttl", 604800)?; // 7 days
 
         // Override with environment variables
         cfg = cfg.add_source(config::Environment::with_prefix("AUTH"));
@@ -47,11 +230,77 @@ impl Config {
         let jwt_secret = env::var("JWT_SECRET")
             .map_err(|_| config::ConfigError::Message("JWT_SECRET must be set".to_string()))?;
 
+        let mfa_encryption_key = env::var("MFA_ENCRYPTION_KEY").map_err(|_| {
+            config::ConfigError::Message("MFA_ENCRYPTION_KEY must be set".to_string())
+        })?;
+        if mfa_encryption_key.len() != 64 || hex::decode(&mfa_encryption_key).is_err() {
+            return Err(config::ConfigError::Message(
+                "MFA_ENCRYPTION_KEY must be 32 bytes as 64 hex characters".to_string(),
+            ));
+        }
+
         cfg = cfg
             .set_override("database_url", database_url)?
             .set_override("redis_url", redis_url)?
-            .set_override("jwt_secret", jwt_secret)?;
+            .set_override("jwt_secret", jwt_secret)?
+            .set_override("mfa_encryption_key", mfa_encryption_key)?;
+
+        let mut config: Config = cfg.build()?.try_deserialize()?;
+
+        config.jwt_previous_secret = env::var("JWT_PREVIOUS_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty());
+
+        // Providers are a JSON array, which flat environment variables can't express
+        if let Ok(providers) = env::var("OIDC_PROVIDERS") {
+            config.oidc_providers = serde_json::from_str(&providers).map_err(|e| {
+                config::ConfigError::Message(format!("Invalid OIDC_PROVIDERS: {}", e))
+            })?;
+        }
+
+        if let Ok(clients) = env::var("OAUTH_CLIENTS") {
+            config.oauth_clients = serde_json::from_str(&clients).map_err(|e| {
+                config::ConfigError::Message(format!("Invalid OAUTH_CLIENTS: {}", e))
+            })?;
+        }
+
+        // A comma-separated list, which the config crate doesn't split
+        if let Ok(proxies) = env::var("AUTH_TRUSTED_PROXIES") {
+            config.trusted_proxies = proxies
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(|p| {
+                    p.parse::<IpNet>()
+                        .or_else(|_| p.parse::<IpAddr>().map(IpNet::from))
+                        .map_err(|_| {
+                            config::ConfigError::Message(format!(
+                                "Invalid AUTH_TRUSTED_PROXIES entry: {}",
+                                p
+                            ))
+                        })
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        // The standard OpenTelemetry variable, shared with the other services
+        if let Ok(endpoint) = env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            if !endpoint.is_empty() {
+                config.otel_exporter_endpoint = Some(endpoint);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Secrets a token may be signed with, the current one first.
+    pub fn jwt_secrets(&self) -> Vec<&str> {
+        std::iter::once(self.jwt_secret.as_str())
+            .chain(self.jwt_previous_secret.as_deref())
+            .collect()
+    }
 
-        cfg.build()?.try_deserialize()
+    pub fn oidc_provider(&self, name: &str) -> Option<&OidcProviderConfig> {
+        self.oidc_providers.iter().find(|p| p.name == name)
     }
 }
@@ -1,6 +1,35 @@
-use sqlx::{PgPool, postgres::PgPoolOptions};
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    postgres::PgPoolOptions,
+    PgConnection, PgPool,
+};
+use std::collections::HashMap;
 use std::time::Duration;
 
+/// The auth schema's migrations, embedded at build time.
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Why the database can't be served from.
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error("Schema is behind; pending migrations: {pending:?}")]
+    Behind { pending: Vec<i64> },
+    #[error("Migration {0} was changed after it was applied")]
+    Modified(i64),
+    #[error("Migration {0} failed part-way and needs fixing by hand")]
+    Dirty(i64),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Where each known migration stands in a database.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
 pub async fn connect(database_url: &str) -> Result<PgPool, sqlx::Error> {
     PgPoolOptions::new()
         .max_connections(20)
@@ -11,3 +40,115 @@ pub async fn connect(database_url: &str) -> Result<PgPool, sqlx::Error> {
         .connect(database_url)
         .await
 }
+
+/// A connection that keeps its migration history in `auth._sqlx_migrations`,
+/// apart from the other services sharing the database. It is detached so the
+/// changed search path never reaches the pool.
+async fn migration_connection(pool: &PgPool) -> Result<PgConnection, sqlx::Error> {
+    let mut conn = pool.acquire().await?.detach();
+
+    sqlx::query("CREATE SCHEMA IF NOT EXISTS auth")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("SET search_path TO auth, public")
+        .execute(&mut conn)
+        .await?;
+
+    Ok(conn)
+}
+
+/// Applies every pending migration.
+pub async fn migrate(pool: &PgPool) -> Result<(), MigrateError> {
+    let mut conn = migration_connection(pool).await?;
+    MIGRATOR.run(&mut conn).await
+}
+
+/// Reverts the most recently applied migration, returning its version, or
+/// `None` when nothing has been applied.
+pub async fn revert_latest(pool: &PgPool) -> Result<Option<i64>, MigrateError> {
+    let applied = applied_migrations(pool).await?;
+    let Some(&latest) = applied.keys().max() else {
+        return Ok(None);
+    };
+    let target = applied
+        .keys()
+        .copied()
+        .filter(|v| *v < latest)
+        .max()
+        .unwrap_or(0);
+
+    let mut conn = migration_connection(pool).await?;
+    MIGRATOR.undo(&mut conn, target).await?;
+
+    Ok(Some(latest))
+}
+
+/// Applied versions with their checksum and whether they completed. Empty
+/// before the first migration has run.
+async fn applied_migrations(pool: &PgPool) -> Result<HashMap<i64, (Vec<u8>, bool)>, sqlx::Error> {
+    let exists: bool =
+        sqlx::query_scalar("SELECT to_regclass('auth._sqlx_migrations') IS NOT NULL")
+            .fetch_one(pool)
+            .await?;
+    if !exists {
+        return Ok(HashMap::new());
+    }
+
+    let rows: Vec<(i64, Vec<u8>, bool)> =
+        sqlx::query_as("SELECT version, checksum, success FROM auth._sqlx_migrations")
+            .fetch_all(pool)
+            .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|(version, checksum, success)| (version, (checksum, success)))
+        .collect())
+}
+
+/// Every migration this build knows about, oldest first.
+pub async fn status(pool: &PgPool) -> Result<Vec<MigrationStatus>, sqlx::Error> {
+    let applied = applied_migrations(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.get(&m.version).is_some_and(|(_, success)| *success),
+        })
+        .collect())
+}
+
+/// Refuses a database whose schema doesn't match this build: one with
+/// pending, failed or edited migrations. A schema that is ahead, as during a
+/// rollback of the service, is only warned about.
+pub async fn check_schema(pool: &PgPool) -> Result<(), SchemaError> {
+    let applied = applied_migrations(pool).await?;
+
+    let mut pending = Vec::new();
+    for migration in MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+    {
+        match applied.get(&migration.version) {
+            None => pending.push(migration.version),
+            Some((_, false)) => return Err(SchemaError::Dirty(migration.version)),
+            Some((checksum, true)) if checksum[..] != migration.checksum[..] => {
+                return Err(SchemaError::Modified(migration.version))
+            }
+            Some(_) => {}
+        }
+    }
+    if !pending.is_empty() {
+        return Err(SchemaError::Behind { pending });
+    }
+
+    for version in applied.keys() {
+        if !MIGRATOR.version_exists(*version) {
+            tracing::warn!(version, "Database has a migration this build doesn't know");
+        }
+    }
+
+    Ok(())
+}
@@ -6,6 +6,8 @@ use axum::{
 use serde_json::json;
 use thiserror::Error;
 
+use crate::{models::MfaChallenge, services::password_policy::PasswordFeedback};
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Database error: {0}")]
@@ -23,6 +25,21 @@ pub enum AppError {
     #[error("Access forbidden")]
     Forbidden,
 
+    #[error("Password does not meet the password policy")]
+    WeakPassword(Box<PasswordFeedback>),
+
+    #[error("Password reset required")]
+    PasswordResetRequired,
+
+    #[error("Not allowed while impersonating")]
+    ImpersonationForbidden,
+
+    #[error("Recent authentication required")]
+    ReauthenticationRequired,
+
+    #[error("Authenticator code required")]
+    MfaRequired(Box<MfaChallenge>),
+
     #[error("Resource not found")]
     NotFound,
 
@@ -47,6 +64,12 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let details = match &self {
+            AppError::WeakPassword(feedback) => serde_json::to_value(feedback).ok(),
+            AppError::MfaRequired(challenge) => serde_json::to_value(challenge).ok(),
+            _ => None,
+        };
+
         let (status, error_message, error_code) = match self {
             AppError::Database(ref e) => {
                 tracing::error!("Database error: {:?}", e);
@@ -77,6 +100,31 @@ impl IntoResponse for AppError {
                 "Access forbidden".to_string(),
                 "FORBIDDEN",
             ),
+            AppError::WeakPassword(_) => (
+                StatusCode::BAD_REQUEST,
+                "Password does not meet the password policy".to_string(),
+                "WEAK_PASSWORD",
+            ),
+            AppError::PasswordResetRequired => (
+                StatusCode::FORBIDDEN,
+                "A password reset is required before signing in".to_string(),
+                "PASSWORD_RESET_REQUIRED",
+            ),
+            AppError::ImpersonationForbidden => (
+                StatusCode::FORBIDDEN,
+                "This action is not available while impersonating a user".to_string(),
+                "IMPERSONATION_FORBIDDEN",
+            ),
+            AppError::ReauthenticationRequired => (
+                StatusCode::UNAUTHORIZED,
+                "Confirm your identity again to continue".to_string(),
+                "REAUTHENTICATION_REQUIRED",
+            ),
+            AppError::MfaRequired(_) => (
+                StatusCode::UNAUTHORIZED,
+                "Enter the code from your authenticator app to finish signing in".to_string(),
+                "MFA_REQUIRED",
+            ),
             AppError::NotFound => (
                 StatusCode::NOT_FOUND,
                 "Resource not found".to_string(),
@@ -117,13 +165,17 @@ impl IntoResponse for AppError {
             }
         };
 
-        let body = Json(json!({
+        let mut body = json!({
             "error": {
                 "code": error_code,
                 "message": error_message,
                 "timestamp": chrono::Utc::now().to_rfc3339()
             }
-        }));
+        });
+        if let Some(details) = details {
+            body["error"]["details"] = details;
+        }
+        let body = Json(body);
 
         (status, body).into_response()
     }
@@ -1,145 +1,424 @@
 use axum::{
+    body::Body,
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
 };
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
+    auth::create_impersonation_token,
     errors::AppError,
-    models::{User, UserProfile, UserRole, UserStatus},
+    middleware::{caller_id, forbid_impersonation, require_admin},
+    models::{
+        AuditEventType, AuditOutcome, Claims, ImpersonationResponse, UserProfile, UserRole,
+        UserStatus,
+    },
+    openapi::ErrorResponse,
+    repositories::NewSession,
+    services::{
+        accounts,
+        audit::{ClientContext, NewAuditEvent},
+        csv_export,
+        user_search::{self, UserCursor, UserSearch},
+    },
     AppState,
 };
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct ListUsersQuery {
-    page: Option<u32>,
+    cursor: Option<String>,
     limit: Option<u32>,
     role: Option<UserRole>,
     status: Option<UserStatus>,
+    email_verified: Option<bool>,
+    phone_verified: Option<bool>,
+    created_from: Option<DateTime<Utc>>,
+    created_to: Option<DateTime<Utc>>,
+    last_login_from: Option<DateTime<Utc>>,
+    last_login_to: Option<DateTime<Utc>>,
     search: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+impl ListUsersQuery {
+    fn filter(&self) -> UserSearch {
+        UserSearch {
+            role: self.role.clone(),
+            status: self.status.clone(),
+            email_verified: self.email_verified,
+            phone_verified: self.phone_verified,
+            created_from: self.created_from,
+            created_to: self.created_to,
+            last_login_from: self.last_login_from,
+            last_login_to: self.last_login_to,
+            search: self.search.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct PaginatedUsers {
     users: Vec<UserProfile>,
-    total: i64,
-    page: u32,
     limit: u32,
-    total_pages: u32,
+    next_cursor: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/admin/users",
+    tag = "admin",
+    params(ListUsersQuery),
+    responses(
+        (status = 200, description = "Page of matching users", body = PaginatedUsers),
+        (status = 400, description = "Invalid cursor", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+    )
+)]
 pub async fn list_users(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Query(params): Query<ListUsersQuery>,
 ) -> Result<Json<PaginatedUsers>, AppError> {
-    let page = params.page.unwrap_or(1);
-    let limit = params.limit.unwrap_or(20).min(100); // Max 100 per page
-    let offset = (page - 1) * limit;
-
-    let mut query = "SELECT id, email, password_hash, first_name, last_name, phone, role, status, email_verified, phone_verified, last_login, created_at, updated_at FROM auth.users".to_string();
-    let mut conditions = Vec::new();
-    let mut bind_params: Vec<Box<dyn sqlx::Encode<'_, sqlx::Postgres> + Send + Sync>> = Vec::new();
-    let mut param_count = 0;
-
-    // Add filters
-    if let Some(role) = &params.role {
-        param_count += 1;
-        conditions.push(format!("role = ${}", param_count));
-        bind_params.push(Box::new(role.clone()));
-    }
+    require_admin(&claims)?;
 
-    if let Some(status) = &params.status {
-        param_count += 1;
-        conditions.push(format!("status = ${}", param_count));
-        bind_params.push(Box::new(status.clone()));
-    }
+    let limit = params.limit.unwrap_or(20).clamp(1, 100); // Max 100 per page
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(UserCursor::decode)
+        .transpose()?;
 
-    if let Some(search) = &params.search {
-        param_count += 1;
-        conditions.push(format!("(email ILIKE ${} OR first_name ILIKE ${} OR last_name ILIKE ${})", param_count, param_count, param_count));
-        let search_pattern = format!("%{}%", search);
-        bind_params.push(Box::new(search_pattern));
-    }
+    // Fetch one extra row to learn whether another page exists
+    let mut users =
+        user_search::search_users(&state.db, &params.filter(), cursor, limit as i64 + 1).await?;
 
-    if !conditions.is_empty() {
-        query.push_str(&format!(" WHERE {}", conditions.join(" AND ")));
-    }
+    let next_cursor = if users.len() > limit as usize {
+        users.truncate(limit as usize);
+        users.last().map(|u| UserCursor::after(u).encode())
+    } else {
+        None
+    };
 
-    // Get total count
-    let count_query = query.replace("SELECT id, email, password_hash, first_name, last_name, phone, role, status, email_verified, phone_verified, last_login, created_at, updated_at", "SELECT COUNT(*)");
-    let total: i64 = sqlx::query_scalar(&count_query)
-        .fetch_one(&state.db)
-        .await?;
+    Ok(Json(PaginatedUsers {
+        users: users.into_iter().map(UserProfile::from).collect(),
+        limit,
+        next_cursor,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/users/export",
+    tag = "admin",
+    params(ListUsersQuery),
+    responses(
+        (status = 200, description = "CSV download", content_type = "text/csv", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+    )
+)]
+pub async fn export_users(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<ListUsersQuery>,
+) -> Result<Response, AppError> {
+    require_admin(&claims)?;
 
-    // Add pagination
-    query.push_str(&format!(" ORDER BY created_at DESC LIMIT {} OFFSET {}", limit, offset));
+    let header_row = csv_row(&[
+        "id",
+        "email",
+        "first_name",
+        "last_name",
+        "phone",
+        "role",
+        "status",
+        "email_verified",
+        "phone_verified",
+        "last_login",
+        "created_at",
+    ])?;
 
-    let users = sqlx::query_as!(
-        User,
-        &query,
-        // Note: In a real implementation, you'd need to properly bind the parameters
-        // This is simplified for demonstration
+    let rows = user_search::stream_users(state.db.clone(), params.filter()).map(|user| {
+        let user = user?;
+        csv_row(&[
+            user.id.to_string(),
+            csv_export::cell(&user.email).into_owned(),
+            csv_export::cell(&user.first_name.unwrap_or_default()).into_owned(),
+            csv_export::cell(&user.last_name.unwrap_or_default()).into_owned(),
+            csv_export::cell(&user.phone.unwrap_or_default()).into_owned(),
+            enum_label(&user.role),
+            enum_label(&user.status),
+            user.email_verified.to_string(),
+            user.phone_verified.to_string(),
+            user.last_login.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            user.created_at.to_rfc3339(),
+        ])
+    });
+
+    let body = Body::from_stream(stream::once(async move { Ok(header_row) }).chain(rows));
+    let filename = format!(
+        "attachment; filename=\"users-{}.csv\"",
+        Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, filename),
+        ],
+        body,
     )
-    .fetch_all(&state.db)
-    .await?;
+        .into_response())
+}
 
-    let user_profiles: Vec<UserProfile> = users.into_iter().map(|u| u.into()).collect();
-    let total_pages = ((total as f64) / (limit as f64)).ceil() as u32;
+fn csv_row<T: AsRef<[u8]>>(fields: &[T]) -> Result<Vec<u8>, AppError> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    writer
+        .write_record(fields)
+        .map_err(|e| AppError::InternalServerError(format!("CSV export failed: {}", e)))?;
 
-    Ok(Json(PaginatedUsers {
-        users: user_profiles,
-        total,
-        page,
-        limit,
-        total_pages,
-    }))
+    writer
+        .into_inner()
+        .map_err(|e| AppError::InternalServerError(format!("CSV export failed: {}", e)))
+}
+
+fn enum_label<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(|s| s.to_lowercase()))
+        .unwrap_or_default()
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/suspend",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 204, description = "User suspended and signed out"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
 pub async fn suspend_user(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
     Path(user_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
+    require_admin(&claims)?;
+
     let id = Uuid::parse_str(&user_id)
         .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
 
-    let result = sqlx::query!(
-        "UPDATE auth.users SET status = 'suspended', updated_at = CURRENT_TIMESTAMP WHERE id = $1",
-        id
-    )
-    .execute(&state.db)
-    .await?;
-
-    if result.rows_affected() == 0 {
-        return Err(AppError::NotFound);
-    }
+    // Signs the user out everywhere
+    let revoked = accounts::suspend(&state.db, state.sessions.as_ref(), id).await?;
 
-    // Invalidate all sessions for this user
-    sqlx::query!("DELETE FROM auth.sessions WHERE user_id = $1", id)
-        .execute(&state.db)
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::UserSuspended, AuditOutcome::Success)
+                .actor(caller_id(&claims)?)
+                .target(id)
+                .details(serde_json::json!({ "sessions_revoked": revoked })),
+        )
         .await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/activate",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 204, description = "User activated"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
 pub async fn activate_user(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
     Path(user_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
+    require_admin(&claims)?;
+
     let id = Uuid::parse_str(&user_id)
         .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
 
-    let result = sqlx::query!(
-        "UPDATE auth.users SET status = 'active', updated_at = CURRENT_TIMESTAMP WHERE id = $1",
-        id
+    accounts::activate(&state.db, id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::UserActivated, AuditOutcome::Success)
+                .actor(caller_id(&claims)?)
+                .target(id),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ChangeRoleRequest {
+    role: UserRole,
+}
+
+/// Changes a user's role. Their sessions end, since every token issued for
+/// them names the old role.
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/role",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    request_body = ChangeRoleRequest,
+    responses(
+        (status = 204, description = "Role changed, or already the requested one"),
+        (status = 400, description = "Caller tried to change their own role", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
     )
-    .execute(&state.db)
-    .await?;
+)]
+pub async fn change_role(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+    Json(payload): Json<ChangeRoleRequest>,
+) -> Result<StatusCode, AppError> {
+    require_admin(&claims)?;
+    forbid_impersonation(&claims)?;
+
+    let id = Uuid::parse_str(&user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let admin_id = caller_id(&claims)?;
+    // Stops the last admin demoting themselves out of the admin API
+    if id == admin_id {
+        return Err(AppError::BadRequest(
+            "Admins cannot change their own role".to_string(),
+        ));
+    }
 
-    if result.rows_affected() == 0 {
-        return Err(AppError::NotFound);
+    let current = state.users.change_role(id, payload.role.clone()).await?;
+    if current == payload.role {
+        return Ok(StatusCode::NO_CONTENT);
     }
 
+    let revoked = state.sessions.delete_for_user(id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::UserRoleChanged, AuditOutcome::Success)
+                .actor(admin_id)
+                .target(id)
+                .details(serde_json::json!({
+                    "from": current.as_str(),
+                    "to": payload.role.as_str(),
+                    "sessions_revoked": revoked,
+                })),
+        )
+        .await?;
+
     Ok(StatusCode::NO_CONTENT)
 }
+
+#[utoipa::path(
+    post,
+    path = "/admin/users/{id}/impersonate",
+    tag = "admin",
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "Short-lived token acting as the user", body = ImpersonationResponse),
+        (status = 400, description = "Target is an admin or not active", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
+pub async fn impersonate_user(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> Result<Json<ImpersonationResponse>, AppError> {
+    require_admin(&claims)?;
+    // No chained impersonation
+    forbid_impersonation(&claims)?;
+
+    let admin = state.users.get_by_id(&claims.sub).await?;
+    let target = state
+        .users
+        .get_by_id(&user_id)
+        .await
+        .map_err(|_| AppError::NotFound)?;
+
+    if target.id == admin.id || target.role == UserRole::Admin {
+        return Err(AppError::BadRequest(
+            "Admins cannot be impersonated".to_string(),
+        ));
+    }
+    if target.status != UserStatus::Active {
+        return Err(AppError::BadRequest(
+            "Only active users can be impersonated".to_string(),
+        ));
+    }
+
+    let client = ClientContext::from_headers(&headers);
+    let session = state
+        .sessions
+        .create(NewSession::impersonation(
+            target.id,
+            admin.id,
+            &client,
+            &state.config,
+        ))
+        .await?;
+
+    let access_token = create_impersonation_token(
+        &target,
+        &session,
+        &admin,
+        state.config.impersonation_ttl,
+        &state.config.jwt_secret,
+    )?;
+
+    state
+        .audit
+        .record(
+            &client,
+            NewAuditEvent::new(AuditEventType::ImpersonationStarted, AuditOutcome::Success)
+                .actor(admin.id)
+                .target(target.id)
+                .details(serde_json::json!({
+                    "session_id": session.id,
+                    "expires_at": session.expires_at,
+                })),
+        )
+        .await?;
+
+    Ok(Json(ImpersonationResponse {
+        access_token,
+        expires_in: state.config.impersonation_ttl,
+        impersonator_id: admin.id,
+        user: target.into(),
+    }))
+}
@@ -1,7 +1,8 @@
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Json,
+    Extension,
 };
 use uuid::Uuid;
 use validator::Validate;
@@ -9,25 +10,34 @@ use validator::Validate;
 use crate::{
     auth::{generate_api_key, hash_api_key},
     errors::AppError,
-    models::{ApiKey, ApiKeyInfo, CreateApiKeyRequest, CreateApiKeyResponse},
+    middleware::{caller_id, forbid_impersonation, require_recent_auth},
+    models::{
+        ApiKeyInfo, AuditEventType, AuditOutcome, Claims, CreateApiKeyRequest, CreateApiKeyResponse,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        webhooks::{self, WebhookEvent},
+    },
     AppState,
 };
 
+#[utoipa::path(
+    get,
+    path = "/api-keys",
+    tag = "api-keys",
+    responses(
+        (status = 200, description = "The caller's keys", body = Vec<ApiKeyInfo>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
 pub async fn list_keys(
     State(state): State<AppState>,
-    Path(user_id): Path<String>,
+    Extension(claims): Extension<Claims>,
 ) -> Result<Json<Vec<ApiKeyInfo>>, AppError> {
-    let id = Uuid::parse_str(&user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-
-    let keys = sqlx::query_as!(
-        ApiKey,
-        "SELECT id, user_id, key_hash, name, scopes, last_used, expires_at, created_at 
-         FROM auth.api_keys WHERE user_id = $1 ORDER BY created_at DESC",
-        id
-    )
-    .fetch_all(&state.db)
-    .await?;
+    let id = caller_id(&claims)?;
+
+    let keys = state.api_keys.list_for_user(id).await?;
 
     let key_infos: Vec<ApiKeyInfo> = keys
         .into_iter()
@@ -44,36 +54,72 @@ pub async fn list_keys(
     Ok(Json(key_infos))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api-keys",
+    tag = "api-keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 200, description = "Key created; the secret is only shown here", body = CreateApiKeyResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token, or re-authentication required", body = ErrorResponse),
+    )
+)]
 pub async fn create_key(
     State(state): State<AppState>,
-    Path(user_id): Path<String>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
     Json(payload): Json<CreateApiKeyRequest>,
 ) -> Result<Json<CreateApiKeyResponse>, AppError> {
+    forbid_impersonation(&claims)?;
+    require_recent_auth(&claims, state.config.reauthentication_max_age)?;
     payload.validate()?;
 
-    let id = Uuid::parse_str(&user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    let id = caller_id(&claims)?;
 
     // Generate API key
     let api_key = generate_api_key();
-    let key_hash = hash_api_key(&api_key)?;
-
-    // Insert into database
-    let key_record = sqlx::query_as!(
-        ApiKey,
-        r#"
-        INSERT INTO auth.api_keys (user_id, key_hash, name, scopes, expires_at)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
-        "#,
+    let key_hash = hash_api_key(&api_key);
+
+    let key_record = state
+        .api_keys
+        .create(
+            id,
+            &key_hash,
+            &payload.name,
+            &payload.scopes,
+            payload.expires_at,
+        )
+        .await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::ApiKeyCreated, AuditOutcome::Success)
+                .actor(id)
+                .target(id)
+                .details(serde_json::json!({
+                    "key_id": key_record.id,
+                    "name": key_record.name,
+                    "scopes": key_record.scopes,
+                })),
+        )
+        .await?;
+
+    webhooks::notify_user(
+        &state.db,
         id,
-        key_hash,
-        payload.name,
-        &payload.scopes,
-        payload.expires_at
+        WebhookEvent::ApiKeyCreated,
+        serde_json::json!({
+            "user_id": id,
+            "key_id": key_record.id,
+            "name": key_record.name,
+            "scopes": key_record.scopes,
+            "expires_at": key_record.expires_at,
+        }),
     )
-    .fetch_one(&state.db)
-    .await?;
+    .await;
 
     Ok(Json(CreateApiKeyResponse {
         id: key_record.id,
@@ -85,24 +131,27 @@ pub async fn create_key(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api-keys/{id}",
+    tag = "api-keys",
+    params(("id" = String, Path, description = "API key ID")),
+    responses(
+        (status = 200, description = "Key", body = ApiKeyInfo),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No such key", body = ErrorResponse),
+    )
+)]
 pub async fn get_key(
     State(state): State<AppState>,
-    Path((user_id, key_id)): Path<(String, String)>,
+    Extension(claims): Extension<Claims>,
+    Path(key_id): Path<String>,
 ) -> Result<Json<ApiKeyInfo>, AppError> {
-    let uid = Uuid::parse_str(&user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-    let kid = Uuid::parse_str(&key_id)
-        .map_err(|_| AppError::BadRequest("Invalid key ID".to_string()))?;
-
-    let key = sqlx::query_as!(
-        ApiKey,
-        "SELECT id, user_id, key_hash, name, scopes, last_used, expires_at, created_at 
-         FROM auth.api_keys WHERE id = $1 AND user_id = $2",
-        kid,
-        uid
-    )
-    .fetch_one(&state.db)
-    .await?;
+    let uid = caller_id(&claims)?;
+    let kid =
+        Uuid::parse_str(&key_id).map_err(|_| AppError::BadRequest("Invalid key ID".to_string()))?;
+
+    let key = state.api_keys.get(kid, uid).await?;
 
     Ok(Json(ApiKeyInfo {
         id: key.id,
@@ -114,34 +163,54 @@ pub async fn get_key(
     }))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api-keys/{id}",
+    tag = "api-keys",
+    request_body = CreateApiKeyRequest,
+    params(("id" = String, Path, description = "API key ID")),
+    responses(
+        (status = 200, description = "Updated key", body = ApiKeyInfo),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token, or re-authentication required", body = ErrorResponse),
+        (status = 404, description = "No such key", body = ErrorResponse),
+    )
+)]
 pub async fn update_key(
     State(state): State<AppState>,
-    Path((user_id, key_id)): Path<(String, String)>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(key_id): Path<String>,
     Json(payload): Json<CreateApiKeyRequest>,
 ) -> Result<Json<ApiKeyInfo>, AppError> {
+    forbid_impersonation(&claims)?;
+    // Widening a key's scopes is as sensitive as creating one
+    require_recent_auth(&claims, state.config.reauthentication_max_age)?;
     payload.validate()?;
 
-    let uid = Uuid::parse_str(&user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-    let kid = Uuid::parse_str(&key_id)
-        .map_err(|_| AppError::BadRequest("Invalid key ID".to_string()))?;
-
-    let key = sqlx::query_as!(
-        ApiKey,
-        r#"
-        UPDATE auth.api_keys 
-        SET name = $3, scopes = $4, expires_at = $5
-        WHERE id = $1 AND user_id = $2
-        RETURNING id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
-        "#,
-        kid,
-        uid,
-        payload.name,
-        &payload.scopes,
-        payload.expires_at
-    )
-    .fetch_one(&state.db)
-    .await?;
+    let uid = caller_id(&claims)?;
+    let kid =
+        Uuid::parse_str(&key_id).map_err(|_| AppError::BadRequest("Invalid key ID".to_string()))?;
+
+    let key = state
+        .api_keys
+        .update(kid, uid, &payload.name, &payload.scopes, payload.expires_at)
+        .await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::ApiKeyUpdated, AuditOutcome::Success)
+                .actor(uid)
+                .target(uid)
+                .details(serde_json::json!({
+                    "key_id": key.id,
+                    "name": key.name,
+                    "scopes": key.scopes,
+                })),
+        )
+        .await?;
 
     Ok(Json(ApiKeyInfo {
         id: key.id,
@@ -153,26 +222,51 @@ pub async fn update_key(
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api-keys/{id}/revoke",
+    tag = "api-keys",
+    params(("id" = String, Path, description = "API key ID")),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No such key", body = ErrorResponse),
+    )
+)]
 pub async fn revoke_key(
     State(state): State<AppState>,
-    Path((user_id, key_id)): Path<(String, String)>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(key_id): Path<String>,
 ) -> Result<StatusCode, AppError> {
-    let uid = Uuid::parse_str(&user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-    let kid = Uuid::parse_str(&key_id)
-        .map_err(|_| AppError::BadRequest("Invalid key ID".to_string()))?;
-
-    let result = sqlx::query!(
-        "DELETE FROM auth.api_keys WHERE id = $1 AND user_id = $2",
-        kid,
-        uid
-    )
-    .execute(&state.db)
-    .await?;
+    forbid_impersonation(&claims)?;
 
-    if result.rows_affected() == 0 {
+    let uid = caller_id(&claims)?;
+    let kid =
+        Uuid::parse_str(&key_id).map_err(|_| AppError::BadRequest("Invalid key ID".to_string()))?;
+
+    if !state.api_keys.delete(kid, uid).await? {
         return Err(AppError::NotFound);
     }
 
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::ApiKeyRevoked, AuditOutcome::Success)
+                .actor(uid)
+                .target(uid)
+                .details(serde_json::json!({ "key_id": kid })),
+        )
+        .await?;
+
+    webhooks::notify_user(
+        &state.db,
+        uid,
+        WebhookEvent::ApiKeyRevoked,
+        serde_json::json!({ "user_id": uid, "key_id": kid }),
+    )
+    .await;
+
     Ok(StatusCode::NO_CONTENT)
 }
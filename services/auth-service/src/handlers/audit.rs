@@ -0,0 +1,180 @@
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    middleware::require_admin,
+    models::{AuditEvent, AuditEventType, AuditOutcome, Claims},
+    openapi::ErrorResponse,
+    services::{
+        audit::{self, AuditFilter},
+        csv_export,
+    },
+    AppState,
+};
+
+const EXPORT_BATCH_SIZE: i64 = 1000;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct AuditEventsQuery {
+    page: Option<u32>,
+    limit: Option<u32>,
+    event_type: Option<AuditEventType>,
+    outcome: Option<AuditOutcome>,
+    actor_id: Option<Uuid>,
+    target_id: Option<Uuid>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+impl AuditEventsQuery {
+    fn filter(&self) -> AuditFilter {
+        AuditFilter {
+            event_type: self.event_type,
+            outcome: self.outcome,
+            actor_id: self.actor_id,
+            target_id: self.target_id,
+            from: self.from,
+            to: self.to,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PaginatedAuditEvents {
+    events: Vec<AuditEvent>,
+    total: i64,
+    page: u32,
+    limit: u32,
+    total_pages: u32,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit-events",
+    tag = "admin",
+    params(AuditEventsQuery),
+    responses(
+        (status = 200, description = "Page of matching events", body = PaginatedAuditEvents),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+    )
+)]
+pub async fn list_events(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<AuditEventsQuery>,
+) -> Result<Json<PaginatedAuditEvents>, AppError> {
+    require_admin(&claims)?;
+
+    let page = params.page.unwrap_or(1).max(1);
+    let limit = params.limit.unwrap_or(50).min(500); // Max 500 per page
+    let offset = (page as i64 - 1) * limit as i64; // u32 overflows for large pages
+    let filter = params.filter();
+
+    let total = audit::count_events(&state.db, &filter).await?;
+    let events = audit::list_events(&state.db, &filter, limit as i64, offset).await?;
+    let total_pages = ((total as f64) / (limit.max(1) as f64)).ceil() as u32;
+
+    Ok(Json(PaginatedAuditEvents {
+        events,
+        total,
+        page,
+        limit,
+        total_pages,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit-events/export",
+    tag = "admin",
+    params(AuditEventsQuery),
+    responses(
+        (status = 200, description = "CSV download", content_type = "text/csv", body = String),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an admin", body = ErrorResponse),
+    )
+)]
+pub async fn export_events(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<AuditEventsQuery>,
+) -> Result<Response, AppError> {
+    require_admin(&claims)?;
+
+    let filter = params.filter();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "id",
+            "created_at",
+            "event_type",
+            "outcome",
+            "actor_id",
+            "target_id",
+            "ip_address",
+            "user_agent",
+            "details",
+        ])
+        .map_err(csv_error)?;
+
+    let mut before = None;
+    loop {
+        let batch =
+            audit::list_events_before(&state.db, &filter, before, EXPORT_BATCH_SIZE).await?;
+        let fetched = batch.len() as i64;
+        before = batch.last().map(|event| (event.created_at, event.id));
+
+        for event in batch {
+            let ip_address = event.ip_address.unwrap_or_default();
+            let user_agent = event.user_agent.unwrap_or_default();
+            writer
+                .write_record([
+                    event.id.to_string(),
+                    event.created_at.to_rfc3339(),
+                    event.event_type.as_str().to_string(),
+                    event.outcome.as_str().to_string(),
+                    event.actor_id.map(|id| id.to_string()).unwrap_or_default(),
+                    event.target_id.map(|id| id.to_string()).unwrap_or_default(),
+                    csv_export::cell(&ip_address).into_owned(),
+                    csv_export::cell(&user_agent).into_owned(),
+                    event.details.to_string(),
+                ])
+                .map_err(csv_error)?;
+        }
+
+        if fetched < EXPORT_BATCH_SIZE {
+            break;
+        }
+    }
+
+    let body = writer
+        .into_inner()
+        .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+    let filename = format!(
+        "attachment; filename=\"audit-events-{}.csv\"",
+        Utc::now().format("%Y%m%dT%H%M%SZ")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, filename),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn csv_error(e: csv::Error) -> AppError {
+    AppError::InternalServerError(format!("CSV export failed: {}", e))
+}
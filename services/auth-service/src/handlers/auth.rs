@@ -1,27 +1,66 @@
 use axum::{
     extract::{Query, State},
-    http::{HeaderMap, StatusCode},
-    response::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Json, Response},
+    Extension, Form,
 };
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
 use validator::Validate;
 
+use uuid::Uuid;
+
 use crate::{
-    auth::{hash_password, verify_password, create_jwt_token, decode_jwt_token},
+    auth::{
+        create_jwt_token, create_mfa_challenge_token, create_refresh_token, decode_jwt_token,
+        generate_token, refresh_generation_hash,
+    },
     errors::AppError,
+    handlers::metrics,
+    middleware::{caller_id, forbid_impersonation},
     models::{
-        LoginRequest, LoginResponse, RegisterRequest, RefreshTokenRequest, 
-        User, UserRole, UserStatus, UserProfile, Claims
+        AuditEventType, AuditOutcome, AuthMethod, Authentication, Claims, EmailChangeTokenRequest,
+        ForgotPasswordRequest, LoginRequest, LoginResponse, MagicLinkRedeemRequest,
+        MagicLinkRequest, MfaChallenge, PasswordCheckRequest, ReauthenticateRequest,
+        RefreshTokenRequest, RegisterRequest, ResetPasswordRequest, TokenUse, User, UserProfile,
+        UserRole, UserStatus, VerifyEmailRequest,
+    },
+    openapi::ErrorResponse,
+    repositories::NewSession,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        email_change, email_verification, login_alerts, magic_link, mailer,
+        password_policy::{PasswordContext, PasswordFeedback},
+        password_reset, phone, totp,
     },
-    services::user_service,
     AppState,
 };
 
-#[derive(Debug, Deserialize)]
+/// Cookie binding a magic sign-in link to the browser that asked for it.
+const MAGIC_LINK_COOKIE: &str = "bm_magic_link_nonce";
+
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct VerifyTokenQuery {
     token: String,
 }
 
+#[derive(Debug, Deserialize, IntoParams, ToSchema)]
+pub struct LinkTokenQuery {
+    token: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    security(()),
+    responses(
+        (status = 200, description = "Account created", body = UserProfile),
+        (status = 400, description = "Invalid input, weak password or a role other than customer", body = ErrorResponse),
+        (status = 409, description = "Email already registered", body = ErrorResponse),
+    )
+)]
 pub async fn register(
     State(state): State<AppState>,
     Json(payload): Json<RegisterRequest>,
@@ -29,30 +68,74 @@ pub async fn register(
     // Validate input
     payload.validate()?;
 
+    // Vendor and admin accounts are granted by an admin, never claimed
+    let role = payload.role.unwrap_or(UserRole::Customer);
+    if role != UserRole::Customer {
+        return Err(AppError::BadRequest(
+            "Only customer accounts can be registered".to_string(),
+        ));
+    }
+
     // Check if user already exists
-    if user_service::get_user_by_email(&state.db, &payload.email).await.is_ok() {
+    if state.users.get_by_email(&payload.email).await.is_ok() {
         return Err(AppError::Conflict("User already exists".to_string()));
     }
 
+    let phone = payload.phone.as_deref().map(phone::normalize).transpose()?;
+
+    state
+        .password_policy
+        .enforce(
+            &payload.password,
+            PasswordContext {
+                email: Some(&payload.email),
+                first_name: Some(&payload.first_name),
+                last_name: Some(&payload.last_name),
+            },
+        )
+        .await?;
+
     // Hash password
-    let password_hash = hash_password(&payload.password, state.config.bcrypt_cost)?;
+    let password_hash = state.password_hasher.hash(&payload.password).await?;
 
     // Create user
-    let user = user_service::create_user(
-        &state.db,
-        &payload.email,
-        &password_hash,
-        &payload.first_name,
-        &payload.last_name,
-        payload.phone.as_deref(),
-        payload.role.unwrap_or(UserRole::Customer),
-    ).await?;
+    let user = state
+        .users
+        .create(
+            &payload.email,
+            &password_hash,
+            &payload.first_name,
+            &payload.last_name,
+            phone.as_deref(),
+            role,
+        )
+        .await?;
 
-    // TODO: Send verification email
+    metrics::record_registration(user.role.as_str());
+
+    let token =
+        email_verification::issue_token(state.users.as_ref(), user.id, &state.config).await?;
+    mailer::send_in_background(
+        state.mailer.clone(),
+        email_verification::verification_email(&user, &token, &state.config),
+    );
 
     Ok(Json(user.into()))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    security(()),
+    responses(
+        (status = 200, description = "Signed in", body = LoginResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Wrong email or password, or `MFA_REQUIRED` with a challenge to answer at /auth/mfa/challenge", body = ErrorResponse),
+        (status = 403, description = "Account not active or password reset required", body = ErrorResponse),
+    )
+)]
 pub async fn login(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -61,82 +144,287 @@ pub async fn login(
     // Validate input
     payload.validate()?;
 
+    let client = ClientContext::from_headers(&headers);
+
     // Get user by email
-    let user = user_service::get_user_by_email(&state.db, &payload.email)
-        .await
-        .map_err(|_| AppError::Unauthorized)?;
+    let user = match state.users.get_by_email(&payload.email).await {
+        Ok(user) => user,
+        Err(_) => {
+            audit_failed_login(
+                &state,
+                &client,
+                None,
+                &payload.email,
+                "password",
+                "unknown_email",
+            )
+            .await?;
+            return Err(AppError::Unauthorized);
+        }
+    };
 
     // Verify password
-    if !verify_password(&payload.password, &user.password_hash)? {
+    if !state
+        .password_hasher
+        .verify(&payload.password, &user.password_hash)
+        .await?
+    {
+        audit_failed_login(
+            &state,
+            &client,
+            Some(user.id),
+            &payload.email,
+            "password",
+            "invalid_password",
+        )
+        .await?;
         return Err(AppError::Unauthorized);
     }
 
+    // Upgrade legacy or weaker hashes while we hold the plaintext
+    if state.password_hasher.needs_rehash(&user.password_hash) {
+        let rehashed = match state.password_hasher.hash(&payload.password).await {
+            Ok(hash) => state.users.update_password_hash(user.id, &hash).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = rehashed {
+            tracing::warn!("Failed to rehash password for user {}: {}", user.id, e);
+        }
+    }
+
     // Check user status
     if user.status != UserStatus::Active {
+        audit_failed_login(
+            &state,
+            &client,
+            Some(user.id),
+            &payload.email,
+            "password",
+            "account_inactive",
+        )
+        .await?;
         return Err(AppError::Forbidden);
     }
 
-    // Extract user agent and IP
-    let user_agent = headers
-        .get("user-agent")
-        .and_then(|h| h.to_str().ok())
-        .map(|s| s.to_string());
-    
-    let ip_address = headers
-        .get("x-forwarded-for")
-        .or_else(|| headers.get("x-real-ip"))
-        .and_then(|h| h.to_str().ok())
-        .map(|s| s.to_string());
+    let response = complete_login(
+        &state,
+        user,
+        &client,
+        payload.remember_me.unwrap_or(false),
+        "password",
+        AuthMethod::Pwd,
+    )
+    .await?;
+
+    Ok(Json(response))
+}
+
+/// Finishes a sign-in once the user's first factor has been checked,
+/// whatever the sign-in method. Every sign-in goes through here, so the
+/// checks below apply to all of them.
+///
+/// Accounts flagged for a password reset, as after a denied sign-in, are
+/// refused so that no sign-in method gets around the reset. Accounts with an
+/// authenticator get an `MFA_REQUIRED` error carrying a challenge instead of
+/// tokens; `/auth/mfa/challenge` finishes the sign-in with a code.
+pub(crate) async fn complete_login(
+    state: &AppState,
+    user: User,
+    client: &ClientContext,
+    remember_me: bool,
+    method: &str,
+    amr: AuthMethod,
+) -> Result<LoginResponse, AppError> {
+    refuse_if_reset_required(state, &user, client, method).await?;
+
+    if totp::is_enabled(state.totp.as_ref(), user.id).await? {
+        let mfa_token = create_mfa_challenge_token(
+            user.id,
+            method,
+            amr,
+            remember_me,
+            state.config.mfa_challenge_ttl,
+            &state.config.jwt_secret,
+        )?;
+        return Err(AppError::MfaRequired(Box::new(MfaChallenge {
+            mfa_token,
+            expires_in: state.config.mfa_challenge_ttl,
+        })));
+    }
+
+    start_session(state, user, client, remember_me, method, vec![amr]).await
+}
+
+pub(crate) async fn refuse_if_reset_required(
+    state: &AppState,
+    user: &User,
+    client: &ClientContext,
+    method: &str,
+) -> Result<(), AppError> {
+    if user.password_reset_required {
+        audit_failed_login(
+            state,
+            client,
+            Some(user.id),
+            &user.email,
+            method,
+            "password_reset_required",
+        )
+        .await?;
+        return Err(AppError::PasswordResetRequired);
+    }
+
+    Ok(())
+}
+
+/// Issues a session and tokens for a user who has given every factor their
+/// account requires. Raises a new-device notice when warranted and records
+/// the successful login.
+pub(crate) async fn start_session(
+    state: &AppState,
+    user: User,
+    client: &ClientContext,
+    remember_me: bool,
+    method: &str,
+    amr: Vec<AuthMethod>,
+) -> Result<LoginResponse, AppError> {
+    // Compare against known devices before this one is added to them
+    let alert_reason =
+        login_alerts::assess(state.sessions.as_ref(), &user, client, &state.config).await?;
 
     // Create session
-    let session = user_service::create_session(
-        &state.db,
-        user.id,
-        user_agent,
-        ip_address,
-        payload.remember_me.unwrap_or(false),
-        &state.config,
-    ).await?;
+    let session = state
+        .sessions
+        .create(NewSession::login(
+            user.id,
+            client,
+            remember_me,
+            &state.config,
+        ))
+        .await?;
+    login_alerts::remember_device(state.sessions.as_ref(), &user, client).await?;
+
+    let org = state.users.default_org(user.id).await?;
+    let authn = Authentication::now(amr);
 
     // Create JWT tokens
     let access_token = create_jwt_token(
         &user,
         &session,
+        org,
+        Some(&authn),
         state.config.jwt_expiration,
         &state.config.jwt_secret,
     )?;
 
-    let refresh_token = create_jwt_token(
+    let generation = state
+        .sessions
+        .rotate_refresh_token(session.id, None)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let refresh_token = create_refresh_token(
         &user,
         &session,
+        &generation,
+        org,
+        Some(&authn),
         state.config.refresh_token_expiration,
         &state.config.jwt_secret,
     )?;
 
     // Update last login
-    user_service::update_last_login(&state.db, user.id).await?;
+    state.users.update_last_login(user.id).await?;
 
-    Ok(Json(LoginResponse {
+    if let Some(reason) = alert_reason {
+        let notice =
+            login_alerts::create_alert(&state.db, &user, &session, reason, &state.config).await?;
+        mailer::send_in_background(state.mailer.clone(), notice);
+    }
+
+    metrics::record_login(method, true);
+
+    state
+        .audit
+        .record(
+            client,
+            NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Success)
+                .actor(user.id)
+                .target(user.id)
+                .details(serde_json::json!({ "session_id": session.id, "method": method })),
+        )
+        .await?;
+
+    Ok(LoginResponse {
         access_token,
         refresh_token,
         expires_in: state.config.jwt_expiration,
         user: user.into(),
-    }))
+    })
 }
 
+pub(crate) async fn audit_failed_login(
+    state: &AppState,
+    client: &ClientContext,
+    user_id: Option<Uuid>,
+    email: &str,
+    method: &str,
+    reason: &str,
+) -> Result<(), AppError> {
+    metrics::record_login(method, false);
+
+    let mut event = NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure)
+        .details(serde_json::json!({ "email": email, "reason": reason, "method": method }));
+    if let Some(user_id) = user_id {
+        event = event.target(user_id);
+    }
+
+    state.audit.record(client, event).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    security(()),
+    responses(
+        (status = 200, description = "New token pair; the old refresh token is spent", body = LoginResponse),
+        (status = 401, description = "Refresh token invalid, expired or already used; reusing one ends the session", body = ErrorResponse),
+    )
+)]
 pub async fn refresh_token(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<RefreshTokenRequest>,
 ) -> Result<Json<LoginResponse>, AppError> {
+    let client = ClientContext::from_headers(&headers);
+    let result = refresh_tokens(&state, &client, &payload).await;
+    metrics::record_token_refresh(result.is_ok());
+
+    result.map(Json)
+}
+
+async fn refresh_tokens(
+    state: &AppState,
+    client: &ClientContext,
+    payload: &RefreshTokenRequest,
+) -> Result<LoginResponse, AppError> {
     // Decode and validate refresh token
-    let claims = decode_jwt_token(&payload.refresh_token, &state.config.jwt_secret)?;
-    
+    let claims = decode_jwt_token(&payload.refresh_token, &state.config.jwt_secrets())?;
+    if claims.token_use == Some(TokenUse::Access) {
+        return Err(AppError::Unauthorized);
+    }
+
     // Get user and session
-    let user = user_service::get_user_by_id(&state.db, &claims.sub.parse().unwrap())
+    let user = state
+        .users
+        .get_by_id(&claims.sub)
         .await
         .map_err(|_| AppError::Unauthorized)?;
 
-    let session = user_service::get_session_by_id(&state.db, &claims.jti.parse().unwrap())
+    let session = state
+        .sessions
+        .get(&claims.jti)
         .await
         .map_err(|_| AppError::Unauthorized)?;
 
@@ -145,29 +433,199 @@ pub async fn refresh_token(
         return Err(AppError::Unauthorized);
     }
 
+    // Impersonation sessions end when their access token expires
+    if session.impersonator_id.is_some() {
+        return Err(AppError::ImpersonationForbidden);
+    }
+
+    // Each refresh token is good for one refresh. Tokens from before rotation
+    // carry no generation and are accepted once, while the session still has
+    // the id it was created with.
+    let generation = match refresh_generation_hash(&claims, &session) {
+        Some(current) => {
+            state
+                .sessions
+                .rotate_refresh_token(session.id, Some(&current))
+                .await?
+        }
+        None => None,
+    };
+
+    // A spent token coming back means it was copied; end the session for
+    // whoever holds it, including the latest token
+    let Some(generation) = generation else {
+        state.sessions.delete(&claims.jti).await?;
+        state
+            .audit
+            .record(
+                client,
+                NewAuditEvent::new(AuditEventType::RefreshTokenReused, AuditOutcome::Failure)
+                    .target(user.id)
+                    .details(serde_json::json!({ "session_id": session.id })),
+            )
+            .await?;
+        return Err(AppError::Unauthorized);
+    };
+
+    // Keep acting for the same organisation, with the current role, while
+    // the user is still a member
+    let org = match claims.org_id {
+        Some(org_id) => state.users.org_membership(org_id, user.id).await?,
+        None => None,
+    };
+
+    // A refresh is not a new authentication, so keep the original time
+    let authn = claims.authentication();
+
     // Create new tokens
     let access_token = create_jwt_token(
         &user,
         &session,
+        org,
+        authn.as_ref(),
         state.config.jwt_expiration,
         &state.config.jwt_secret,
     )?;
 
-    let new_refresh_token = create_jwt_token(
+    let new_refresh_token = create_refresh_token(
         &user,
         &session,
+        &generation,
+        org,
+        authn.as_ref(),
         state.config.refresh_token_expiration,
         &state.config.jwt_secret,
     )?;
 
-    Ok(Json(LoginResponse {
+    Ok(LoginResponse {
         access_token,
         refresh_token: new_refresh_token,
         expires_in: state.config.jwt_expiration,
         user: user.into(),
+    })
+}
+
+/// Proves the caller's identity again, with their password or an
+/// authenticator code, and re-issues their tokens on the same session with a
+/// fresh `auth_time`. Sensitive operations require this when the sign-in is
+/// older than `reauthentication_max_age`.
+#[utoipa::path(
+    post,
+    path = "/auth/reauthenticate",
+    tag = "auth",
+    request_body = ReauthenticateRequest,
+    responses(
+        (status = 200, description = "Tokens with a fresh auth_time", body = LoginResponse),
+        (status = 400, description = "Neither a password nor a code given", body = ErrorResponse),
+        (status = 401, description = "Wrong password or code", body = ErrorResponse),
+        (status = 403, description = "Not available while impersonating", body = ErrorResponse),
+    )
+)]
+pub async fn reauthenticate(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<ReauthenticateRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let client = ClientContext::from_headers(&headers);
+    let user = state.users.get_by_id(&claims.sub).await?;
+    let session = state.sessions.get(&claims.jti).await?;
+
+    let (method, verified) = match (payload.password, payload.code) {
+        (Some(password), None) => (
+            AuthMethod::Pwd,
+            state
+                .password_hasher
+                .verify(&password, &user.password_hash)
+                .await?,
+        ),
+        (None, Some(code)) => {
+            let verified = totp::verify(state.totp.as_ref(), &state.config, user.id, &code).await?;
+            metrics::record_mfa_challenge("reauthentication", verified);
+            (AuthMethod::Otp, verified)
+        }
+        _ => {
+            return Err(AppError::BadRequest(
+                "Provide either a password or an authenticator code".to_string(),
+            ))
+        }
+    };
+
+    if !verified {
+        state
+            .audit
+            .record(
+                &client,
+                NewAuditEvent::new(AuditEventType::Reauthenticated, AuditOutcome::Failure)
+                    .actor(user.id)
+                    .target(user.id)
+                    .details(serde_json::json!({ "session_id": session.id, "method": method })),
+            )
+            .await?;
+        return Err(AppError::Unauthorized);
+    }
+
+    // Keep acting for the same organisation while the user is still a member
+    let org = match claims.org_id {
+        Some(org_id) => state.users.org_membership(org_id, user.id).await?,
+        None => None,
+    };
+    let authn = Authentication::now(vec![method]);
+
+    let access_token = create_jwt_token(
+        &user,
+        &session,
+        org,
+        Some(&authn),
+        state.config.jwt_expiration,
+        &state.config.jwt_secret,
+    )?;
+
+    let generation = state
+        .sessions
+        .rotate_refresh_token(session.id, None)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let refresh_token = create_refresh_token(
+        &user,
+        &session,
+        &generation,
+        org,
+        Some(&authn),
+        state.config.refresh_token_expiration,
+        &state.config.jwt_secret,
+    )?;
+
+    state
+        .audit
+        .record(
+            &client,
+            NewAuditEvent::new(AuditEventType::Reauthenticated, AuditOutcome::Success)
+                .actor(user.id)
+                .target(user.id)
+                .details(serde_json::json!({ "session_id": session.id, "method": method })),
+        )
+        .await?;
+
+    Ok(Json(LoginResponse {
+        access_token,
+        refresh_token,
+        expires_in: state.config.jwt_expiration,
+        user: user.into(),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    responses(
+        (status = 204, description = "Session ended"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
 pub async fn logout(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -180,28 +638,94 @@ pub async fn logout(
         .ok_or(AppError::Unauthorized)?;
 
     // Decode token to get session ID
-    let claims = decode_jwt_token(token, &state.config.jwt_secret)?;
-    
+    let claims = decode_jwt_token(token, &state.config.jwt_secrets())?;
+
     // Delete session
-    user_service::delete_session(&state.db, &claims.jti.parse().unwrap()).await?;
+    state.sessions.delete(&claims.jti).await?;
+
+    let user_id = caller_id(&claims)?;
+    let event = match &claims.act {
+        Some(act) => {
+            NewAuditEvent::new(AuditEventType::ImpersonationStopped, AuditOutcome::Success)
+                .actor(Uuid::parse_str(&act.sub).map_err(|_| AppError::Unauthorized)?)
+        }
+        None => NewAuditEvent::new(AuditEventType::Logout, AuditOutcome::Success).actor(user_id),
+    };
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            event
+                .target(user_id)
+                .details(serde_json::json!({ "session_id": claims.jti })),
+        )
+        .await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Ends an impersonation session early. Equivalent to logging out with the
+/// impersonation token, but refuses ordinary tokens.
+#[utoipa::path(
+    post,
+    path = "/auth/impersonation/stop",
+    tag = "auth",
+    responses(
+        (status = 204, description = "Impersonation session ended"),
+        (status = 400, description = "Not an impersonation session", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn stop_impersonation(
+    state: State<AppState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    let token = headers
+        .get("authorization")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.strip_prefix("Bearer "))
+        .ok_or(AppError::Unauthorized)?;
+
+    let claims = decode_jwt_token(token, &state.config.jwt_secrets())?;
+    if claims.act.is_none() {
+        return Err(AppError::BadRequest(
+            "Not an impersonation session".to_string(),
+        ));
+    }
+
+    logout(state, headers).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/verify",
+    tag = "auth",
+    params(VerifyTokenQuery),
+    security(()),
+    responses(
+        (status = 200, description = "Token is valid", body = UserProfile),
+        (status = 401, description = "Token invalid or session ended", body = ErrorResponse),
+    )
+)]
 pub async fn verify_token(
     State(state): State<AppState>,
     Query(params): Query<VerifyTokenQuery>,
 ) -> Result<Json<UserProfile>, AppError> {
     // Decode and validate token
-    let claims = decode_jwt_token(&params.token, &state.config.jwt_secret)?;
-    
+    let claims = decode_jwt_token(&params.token, &state.config.jwt_secrets())?;
+
     // Get user
-    let user = user_service::get_user_by_id(&state.db, &claims.sub.parse().unwrap())
+    let user = state
+        .users
+        .get_by_id(&claims.sub)
         .await
         .map_err(|_| AppError::Unauthorized)?;
 
     // Verify session exists and is valid
-    let session = user_service::get_session_by_id(&state.db, &claims.jti.parse().unwrap())
+    let session = state
+        .sessions
+        .get(&claims.jti)
         .await
         .map_err(|_| AppError::Unauthorized)?;
 
@@ -211,3 +735,481 @@ pub async fn verify_token(
 
     Ok(Json(user.into()))
 }
+
+/// Target of the "this wasn't me" link in new sign-in notices. Mail scanners
+/// follow links, so this only asks for confirmation; the form posts back to
+/// `deny_login`.
+#[utoipa::path(
+    get,
+    path = "/auth/login-alerts/deny",
+    tag = "auth",
+    params(LinkTokenQuery),
+    security(()),
+    responses(
+        (status = 200, description = "Confirmation page", content_type = "text/html"),
+        (status = 400, description = "Malformed link", body = ErrorResponse),
+    )
+)]
+pub async fn confirm_deny_login(
+    Query(params): Query<LinkTokenQuery>,
+) -> Result<Html<String>, AppError> {
+    // Tokens are hex, which also makes them safe to embed in the page
+    if params.token.is_empty() || !params.token.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(AppError::BadRequest("Invalid or expired link".to_string()));
+    }
+
+    Ok(Html(DENY_LOGIN_PAGE.replace("{token}", &params.token)))
+}
+
+/// Signs out every session on the account and sends a password reset.
+#[utoipa::path(
+    post,
+    path = "/auth/login-alerts/deny",
+    tag = "auth",
+    request_body(content = LinkTokenQuery, content_type = "application/x-www-form-urlencoded"),
+    security(()),
+    responses(
+        (status = 200, description = "Sessions from the sign-in ended and a password reset sent", content_type = "text/html"),
+        (status = 400, description = "Invalid or expired link", body = ErrorResponse),
+    )
+)]
+pub async fn deny_login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(params): Form<LinkTokenQuery>,
+) -> Result<Html<&'static str>, AppError> {
+    let denied = login_alerts::deny(&state.db, state.sessions.as_ref(), &params.token).await?;
+    let user = state.users.get_by_id(&denied.user_id.to_string()).await?;
+
+    let token = password_reset::issue_token(&state.db, user.id, &state.config).await?;
+    mailer::send_in_background(
+        state.mailer.clone(),
+        password_reset::reset_email(&user, &token, &state.config),
+    );
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::LoginAlertDenied, AuditOutcome::Success)
+                .actor(user.id)
+                .target(user.id)
+                .details(serde_json::json!({
+                    "alert_id": denied.alert_id,
+                    "session_id": denied.session_id,
+                    "sessions_revoked": denied.sessions_revoked,
+                })),
+        )
+        .await?;
+
+    Ok(Html(LOGIN_DENIED_PAGE))
+}
+
+const DENY_LOGIN_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Secure your BookMarket account</title>
+</head>
+<body>
+  <h1>Wasn't you?</h1>
+  <p>We will sign out every session on your account and email you a link to choose a new password.</p>
+  <form method="post" action="/auth/login-alerts/deny">
+    <input type="hidden" name="token" value="{token}">
+    <button type="submit">Sign out everywhere</button>
+  </form>
+</body>
+</html>
+"##;
+
+const LOGIN_DENIED_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Account secured</title>
+</head>
+<body>
+  <h1>Account secured</h1>
+  <p>All sessions have been signed out. Check your email to choose a new password.</p>
+</body>
+</html>
+"##;
+
+/// Scores a candidate password against the policy so the register and
+/// change-password pages can show feedback while the user types.
+#[utoipa::path(
+    post,
+    path = "/auth/password/check",
+    tag = "auth",
+    request_body = PasswordCheckRequest,
+    security(()),
+    responses(
+        (status = 200, description = "Policy feedback", body = PasswordFeedback),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+    )
+)]
+pub async fn check_password(
+    State(state): State<AppState>,
+    Json(payload): Json<PasswordCheckRequest>,
+) -> Result<Json<PasswordFeedback>, AppError> {
+    payload.validate()?;
+
+    let feedback = state
+        .password_policy
+        .evaluate(
+            &payload.password,
+            PasswordContext {
+                email: payload.email.as_deref(),
+                first_name: payload.first_name.as_deref(),
+                last_name: payload.last_name.as_deref(),
+            },
+        )
+        .await?;
+
+    Ok(Json(feedback))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password/forgot",
+    tag = "auth",
+    request_body = ForgotPasswordRequest,
+    security(()),
+    responses(
+        (status = 202, description = "Reset email sent if the account exists"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+    )
+)]
+pub async fn forgot_password(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ForgotPasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    payload.validate()?;
+
+    // Respond identically whether or not the account exists
+    if let Ok(user) = state.users.get_by_email(&payload.email).await {
+        let token = password_reset::issue_token(&state.db, user.id, &state.config).await?;
+        mailer::send_in_background(
+            state.mailer.clone(),
+            password_reset::reset_email(&user, &token, &state.config),
+        );
+
+        state
+            .audit
+            .record(
+                &ClientContext::from_headers(&headers),
+                NewAuditEvent::new(
+                    AuditEventType::PasswordResetRequested,
+                    AuditOutcome::Success,
+                )
+                .target(user.id),
+            )
+            .await?;
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/password/reset",
+    tag = "auth",
+    request_body = ResetPasswordRequest,
+    security(()),
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Invalid or expired token, or weak password", body = ErrorResponse),
+    )
+)]
+pub async fn reset_password(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, AppError> {
+    payload.validate()?;
+
+    let user = password_reset::token_user(&state.db, state.users.as_ref(), &payload.token).await?;
+    state
+        .password_policy
+        .enforce(&payload.new_password, PasswordContext::for_user(&user))
+        .await?;
+
+    let password_hash = state.password_hasher.hash(&payload.new_password).await?;
+    let user_id = password_reset::reset_password(
+        &state.db,
+        state.sessions.as_ref(),
+        &payload.token,
+        &password_hash,
+    )
+    .await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::PasswordReset, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/email/verify",
+    tag = "auth",
+    request_body = VerifyEmailRequest,
+    security(()),
+    responses(
+        (status = 204, description = "Email verified"),
+        (status = 400, description = "Invalid or expired token", body = ErrorResponse),
+    )
+)]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> Result<StatusCode, AppError> {
+    let user_id = email_verification::verify_email(&state.db, &payload.token).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::EmailVerified, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Applies a pending email change from the link sent to the new address.
+#[utoipa::path(
+    post,
+    path = "/auth/email/change/confirm",
+    tag = "auth",
+    request_body = EmailChangeTokenRequest,
+    security(()),
+    responses(
+        (status = 204, description = "Email address changed"),
+        (status = 400, description = "Invalid or expired token", body = ErrorResponse),
+        (status = 409, description = "Address taken in the meantime", body = ErrorResponse),
+    )
+)]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<EmailChangeTokenRequest>,
+) -> Result<StatusCode, AppError> {
+    let change =
+        email_change::confirm_change(&state.db, state.sessions.as_ref(), &payload.token).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::EmailChanged, AuditOutcome::Success)
+                .actor(change.user_id)
+                .target(change.user_id)
+                .details(serde_json::json!({
+                    "old_email": change.old_email,
+                    "new_email": change.new_email,
+                    "sessions_revoked": change.sessions_revoked,
+                })),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stops a pending email change from the link sent to the old address.
+#[utoipa::path(
+    post,
+    path = "/auth/email/change/cancel",
+    tag = "auth",
+    request_body = EmailChangeTokenRequest,
+    security(()),
+    responses(
+        (status = 204, description = "Email change cancelled"),
+        (status = 400, description = "Invalid or expired token", body = ErrorResponse),
+    )
+)]
+pub async fn cancel_email_change(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<EmailChangeTokenRequest>,
+) -> Result<StatusCode, AppError> {
+    let user_id = email_change::cancel_change(&state.db, &payload.token).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::EmailChangeCancelled, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Emails a one-time sign-in link. The response sets a nonce cookie that the
+/// link must be redeemed with, so a forwarded or intercepted email is useless
+/// in another browser. Requests are throttled per client address and per
+/// email address, whether or not the account exists.
+#[utoipa::path(
+    post,
+    path = "/auth/magic-link",
+    tag = "auth",
+    request_body = MagicLinkRequest,
+    security(()),
+    responses(
+        (status = 202, description = "Link sent if the account may use one", headers(("set-cookie" = String, description = "Nonce the link must be redeemed with"))),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 429, description = "Too many links requested", body = ErrorResponse),
+    )
+)]
+pub async fn request_magic_link(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<MagicLinkRequest>,
+) -> Result<Response, AppError> {
+    payload.validate()?;
+
+    let client = ClientContext::from_headers(&headers);
+    if let Some(ip) = &client.ip_address {
+        state
+            .rate_limiter
+            .check(
+                "magic_link:ip",
+                ip,
+                state.config.magic_link_ip_hourly_limit,
+                3600,
+            )
+            .await?;
+    }
+    state
+        .rate_limiter
+        .check(
+            "magic_link:email",
+            &payload.email.trim().to_lowercase(),
+            state.config.magic_link_email_hourly_limit,
+            3600,
+        )
+        .await?;
+
+    // The cookie is set and the response is the same whether or not a link
+    // was sent, so the endpoint doesn't reveal which accounts exist
+    let nonce = generate_token();
+
+    if let Ok(user) = state.users.get_by_email(&payload.email).await {
+        // Admin accounts must sign in with their password
+        if user.role != UserRole::Admin && user.status == UserStatus::Active {
+            let token = magic_link::issue_link(&state.db, &user, &nonce, &state.config).await?;
+            mailer::send_in_background(
+                state.mailer.clone(),
+                magic_link::magic_link_email(&user, &token, &state.config),
+            );
+
+            state
+                .audit
+                .record(
+                    &client,
+                    NewAuditEvent::new(AuditEventType::MagicLinkRequested, AuditOutcome::Success)
+                        .target(user.id),
+                )
+                .await?;
+        }
+    }
+
+    let cookie = magic_link_cookie(&state, &nonce, state.config.magic_link_ttl);
+
+    Ok((StatusCode::ACCEPTED, [(header::SET_COOKIE, cookie)]).into_response())
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/magic-link/redeem",
+    tag = "auth",
+    request_body = MagicLinkRedeemRequest,
+    security(()),
+    responses(
+        (status = 200, description = "Signed in", body = LoginResponse),
+        (status = 400, description = "Invalid or expired link, or opened in another browser", body = ErrorResponse),
+        (status = 401, description = "`MFA_REQUIRED` with a challenge to answer at /auth/mfa/challenge", body = ErrorResponse),
+        (status = 403, description = "Account may not sign in with a link, or a password reset is required", body = ErrorResponse),
+    )
+)]
+pub async fn redeem_magic_link(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<MagicLinkRedeemRequest>,
+) -> Result<Response, AppError> {
+    let client = ClientContext::from_headers(&headers);
+
+    let nonce = cookie_value(&headers, MAGIC_LINK_COOKIE).ok_or_else(|| {
+        AppError::BadRequest(
+            "Open the sign-in link in the browser where you requested it".to_string(),
+        )
+    })?;
+
+    let user_id = magic_link::redeem_link(&state.db, &payload.token, nonce, &state.config).await?;
+    let user = state.users.get_by_id(&user_id.to_string()).await?;
+
+    // Re-checked here in case the account changed after the link was sent
+    if user.role == UserRole::Admin || user.status != UserStatus::Active {
+        audit_failed_login(
+            &state,
+            &client,
+            Some(user.id),
+            &user.email,
+            "magic_link",
+            "magic_link_not_allowed",
+        )
+        .await?;
+        return Err(AppError::Forbidden);
+    }
+
+    let response = complete_login(
+        &state,
+        user,
+        &client,
+        false,
+        "magic_link",
+        AuthMethod::Email,
+    )
+    .await?;
+    let cookie = magic_link_cookie(&state, "", 0);
+
+    Ok(([(header::SET_COOKIE, cookie)], Json(response)).into_response())
+}
+
+fn magic_link_cookie(state: &AppState, value: &str, max_age: i64) -> String {
+    let secure = if state.config.public_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+
+    format!(
+        "{}={}; Path=/auth/magic-link; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        MAGIC_LINK_COOKIE, value, max_age, secure
+    )
+}
+
+pub(crate) fn cookie_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, value)| *key == name && !value.is_empty())
+        .map(|(_, value)| value)
+}
@@ -1,48 +1,102 @@
-use axum::{extract::State, response::Response, http::header};
-use prometheus::{Encoder, TextEncoder, Counter, Histogram, Gauge, register_counter, register_histogram, register_gauge};
-use std::sync::Arc;
+use axum::{http::header, response::Response};
 use lazy_static::lazy_static;
+use prometheus::{
+    register_gauge_vec, register_histogram_vec, register_int_counter_vec, register_int_gauge,
+    Encoder, GaugeVec, HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
 
-use crate::{errors::AppError, AppState};
+use crate::errors::AppError;
 
 lazy_static! {
-    static ref AUTH_REQUESTS_TOTAL: Counter = register_counter!(
+    static ref AUTH_REQUESTS_TOTAL: IntCounterVec = register_int_counter_vec!(
         "auth_requests_total",
-        "Total number of authentication requests"
-    ).unwrap();
-    
-    static ref AUTH_REQUEST_DURATION: Histogram = register_histogram!(
+        "HTTP requests by route, method and status",
+        &["route", "method", "status"]
+    )
+    .unwrap();
+    static ref AUTH_REQUEST_DURATION: HistogramVec = register_histogram_vec!(
         "auth_request_duration_seconds",
-        "Authentication request duration in seconds"
-    ).unwrap();
-    
-    static ref ACTIVE_SESSIONS: Gauge = register_gauge!(
-        "auth_active_sessions",
-        "Number of active user sessions"
-    ).unwrap();
-    
-    static ref FAILED_LOGINS_TOTAL: Counter = register_counter!(
-        "auth_failed_logins_total",
-        "Total number of failed login attempts"
-    ).unwrap();
+        "HTTP request duration in seconds by route and method",
+        &["route", "method"]
+    )
+    .unwrap();
+    static ref ACTIVE_SESSIONS: IntGauge =
+        register_int_gauge!("auth_active_sessions", "Number of unexpired user sessions").unwrap();
+    static ref LOGINS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "auth_logins_total",
+        "Sign-in attempts by method and outcome",
+        &["method", "outcome"]
+    )
+    .unwrap();
+    static ref REGISTRATIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "auth_registrations_total",
+        "Accounts registered, by role",
+        &["role"]
+    )
+    .unwrap();
+    static ref TOKEN_REFRESHES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "auth_token_refreshes_total",
+        "Refresh token exchanges by outcome",
+        &["outcome"]
+    )
+    .unwrap();
+    static ref MFA_CHALLENGES_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "auth_mfa_challenges_total",
+        "Authenticator codes checked, by purpose and outcome",
+        &["purpose", "outcome"]
+    )
+    .unwrap();
+    static ref API_KEY_AUTHENTICATIONS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "auth_api_key_authentications_total",
+        "API keys presented for introspection, by outcome",
+        &["outcome"]
+    )
+    .unwrap();
+    static ref JOB_RUNS_TOTAL: IntCounterVec = register_int_counter_vec!(
+        "auth_job_runs_total",
+        "Background job runs by job and outcome",
+        &["job", "outcome"]
+    )
+    .unwrap();
+    static ref JOB_DURATION: HistogramVec = register_histogram_vec!(
+        "auth_job_duration_seconds",
+        "Background job run duration in seconds",
+        &["job"],
+        vec![0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 15.0, 60.0, 300.0]
+    )
+    .unwrap();
+    static ref JOB_ITEMS_PROCESSED: IntCounterVec = register_int_counter_vec!(
+        "auth_job_items_processed_total",
+        "Rows or accounts handled by background jobs",
+        &["job"]
+    )
+    .unwrap();
+    static ref JOB_LAST_SUCCESS: GaugeVec = register_gauge_vec!(
+        "auth_job_last_success_timestamp_seconds",
+        "Unix time of the last successful run of each background job",
+        &["job"]
+    )
+    .unwrap();
 }
 
-pub async fn metrics(State(state): State<AppState>) -> Result<Response, AppError> {
-    // Update active sessions gauge
-    let active_sessions_count: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM auth.sessions WHERE expires_at > CURRENT_TIMESTAMP"
+/// Prometheus scrape endpoint. Only renders what is already recorded; the
+/// session gauge is kept up to date by a background job.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    security(()),
+    responses(
+        (status = 200, description = "Prometheus text exposition format", content_type = "text/plain", body = String),
     )
-    .fetch_one(&state.db)
-    .await
-    .unwrap_or(0);
-    
-    ACTIVE_SESSIONS.set(active_sessions_count as f64);
-
-    // Encode metrics
+)]
+pub async fn metrics() -> Result<Response, AppError> {
     let encoder = TextEncoder::new();
     let metric_families = prometheus::gather();
     let mut buffer = Vec::new();
-    encoder.encode(&metric_families, &mut buffer).unwrap();
+    encoder
+        .encode(&metric_families, &mut buffer)
+        .map_err(|e| AppError::InternalServerError(format!("Metrics encoding failed: {}", e)))?;
 
     Ok(Response::builder()
         .header(header::CONTENT_TYPE, encoder.format_type())
@@ -51,14 +105,65 @@ pub async fn metrics(State(state): State<AppState>) -> Result<Response, AppError
 }
 
 // Helper functions to update metrics from other handlers
-pub fn increment_auth_requests() {
-    AUTH_REQUESTS_TOTAL.inc();
+pub fn record_http_request(route: &str, method: &str, status: u16, duration: f64) {
+    AUTH_REQUESTS_TOTAL
+        .with_label_values(&[route, method, &status.to_string()])
+        .inc();
+    AUTH_REQUEST_DURATION
+        .with_label_values(&[route, method])
+        .observe(duration);
 }
 
-pub fn record_auth_duration(duration: f64) {
-    AUTH_REQUEST_DURATION.observe(duration);
+pub fn set_active_sessions(count: i64) {
+    ACTIVE_SESSIONS.set(count);
+}
+
+pub fn record_login(method: &str, success: bool) {
+    LOGINS_TOTAL
+        .with_label_values(&[method, outcome(success)])
+        .inc();
+}
+
+pub fn record_registration(role: &str) {
+    REGISTRATIONS_TOTAL.with_label_values(&[role]).inc();
+}
+
+pub fn record_token_refresh(success: bool) {
+    TOKEN_REFRESHES_TOTAL
+        .with_label_values(&[outcome(success)])
+        .inc();
+}
+
+pub fn record_mfa_challenge(purpose: &str, success: bool) {
+    MFA_CHALLENGES_TOTAL
+        .with_label_values(&[purpose, outcome(success)])
+        .inc();
+}
+
+pub fn record_api_key_authentication(success: bool) {
+    API_KEY_AUTHENTICATIONS_TOTAL
+        .with_label_values(&[outcome(success)])
+        .inc();
+}
+
+pub fn record_job_run(job: &str, success: bool, duration: f64, items: u64) {
+    JOB_RUNS_TOTAL
+        .with_label_values(&[job, outcome(success)])
+        .inc();
+    JOB_DURATION.with_label_values(&[job]).observe(duration);
+
+    if success {
+        JOB_ITEMS_PROCESSED.with_label_values(&[job]).inc_by(items);
+        JOB_LAST_SUCCESS
+            .with_label_values(&[job])
+            .set(chrono::Utc::now().timestamp() as f64);
+    }
 }
 
-pub fn increment_failed_logins() {
-    FAILED_LOGINS_TOTAL.inc();
+fn outcome(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "failure"
+    }
 }
@@ -1,69 +1,256 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::State,
+    http::{HeaderMap, StatusCode},
     response::Json,
+    Extension,
 };
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::{errors::AppError, AppState};
+use crate::{
+    auth::decode_mfa_challenge_token,
+    errors::AppError,
+    handlers::{
+        auth::{audit_failed_login, refuse_if_reset_required, start_session},
+        metrics,
+    },
+    middleware::{caller_id, forbid_impersonation, require_recent_auth},
+    models::{
+        AuditEventType, AuditOutcome, AuthMethod, Claims, LoginResponse, MfaChallengeRequest,
+        UserStatus,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        totp,
+    },
+    AppState,
+};
+
+/// Issuer shown next to the account in authenticator apps.
+const TOTP_ISSUER: &str = "BookMarket";
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SetupMfaRequest {
     pub method: String, // "totp" or "sms"
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SetupMfaResponse {
+    /// Always absent: clients draw the QR code from `otpauth_uri`.
     pub qr_code: Option<String>,
+    pub secret: String,
+    pub otpauth_uri: String,
+    /// Always empty: backup codes are not issued.
     pub backup_codes: Vec<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct VerifyMfaRequest {
     pub code: String,
 }
 
+/// Finishes a sign-in that stopped with `MFA_REQUIRED`, given a code from
+/// the account's authenticator app. Wrong codes are throttled per account,
+/// and each challenge signs in at most once.
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/challenge",
+    tag = "mfa",
+    request_body = MfaChallengeRequest,
+    security(()),
+    responses(
+        (status = 200, description = "Signed in", body = LoginResponse),
+        (status = 401, description = "Wrong code, or an invalid, expired or used challenge", body = ErrorResponse),
+        (status = 403, description = "Account no longer active or password reset required", body = ErrorResponse),
+        (status = 429, description = "Too many codes tried", body = ErrorResponse),
+    )
+)]
+pub async fn challenge_mfa(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<MfaChallengeRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let client = ClientContext::from_headers(&headers);
+    let challenge = decode_mfa_challenge_token(&payload.mfa_token, &state.config.jwt_secrets())?;
+
+    state
+        .rate_limiter
+        .check(
+            "mfa_challenge:user",
+            &challenge.sub,
+            state.config.mfa_challenge_max_attempts,
+            state.config.mfa_challenge_ttl,
+        )
+        .await?;
+
+    let user = state.users.get_by_id(&challenge.sub).await?;
+    let verified = totp::verify(state.totp.as_ref(), &state.config, user.id, &payload.code).await?;
+    metrics::record_mfa_challenge("login", verified);
+
+    if !verified {
+        audit_failed_login(
+            &state,
+            &client,
+            Some(user.id),
+            &user.email,
+            &challenge.method,
+            "invalid_mfa_code",
+        )
+        .await?;
+        return Err(AppError::Unauthorized);
+    }
+
+    // A counter that allows one use per challenge lifetime
+    match state
+        .rate_limiter
+        .check(
+            "mfa_challenge:redeemed",
+            &challenge.jti,
+            1,
+            state.config.mfa_challenge_ttl,
+        )
+        .await
+    {
+        Err(AppError::RateLimitExceeded) => return Err(AppError::Unauthorized),
+        other => other?,
+    }
+
+    // The account may have changed while the code was being entered
+    if user.status != UserStatus::Active {
+        audit_failed_login(
+            &state,
+            &client,
+            Some(user.id),
+            &user.email,
+            &challenge.method,
+            "account_inactive",
+        )
+        .await?;
+        return Err(AppError::Forbidden);
+    }
+    refuse_if_reset_required(&state, &user, &client, &challenge.method).await?;
+
+    let response = start_session(
+        &state,
+        user,
+        &client,
+        challenge.remember_me,
+        &challenge.method,
+        vec![challenge.amr, AuthMethod::Otp],
+    )
+    .await?;
+
+    Ok(Json(response))
+}
+
+/// Starts enrolling an authenticator app. MFA is only enabled once a code
+/// from the app is confirmed through `verify_mfa`.
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/setup",
+    tag = "mfa",
+    request_body = SetupMfaRequest,
+    responses(
+        (status = 200, description = "Secret to add to an authenticator app", body = SetupMfaResponse),
+        (status = 400, description = "Unsupported method", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 409, description = "MFA is already enabled", body = ErrorResponse),
+    )
+)]
 pub async fn setup_mfa(
-    State(_state): State<AppState>,
-    Path(_user_id): Path<String>,
-    Json(_payload): Json<SetupMfaRequest>,
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<SetupMfaRequest>,
 ) -> Result<Json<SetupMfaResponse>, AppError> {
-    // TODO: Implement MFA setup
-    // This would involve:
-    // 1. Generate TOTP secret
-    // 2. Create QR code for authenticator apps
-    // 3. Generate backup codes
-    // 4. Store in database
-    
+    forbid_impersonation(&claims)?;
+
+    if payload.method != "totp" {
+        return Err(AppError::BadRequest(
+            "Only authenticator apps (totp) are supported".to_string(),
+        ));
+    }
+
+    let secret = totp::enroll(state.totp.as_ref(), &state.config, caller_id(&claims)?).await?;
+    let otpauth_uri = totp::otpauth_uri(TOTP_ISSUER, &claims.email, &secret);
+
     Ok(Json(SetupMfaResponse {
-        qr_code: Some("data:image/png;base64,placeholder".to_string()),
-        backup_codes: vec!["123456".to_string(), "789012".to_string()],
+        qr_code: None,
+        secret,
+        otpauth_uri,
+        backup_codes: Vec::new(),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/verify",
+    tag = "mfa",
+    request_body = VerifyMfaRequest,
+    responses(
+        (status = 200, description = "MFA enabled"),
+        (status = 400, description = "No setup in progress or invalid code", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
 pub async fn verify_mfa(
-    State(_state): State<AppState>,
-    Path(_user_id): Path<String>,
-    Json(_payload): Json<VerifyMfaRequest>,
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<VerifyMfaRequest>,
 ) -> Result<StatusCode, AppError> {
-    // TODO: Implement MFA verification
-    // This would involve:
-    // 1. Verify TOTP code or SMS code
-    // 2. Enable MFA for user
-    // 3. Invalidate setup session
-    
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    let confirmed = totp::confirm(state.totp.as_ref(), &state.config, user_id, &payload.code).await;
+    metrics::record_mfa_challenge("enrolment", confirmed.is_ok());
+    confirmed?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::MfaEnabled, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({ "method": "totp" })),
+        )
+        .await?;
+
     Ok(StatusCode::OK)
 }
 
+#[utoipa::path(
+    post,
+    path = "/auth/mfa/disable",
+    tag = "mfa",
+    responses(
+        (status = 204, description = "MFA disabled"),
+        (status = 401, description = "Missing or invalid token, or re-authentication required", body = ErrorResponse),
+        (status = 404, description = "MFA is not enabled", body = ErrorResponse),
+    )
+)]
 pub async fn disable_mfa(
-    State(_state): State<AppState>,
-    Path(_user_id): Path<String>,
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
 ) -> Result<StatusCode, AppError> {
-    // TODO: Implement MFA disable
-    // This would involve:
-    // 1. Verify current password or admin privileges
-    // 2. Remove MFA settings from user
-    // 3. Invalidate all sessions (force re-login)
-    
+    forbid_impersonation(&claims)?;
+    require_recent_auth(&claims, state.config.reauthentication_max_age)?;
+
+    let user_id = caller_id(&claims)?;
+    totp::disable(state.totp.as_ref(), user_id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::MfaDisabled, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({ "method": "totp" })),
+        )
+        .await?;
+
     Ok(StatusCode::NO_CONTENT)
 }
@@ -1,6 +1,13 @@
 pub mod admin;
 pub mod api_keys;
+pub mod audit;
 pub mod auth;
 pub mod metrics;
 pub mod mfa;
+pub mod oauth;
+pub mod oidc;
+pub mod organizations;
+pub mod phone;
+pub mod privacy;
 pub mod users;
+pub mod webhooks;
@@ -0,0 +1,165 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::Json,
+    Form,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{
+    config::OAuthClientConfig,
+    errors::AppError,
+    models::{AuditEventType, AuditOutcome, IntrospectionResponse, TokenRequest},
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        tokens::{self, RevokedToken},
+        webhooks::{self, WebhookEvent},
+    },
+    AppState,
+};
+
+/// RFC 7662 introspection for backend services. Lets a resource server check
+/// a token against live session state instead of trusting the signature alone.
+#[utoipa::path(
+    post,
+    path = "/oauth/introspect",
+    tag = "oauth",
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    security(()),
+    responses(
+        (status = 200, description = "RFC 7662 introspection result", body = IntrospectionResponse),
+        (status = 401, description = "Client authentication failed", body = ErrorResponse),
+    )
+)]
+pub async fn introspect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(payload): Form<TokenRequest>,
+) -> Result<Json<IntrospectionResponse>, AppError> {
+    authenticate_client(&state, &headers, &payload)?;
+
+    let response = tokens::introspect(
+        &state.db,
+        state.users.as_ref(),
+        state.sessions.as_ref(),
+        &state.config,
+        &payload.token,
+    )
+    .await?;
+
+    Ok(Json(response))
+}
+
+/// RFC 7009 revocation for first-party clients, the ones tokens are issued
+/// to. Answers 200 whether or not the token was valid, so callers learn
+/// nothing about tokens they hold.
+#[utoipa::path(
+    post,
+    path = "/oauth/revoke",
+    tag = "oauth",
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    security(()),
+    responses(
+        (status = 200, description = "Token revoked, or was not valid"),
+        (status = 401, description = "Client authentication failed", body = ErrorResponse),
+        (status = 403, description = "Client is not first-party, so no tokens are issued to it", body = ErrorResponse),
+    )
+)]
+pub async fn revoke(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(payload): Form<TokenRequest>,
+) -> Result<StatusCode, AppError> {
+    let client = authenticate_client(&state, &headers, &payload)?;
+    let client_id = &client.client_id;
+
+    let revoked = tokens::revoke(
+        state.api_keys.as_ref(),
+        state.sessions.as_ref(),
+        &state.config,
+        client,
+        &payload.token,
+    )
+    .await?;
+
+    let event = match revoked {
+        Some(RevokedToken::Session {
+            session_id,
+            user_id,
+        }) => NewAuditEvent::new(AuditEventType::TokenRevoked, AuditOutcome::Success)
+            .target(user_id)
+            .details(serde_json::json!({
+                "client_id": client_id,
+                "kind": "session",
+                "session_id": session_id,
+            })),
+        Some(RevokedToken::ApiKey { key_id, user_id }) => {
+            webhooks::notify_user(
+                &state.db,
+                user_id,
+                WebhookEvent::ApiKeyRevoked,
+                serde_json::json!({ "user_id": user_id, "key_id": key_id }),
+            )
+            .await;
+
+            NewAuditEvent::new(AuditEventType::TokenRevoked, AuditOutcome::Success)
+                .target(user_id)
+                .details(serde_json::json!({
+                    "client_id": client_id,
+                    "kind": "api_key",
+                    "key_id": key_id,
+                }))
+        }
+        None => return Ok(StatusCode::OK),
+    };
+    state
+        .audit
+        .record(&ClientContext::from_headers(&headers), event)
+        .await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Authenticates the calling service with HTTP Basic credentials, or with
+/// `client_id`/`client_secret` in the form body, against the configured
+/// clients. Returns the client's configuration.
+fn authenticate_client<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+    payload: &TokenRequest,
+) -> Result<&'a OAuthClientConfig, AppError> {
+    let (client_id, client_secret) = match basic_credentials(headers) {
+        Some(credentials) => credentials,
+        None => match (&payload.client_id, &payload.client_secret) {
+            (Some(id), Some(secret)) => (id.clone(), secret.clone()),
+            _ => return Err(AppError::Unauthorized),
+        },
+    };
+
+    let client = state
+        .config
+        .oauth_clients
+        .iter()
+        .find(|client| client.client_id == client_id)
+        .ok_or(AppError::Unauthorized)?;
+
+    if !constant_time_eq(client.client_secret.as_bytes(), client_secret.as_bytes()) {
+        return Err(AppError::Unauthorized);
+    }
+
+    Ok(client)
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (id, secret) = decoded.split_once(':')?;
+
+    Some((id.to_string(), secret.to_string()))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
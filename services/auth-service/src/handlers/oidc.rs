@@ -0,0 +1,424 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Redirect, Response},
+    Extension,
+};
+use reqwest::Url;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    auth::hash_token,
+    config::OidcProviderConfig,
+    errors::AppError,
+    handlers::{
+        auth::{complete_login, cookie_value},
+        metrics,
+    },
+    middleware::{caller_id, forbid_impersonation},
+    models::{
+        AuditEventType, AuditOutcome, AuthMethod, Claims, OidcAuthorizationResponse, UserIdentity,
+        UserStatus,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        identities::{self, IdentityResolution},
+    },
+    AppState,
+};
+
+/// Cookie holding a hash of the `state` of the authorization request the
+/// browser started. The callback only proceeds when it matches, so a
+/// provider redirect can't be replayed into someone else's browser.
+const OIDC_STATE_COOKIE: &str = "bm_oidc_state";
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+fn provider_config<'a>(
+    state: &'a AppState,
+    name: &str,
+) -> Result<&'a OidcProviderConfig, AppError> {
+    state.config.oidc_provider(name).ok_or(AppError::NotFound)
+}
+
+/// Sends the browser to the provider to sign in.
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/{provider}/login",
+    tag = "auth",
+    params(("provider" = String, Path, description = "Configured identity provider")),
+    security(()),
+    responses(
+        (status = 303, description = "Redirect to the provider", headers(("set-cookie" = String, description = "Binds the request to this browser"))),
+        (status = 404, description = "Unknown provider", body = ErrorResponse),
+    )
+)]
+pub async fn login(
+    State(state): State<AppState>,
+    Path(provider): Path<String>,
+) -> Result<Response, AppError> {
+    let provider = provider_config(&state, &provider)?;
+    let request = state
+        .oidc
+        .authorization_url(provider, &state.config, None)
+        .await?;
+    let cookie = oidc_state_cookie(
+        &state,
+        &hash_token(&request.state),
+        state.config.oidc_state_ttl,
+    );
+
+    Ok(([(header::SET_COOKIE, cookie)], Redirect::to(&request.url)).into_response())
+}
+
+/// Where the provider sends the browser back. The outcome is handed to the
+/// frontend in the redirect, never rendered here: tokens go in the URL
+/// fragment so they don't reach server logs, errors as a short code. A
+/// browser without the cookie set when the request started is turned away.
+#[utoipa::path(
+    get,
+    path = "/auth/oidc/{provider}/callback",
+    tag = "auth",
+    params(("provider" = String, Path, description = "Configured identity provider"), CallbackQuery),
+    security(()),
+    responses(
+        (status = 303, description = "Redirect to the frontend, with tokens on success or an MFA challenge when the account has an authenticator"),
+        (status = 404, description = "Unknown provider", body = ErrorResponse),
+    )
+)]
+pub async fn callback(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+    Query(params): Query<CallbackQuery>,
+) -> Result<Response, AppError> {
+    let provider = provider_config(&state, &provider)?;
+
+    let started_here = match (&params.state, cookie_value(&headers, OIDC_STATE_COOKIE)) {
+        (Some(authorization_state), Some(expected)) => hash_token(authorization_state) == expected,
+        _ => false,
+    };
+    let redirect = if started_here {
+        finish_callback(&state, &headers, provider, params).await?
+    } else {
+        tracing::warn!(provider = %provider.name, "OIDC callback without a matching state cookie");
+        frontend_redirect(&state, "/auth/callback", "error=sign_in_failed")
+    };
+    let cookie = oidc_state_cookie(&state, "", 0);
+
+    Ok(([(header::SET_COOKIE, cookie)], redirect).into_response())
+}
+
+async fn finish_callback(
+    state: &AppState,
+    headers: &HeaderMap,
+    provider: &OidcProviderConfig,
+    params: CallbackQuery,
+) -> Result<Redirect, AppError> {
+    let client = ClientContext::from_headers(headers);
+
+    let (code, authorization_state) = match (params.code, params.state, params.error) {
+        (Some(code), Some(authorization_state), None) => (code, authorization_state),
+        (_, _, error) => {
+            tracing::warn!(provider = %provider.name, error = ?error, "OIDC authorization failed");
+            return Ok(frontend_redirect(
+                state,
+                "/auth/callback",
+                "error=sign_in_failed",
+            ));
+        }
+    };
+
+    let completed = match state
+        .oidc
+        .complete(provider, &state.config, &code, &authorization_state)
+        .await
+    {
+        Ok(completed) => completed,
+        Err(e) => {
+            tracing::warn!(provider = %provider.name, "OIDC callback rejected: {}", e);
+            return Ok(frontend_redirect(
+                state,
+                "/auth/callback",
+                "error=sign_in_failed",
+            ));
+        }
+    };
+
+    // Linking an additional identity from a signed-in session
+    if let Some(user_id) = completed.link_user_id {
+        let identity = &completed.identity;
+        let result = identities::link(
+            &state.db,
+            user_id,
+            &identity.provider,
+            &identity.subject,
+            identity.email.as_deref(),
+        )
+        .await;
+
+        return match result {
+            Ok(linked) => {
+                state
+                    .audit
+                    .record(
+                        &client,
+                        NewAuditEvent::new(AuditEventType::IdentityLinked, AuditOutcome::Success)
+                            .actor(user_id)
+                            .target(user_id)
+                            .details(serde_json::json!({
+                                "provider": linked.provider,
+                                "identity_id": linked.id,
+                            })),
+                    )
+                    .await?;
+                Ok(frontend_redirect(
+                    state,
+                    "/account/identities",
+                    &format!("linked={}", provider.name),
+                ))
+            }
+            Err(AppError::Conflict(_)) => Ok(frontend_redirect(
+                state,
+                "/account/identities",
+                "error=already_linked",
+            )),
+            Err(e) => Err(e),
+        };
+    }
+
+    let (user, resolution) = match identities::resolve_user(
+        &state.db,
+        state.users.as_ref(),
+        state.sessions.as_ref(),
+        &completed.identity,
+        &state.password_hasher,
+    )
+    .await
+    {
+        Ok(resolved) => resolved,
+        Err(e @ (AppError::BadRequest(_) | AppError::Conflict(_))) => {
+            tracing::warn!(provider = %provider.name, "OIDC sign-in refused: {}", e);
+            return Ok(frontend_redirect(
+                state,
+                "/auth/callback",
+                "error=sign_in_failed",
+            ));
+        }
+        Err(e) => return Err(e),
+    };
+
+    if resolution != IdentityResolution::Existing {
+        state
+            .audit
+            .record(
+                &client,
+                NewAuditEvent::new(AuditEventType::IdentityLinked, AuditOutcome::Success)
+                    .actor(user.id)
+                    .target(user.id)
+                    .details(serde_json::json!({
+                        "provider": provider.name,
+                        "resolution": resolution.as_str(),
+                    })),
+            )
+            .await?;
+    }
+
+    let method = format!("oidc:{}", provider.name);
+
+    if user.status != UserStatus::Active {
+        metrics::record_login(&method, false);
+        state
+            .audit
+            .record(
+                &client,
+                NewAuditEvent::new(AuditEventType::Login, AuditOutcome::Failure)
+                    .target(user.id)
+                    .details(serde_json::json!({
+                        "email": user.email,
+                        "reason": "account_inactive",
+                        "method": provider.name,
+                    })),
+            )
+            .await?;
+        return Ok(frontend_redirect(
+            state,
+            "/auth/callback",
+            "error=account_inactive",
+        ));
+    }
+
+    let response = match complete_login(state, user, &client, false, &method, AuthMethod::Fed).await
+    {
+        Ok(response) => response,
+        Err(AppError::PasswordResetRequired) => {
+            return Ok(frontend_redirect(
+                state,
+                "/auth/callback",
+                "error=password_reset_required",
+            ))
+        }
+        // The frontend asks for the code and posts it to /auth/mfa/challenge
+        Err(AppError::MfaRequired(challenge)) => {
+            return Ok(frontend_redirect(
+                state,
+                "/auth/callback",
+                &format!(
+                    "#mfa_token={}&expires_in={}",
+                    challenge.mfa_token, challenge.expires_in
+                ),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+
+    Ok(frontend_redirect(
+        state,
+        "/auth/callback",
+        &format!(
+            "#access_token={}&refresh_token={}&expires_in={}",
+            response.access_token, response.refresh_token, response.expires_in
+        ),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/me/identities",
+    tag = "users",
+    responses(
+        (status = 200, description = "Linked provider accounts", body = Vec<UserIdentity>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn list_identities(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<UserIdentity>>, AppError> {
+    let user_id = caller_id(&claims)?;
+    let identities = identities::list_for_user(&state.db, user_id).await?;
+
+    Ok(Json(identities))
+}
+
+/// Starts linking a provider account to the caller. The frontend sends the
+/// browser to the returned URL; the provider redirects back to the shared
+/// callback, which recognises the pending request as a link. The response
+/// sets the same cookie as the sign-in flow, so the frontend must keep it
+/// (`credentials: "include"`) for the callback to accept the link.
+#[utoipa::path(
+    post,
+    path = "/users/me/identities/{provider}",
+    tag = "users",
+    params(("provider" = String, Path, description = "Configured identity provider")),
+    responses(
+        (status = 200, description = "URL to send the user to", body = OidcAuthorizationResponse, headers(("set-cookie" = String, description = "Binds the request to this browser"))),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "Unknown provider", body = ErrorResponse),
+    )
+)]
+pub async fn start_link(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(provider): Path<String>,
+) -> Result<Response, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    let provider = provider_config(&state, &provider)?;
+    let request = state
+        .oidc
+        .authorization_url(provider, &state.config, Some(user_id))
+        .await?;
+    let cookie = oidc_state_cookie(
+        &state,
+        &hash_token(&request.state),
+        state.config.oidc_state_ttl,
+    );
+    let response = OidcAuthorizationResponse {
+        authorization_url: request.url,
+    };
+
+    Ok(([(header::SET_COOKIE, cookie)], Json(response)).into_response())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/me/identities/{provider}",
+    tag = "users",
+    params(("provider" = String, Path, description = "Configured identity provider")),
+    responses(
+        (status = 204, description = "Identity unlinked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No identity linked for the provider", body = ErrorResponse),
+    )
+)]
+pub async fn unlink_identity(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(provider): Path<String>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    let identity = identities::unlink(&state.db, user_id, &provider).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::IdentityUnlinked, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({
+                    "provider": identity.provider,
+                    "identity_id": identity.id,
+                })),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn oidc_state_cookie(state: &AppState, value: &str, max_age: u64) -> String {
+    let secure = if state.config.public_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+
+    format!(
+        "{}={}; Path=/auth/oidc; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        OIDC_STATE_COOKIE, value, max_age, secure
+    )
+}
+
+/// Builds a redirect to a frontend page. `outcome` is appended as the query
+/// string, or as the fragment when it starts with `#`.
+fn frontend_redirect(state: &AppState, path: &str, outcome: &str) -> Redirect {
+    let base = format!(
+        "{}{}",
+        state.config.frontend_url.trim_end_matches('/'),
+        path
+    );
+    let target = match outcome.strip_prefix('#') {
+        Some(fragment) => Url::parse(&base).map(|mut url| {
+            url.set_fragment(Some(fragment));
+            url.to_string()
+        }),
+        None => Url::parse(&base).map(|mut url| {
+            url.set_query(Some(outcome));
+            url.to_string()
+        }),
+    };
+
+    Redirect::to(&target.unwrap_or(base))
+}
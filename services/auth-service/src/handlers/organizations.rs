@@ -0,0 +1,528 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    auth::{create_jwt_token, create_refresh_token},
+    errors::AppError,
+    middleware::{caller_id, forbid_impersonation},
+    models::{
+        AuditEventType, AuditOutcome, Claims, CreateOrganizationRequest, InvitationTokenRequest,
+        InviteMemberRequest, LoginResponse, OrgInvitation, OrgMember, OrgMembership, OrgRole,
+        Organization, SwitchOrganizationRequest, UpdateMemberRoleRequest, UserRole,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        mailer, organizations,
+        webhooks::{self, WebhookEvent},
+    },
+    AppState,
+};
+
+/// Creates a shop organisation owned by the caller.
+#[utoipa::path(
+    post,
+    path = "/orgs",
+    tag = "organizations",
+    request_body = CreateOrganizationRequest,
+    responses(
+        (status = 201, description = "Organisation created with the caller as owner", body = Organization),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Customers cannot create organisations", body = ErrorResponse),
+    )
+)]
+pub async fn create_organization(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateOrganizationRequest>,
+) -> Result<(StatusCode, Json<Organization>), AppError> {
+    forbid_impersonation(&claims)?;
+    payload.validate()?;
+
+    if claims.role == UserRole::Customer {
+        return Err(AppError::Forbidden);
+    }
+
+    let user_id = caller_id(&claims)?;
+    let organization = organizations::create(&state.db, payload.name.trim(), user_id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgCreated, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({ "org_id": organization.id })),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(organization)))
+}
+
+/// The organisations the caller belongs to, for the switcher.
+#[utoipa::path(
+    get,
+    path = "/orgs",
+    tag = "organizations",
+    responses(
+        (status = 200, description = "The caller's organisations", body = Vec<OrgMembership>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn list_organizations(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<OrgMembership>>, AppError> {
+    let user_id = caller_id(&claims)?;
+    let memberships = organizations::list_for_user(&state.db, user_id).await?;
+
+    Ok(Json(memberships))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orgs/{id}",
+    tag = "organizations",
+    params(("id" = Uuid, Path, description = "Organisation ID")),
+    responses(
+        (status = 200, description = "Organisation", body = Organization),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+        (status = 404, description = "No such organisation", body = ErrorResponse),
+    )
+)]
+pub async fn get_organization(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(org_id): Path<Uuid>,
+) -> Result<Json<Organization>, AppError> {
+    organizations::require_role(&state.db, org_id, caller_id(&claims)?, &[]).await?;
+    let organization = organizations::get(&state.db, org_id).await?;
+
+    Ok(Json(organization))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orgs/{id}/members",
+    tag = "organizations",
+    params(("id" = Uuid, Path, description = "Organisation ID")),
+    responses(
+        (status = 200, description = "Members", body = Vec<OrgMember>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+    )
+)]
+pub async fn list_members(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(org_id): Path<Uuid>,
+) -> Result<Json<Vec<OrgMember>>, AppError> {
+    organizations::require_role(&state.db, org_id, caller_id(&claims)?, &[]).await?;
+    let members = organizations::list_members(&state.db, org_id).await?;
+
+    Ok(Json(members))
+}
+
+/// Changes a member's role. The member's sessions are revoked so no token
+/// goes on carrying the old role.
+#[utoipa::path(
+    put,
+    path = "/orgs/{id}/members/{user_id}",
+    tag = "organizations",
+    request_body = UpdateMemberRoleRequest,
+    params(("id" = Uuid, Path, description = "Organisation ID"), ("user_id" = Uuid, Path, description = "Member's user ID")),
+    responses(
+        (status = 204, description = "Role changed"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+        (status = 404, description = "No such member", body = ErrorResponse),
+    )
+)]
+pub async fn update_member(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(Uuid, Uuid)>,
+    Json(payload): Json<UpdateMemberRoleRequest>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    organizations::require_role(&state.db, org_id, user_id, &[OrgRole::Owner]).await?;
+    organizations::update_member_role(&state.db, org_id, member_id, payload.role).await?;
+    let revoked = state.sessions.delete_for_user(member_id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgMemberRoleChanged, AuditOutcome::Success)
+                .actor(user_id)
+                .target(member_id)
+                .details(serde_json::json!({
+                    "org_id": org_id,
+                    "role": payload.role,
+                    "sessions_revoked": revoked,
+                })),
+        )
+        .await?;
+
+    webhooks::notify_org(
+        &state.db,
+        org_id,
+        WebhookEvent::OrgMemberRoleChanged,
+        serde_json::json!({ "org_id": org_id, "user_id": member_id, "role": payload.role }),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes a member. Owners can remove anyone; other members can only leave.
+/// The member's sessions are revoked so no token keeps the organisation.
+#[utoipa::path(
+    delete,
+    path = "/orgs/{id}/members/{user_id}",
+    tag = "organizations",
+    params(("id" = Uuid, Path, description = "Organisation ID"), ("user_id" = Uuid, Path, description = "Member's user ID")),
+    responses(
+        (status = 204, description = "Member removed"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+        (status = 404, description = "No such member", body = ErrorResponse),
+    )
+)]
+pub async fn remove_member(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path((org_id, member_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    let required: &[OrgRole] = if member_id == user_id {
+        &[]
+    } else {
+        &[OrgRole::Owner]
+    };
+    organizations::require_role(&state.db, org_id, user_id, required).await?;
+    organizations::remove_member(&state.db, org_id, member_id).await?;
+    let revoked = state.sessions.delete_for_user(member_id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgMemberRemoved, AuditOutcome::Success)
+                .actor(user_id)
+                .target(member_id)
+                .details(serde_json::json!({ "org_id": org_id, "sessions_revoked": revoked })),
+        )
+        .await?;
+
+    webhooks::notify_org(
+        &state.db,
+        org_id,
+        WebhookEvent::OrgMemberRemoved,
+        serde_json::json!({ "org_id": org_id, "user_id": member_id }),
+    )
+    .await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/orgs/{id}/invitations",
+    tag = "organizations",
+    request_body = InviteMemberRequest,
+    params(("id" = Uuid, Path, description = "Organisation ID")),
+    responses(
+        (status = 201, description = "Invitation sent", body = OrgInvitation),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+        (status = 409, description = "Already a member or invited", body = ErrorResponse),
+    )
+)]
+pub async fn invite_member(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(org_id): Path<Uuid>,
+    Json(payload): Json<InviteMemberRequest>,
+) -> Result<(StatusCode, Json<OrgInvitation>), AppError> {
+    forbid_impersonation(&claims)?;
+    payload.validate()?;
+
+    let user_id = caller_id(&claims)?;
+    organizations::require_role(&state.db, org_id, user_id, &[OrgRole::Owner]).await?;
+
+    let organization = organizations::get(&state.db, org_id).await?;
+    let inviter = state.users.get_by_id(&user_id.to_string()).await?;
+    let (invitation, token) = organizations::create_invitation(
+        &state.db,
+        org_id,
+        payload.email.trim(),
+        payload.role,
+        user_id,
+        &state.config,
+    )
+    .await?;
+
+    mailer::send_in_background(
+        state.mailer.clone(),
+        organizations::invitation_email(
+            &organization,
+            &inviter,
+            &invitation,
+            &token,
+            &state.config,
+        ),
+    );
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgInvitationSent, AuditOutcome::Success)
+                .actor(user_id)
+                .details(serde_json::json!({
+                    "org_id": org_id,
+                    "invitation_id": invitation.id,
+                    "email": invitation.email,
+                    "role": invitation.role,
+                })),
+        )
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orgs/{id}/invitations",
+    tag = "organizations",
+    params(("id" = Uuid, Path, description = "Organisation ID")),
+    responses(
+        (status = 200, description = "Pending invitations", body = Vec<OrgInvitation>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+    )
+)]
+pub async fn list_invitations(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(org_id): Path<Uuid>,
+) -> Result<Json<Vec<OrgInvitation>>, AppError> {
+    organizations::require_role(&state.db, org_id, caller_id(&claims)?, &[OrgRole::Owner]).await?;
+    let invitations = organizations::list_invitations(&state.db, org_id).await?;
+
+    Ok(Json(invitations))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/orgs/{id}/invitations/{invitation_id}",
+    tag = "organizations",
+    params(("id" = Uuid, Path, description = "Organisation ID"), ("invitation_id" = Uuid, Path, description = "Invitation ID")),
+    responses(
+        (status = 204, description = "Invitation revoked"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller lacks the required organisation role", body = ErrorResponse),
+        (status = 404, description = "No such pending invitation", body = ErrorResponse),
+    )
+)]
+pub async fn revoke_invitation(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path((org_id, invitation_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    organizations::require_role(&state.db, org_id, user_id, &[OrgRole::Owner]).await?;
+    organizations::revoke_invitation(&state.db, org_id, invitation_id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgInvitationRevoked, AuditOutcome::Success)
+                .actor(user_id)
+                .details(serde_json::json!({ "org_id": org_id, "invitation_id": invitation_id })),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Joins the organisation an invitation is for. The caller must be signed in
+/// with the invited email address.
+#[utoipa::path(
+    post,
+    path = "/orgs/invitations/accept",
+    tag = "organizations",
+    request_body = InvitationTokenRequest,
+    responses(
+        (status = 200, description = "Joined the organisation", body = OrgMembership),
+        (status = 400, description = "Invalid or expired invitation", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn accept_invitation(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<InvitationTokenRequest>,
+) -> Result<Json<OrgMembership>, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user = state.users.get_by_id(&claims.sub).await?;
+    let invitation = organizations::accept_invitation(&state.db, &payload.token, &user).await?;
+    let membership = organizations::list_for_user(&state.db, user.id)
+        .await?
+        .into_iter()
+        .find(|membership| membership.org_id == invitation.org_id)
+        .ok_or(AppError::NotFound)?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgInvitationAccepted, AuditOutcome::Success)
+                .actor(user.id)
+                .target(user.id)
+                .details(serde_json::json!({
+                    "org_id": invitation.org_id,
+                    "invitation_id": invitation.id,
+                    "role": invitation.role,
+                })),
+        )
+        .await?;
+
+    webhooks::notify_org(
+        &state.db,
+        invitation.org_id,
+        WebhookEvent::OrgMemberJoined,
+        serde_json::json!({
+            "org_id": invitation.org_id,
+            "user_id": user.id,
+            "role": invitation.role,
+        }),
+    )
+    .await;
+
+    Ok(Json(membership))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orgs/invitations/decline",
+    tag = "organizations",
+    request_body = InvitationTokenRequest,
+    security(()),
+    responses(
+        (status = 204, description = "Invitation declined"),
+        (status = 400, description = "Invalid or expired invitation", body = ErrorResponse),
+    )
+)]
+pub async fn decline_invitation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<InvitationTokenRequest>,
+) -> Result<StatusCode, AppError> {
+    let invitation = organizations::decline_invitation(&state.db, &payload.token).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::OrgInvitationDeclined, AuditOutcome::Success)
+                .details(serde_json::json!({
+                    "org_id": invitation.org_id,
+                    "invitation_id": invitation.id,
+                })),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Re-issues the caller's tokens for another organisation, or for none, on
+/// the same session. An organisation switched to becomes the default for
+/// later sign-ins.
+#[utoipa::path(
+    post,
+    path = "/auth/switch-org",
+    tag = "organizations",
+    request_body = SwitchOrganizationRequest,
+    responses(
+        (status = 200, description = "Tokens acting for the organisation", body = LoginResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Not a member of the organisation", body = ErrorResponse),
+    )
+)]
+pub async fn switch_organization(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<SwitchOrganizationRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user = state.users.get_by_id(&claims.sub).await?;
+    let session = state.sessions.get(&claims.jti).await?;
+
+    let org = match payload.org_id {
+        Some(org_id) => {
+            let org = organizations::require_role(&state.db, org_id, user.id, &[]).await?;
+            organizations::mark_selected(&state.db, org_id, user.id).await?;
+            Some(org)
+        }
+        None => None,
+    };
+    let authn = claims.authentication();
+
+    let access_token = create_jwt_token(
+        &user,
+        &session,
+        org,
+        authn.as_ref(),
+        state.config.jwt_expiration,
+        &state.config.jwt_secret,
+    )?;
+
+    let generation = state
+        .sessions
+        .rotate_refresh_token(session.id, None)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    let refresh_token = create_refresh_token(
+        &user,
+        &session,
+        &generation,
+        org,
+        authn.as_ref(),
+        state.config.refresh_token_expiration,
+        &state.config.jwt_secret,
+    )?;
+
+    Ok(Json(LoginResponse {
+        access_token,
+        refresh_token,
+        expires_in: state.config.jwt_expiration,
+        user: user.into(),
+    }))
+}
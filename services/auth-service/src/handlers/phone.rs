@@ -0,0 +1,123 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use validator::Validate;
+
+use crate::{
+    errors::AppError,
+    middleware::{caller_id, forbid_impersonation},
+    models::{
+        AuditEventType, AuditOutcome, Claims, ConfirmPhoneCodeRequest, SendPhoneCodeRequest,
+        UserProfile,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        phone,
+        sms::SmsMessage,
+    },
+    AppState,
+};
+
+/// Texts a verification code to the given number, or to the number on the
+/// caller's profile.
+#[utoipa::path(
+    post,
+    path = "/users/me/phone/send-code",
+    tag = "users",
+    request_body = SendPhoneCodeRequest,
+    responses(
+        (status = 202, description = "Code sent by SMS"),
+        (status = 400, description = "Invalid or missing phone number", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 429, description = "Too many codes requested", body = ErrorResponse),
+    )
+)]
+pub async fn send_code(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(payload): Json<SendPhoneCodeRequest>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+    payload.validate()?;
+
+    let user_id = caller_id(&claims)?;
+    let user = state.users.get_by_id(&claims.sub).await?;
+
+    let number = payload
+        .phone
+        .or(user.phone)
+        .ok_or_else(|| AppError::BadRequest("No phone number to verify".to_string()))?;
+    let number = phone::normalize(&number)?;
+
+    if state.users.phone_taken(&number, user_id).await? {
+        return Err(AppError::Conflict(
+            "Phone number is already verified on another account".to_string(),
+        ));
+    }
+
+    let code = state
+        .phone_otp
+        .issue(user_id, &number, &state.config)
+        .await?;
+    state
+        .sms
+        .send(SmsMessage {
+            to: number,
+            body: format!(
+                "{} is your BookMarket verification code. It expires in {} minutes.",
+                code,
+                state.config.phone_otp_ttl / 60
+            ),
+        })
+        .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/me/phone/confirm",
+    tag = "users",
+    request_body = ConfirmPhoneCodeRequest,
+    responses(
+        (status = 200, description = "Phone number verified", body = UserProfile),
+        (status = 400, description = "Invalid or expired code", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn confirm_code(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<ConfirmPhoneCodeRequest>,
+) -> Result<Json<UserProfile>, AppError> {
+    forbid_impersonation(&claims)?;
+    payload.validate()?;
+
+    let user_id = caller_id(&claims)?;
+    let number = state
+        .phone_otp
+        .verify(user_id, &payload.code, &state.config)
+        .await?;
+
+    state.users.set_verified_phone(user_id, &number).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::PhoneVerified, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({ "phone": number })),
+        )
+        .await?;
+
+    let user = state.users.get_by_id(&claims.sub).await?;
+
+    Ok(Json(user.into()))
+}
@@ -0,0 +1,195 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+
+use crate::{
+    errors::AppError,
+    middleware::{caller_id, forbid_impersonation},
+    models::{
+        AccountDeletion, AccountDeletionRequest, AuditEventType, AuditOutcome, Claims,
+        PersonalDataExport,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        mailer, personal_data,
+    },
+    AppState,
+};
+
+/// Downloads the caller's personal data as a JSON document.
+#[utoipa::path(
+    get,
+    path = "/users/me/export",
+    tag = "users",
+    responses(
+        (status = 200, description = "Everything held about the caller, as a download", body = PersonalDataExport),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn export_data(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    let export = personal_data::export(&state.db, state.users.as_ref(), user_id).await?;
+    let body = serde_json::to_vec_pretty(&export)
+        .map_err(|e| AppError::InternalServerError(format!("Data export failed: {}", e)))?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::PersonalDataExported, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id),
+        )
+        .await?;
+
+    let filename = format!(
+        "attachment; filename=\"bookmarket-data-{}.json\"",
+        export.generated_at.format("%Y%m%dT%H%M%SZ")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::CONTENT_DISPOSITION, filename),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/me/deletion",
+    tag = "users",
+    responses(
+        (status = 200, description = "Pending or completed deletion request", body = AccountDeletion),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No deletion requested", body = ErrorResponse),
+    )
+)]
+pub async fn deletion_status(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<AccountDeletion>, AppError> {
+    let user_id = caller_id(&claims)?;
+    let deletion = personal_data::deletion_status(&state.db, user_id)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    Ok(Json(deletion))
+}
+
+/// Schedules the caller's account for deletion after the cooling-off period.
+/// Requires the password so an unattended session can't be used to do it.
+#[utoipa::path(
+    post,
+    path = "/users/me/deletion",
+    tag = "users",
+    request_body = AccountDeletionRequest,
+    responses(
+        (status = 202, description = "Deletion scheduled", body = AccountDeletion),
+        (status = 400, description = "Wrong password", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+    )
+)]
+pub async fn request_deletion(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<AccountDeletionRequest>,
+) -> Result<(StatusCode, Json<AccountDeletion>), AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    let client = ClientContext::from_headers(&headers);
+    let user = state.users.get_by_id(&user_id.to_string()).await?;
+
+    if !state
+        .password_hasher
+        .verify(&payload.password, &user.password_hash)
+        .await?
+    {
+        state
+            .audit
+            .record(
+                &client,
+                NewAuditEvent::new(
+                    AuditEventType::AccountDeletionRequested,
+                    AuditOutcome::Failure,
+                )
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({ "reason": "invalid_password" })),
+            )
+            .await?;
+        return Err(AppError::BadRequest("Password is incorrect".to_string()));
+    }
+
+    let deletion = personal_data::request_deletion(&state.db, user_id, &state.config).await?;
+    mailer::send_in_background(
+        state.mailer.clone(),
+        personal_data::deletion_scheduled_email(&user, &deletion, &state.config),
+    );
+
+    state
+        .audit
+        .record(
+            &client,
+            NewAuditEvent::new(
+                AuditEventType::AccountDeletionRequested,
+                AuditOutcome::Success,
+            )
+            .actor(user_id)
+            .target(user_id)
+            .details(serde_json::json!({ "scheduled_for": deletion.scheduled_for })),
+        )
+        .await?;
+
+    Ok((StatusCode::ACCEPTED, Json(deletion)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/me/deletion",
+    tag = "users",
+    responses(
+        (status = 204, description = "Deletion cancelled"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No pending deletion", body = ErrorResponse),
+    )
+)]
+pub async fn cancel_deletion(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let user_id = caller_id(&claims)?;
+    personal_data::cancel_deletion(&state.db, user_id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(
+                AuditEventType::AccountDeletionCancelled,
+                AuditOutcome::Success,
+            )
+            .actor(user_id)
+            .target(user_id),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
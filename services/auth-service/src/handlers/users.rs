@@ -1,92 +1,252 @@
 use axum::{
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     response::Json,
+    Extension,
 };
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::{
-    auth::{hash_password, verify_password},
     errors::AppError,
-    models::{ChangePasswordRequest, UpdateUserRequest, UserProfile},
-    services::user_service,
+    middleware::{caller_id, forbid_impersonation, require_recent_auth, require_self_or_admin},
+    models::{
+        AuditEventType, AuditOutcome, ChangeEmailRequest, ChangePasswordRequest, Claims,
+        UpdateUserRequest, UserProfile, UserRole,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        email_change, mailer,
+        password_policy::PasswordContext,
+        phone,
+    },
     AppState,
 };
 
+#[utoipa::path(
+    get,
+    path = "/users/{id}",
+    tag = "users",
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "User profile", body = UserProfile),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Not the caller's account, and the caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
 pub async fn get_user(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Path(user_id): Path<String>,
 ) -> Result<Json<UserProfile>, AppError> {
-    let user = user_service::get_user_by_id(&state.db, &user_id).await?;
+    require_self_or_admin(&claims, &user_id)?;
+
+    let user = state.users.get_by_id(&user_id).await?;
     Ok(Json(user.into()))
 }
 
+#[utoipa::path(
+    put,
+    path = "/users/{id}",
+    tag = "users",
+    request_body = UpdateUserRequest,
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "Updated profile", body = UserProfile),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Not the caller's account, and the caller is not an admin", body = ErrorResponse),
+        (status = 404, description = "No such user", body = ErrorResponse),
+    )
+)]
 pub async fn update_user(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
     Path(user_id): Path<String>,
     Json(payload): Json<UpdateUserRequest>,
 ) -> Result<Json<UserProfile>, AppError> {
+    let id = require_self_or_admin(&claims, &user_id)?;
     payload.validate()?;
 
-    let id = Uuid::parse_str(&user_id)
-        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
-
-    let user = sqlx::query_as!(
-        crate::models::User,
-        r#"
-        UPDATE auth.users 
-        SET first_name = COALESCE($2, first_name),
-            last_name = COALESCE($3, last_name),
-            phone = COALESCE($4, phone),
-            updated_at = CURRENT_TIMESTAMP
-        WHERE id = $1
-        RETURNING id, email, password_hash, first_name, last_name, phone,
-                  role as "role: crate::models::UserRole", 
-                  status as "status: crate::models::UserStatus",
-                  email_verified, phone_verified, last_login, created_at, updated_at
-        "#,
-        id,
-        payload.first_name,
-        payload.last_name,
-        payload.phone
-    )
-    .fetch_one(&state.db)
-    .await?;
+    let phone = payload.phone.as_deref().map(phone::normalize).transpose()?;
+
+    let user = state
+        .users
+        .update_profile(
+            id,
+            payload.first_name.as_deref(),
+            payload.last_name.as_deref(),
+            phone.as_deref(),
+        )
+        .await?;
 
     Ok(Json(user.into()))
 }
 
+/// Changes the caller's password, given the current one. Admins can also set
+/// a new password for another non-admin account without it, which is
+/// recorded as a reset.
+#[utoipa::path(
+    put,
+    path = "/users/{id}/password",
+    tag = "users",
+    request_body = ChangePasswordRequest,
+    params(("id" = String, Path, description = "User ID")),
+    responses(
+        (status = 204, description = "Password changed"),
+        (status = 400, description = "Wrong current password or weak new password", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token, or re-authentication required", body = ErrorResponse),
+        (status = 403, description = "Not the caller's account, and the caller is not an admin or the account is another admin's", body = ErrorResponse),
+    )
+)]
 pub async fn change_password(
     State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
     Path(user_id): Path<String>,
     Json(payload): Json<ChangePasswordRequest>,
 ) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let actor_id = caller_id(&claims)?;
+    let admin_reset = require_self_or_admin(&claims, &user_id)? != actor_id;
+
+    require_recent_auth(&claims, state.config.reauthentication_max_age)?;
     payload.validate()?;
 
-    let user = user_service::get_user_by_id(&state.db, &user_id).await?;
+    let client = ClientContext::from_headers(&headers);
+    let user = state.users.get_by_id(&user_id).await?;
 
-    // Verify current password
-    if !verify_password(&payload.current_password, &user.password_hash)? {
-        return Err(AppError::BadRequest("Current password is incorrect".to_string()));
+    if admin_reset {
+        // As with impersonation, admins can't take over each other's accounts
+        if user.role == UserRole::Admin {
+            return Err(AppError::Forbidden);
+        }
+    } else if !state
+        .password_hasher
+        .verify(
+            payload.current_password.as_deref().unwrap_or_default(),
+            &user.password_hash,
+        )
+        .await?
+    {
+        state
+            .audit
+            .record(
+                &client,
+                NewAuditEvent::new(AuditEventType::PasswordChanged, AuditOutcome::Failure)
+                    .actor(actor_id)
+                    .target(user.id)
+                    .details(serde_json::json!({ "reason": "invalid_current_password" })),
+            )
+            .await?;
+        return Err(AppError::BadRequest(
+            "Current password is incorrect".to_string(),
+        ));
     }
 
+    state
+        .password_policy
+        .enforce(&payload.new_password, PasswordContext::for_user(&user))
+        .await?;
+
     // Hash new password
-    let new_password_hash = hash_password(&payload.new_password, state.config.bcrypt_cost)?;
+    let new_password_hash = state.password_hasher.hash(&payload.new_password).await?;
 
     // Update password
-    sqlx::query!(
-        "UPDATE auth.users SET password_hash = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2",
-        new_password_hash,
-        user.id
-    )
-    .execute(&state.db)
-    .await?;
+    state
+        .users
+        .update_password_hash(user.id, &new_password_hash)
+        .await?;
 
     // Invalidate all sessions for this user (force re-login)
-    sqlx::query!("DELETE FROM auth.sessions WHERE user_id = $1", user.id)
-        .execute(&state.db)
+    let revoked = state.sessions.delete_for_user(user.id).await?;
+
+    let event_type = if admin_reset {
+        AuditEventType::PasswordReset
+    } else {
+        AuditEventType::PasswordChanged
+    };
+    state
+        .audit
+        .record(
+            &client,
+            NewAuditEvent::new(event_type, AuditOutcome::Success)
+                .actor(actor_id)
+                .target(user.id)
+                .details(serde_json::json!({ "sessions_revoked": revoked })),
+        )
         .await?;
 
     Ok(StatusCode::NO_CONTENT)
 }
+
+/// Starts changing the caller's email address. The change only takes effect
+/// once confirmed from the new address; the old address is told and can
+/// cancel it. Requires a recent sign-in rather than the password, which
+/// accounts using other sign-in methods don't have.
+#[utoipa::path(
+    post,
+    path = "/users/me/email",
+    tag = "users",
+    request_body = ChangeEmailRequest,
+    responses(
+        (status = 202, description = "Confirmation sent to the new address"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token, or re-authentication required", body = ErrorResponse),
+        (status = 409, description = "Address already in use", body = ErrorResponse),
+    )
+)]
+pub async fn request_email_change(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<ChangeEmailRequest>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+    require_recent_auth(&claims, state.config.reauthentication_max_age)?;
+    payload.validate()?;
+
+    let user_id = caller_id(&claims)?;
+    let session_id = Uuid::parse_str(&claims.jti).map_err(|_| AppError::Unauthorized)?;
+    let client = ClientContext::from_headers(&headers);
+    let user = state.users.get_by_id(&user_id.to_string()).await?;
+
+    let new_email = payload.new_email.trim();
+    let change = email_change::request_change(
+        &state.db,
+        state.users.as_ref(),
+        &user,
+        new_email,
+        session_id,
+        &state.config,
+    )
+    .await?;
+
+    mailer::send_in_background(
+        state.mailer.clone(),
+        email_change::confirmation_email(&user, new_email, &change.confirm_token, &state.config),
+    );
+    mailer::send_in_background(
+        state.mailer.clone(),
+        email_change::change_notice_email(&user, new_email, &change.cancel_token, &state.config),
+    );
+
+    state
+        .audit
+        .record(
+            &client,
+            NewAuditEvent::new(AuditEventType::EmailChangeRequested, AuditOutcome::Success)
+                .actor(user_id)
+                .target(user_id)
+                .details(serde_json::json!({
+                    "request_id": change.id,
+                    "new_email": new_email,
+                })),
+        )
+        .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
@@ -0,0 +1,261 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    errors::AppError,
+    middleware::{caller_id, forbid_impersonation, require_recent_auth},
+    models::{
+        AuditEventType, AuditOutcome, Claims, CreateWebhookRequest, CreateWebhookResponse, OrgRole,
+        WebhookDelivery, WebhookDeliveryStatus, WebhookSubscription,
+    },
+    openapi::ErrorResponse,
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        organizations,
+        webhooks::{self, WebhookEvent},
+    },
+    AppState,
+};
+
+const DEFAULT_DELIVERIES_LIMIT: u32 = 50;
+const MAX_DELIVERIES_LIMIT: u32 = 200;
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct WebhooksQuery {
+    /// Lists the organisation's subscriptions instead of the caller's.
+    org_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DeliveriesQuery {
+    status: Option<WebhookDeliveryStatus>,
+    limit: Option<u32>,
+}
+
+/// Loads a subscription the caller may manage: their own, or one of an
+/// organisation they own. Anything else is reported as missing.
+async fn authorize(
+    state: &AppState,
+    user_id: Uuid,
+    subscription_id: Uuid,
+) -> Result<WebhookSubscription, AppError> {
+    let subscription = webhooks::get_subscription(&state.db, subscription_id).await?;
+
+    let allowed = match subscription.org_id {
+        Some(org_id) => organizations::require_role(&state.db, org_id, user_id, &[OrgRole::Owner])
+            .await
+            .is_ok(),
+        None => subscription.user_id == Some(user_id),
+    };
+    if !allowed {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(subscription)
+}
+
+/// Subscribes an HTTPS endpoint to account events for the caller or, for its
+/// owners, an organisation. Events: `api_key.created`, `api_key.revoked`,
+/// `org.member_joined`, `org.member_role_changed` and `org.member_removed`.
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    tag = "webhooks",
+    request_body = CreateWebhookRequest,
+    responses(
+        (status = 201, description = "Subscription created; the signing secret is only shown here", body = CreateWebhookResponse),
+        (status = 400, description = "Invalid input, or a URL that isn't HTTPS or doesn't resolve to a public address", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token, or re-authentication required", body = ErrorResponse),
+        (status = 403, description = "Caller does not own the organisation", body = ErrorResponse),
+    )
+)]
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreateWebhookResponse>), AppError> {
+    forbid_impersonation(&claims)?;
+    require_recent_auth(&claims, state.config.reauthentication_max_age)?;
+    payload.validate()?;
+    webhooks::validate_url(&payload.url).await?;
+
+    let events = payload
+        .events
+        .iter()
+        .map(|event| {
+            WebhookEvent::parse(event)
+                .ok_or_else(|| AppError::Validation(format!("Unknown event type: {}", event)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let id = caller_id(&claims)?;
+    if let Some(org_id) = payload.org_id {
+        organizations::require_role(&state.db, org_id, id, &[OrgRole::Owner]).await?;
+    }
+    let owner = if payload.org_id.is_some() {
+        None
+    } else {
+        Some(id)
+    };
+
+    let subscription =
+        webhooks::create_subscription(&state.db, owner, payload.org_id, &payload.url, &events, id)
+            .await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::WebhookCreated, AuditOutcome::Success)
+                .actor(id)
+                .target(id)
+                .details(serde_json::json!({
+                    "webhook_id": subscription.id,
+                    "org_id": subscription.org_id,
+                    "url": subscription.url,
+                    "events": subscription.events,
+                })),
+        )
+        .await?;
+
+    let secret = subscription.secret.clone();
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateWebhookResponse {
+            subscription,
+            secret,
+        }),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    tag = "webhooks",
+    params(WebhooksQuery),
+    responses(
+        (status = 200, description = "Subscriptions", body = Vec<WebhookSubscription>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 403, description = "Caller does not own the organisation", body = ErrorResponse),
+    )
+)]
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Query(params): Query<WebhooksQuery>,
+) -> Result<Json<Vec<WebhookSubscription>>, AppError> {
+    let id = caller_id(&claims)?;
+    if let Some(org_id) = params.org_id {
+        organizations::require_role(&state.db, org_id, id, &[OrgRole::Owner]).await?;
+    }
+
+    let subscriptions = webhooks::list_subscriptions(&state.db, id, params.org_id).await?;
+
+    Ok(Json(subscriptions))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription ID")),
+    responses(
+        (status = 204, description = "Subscription and its deliveries deleted"),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No such subscription", body = ErrorResponse),
+    )
+)]
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    headers: HeaderMap,
+    Path(subscription_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    forbid_impersonation(&claims)?;
+
+    let id = caller_id(&claims)?;
+    let subscription = authorize(&state, id, subscription_id).await?;
+    webhooks::delete_subscription(&state.db, subscription.id).await?;
+
+    state
+        .audit
+        .record(
+            &ClientContext::from_headers(&headers),
+            NewAuditEvent::new(AuditEventType::WebhookDeleted, AuditOutcome::Success)
+                .actor(id)
+                .target(id)
+                .details(serde_json::json!({
+                    "webhook_id": subscription.id,
+                    "org_id": subscription.org_id,
+                    "url": subscription.url,
+                })),
+        )
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Recent delivery attempts, newest first, with the receiver's last answer.
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription ID"), DeliveriesQuery),
+    responses(
+        (status = 200, description = "Deliveries", body = Vec<WebhookDelivery>),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No such subscription", body = ErrorResponse),
+    )
+)]
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(subscription_id): Path<Uuid>,
+    Query(params): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, AppError> {
+    let subscription = authorize(&state, caller_id(&claims)?, subscription_id).await?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .clamp(1, MAX_DELIVERIES_LIMIT);
+
+    let deliveries =
+        webhooks::list_deliveries(&state.db, subscription.id, params.status, limit as i64).await?;
+
+    Ok(Json(deliveries))
+}
+
+/// Sends a delivery again on the next delivery run, with a fresh set of
+/// retries. Receivers see the same event ID as before.
+#[utoipa::path(
+    post,
+    path = "/webhooks/{id}/deliveries/{delivery_id}/redeliver",
+    tag = "webhooks",
+    params(("id" = Uuid, Path, description = "Subscription ID"), ("delivery_id" = Uuid, Path, description = "Delivery ID")),
+    responses(
+        (status = 202, description = "Delivery queued", body = WebhookDelivery),
+        (status = 401, description = "Missing or invalid access token", body = ErrorResponse),
+        (status = 404, description = "No such subscription or delivery", body = ErrorResponse),
+    )
+)]
+pub async fn redeliver(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path((subscription_id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Json<WebhookDelivery>), AppError> {
+    forbid_impersonation(&claims)?;
+
+    let subscription = authorize(&state, caller_id(&claims)?, subscription_id).await?;
+    let delivery = webhooks::redeliver(&state.db, subscription.id, delivery_id).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
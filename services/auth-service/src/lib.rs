@@ -0,0 +1,302 @@
+use axum::{
+    extract::State,
+    response::Json,
+    routing::{delete, get, post, put},
+    Router,
+};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
+
+pub mod auth;
+pub mod config;
+pub mod database;
+pub mod errors;
+pub mod handlers;
+pub mod middleware;
+pub mod models;
+pub mod openapi;
+pub mod repositories;
+pub mod services;
+pub mod telemetry;
+
+use config::Config;
+use errors::AppError;
+use openapi::ErrorResponse;
+use repositories::{
+    ApiKeyRepository, AuditRepository, SessionRepository, TotpRepository, UserRepository,
+};
+use services::{
+    mailer::EmailSender, oidc::OidcClient, password_hasher::PasswordHasher,
+    password_policy::PasswordPolicy, phone::PhoneOtpStore, rate_limit::RateLimiter, sms::SmsSender,
+};
+
+/// Shared handler state. Accounts, sessions, API keys, authenticator apps
+/// and the audit trail go through the repository traits, so the sign-up,
+/// sign-in and profile routes run on either backend.
+#[derive(Clone)]
+pub struct AppState {
+    /// Used directly by organisations, webhooks, linked identities, the
+    /// outbox and the background jobs, which have no in-memory backend.
+    pub db: PgPool,
+    pub redis: redis::Client,
+    pub users: Arc<dyn UserRepository>,
+    pub sessions: Arc<dyn SessionRepository>,
+    pub api_keys: Arc<dyn ApiKeyRepository>,
+    pub totp: Arc<dyn TotpRepository>,
+    pub audit: Arc<dyn AuditRepository>,
+    pub mailer: Arc<dyn EmailSender>,
+    pub sms: Arc<dyn SmsSender>,
+    pub phone_otp: PhoneOtpStore,
+    pub rate_limiter: RateLimiter,
+    pub oidc: OidcClient,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub password_hasher: PasswordHasher,
+    pub config: Arc<Config>,
+}
+
+pub fn create_app(state: AppState) -> Router {
+    Router::new()
+        // Health check
+        .route("/health", get(health_check))
+        .route("/ready", get(readiness_check))
+        // Authentication routes
+        .route("/auth/register", post(handlers::auth::register))
+        .route("/auth/login", post(handlers::auth::login))
+        .route("/auth/refresh", post(handlers::auth::refresh_token))
+        .route("/auth/logout", post(handlers::auth::logout))
+        .route(
+            "/auth/impersonation/stop",
+            post(handlers::auth::stop_impersonation),
+        )
+        .route("/auth/verify", get(handlers::auth::verify_token))
+        .route(
+            "/auth/login-alerts/deny",
+            get(handlers::auth::confirm_deny_login).post(handlers::auth::deny_login),
+        )
+        .route("/auth/password/check", post(handlers::auth::check_password))
+        .route(
+            "/auth/password/forgot",
+            post(handlers::auth::forgot_password),
+        )
+        .route("/auth/password/reset", post(handlers::auth::reset_password))
+        .route("/auth/email/verify", post(handlers::auth::verify_email))
+        .route(
+            "/auth/email/change/confirm",
+            post(handlers::auth::confirm_email_change),
+        )
+        .route(
+            "/auth/email/change/cancel",
+            post(handlers::auth::cancel_email_change),
+        )
+        .route("/auth/magic-link", post(handlers::auth::request_magic_link))
+        .route(
+            "/auth/magic-link/redeem",
+            post(handlers::auth::redeem_magic_link),
+        )
+        .route("/auth/oidc/:provider/login", get(handlers::oidc::login))
+        .route(
+            "/auth/oidc/:provider/callback",
+            get(handlers::oidc::callback),
+        )
+        // Token introspection and revocation for backend services
+        .route("/oauth/introspect", post(handlers::oauth::introspect))
+        .route("/oauth/revoke", post(handlers::oauth::revoke))
+        // Step-up authentication and MFA
+        .route("/auth/reauthenticate", post(handlers::auth::reauthenticate))
+        .route("/auth/mfa/challenge", post(handlers::mfa::challenge_mfa))
+        .route("/auth/mfa/setup", post(handlers::mfa::setup_mfa))
+        .route("/auth/mfa/verify", post(handlers::mfa::verify_mfa))
+        .route("/auth/mfa/disable", post(handlers::mfa::disable_mfa))
+        // User management
+        .route("/users/:id", get(handlers::users::get_user))
+        .route("/users/:id", put(handlers::users::update_user))
+        .route("/users/:id/password", put(handlers::users::change_password))
+        .route(
+            "/users/me/phone/send-code",
+            post(handlers::phone::send_code),
+        )
+        .route(
+            "/users/me/phone/confirm",
+            post(handlers::phone::confirm_code),
+        )
+        .route("/users/me/identities", get(handlers::oidc::list_identities))
+        .route(
+            "/users/me/identities/:provider",
+            post(handlers::oidc::start_link).delete(handlers::oidc::unlink_identity),
+        )
+        .route(
+            "/users/me/email",
+            post(handlers::users::request_email_change),
+        )
+        .route("/users/me/export", get(handlers::privacy::export_data))
+        .route(
+            "/users/me/deletion",
+            get(handlers::privacy::deletion_status)
+                .post(handlers::privacy::request_deletion)
+                .delete(handlers::privacy::cancel_deletion),
+        )
+        // Vendor organisations
+        .route(
+            "/auth/switch-org",
+            post(handlers::organizations::switch_organization),
+        )
+        .route(
+            "/orgs",
+            get(handlers::organizations::list_organizations)
+                .post(handlers::organizations::create_organization),
+        )
+        .route(
+            "/orgs/invitations/accept",
+            post(handlers::organizations::accept_invitation),
+        )
+        .route(
+            "/orgs/invitations/decline",
+            post(handlers::organizations::decline_invitation),
+        )
+        .route("/orgs/:id", get(handlers::organizations::get_organization))
+        .route(
+            "/orgs/:id/members",
+            get(handlers::organizations::list_members),
+        )
+        .route(
+            "/orgs/:id/members/:user_id",
+            put(handlers::organizations::update_member)
+                .delete(handlers::organizations::remove_member),
+        )
+        .route(
+            "/orgs/:id/invitations",
+            get(handlers::organizations::list_invitations)
+                .post(handlers::organizations::invite_member),
+        )
+        .route(
+            "/orgs/:id/invitations/:invitation_id",
+            delete(handlers::organizations::revoke_invitation),
+        )
+        // API key management
+        .route("/api-keys", get(handlers::api_keys::list_keys))
+        .route("/api-keys", post(handlers::api_keys::create_key))
+        .route("/api-keys/:id", get(handlers::api_keys::get_key))
+        .route("/api-keys/:id", put(handlers::api_keys::update_key))
+        .route("/api-keys/:id/revoke", post(handlers::api_keys::revoke_key))
+        // Outbound webhooks
+        .route("/webhooks", get(handlers::webhooks::list_webhooks))
+        .route("/webhooks", post(handlers::webhooks::create_webhook))
+        .route("/webhooks/:id", delete(handlers::webhooks::delete_webhook))
+        .route(
+            "/webhooks/:id/deliveries",
+            get(handlers::webhooks::list_deliveries),
+        )
+        .route(
+            "/webhooks/:id/deliveries/:delivery_id/redeliver",
+            post(handlers::webhooks::redeliver),
+        )
+        // Admin routes
+        .route("/admin/users", get(handlers::admin::list_users))
+        .route("/admin/users/export", get(handlers::admin::export_users))
+        .route(
+            "/admin/users/:id/suspend",
+            post(handlers::admin::suspend_user),
+        )
+        .route(
+            "/admin/users/:id/activate",
+            post(handlers::admin::activate_user),
+        )
+        .route("/admin/users/:id/role", put(handlers::admin::change_role))
+        .route(
+            "/admin/users/:id/impersonate",
+            post(handlers::admin::impersonate_user),
+        )
+        .route("/admin/audit-events", get(handlers::audit::list_events))
+        .route(
+            "/admin/audit-events/export",
+            get(handlers::audit::export_events),
+        )
+        // Metrics
+        .route("/metrics", get(handlers::metrics::metrics))
+        // API documentation
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/docs", get(openapi::swagger_ui))
+        // Route layers see the matched route; metrics wraps auth so rejected
+        // requests are counted too
+        .route_layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::auth_middleware,
+        ))
+        .route_layer(axum::middleware::from_fn(middleware::track_metrics))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::client_address,
+        ))
+        .layer(CorsLayer::permissive())
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::request_span))
+        .with_state(state)
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Service is running", body = serde_json::Value),
+    )
+)]
+async fn health_check() -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(serde_json::json!({
+        "status": "healthy",
+        "service": "auth-service",
+        "version": env!("CARGO_PKG_VERSION"),
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Database and Redis status", body = serde_json::Value),
+        (status = 500, description = "Redis unreachable", body = ErrorResponse),
+    )
+)]
+async fn readiness_check(
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    // Check database connectivity
+    let db_status = match sqlx::query("SELECT 1").fetch_one(&state.db).await {
+        Ok(_) => "healthy",
+        Err(_) => "unhealthy",
+    };
+
+    // Check Redis connectivity
+    let mut redis_conn = state
+        .redis
+        .get_async_connection()
+        .await
+        .map_err(|_| AppError::InternalServerError("Failed to connect to Redis".to_string()))?;
+
+    let redis_status = match redis::cmd("PING")
+        .query_async::<_, String>(&mut redis_conn)
+        .await
+    {
+        Ok(_) => "healthy",
+        Err(_) => "unhealthy",
+    };
+
+    let overall_status = if db_status == "healthy" && redis_status == "healthy" {
+        "ready"
+    } else {
+        "not_ready"
+    };
+
+    Ok(Json(serde_json::json!({
+        "status": overall_status,
+        "checks": {
+            "database": db_status,
+            "redis": redis_status
+        },
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    })))
+}
@@ -1,11 +1,49 @@
 use axum::{
-    extract::{Request, State},
-    http::{header, StatusCode},
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{header, HeaderValue},
     middleware::Next,
     response::Response,
 };
 
-use crate::{auth::decode_jwt_token, errors::AppError, AppState};
+use chrono::Utc;
+use std::net::SocketAddr;
+use std::time::Instant;
+use uuid::Uuid;
+
+use crate::{
+    auth::decode_jwt_token,
+    errors::AppError,
+    handlers::metrics,
+    models::{Claims, TokenUse, UserRole},
+    services::audit,
+    AppState,
+};
+
+/// Replaces the client's forwarding headers with a single `x-real-ip`
+/// holding the address resolved by `audit::client_ip`, so handlers can't be
+/// handed an address the client made up.
+pub async fn client_address(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let client = audit::client_ip(peer, request.headers(), &state.config.trusted_proxies);
+
+    let headers = request.headers_mut();
+    headers.remove("x-forwarded-for");
+    headers.remove("x-real-ip");
+    if let Some(ip) = client {
+        if let Ok(value) = HeaderValue::from_str(&ip.to_string()) {
+            headers.insert("x-real-ip", value);
+        }
+    }
+
+    next.run(request).await
+}
 
 pub async fn auth_middleware(
     State(state): State<AppState>,
@@ -14,12 +52,25 @@ pub async fn auth_middleware(
 ) -> Result<Response, AppError> {
     // Skip auth for health checks and public endpoints
     let path = request.uri().path();
-    if path == "/health" 
-        || path == "/ready" 
+    if path == "/health"
+        || path == "/ready"
         || path == "/metrics"
+        || path == "/openapi.json"
+        || path == "/docs"
         || path.starts_with("/auth/register")
         || path.starts_with("/auth/login")
-        || path.starts_with("/auth/verify") {
+        || path == "/auth/refresh"
+        || path.starts_with("/auth/verify")
+        || path.starts_with("/auth/login-alerts/")
+        || path.starts_with("/auth/password/")
+        || path.starts_with("/auth/email/verify")
+        || path.starts_with("/auth/email/change/")
+        || path.starts_with("/auth/oidc/")
+        || path.starts_with("/auth/magic-link")
+        || path == "/auth/mfa/challenge"
+        || path.starts_with("/oauth/")
+        || path == "/orgs/invitations/decline"
+    {
         return Ok(next.run(request).await);
     }
 
@@ -36,14 +87,21 @@ pub async fn auth_middleware(
     };
 
     // Decode and validate JWT token
-    let claims = decode_jwt_token(token, &state.config.jwt_secret)?;
+    let claims = decode_jwt_token(token, &state.config.jwt_secrets())?;
 
-    // Verify session is still valid
-    let session = crate::services::user_service::get_session_by_id(&state.db, &claims.jti)
+    // Refresh tokens are only good for /auth/refresh, which reads them from the body
+    if claims.token_use == Some(TokenUse::Refresh) {
+        return Err(AppError::Unauthorized);
+    }
+
+    // Verify session is still valid (served from Redis when cached)
+    let session = state
+        .sessions
+        .get_active(&claims.jti)
         .await
         .map_err(|_| AppError::Unauthorized)?;
 
-    if session.expires_at < chrono::Utc::now() {
+    if session.user_id.to_string() != claims.sub {
         return Err(AppError::Unauthorized);
     }
 
@@ -52,3 +110,72 @@ pub async fn auth_middleware(
 
     Ok(next.run(request).await)
 }
+
+/// Records the count and latency of each request, labelled with the route
+/// template rather than the raw path so ids don't multiply the series.
+pub async fn track_metrics(request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = request.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
+
+    metrics::record_http_request(
+        &route,
+        &method,
+        response.status().as_u16(),
+        started.elapsed().as_secs_f64(),
+    );
+
+    response
+}
+
+/// Returns the authenticated caller's user ID.
+pub fn caller_id(claims: &Claims) -> Result<Uuid, AppError> {
+    Uuid::parse_str(&claims.sub).map_err(|_| AppError::Unauthorized)
+}
+
+/// Rejects callers whose token does not carry the admin role.
+pub fn require_admin(claims: &Claims) -> Result<(), AppError> {
+    if claims.role != UserRole::Admin {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(())
+}
+
+/// Returns the ID of the account a `/users/{id}` route names, rejecting
+/// callers who are neither its owner nor an admin.
+pub fn require_self_or_admin(claims: &Claims, user_id: &str) -> Result<Uuid, AppError> {
+    let target_id = Uuid::parse_str(user_id)
+        .map_err(|_| AppError::BadRequest("Invalid user ID".to_string()))?;
+    if target_id != caller_id(claims)? && claims.role != UserRole::Admin {
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(target_id)
+}
+
+/// Rejects tokens whose user authenticated longer than `max_age` seconds ago,
+/// so a sensitive operation can't ride on a long-lived session. Clients
+/// recover by calling `/auth/reauthenticate` and retrying.
+pub fn require_recent_auth(claims: &Claims, max_age: i64) -> Result<(), AppError> {
+    match claims.auth_time {
+        Some(auth_time) if Utc::now().timestamp() - auth_time <= max_age => Ok(()),
+        _ => Err(AppError::ReauthenticationRequired),
+    }
+}
+
+/// Rejects tokens issued through admin impersonation. Applied to operations
+/// that change a user's credentials.
+pub fn forbid_impersonation(claims: &Claims) -> Result<(), AppError> {
+    if claims.act.is_some() {
+        return Err(AppError::ImpersonationForbidden);
+    }
+
+    Ok(())
+}
@@ -1,10 +1,11 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::Validate;
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
@@ -17,12 +18,13 @@ pub struct User {
     pub status: UserStatus,
     pub email_verified: bool,
     pub phone_verified: bool,
+    pub password_reset_required: bool,
     pub last_login: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
 #[sqlx(type_name = "varchar", rename_all = "lowercase")]
 pub enum UserRole {
     Customer,
@@ -30,15 +32,123 @@ pub enum UserRole {
     Admin,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Customer => "customer",
+            UserRole::Vendor => "vendor",
+            UserRole::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
 #[sqlx(type_name = "varchar", rename_all = "lowercase")]
 pub enum UserStatus {
     Active,
     Inactive,
     Suspended,
+    /// Anonymised after an account deletion request; kept so records that
+    /// reference the user stay intact.
+    Deleted,
+}
+
+/// A vendor's shop, shared by the staff who run it.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Owner,
+    CatalogManager,
+    Fulfilment,
+    Accountant,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Owner => "owner",
+            OrgRole::CatalogManager => "catalog_manager",
+            OrgRole::Fulfilment => "fulfilment",
+            OrgRole::Accountant => "accountant",
+        }
+    }
+}
+
+/// The organisation a token acts for, carried in `Claims`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrgContext {
+    pub org_id: Uuid,
+    pub role: OrgRole,
+}
+
+/// How the user proved who they are, per RFC 8176 where it has a value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthMethod {
+    Pwd,
+    Otp,
+    Email, // Magic sign-in link
+    Fed,   // External identity provider
+}
+
+/// When and how the user last authenticated, carried in `Claims` as
+/// `auth_time` and `amr`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authentication {
+    pub auth_time: i64,
+    pub amr: Vec<AuthMethod>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+impl Authentication {
+    pub fn now(amr: Vec<AuthMethod>) -> Self {
+        Self {
+            auth_time: Utc::now().timestamp(),
+            amr,
+        }
+    }
+}
+
+/// One of the caller's organisations, as listed for switching.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct OrgMembership {
+    pub org_id: Uuid,
+    pub org_name: String,
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct OrgMember {
+    pub user_id: Uuid,
+    pub email: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct OrgInvitation {
+    pub id: Uuid,
+    pub org_id: Uuid,
+    pub email: String,
+    pub role: OrgRole,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct Session {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -47,10 +157,11 @@ pub struct Session {
     pub expires_at: DateTime<Utc>,
     pub user_agent: Option<String>,
     pub ip_address: Option<String>,
+    pub impersonator_id: Option<Uuid>,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
 pub struct ApiKey {
     pub id: Uuid,
     pub user_id: Uuid,
@@ -63,23 +174,199 @@ pub struct ApiKey {
     pub created_at: DateTime<Utc>,
 }
 
+/// An account at an external OpenID Connect provider linked to a user.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub provider: String,
+    #[serde(skip_serializing)]
+    pub subject: String,
+    pub email: Option<String>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventType {
+    Login,
+    Logout,
+    PasswordChanged,
+    UserSuspended,
+    UserActivated,
+    UserRoleChanged,
+    ApiKeyCreated,
+    ApiKeyUpdated,
+    ApiKeyRevoked,
+    ApiKeyDisabled,
+    LoginAlertDenied,
+    PasswordResetRequested,
+    PasswordReset,
+    ImpersonationStarted,
+    ImpersonationStopped,
+    IdentityLinked,
+    IdentityUnlinked,
+    MagicLinkRequested,
+    PhoneVerified,
+    TokenRevoked,
+    EmailVerified,
+    PersonalDataExported,
+    AccountDeletionRequested,
+    AccountDeletionCancelled,
+    AccountDeleted,
+    OrgCreated,
+    OrgInvitationSent,
+    OrgInvitationRevoked,
+    OrgInvitationAccepted,
+    OrgInvitationDeclined,
+    OrgMemberRoleChanged,
+    OrgMemberRemoved,
+    EmailChangeRequested,
+    EmailChanged,
+    EmailChangeCancelled,
+    Reauthenticated,
+    MfaEnabled,
+    MfaDisabled,
+    WebhookCreated,
+    WebhookDeleted,
+    AdminCreated,
+    SessionsRevoked,
+    RefreshTokenReused,
+}
+
+impl AuditEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEventType::Login => "login",
+            AuditEventType::Logout => "logout",
+            AuditEventType::PasswordChanged => "password_changed",
+            AuditEventType::UserSuspended => "user_suspended",
+            AuditEventType::UserActivated => "user_activated",
+            AuditEventType::UserRoleChanged => "user_role_changed",
+            AuditEventType::ApiKeyCreated => "api_key_created",
+            AuditEventType::ApiKeyUpdated => "api_key_updated",
+            AuditEventType::ApiKeyRevoked => "api_key_revoked",
+            AuditEventType::ApiKeyDisabled => "api_key_disabled",
+            AuditEventType::LoginAlertDenied => "login_alert_denied",
+            AuditEventType::PasswordResetRequested => "password_reset_requested",
+            AuditEventType::PasswordReset => "password_reset",
+            AuditEventType::ImpersonationStarted => "impersonation_started",
+            AuditEventType::ImpersonationStopped => "impersonation_stopped",
+            AuditEventType::IdentityLinked => "identity_linked",
+            AuditEventType::IdentityUnlinked => "identity_unlinked",
+            AuditEventType::MagicLinkRequested => "magic_link_requested",
+            AuditEventType::PhoneVerified => "phone_verified",
+            AuditEventType::TokenRevoked => "token_revoked",
+            AuditEventType::EmailVerified => "email_verified",
+            AuditEventType::PersonalDataExported => "personal_data_exported",
+            AuditEventType::AccountDeletionRequested => "account_deletion_requested",
+            AuditEventType::AccountDeletionCancelled => "account_deletion_cancelled",
+            AuditEventType::AccountDeleted => "account_deleted",
+            AuditEventType::OrgCreated => "org_created",
+            AuditEventType::OrgInvitationSent => "org_invitation_sent",
+            AuditEventType::OrgInvitationRevoked => "org_invitation_revoked",
+            AuditEventType::OrgInvitationAccepted => "org_invitation_accepted",
+            AuditEventType::OrgInvitationDeclined => "org_invitation_declined",
+            AuditEventType::OrgMemberRoleChanged => "org_member_role_changed",
+            AuditEventType::OrgMemberRemoved => "org_member_removed",
+            AuditEventType::EmailChangeRequested => "email_change_requested",
+            AuditEventType::EmailChanged => "email_changed",
+            AuditEventType::EmailChangeCancelled => "email_change_cancelled",
+            AuditEventType::Reauthenticated => "reauthenticated",
+            AuditEventType::MfaEnabled => "mfa_enabled",
+            AuditEventType::MfaDisabled => "mfa_disabled",
+            AuditEventType::WebhookCreated => "webhook_created",
+            AuditEventType::WebhookDeleted => "webhook_deleted",
+            AuditEventType::AdminCreated => "admin_created",
+            AuditEventType::SessionsRevoked => "sessions_revoked",
+            AuditEventType::RefreshTokenReused => "refresh_token_reused",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Success => "success",
+            AuditOutcome::Failure => "failure",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub event_type: AuditEventType,
+    pub outcome: AuditOutcome,
+    pub actor_id: Option<Uuid>,
+    pub target_id: Option<Uuid>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A pending or completed request to delete an account.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct AccountDeletion {
+    pub requested_at: DateTime<Utc>,
+    pub scheduled_for: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// A device a user has signed in from, remembered for new-device login
+/// alerts. Empty strings stand for a value the sign-in didn't reveal.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct KnownDevice {
+    pub user_agent: String,
+    pub network: String,
+    pub first_seen: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Everything auth-service holds about one user, as handed out on a data
+/// subject access request.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PersonalDataExport {
+    pub generated_at: DateTime<Utc>,
+    pub profile: User,
+    pub sessions: Vec<Session>,
+    pub known_devices: Vec<KnownDevice>,
+    pub api_keys: Vec<ApiKey>,
+    pub identities: Vec<UserIdentity>,
+    pub audit_events: Vec<AuditEvent>,
+    pub account_deletion: Option<AccountDeletion>,
+}
+
 // Request/Response DTOs
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct RegisterRequest {
     #[validate(email)]
     pub email: String,
-    #[validate(length(min = 8, max = 128))]
+    #[validate(length(max = 128))] // the password policy checks the rest
     pub password: String,
     #[validate(length(min = 1, max = 100))]
     pub first_name: String,
     #[validate(length(min = 1, max = 100))]
     pub last_name: String,
-    #[validate(phone)]
+    #[validate(custom(function = "crate::services::phone::validate_phone"))]
     pub phone: Option<String>,
+    /// Only `customer`, the default, can be chosen at registration.
     pub role: Option<UserRole>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct LoginRequest {
     #[validate(email)]
     pub email: String,
@@ -87,7 +374,7 @@ pub struct LoginRequest {
     pub remember_me: Option<bool>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
     pub access_token: String,
     pub refresh_token: String,
@@ -95,7 +382,7 @@ pub struct LoginResponse {
     pub user: UserProfile,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct UserProfile {
     pub id: Uuid,
     pub email: String,
@@ -109,29 +396,120 @@ pub struct UserProfile {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ForgotPasswordRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct SendPhoneCodeRequest {
+    /// Defaults to the phone number on the profile.
+    #[validate(custom(function = "crate::services::phone::validate_phone"))]
+    pub phone: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ConfirmPhoneCodeRequest {
+    #[validate(length(equal = 6))]
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct MagicLinkRequest {
+    #[validate(email)]
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MagicLinkRedeemRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MfaChallengeRequest {
+    /// From the `MFA_REQUIRED` error of the sign-in.
+    pub mfa_token: String,
+    pub code: String,
+}
+
+/// Sent with an `MFA_REQUIRED` error in place of tokens when the account
+/// has an authenticator. The sign-in finishes at `/auth/mfa/challenge`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MfaChallenge {
+    pub mfa_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct PasswordCheckRequest {
+    #[validate(length(max = 128))]
+    pub password: String,
+    pub email: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ResetPasswordRequest {
+    pub token: String,
+    #[validate(length(max = 128))] // the password policy checks the rest
+    pub new_password: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+/// Proves the caller's identity again, with either their password or a
+/// code from their authenticator app.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReauthenticateRequest {
+    pub password: Option<String>,
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct ChangeEmailRequest {
+    #[validate(email)]
+    pub new_email: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EmailChangeTokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct ChangePasswordRequest {
-    pub current_password: String,
-    #[validate(length(min = 8, max = 128))]
+    /// Required unless an admin is resetting another account's password.
+    pub current_password: Option<String>,
+    #[validate(length(max = 128))] // the password policy checks the rest
     pub new_password: String,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AccountDeletionRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct UpdateUserRequest {
     #[validate(length(min = 1, max = 100))]
     pub first_name: Option<String>,
     #[validate(length(min = 1, max = 100))]
     pub last_name: Option<String>,
-    #[validate(phone)]
+    #[validate(custom(function = "crate::services::phone::validate_phone"))]
     pub phone: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, ToSchema)]
 pub struct CreateApiKeyRequest {
     #[validate(length(min = 1, max = 100))]
     pub name: String,
@@ -139,7 +517,7 @@ pub struct CreateApiKeyRequest {
     pub expires_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CreateApiKeyResponse {
     pub id: Uuid,
     pub name: String,
@@ -149,7 +527,66 @@ pub struct CreateApiKeyResponse {
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Serialize)]
+/// Where account events for a user or an organisation are posted.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    pub url: String,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub events: Vec<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct CreateWebhookRequest {
+    #[validate(url, length(max = 2048))]
+    pub url: String,
+    /// Event types to receive; see the `webhooks` tag for the list.
+    #[validate(length(min = 1))]
+    pub events: Vec<String>,
+    /// Subscribes the organisation instead of the caller. Owners only.
+    pub org_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateWebhookResponse {
+    #[serde(flatten)]
+    pub subscription: WebhookSubscription,
+    pub secret: String, // Only returned once
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, sqlx::Type, ToSchema)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    /// Out of retries; only a manual redelivery sends it again.
+    Dead,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub event_id: Uuid,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_attempt_at: Option<DateTime<Utc>>,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ApiKeyInfo {
     pub id: Uuid,
     pub name: String,
@@ -159,8 +596,83 @@ pub struct ApiKeyInfo {
     pub created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImpersonationResponse {
+    pub access_token: String,
+    pub expires_in: i64,
+    pub user: UserProfile,
+    pub impersonator_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct CreateOrganizationRequest {
+    #[validate(length(min = 1, max = 200))]
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct InviteMemberRequest {
+    #[validate(email)]
+    pub email: String,
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMemberRoleRequest {
+    pub role: OrgRole,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct InvitationTokenRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SwitchOrganizationRequest {
+    /// `None` switches back to acting as an individual.
+    pub org_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OidcAuthorizationResponse {
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TokenRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// RFC 7662 introspection response. Only `active` is present for tokens that
+/// are not active.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<UserRole>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActorClaim>,
+}
+
 // JWT Claims
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // User ID
     pub email: String,
@@ -168,6 +680,69 @@ pub struct Claims {
     pub iat: i64,
     pub exp: i64,
     pub jti: String, // JWT ID (session ID)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActorClaim>, // Set when an admin is impersonating `sub`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_use: Option<TokenUse>, // Absent on tokens issued before it was added
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rti: Option<String>, // Refresh token generation; only the session's latest is accepted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<Uuid>, // Organisation the token acts for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub org_role: Option<OrgRole>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>, // When the user last proved who they are
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub amr: Vec<AuthMethod>,
+}
+
+impl Claims {
+    /// The authentication the token was issued from, if it records one.
+    pub fn authentication(&self) -> Option<Authentication> {
+        self.auth_time.map(|auth_time| Authentication {
+            auth_time,
+            amr: self.amr.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenUse {
+    Access,
+    Refresh,
+}
+
+/// Claims of a sign-in waiting for an authenticator code: who passed the
+/// first factor, and how, so the sign-in can finish as it started.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MfaChallengeClaims {
+    pub sub: String, // User ID
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String, // Challenge ID
+    pub method: String,
+    pub amr: AuthMethod,
+    pub remember_me: bool,
+}
+
+/// Claims of a magic sign-in link. The audience keeps these tokens from
+/// being accepted anywhere an access token is expected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MagicLinkClaims {
+    pub sub: String, // User ID
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String, // Magic link ID
+}
+
+/// RFC 8693 actor claim naming the party acting on behalf of the subject.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ActorClaim {
+    pub sub: String,
+    pub email: String,
 }
 
 impl From<User> for UserProfile {
@@ -0,0 +1,201 @@
+use axum::response::{Html, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi, ToSchema,
+};
+
+use crate::{handlers, models::MfaChallenge, services::password_policy::PasswordFeedback};
+
+/// The auth-service API as an OpenAPI 3.1 document. Every route registered in
+/// `create_app` must be listed under `paths`; `tests/openapi.rs` checks this.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "BookMarket auth-service",
+        description = "Accounts, sessions, tokens and vendor organisations."
+    ),
+    paths(
+        crate::health_check,
+        crate::readiness_check,
+        handlers::auth::register,
+        handlers::auth::login,
+        handlers::auth::refresh_token,
+        handlers::auth::logout,
+        handlers::auth::stop_impersonation,
+        handlers::auth::verify_token,
+        handlers::auth::confirm_deny_login,
+        handlers::auth::deny_login,
+        handlers::auth::check_password,
+        handlers::auth::forgot_password,
+        handlers::auth::reset_password,
+        handlers::auth::verify_email,
+        handlers::auth::confirm_email_change,
+        handlers::auth::cancel_email_change,
+        handlers::auth::request_magic_link,
+        handlers::auth::redeem_magic_link,
+        handlers::auth::reauthenticate,
+        handlers::oidc::login,
+        handlers::oidc::callback,
+        handlers::oidc::list_identities,
+        handlers::oidc::start_link,
+        handlers::oidc::unlink_identity,
+        handlers::oauth::introspect,
+        handlers::oauth::revoke,
+        handlers::mfa::challenge_mfa,
+        handlers::mfa::setup_mfa,
+        handlers::mfa::verify_mfa,
+        handlers::mfa::disable_mfa,
+        handlers::users::get_user,
+        handlers::users::update_user,
+        handlers::users::change_password,
+        handlers::users::request_email_change,
+        handlers::phone::send_code,
+        handlers::phone::confirm_code,
+        handlers::privacy::export_data,
+        handlers::privacy::deletion_status,
+        handlers::privacy::request_deletion,
+        handlers::privacy::cancel_deletion,
+        handlers::organizations::switch_organization,
+        handlers::organizations::list_organizations,
+        handlers::organizations::create_organization,
+        handlers::organizations::accept_invitation,
+        handlers::organizations::decline_invitation,
+        handlers::organizations::get_organization,
+        handlers::organizations::list_members,
+        handlers::organizations::update_member,
+        handlers::organizations::remove_member,
+        handlers::organizations::list_invitations,
+        handlers::organizations::invite_member,
+        handlers::organizations::revoke_invitation,
+        handlers::api_keys::list_keys,
+        handlers::api_keys::create_key,
+        handlers::api_keys::get_key,
+        handlers::api_keys::update_key,
+        handlers::api_keys::revoke_key,
+        handlers::webhooks::create_webhook,
+        handlers::webhooks::list_webhooks,
+        handlers::webhooks::delete_webhook,
+        handlers::webhooks::list_deliveries,
+        handlers::webhooks::redeliver,
+        handlers::admin::list_users,
+        handlers::admin::export_users,
+        handlers::admin::suspend_user,
+        handlers::admin::activate_user,
+        handlers::admin::change_role,
+        handlers::admin::impersonate_user,
+        handlers::audit::list_events,
+        handlers::audit::export_events,
+        handlers::metrics::metrics,
+        openapi_json,
+        swagger_ui,
+    ),
+    components(schemas(ErrorResponse, ErrorBody, ErrorDetails)),
+    modifiers(&BearerAuth),
+    security(("bearer" = [])),
+    tags(
+        (name = "health", description = "Liveness and readiness probes"),
+        (name = "auth", description = "Registration, sign-in and tokens"),
+        (name = "oauth", description = "Token introspection and revocation for backend services"),
+        (name = "mfa", description = "Authenticator app enrolment"),
+        (name = "users", description = "The caller's account"),
+        (name = "organizations", description = "Vendor organisations and their staff"),
+        (name = "api-keys", description = "API keys for programmatic access"),
+        (name = "webhooks", description = "Signed notifications of account events"),
+        (name = "admin", description = "User administration and the audit log"),
+        (name = "metrics", description = "Prometheus metrics"),
+        (name = "docs", description = "This document"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Body of every error response, as rendered by `AppError`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable UPPER_SNAKE_CASE code, e.g. `WEAK_PASSWORD`.
+    pub code: String,
+    pub message: String,
+    pub timestamp: DateTime<Utc>,
+    /// Present on `WEAK_PASSWORD` and `MFA_REQUIRED` errors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<ErrorDetails>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ErrorDetails {
+    WeakPassword(PasswordFeedback),
+    MfaRequired(MfaChallenge),
+}
+
+/// Registers the bearer scheme the routes are secured with by default. Public
+/// routes opt out with `security(())`.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// The OpenAPI document for this service.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "docs",
+    security(()),
+    responses(
+        (status = 200, description = "OpenAPI 3.1 document", content_type = "application/json"),
+    )
+)]
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI for browsing `/openapi.json`.
+#[utoipa::path(
+    get,
+    path = "/docs",
+    tag = "docs",
+    security(()),
+    responses(
+        (status = 200, description = "Swagger UI page", content_type = "text/html"),
+    )
+)]
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI_PAGE)
+}
+
+const SWAGGER_UI_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>auth-service API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
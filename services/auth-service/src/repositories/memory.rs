@@ -0,0 +1,629 @@
+//! In-process backends for tests. Lookups that find nothing fail with
+//! `sqlx::Error::RowNotFound`, as the Postgres queries do, so handlers map
+//! them the same way on either backend.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, sync::Mutex};
+use uuid::Uuid;
+
+use super::{
+    parse_id, ApiKeyRepository, AuditRepository, NewSession, SessionRepository, TotpRepository,
+    UserRepository,
+};
+use crate::{
+    auth::{generate_token, hash_token},
+    errors::AppError,
+    models::{ApiKey, OrgContext, Session, User, UserRole, UserStatus},
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        outbox::OutboxEvent,
+        session_cache::CachedSession,
+    },
+};
+
+fn not_found() -> AppError {
+    AppError::Database(sqlx::Error::RowNotFound)
+}
+
+#[derive(Default)]
+pub struct MemoryUserRepository {
+    users: Mutex<HashMap<Uuid, User>>,
+    verification_tokens: Mutex<Vec<(Uuid, String, DateTime<Utc>)>>,
+    outbox: Mutex<Vec<OutboxEvent>>,
+}
+
+impl MemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lifecycle events written alongside user changes, oldest first.
+    pub fn outbox_events(&self) -> Vec<OutboxEvent> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    /// Flags the account as needing a new password, as a denied sign-in does.
+    pub fn require_password_reset(&self, user_id: Uuid) -> Result<(), AppError> {
+        self.update(user_id, |user| user.password_reset_required = true)
+    }
+
+    /// Moves the account to `status`, as an admin or a deletion request does.
+    pub fn set_status(&self, user_id: Uuid, status: UserStatus) -> Result<(), AppError> {
+        self.update(user_id, |user| user.status = status)
+    }
+
+    /// Gives the account `role`, as an admin or `bm-auth-admin` does.
+    pub fn set_role(&self, user_id: Uuid, role: UserRole) -> Result<(), AppError> {
+        self.update(user_id, |user| user.role = role)
+    }
+
+    fn update(&self, user_id: Uuid, apply: impl FnOnce(&mut User)) -> Result<(), AppError> {
+        let mut users = self.users.lock().unwrap();
+        let user = users.get_mut(&user_id).ok_or_else(not_found)?;
+        apply(user);
+        user.updated_at = Utc::now();
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserRepository for MemoryUserRepository {
+    async fn create(
+        &self,
+        email: &str,
+        password_hash: &str,
+        first_name: &str,
+        last_name: &str,
+        phone: Option<&str>,
+        role: UserRole,
+    ) -> Result<User, AppError> {
+        let mut users = self.users.lock().unwrap();
+        if users.values().any(|u| u.email == email) {
+            return Err(AppError::Conflict("User already exists".to_string()));
+        }
+
+        let now = Utc::now();
+        let user = User {
+            id: Uuid::new_v4(),
+            email: email.to_string(),
+            password_hash: password_hash.to_string(),
+            first_name: Some(first_name.to_string()),
+            last_name: Some(last_name.to_string()),
+            phone: phone.map(str::to_string),
+            role,
+            status: UserStatus::Active,
+            email_verified: false,
+            phone_verified: false,
+            password_reset_required: false,
+            last_login: None,
+            created_at: now,
+            updated_at: now,
+        };
+        users.insert(user.id, user.clone());
+        self.outbox
+            .lock()
+            .unwrap()
+            .push(OutboxEvent::user_registered(&user));
+
+        Ok(user)
+    }
+
+    async fn get_by_email(&self, email: &str) -> Result<User, AppError> {
+        self.users
+            .lock()
+            .unwrap()
+            .values()
+            .find(|u| u.email == email)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn get_by_id(&self, user_id: &str) -> Result<User, AppError> {
+        let id = parse_id(user_id, "user")?;
+
+        self.users
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn update_last_login(&self, user_id: Uuid) -> Result<(), AppError> {
+        // Postgres doesn't bump updated_at here either
+        let mut users = self.users.lock().unwrap();
+        if let Some(user) = users.get_mut(&user_id) {
+            user.last_login = Some(Utc::now());
+        }
+
+        Ok(())
+    }
+
+    async fn update_password_hash(
+        &self,
+        user_id: Uuid,
+        password_hash: &str,
+    ) -> Result<(), AppError> {
+        let mut users = self.users.lock().unwrap();
+        if let Some(user) = users.get_mut(&user_id) {
+            user.password_hash = password_hash.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn update_profile(
+        &self,
+        user_id: Uuid,
+        first_name: Option<&str>,
+        last_name: Option<&str>,
+        phone: Option<&str>,
+    ) -> Result<User, AppError> {
+        self.update(user_id, |user| {
+            if let Some(first_name) = first_name {
+                user.first_name = Some(first_name.to_string());
+            }
+            if let Some(last_name) = last_name {
+                user.last_name = Some(last_name.to_string());
+            }
+            if let Some(phone) = phone {
+                user.phone_verified &= user.phone.as_deref() == Some(phone);
+                user.phone = Some(phone.to_string());
+            }
+        })?;
+
+        self.get_by_id(&user_id.to_string()).await
+    }
+
+    async fn change_role(&self, user_id: Uuid, role: UserRole) -> Result<UserRole, AppError> {
+        let mut users = self.users.lock().unwrap();
+        let user = users
+            .get_mut(&user_id)
+            .filter(|user| user.status != UserStatus::Deleted)
+            .ok_or(AppError::NotFound)?;
+
+        let current = user.role.clone();
+        if current != role {
+            self.outbox
+                .lock()
+                .unwrap()
+                .push(OutboxEvent::user_role_changed(user_id, &current, &role));
+            user.role = role;
+            user.updated_at = Utc::now();
+        }
+
+        Ok(current)
+    }
+
+    async fn email_taken(&self, email: &str) -> Result<bool, AppError> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .values()
+            .any(|u| u.email.eq_ignore_ascii_case(email)))
+    }
+
+    async fn phone_taken(&self, phone: &str, user_id: Uuid) -> Result<bool, AppError> {
+        Ok(self
+            .users
+            .lock()
+            .unwrap()
+            .values()
+            .any(|u| u.phone.as_deref() == Some(phone) && u.phone_verified && u.id != user_id))
+    }
+
+    async fn set_verified_phone(&self, user_id: Uuid, phone: &str) -> Result<(), AppError> {
+        if self.phone_taken(phone, user_id).await? {
+            return Err(AppError::Conflict(
+                "Phone number is already verified on another account".to_string(),
+            ));
+        }
+
+        self.update(user_id, |user| {
+            user.phone = Some(phone.to_string());
+            user.phone_verified = true;
+        })
+    }
+
+    async fn add_verification_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        self.verification_tokens.lock().unwrap().push((
+            user_id,
+            token_hash.to_string(),
+            expires_at,
+        ));
+
+        Ok(())
+    }
+
+    /// Organisations aren't modelled here, so nobody belongs to one.
+    async fn default_org(&self, _user_id: Uuid) -> Result<Option<OrgContext>, AppError> {
+        Ok(None)
+    }
+
+    async fn org_membership(
+        &self,
+        _org_id: Uuid,
+        _user_id: Uuid,
+    ) -> Result<Option<OrgContext>, AppError> {
+        Ok(None)
+    }
+}
+
+#[derive(Default)]
+pub struct MemorySessionRepository {
+    sessions: Mutex<HashMap<Uuid, Session>>,
+    // When each (user, user agent, network) was last seen
+    devices: Mutex<HashMap<(Uuid, String, String), DateTime<Utc>>>,
+}
+
+impl MemorySessionRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn delete_where(&self, predicate: impl Fn(&Session) -> bool) -> u64 {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, session| !predicate(session));
+
+        (before - sessions.len()) as u64
+    }
+}
+
+#[async_trait]
+impl SessionRepository for MemorySessionRepository {
+    async fn create(&self, session: NewSession) -> Result<Session, AppError> {
+        let session = Session {
+            id: Uuid::new_v4(),
+            user_id: session.user_id,
+            token_hash: Uuid::new_v4().to_string(),
+            expires_at: session.expires_at,
+            user_agent: session.user_agent,
+            ip_address: session.ip_address,
+            impersonator_id: session.impersonator_id,
+            created_at: Utc::now(),
+        };
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.id, session.clone());
+
+        Ok(session)
+    }
+
+    async fn get(&self, session_id: &str) -> Result<Session, AppError> {
+        let id = parse_id(session_id, "session")?;
+
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn get_active(&self, session_id: &str) -> Result<CachedSession, AppError> {
+        let session = self.get(session_id).await?;
+
+        if session.expires_at < Utc::now() {
+            return Err(AppError::Unauthorized);
+        }
+
+        Ok(CachedSession::from(&session))
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        current: Option<&str>,
+    ) -> Result<Option<String>, AppError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let Some(session) = sessions.get_mut(&session_id) else {
+            return Ok(None);
+        };
+        if current.is_some_and(|current| current != session.token_hash) {
+            return Ok(None);
+        }
+
+        let generation = generate_token();
+        session.token_hash = hash_token(&generation);
+
+        Ok(Some(generation))
+    }
+
+    async fn delete(&self, session_id: &str) -> Result<(), AppError> {
+        let id = parse_id(session_id, "session")?;
+        self.sessions.lock().unwrap().remove(&id);
+
+        Ok(())
+    }
+
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<u64, AppError> {
+        Ok(self.delete_where(|s| s.user_id == user_id))
+    }
+
+    async fn delete_others(&self, user_id: Uuid, keep: Uuid) -> Result<u64, AppError> {
+        Ok(self.delete_where(|s| s.user_id == user_id && s.id != keep))
+    }
+
+    async fn delete_expired(&self) -> Result<u64, AppError> {
+        let now = Utc::now();
+        Ok(self.delete_where(|s| s.expires_at < now))
+    }
+
+    /// Nothing is cached here, so no revocation can be left undelivered.
+    async fn retry_revocations(&self) -> Result<u64, AppError> {
+        Ok(0)
+    }
+
+    async fn seen_device(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        network: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        let key = (
+            user_id,
+            user_agent.unwrap_or_default().to_string(),
+            network.unwrap_or_default().to_string(),
+        );
+
+        Ok(self
+            .devices
+            .lock()
+            .unwrap()
+            .get(&key)
+            .is_some_and(|last_seen| *last_seen > since))
+    }
+
+    async fn record_device(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        network: Option<&str>,
+    ) -> Result<(), AppError> {
+        let key = (
+            user_id,
+            user_agent.unwrap_or_default().to_string(),
+            network.unwrap_or_default().to_string(),
+        );
+        self.devices.lock().unwrap().insert(key, Utc::now());
+
+        Ok(())
+    }
+
+    async fn delete_devices_before(&self, before: DateTime<Utc>) -> Result<u64, AppError> {
+        let mut devices = self.devices.lock().unwrap();
+        let count = devices.len();
+        devices.retain(|_, last_seen| *last_seen >= before);
+
+        Ok((count - devices.len()) as u64)
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryApiKeyRepository {
+    keys: Mutex<HashMap<Uuid, ApiKey>>,
+}
+
+impl MemoryApiKeyRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MemoryApiKeyRepository {
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, AppError> {
+        let mut keys: Vec<ApiKey> = self
+            .keys
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|k| k.user_id == user_id)
+            .cloned()
+            .collect();
+        keys.sort_by_key(|k| std::cmp::Reverse(k.created_at));
+
+        Ok(keys)
+    }
+
+    async fn create(
+        &self,
+        user_id: Uuid,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, AppError> {
+        let key = ApiKey {
+            id: Uuid::new_v4(),
+            user_id,
+            key_hash: key_hash.to_string(),
+            name: name.to_string(),
+            scopes: scopes.to_vec(),
+            last_used: None,
+            expires_at,
+            created_at: Utc::now(),
+        };
+        self.keys.lock().unwrap().insert(key.id, key.clone());
+
+        Ok(key)
+    }
+
+    async fn get(&self, key_id: Uuid, user_id: Uuid) -> Result<ApiKey, AppError> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(&key_id)
+            .filter(|k| k.user_id == user_id)
+            .cloned()
+            .ok_or_else(not_found)
+    }
+
+    async fn update(
+        &self,
+        key_id: Uuid,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, AppError> {
+        let mut keys = self.keys.lock().unwrap();
+        let key = keys
+            .get_mut(&key_id)
+            .filter(|k| k.user_id == user_id)
+            .ok_or_else(not_found)?;
+        key.name = name.to_string();
+        key.scopes = scopes.to_vec();
+        key.expires_at = expires_at;
+
+        Ok(key.clone())
+    }
+
+    async fn delete(&self, key_id: Uuid, user_id: Uuid) -> Result<bool, AppError> {
+        let mut keys = self.keys.lock().unwrap();
+        if keys.get(&key_id).is_some_and(|k| k.user_id == user_id) {
+            keys.remove(&key_id);
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    async fn delete_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        let mut keys = self.keys.lock().unwrap();
+        let key_id = keys.values().find(|k| k.key_hash == key_hash).map(|k| k.id);
+
+        Ok(key_id.and_then(|id| keys.remove(&id)))
+    }
+}
+
+struct TotpFactor {
+    secret: String,
+    confirmed: bool,
+    last_used_step: Option<i64>,
+}
+
+#[derive(Default)]
+pub struct MemoryTotpRepository {
+    factors: Mutex<HashMap<Uuid, TotpFactor>>,
+}
+
+impl MemoryTotpRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The secret as stored, to check what reaches the database.
+    pub fn stored_secret(&self, user_id: Uuid) -> Option<String> {
+        self.factors
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .map(|f| f.secret.clone())
+    }
+}
+
+#[async_trait]
+impl TotpRepository for MemoryTotpRepository {
+    async fn begin(&self, user_id: Uuid, secret: &str) -> Result<bool, AppError> {
+        let mut factors = self.factors.lock().unwrap();
+        if factors.get(&user_id).is_some_and(|f| f.confirmed) {
+            return Ok(false);
+        }
+        factors.insert(
+            user_id,
+            TotpFactor {
+                secret: secret.to_string(),
+                confirmed: false,
+                last_used_step: None,
+            },
+        );
+
+        Ok(true)
+    }
+
+    async fn secret(&self, user_id: Uuid, confirmed: bool) -> Result<Option<String>, AppError> {
+        Ok(self
+            .factors
+            .lock()
+            .unwrap()
+            .get(&user_id)
+            .filter(|f| f.confirmed == confirmed)
+            .map(|f| f.secret.clone()))
+    }
+
+    async fn replace_secret(&self, user_id: Uuid, secret: &str) -> Result<(), AppError> {
+        if let Some(factor) = self.factors.lock().unwrap().get_mut(&user_id) {
+            factor.secret = secret.to_string();
+        }
+
+        Ok(())
+    }
+
+    async fn confirm(&self, user_id: Uuid, step: i64) -> Result<(), AppError> {
+        if let Some(factor) = self
+            .factors
+            .lock()
+            .unwrap()
+            .get_mut(&user_id)
+            .filter(|f| !f.confirmed)
+        {
+            factor.confirmed = true;
+            factor.last_used_step = Some(step);
+        }
+
+        Ok(())
+    }
+
+    async fn use_step(&self, user_id: Uuid, step: i64) -> Result<bool, AppError> {
+        let mut factors = self.factors.lock().unwrap();
+        match factors.get_mut(&user_id).filter(|f| f.confirmed) {
+            Some(factor) if factor.last_used_step.is_none_or(|last| last < step) => {
+                factor.last_used_step = Some(step);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete(&self, user_id: Uuid) -> Result<bool, AppError> {
+        Ok(self.factors.lock().unwrap().remove(&user_id).is_some())
+    }
+}
+
+/// Keeps recorded events so tests can assert on them.
+#[derive(Default)]
+pub struct MemoryAuditRepository {
+    events: Mutex<Vec<NewAuditEvent>>,
+}
+
+impl MemoryAuditRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything recorded so far, oldest first.
+    pub fn events(&self) -> Vec<NewAuditEvent> {
+        self.events.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl AuditRepository for MemoryAuditRepository {
+    async fn record(&self, _client: &ClientContext, event: NewAuditEvent) -> Result<(), AppError> {
+        self.events.lock().unwrap().push(event);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+//! Storage behind the handlers for users, sessions, API keys, authenticator
+//! apps and the audit trail. `postgres` is what the service runs on; `memory` keeps everything
+//! in process so the account routes can be exercised without a database.
+//! Features outside these traits still query `AppState::db` directly.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    errors::AppError,
+    models::{ApiKey, OrgContext, Session, User, UserRole},
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        session_cache::CachedSession,
+    },
+};
+
+pub mod memory;
+pub mod postgres;
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn create(
+        &self,
+        email: &str,
+        password_hash: &str,
+        first_name: &str,
+        last_name: &str,
+        phone: Option<&str>,
+        role: UserRole,
+    ) -> Result<User, AppError>;
+
+    async fn get_by_email(&self, email: &str) -> Result<User, AppError>;
+
+    /// Fails with `BadRequest` when `user_id` is not a UUID.
+    async fn get_by_id(&self, user_id: &str) -> Result<User, AppError>;
+
+    async fn update_last_login(&self, user_id: Uuid) -> Result<(), AppError>;
+
+    /// Replaces a password hash without touching sessions, which callers
+    /// setting a new password revoke themselves.
+    async fn update_password_hash(
+        &self,
+        user_id: Uuid,
+        password_hash: &str,
+    ) -> Result<(), AppError>;
+
+    /// Applies the given profile fields, leaving `None` ones alone. A changed
+    /// phone number needs verifying again.
+    async fn update_profile(
+        &self,
+        user_id: Uuid,
+        first_name: Option<&str>,
+        last_name: Option<&str>,
+        phone: Option<&str>,
+    ) -> Result<User, AppError>;
+
+    /// Gives the user `role` and returns the one they had. Deleted accounts
+    /// are `NotFound`; an unchanged role writes nothing.
+    async fn change_role(&self, user_id: Uuid, role: UserRole) -> Result<UserRole, AppError>;
+
+    /// Whether any account uses this email address, ignoring case.
+    async fn email_taken(&self, email: &str) -> Result<bool, AppError>;
+
+    /// Whether another account has already verified this phone number.
+    async fn phone_taken(&self, phone: &str, user_id: Uuid) -> Result<bool, AppError>;
+
+    /// Stores a phone number the user just proved they control.
+    async fn set_verified_phone(&self, user_id: Uuid, phone: &str) -> Result<(), AppError>;
+
+    /// Stores the hash of an email verification link token.
+    async fn add_verification_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError>;
+
+    /// The organisation a fresh sign-in acts for: the one the user last
+    /// switched to, or the first they joined.
+    async fn default_org(&self, user_id: Uuid) -> Result<Option<OrgContext>, AppError>;
+
+    /// The user's role in an organisation, if they belong to it.
+    async fn org_membership(
+        &self,
+        org_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<OrgContext>, AppError>;
+}
+
+/// A session about to be created.
+#[derive(Debug, Clone)]
+pub struct NewSession {
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub impersonator_id: Option<Uuid>,
+}
+
+impl NewSession {
+    /// A sign-in session. Without `remember_me` it outlives the access token
+    /// only briefly.
+    pub fn login(
+        user_id: Uuid,
+        client: &ClientContext,
+        remember_me: bool,
+        config: &Config,
+    ) -> Self {
+        let lifetime = if remember_me {
+            config.refresh_token_expiration
+        } else {
+            config.jwt_expiration * 2 // 2x access token lifetime
+        };
+
+        Self {
+            user_id,
+            expires_at: Utc::now() + Duration::seconds(lifetime),
+            user_agent: client.user_agent.clone(),
+            ip_address: client.ip_address.clone(),
+            impersonator_id: None,
+        }
+    }
+
+    /// A short-lived session for `user_id` on behalf of an admin.
+    pub fn impersonation(
+        user_id: Uuid,
+        impersonator_id: Uuid,
+        client: &ClientContext,
+        config: &Config,
+    ) -> Self {
+        Self {
+            user_id,
+            expires_at: Utc::now() + Duration::seconds(config.impersonation_ttl),
+            user_agent: client.user_agent.clone(),
+            ip_address: client.ip_address.clone(),
+            impersonator_id: Some(impersonator_id),
+        }
+    }
+}
+
+#[async_trait]
+pub trait SessionRepository: Send + Sync {
+    async fn create(&self, session: NewSession) -> Result<Session, AppError>;
+
+    /// Fails with `BadRequest` when `session_id` is not a UUID.
+    async fn get(&self, session_id: &str) -> Result<Session, AppError>;
+
+    /// Resolves an unexpired session for request authentication. May be
+    /// served from a cache.
+    async fn get_active(&self, session_id: &str) -> Result<CachedSession, AppError>;
+
+    /// Starts a new refresh token generation for the session and returns it.
+    /// Only its hash is kept. With `current`, this succeeds only while the
+    /// session still holds that hash, so a spent refresh token can't start a
+    /// generation of its own; `None` is returned instead.
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        current: Option<&str>,
+    ) -> Result<Option<String>, AppError>;
+
+    async fn delete(&self, session_id: &str) -> Result<(), AppError>;
+
+    /// Revokes every session belonging to a user, returning how many were
+    /// removed.
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<u64, AppError>;
+
+    /// Revokes every session of a user except `keep`, returning how many were
+    /// removed.
+    async fn delete_others(&self, user_id: Uuid, keep: Uuid) -> Result<u64, AppError>;
+
+    async fn delete_expired(&self) -> Result<u64, AppError>;
+
+    /// Delivers revocations that failed to reach the session cache, returning
+    /// how many were delivered.
+    async fn retry_revocations(&self) -> Result<u64, AppError>;
+
+    /// Whether the user has signed in from this user agent and network since
+    /// `since`. Devices are remembered apart from sessions, so one stays
+    /// known after its sessions end.
+    async fn seen_device(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        network: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<bool, AppError>;
+
+    /// Remembers a sign-in from this user agent and network, or refreshes
+    /// when it was last seen.
+    async fn record_device(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        network: Option<&str>,
+    ) -> Result<(), AppError>;
+
+    /// Forgets devices not seen since `before`, returning how many.
+    async fn delete_devices_before(&self, before: DateTime<Utc>) -> Result<u64, AppError>;
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    /// The user's keys, newest first.
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, AppError>;
+
+    async fn create(
+        &self,
+        user_id: Uuid,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, AppError>;
+
+    async fn get(&self, key_id: Uuid, user_id: Uuid) -> Result<ApiKey, AppError>;
+
+    async fn update(
+        &self,
+        key_id: Uuid,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, AppError>;
+
+    /// Returns whether the user had such a key.
+    async fn delete(&self, key_id: Uuid, user_id: Uuid) -> Result<bool, AppError>;
+
+    /// Deletes the key with this hash, whoever owns it.
+    async fn delete_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError>;
+}
+
+/// Authenticator app (TOTP) factors, at most one per user. Secrets are
+/// stored as given; `services::totp` encrypts them first.
+#[async_trait]
+pub trait TotpRepository: Send + Sync {
+    /// Stores a pending factor, replacing one that was never confirmed.
+    /// Returns false when the user already has a confirmed factor.
+    async fn begin(&self, user_id: Uuid, secret: &str) -> Result<bool, AppError>;
+
+    /// The stored secret of the user's confirmed factor, or with
+    /// `confirmed: false` of their pending one.
+    async fn secret(&self, user_id: Uuid, confirmed: bool) -> Result<Option<String>, AppError>;
+
+    /// Replaces the stored secret in place, keeping the factor's state.
+    async fn replace_secret(&self, user_id: Uuid, secret: &str) -> Result<(), AppError>;
+
+    /// Confirms the pending factor, recording `step` as used.
+    async fn confirm(&self, user_id: Uuid, step: i64) -> Result<(), AppError>;
+
+    /// Records `step` as used if it is later than the last one, returning
+    /// whether it was.
+    async fn use_step(&self, user_id: Uuid, step: i64) -> Result<bool, AppError>;
+
+    /// Removes the user's factor, confirmed or not. Returns whether there
+    /// was one.
+    async fn delete(&self, user_id: Uuid) -> Result<bool, AppError>;
+}
+
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    async fn record(&self, client: &ClientContext, event: NewAuditEvent) -> Result<(), AppError>;
+}
+
+/// Parses an ID taken from a path or token, as both backends do.
+fn parse_id(id: &str, what: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(id).map_err(|_| AppError::BadRequest(format!("Invalid {} ID", what)))
+}
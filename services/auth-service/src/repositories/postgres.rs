@@ -0,0 +1,901 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use super::{
+    parse_id, ApiKeyRepository, AuditRepository, NewSession, SessionRepository, TotpRepository,
+    UserRepository,
+};
+use crate::{
+    auth::{generate_token, hash_token},
+    errors::AppError,
+    models::{ApiKey, OrgContext, Session, User, UserRole, UserStatus},
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        organizations,
+        outbox::{self, OutboxEvent},
+        session_cache::{CachedSession, SessionCache},
+    },
+};
+
+#[derive(Clone)]
+pub struct PgUserRepository {
+    pool: PgPool,
+}
+
+impl PgUserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgUserRepository {
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn create(
+        &self,
+        email: &str,
+        password_hash: &str,
+        first_name: &str,
+        last_name: &str,
+        phone: Option<&str>,
+        role: UserRole,
+    ) -> Result<User, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            INSERT INTO auth.users (email, password_hash, first_name, last_name, phone, role)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, email, password_hash, first_name, last_name, phone,
+                      role as "role: UserRole", status as "status: UserStatus",
+                      email_verified, phone_verified, password_reset_required,
+                      last_login, created_at, updated_at
+            "#,
+            email,
+            password_hash,
+            first_name,
+            last_name,
+            phone,
+            role as UserRole
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        outbox::enqueue(&mut tx, OutboxEvent::user_registered(&user)).await?;
+        tx.commit().await?;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn get_by_email(&self, email: &str) -> Result<User, AppError> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT id, email, password_hash, first_name, last_name, phone,
+                   role as "role: UserRole", status as "status: UserStatus",
+                   email_verified, phone_verified, password_reset_required,
+                   last_login, created_at, updated_at
+            FROM auth.users
+            WHERE email = $1
+            "#,
+            email
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn get_by_id(&self, user_id: &str) -> Result<User, AppError> {
+        let id = parse_id(user_id, "user")?;
+
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            SELECT id, email, password_hash, first_name, last_name, phone,
+                   role as "role: UserRole", status as "status: UserStatus",
+                   email_verified, phone_verified, password_reset_required,
+                   last_login, created_at, updated_at
+            FROM auth.users
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn update_last_login(&self, user_id: Uuid) -> Result<(), AppError> {
+        sqlx::query!(
+            "UPDATE auth.users SET last_login = CURRENT_TIMESTAMP WHERE id = $1",
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn update_password_hash(
+        &self,
+        user_id: Uuid,
+        password_hash: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            "UPDATE auth.users SET password_hash = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2",
+            password_hash,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn update_profile(
+        &self,
+        user_id: Uuid,
+        first_name: Option<&str>,
+        last_name: Option<&str>,
+        phone: Option<&str>,
+    ) -> Result<User, AppError> {
+        let user = sqlx::query_as!(
+            User,
+            r#"
+            UPDATE auth.users
+            SET first_name = COALESCE($2, first_name),
+                last_name = COALESCE($3, last_name),
+                phone = COALESCE($4, phone),
+                phone_verified = phone_verified AND ($4::varchar IS NULL OR $4 = phone),
+                updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            RETURNING id, email, password_hash, first_name, last_name, phone,
+                      role as "role: UserRole", status as "status: UserStatus",
+                      email_verified, phone_verified, password_reset_required,
+                      last_login, created_at, updated_at
+            "#,
+            user_id,
+            first_name,
+            last_name,
+            phone
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn change_role(&self, user_id: Uuid, role: UserRole) -> Result<UserRole, AppError> {
+        let mut tx = self.pool.begin().await?;
+
+        let current = sqlx::query_scalar!(
+            r#"
+            SELECT role as "role: UserRole"
+            FROM auth.users
+            WHERE id = $1 AND status <> 'deleted'
+            FOR UPDATE
+            "#,
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+        if current == role {
+            return Ok(current);
+        }
+
+        sqlx::query!(
+            "UPDATE auth.users SET role = $2, updated_at = CURRENT_TIMESTAMP WHERE id = $1",
+            user_id,
+            role.clone() as UserRole
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        outbox::enqueue(
+            &mut tx,
+            OutboxEvent::user_role_changed(user_id, &current, &role),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(current)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn email_taken(&self, email: &str) -> Result<bool, AppError> {
+        let taken = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM auth.users WHERE lower(email) = lower($1)) as "exists!""#,
+            email
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(taken)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn phone_taken(&self, phone: &str, user_id: Uuid) -> Result<bool, AppError> {
+        let taken = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM auth.users
+                WHERE phone = $1 AND phone_verified AND id <> $2
+            ) as "exists!"
+            "#,
+            phone,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(taken)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn set_verified_phone(&self, user_id: Uuid, phone: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            UPDATE auth.users
+            SET phone = $2, phone_verified = TRUE, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1
+            "#,
+            user_id,
+            phone
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => AppError::Conflict(
+                "Phone number is already verified on another account".to_string(),
+            ),
+            e => AppError::from(e),
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn add_verification_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO auth.email_verification_tokens (user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3)
+            "#,
+            user_id,
+            token_hash,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn default_org(&self, user_id: Uuid) -> Result<Option<OrgContext>, AppError> {
+        organizations::default_for_user(&self.pool, user_id).await
+    }
+
+    async fn org_membership(
+        &self,
+        org_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<OrgContext>, AppError> {
+        organizations::membership(&self.pool, org_id, user_id).await
+    }
+}
+
+/// How long a replica relies on its last look at pending revocations.
+const PENDING_REVOCATIONS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Revocations retried per run of the retry job.
+const REVOCATION_RETRY_BATCH_SIZE: i64 = 1000;
+
+/// Sessions in Postgres, with validity cached in Redis for the auth
+/// middleware. Every deletion goes through here so the cache is invalidated
+/// alongside the database.
+///
+/// A deletion that can't reach Redis is recorded in
+/// `auth.pending_session_revocations`. While any are recorded, every replica
+/// stops trusting cached entries and reads sessions from Postgres, until
+/// `retry_revocations` has delivered them.
+#[derive(Clone)]
+pub struct PgSessionRepository {
+    pool: PgPool,
+    cache: SessionCache,
+    /// When pending revocations were last looked for, and whether there were any.
+    pending_revocations: Arc<Mutex<Option<(Instant, bool)>>>,
+}
+
+impl PgSessionRepository {
+    pub fn new(pool: PgPool, cache: SessionCache) -> Self {
+        Self {
+            pool,
+            cache,
+            pending_revocations: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn set_pending_revocations(&self, pending: bool) {
+        *self.pending_revocations.lock().unwrap() = Some((Instant::now(), pending));
+    }
+
+    /// Whether cached entries can be trusted: no revocation is waiting to
+    /// reach Redis. Postgres is asked at most once a second per replica.
+    async fn cache_trusted(&self) -> bool {
+        if let Some((checked_at, pending)) = *self.pending_revocations.lock().unwrap() {
+            if checked_at.elapsed() < PENDING_REVOCATIONS_CHECK_INTERVAL {
+                return !pending;
+            }
+        }
+
+        let pending = sqlx::query_scalar!(
+            r#"SELECT EXISTS (SELECT 1 FROM auth.pending_session_revocations) as "exists!""#
+        )
+        .fetch_one(&self.pool)
+        .await;
+
+        match pending {
+            Ok(pending) => {
+                self.set_pending_revocations(pending);
+                !pending
+            }
+            Err(e) => {
+                tracing::warn!("Failed to check pending session revocations: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Removes deleted sessions from the cache and leaves revocation markers,
+    /// or records them for `retry_revocations` when Redis can't be reached.
+    async fn revoke_cached(&self, session_ids: &[Uuid]) -> Result<(), AppError> {
+        let Err(e) = self.cache.invalidate_many(session_ids).await else {
+            return Ok(());
+        };
+        tracing::warn!(
+            "Failed to invalidate {} cached sessions, retrying later: {}",
+            session_ids.len(),
+            e
+        );
+
+        sqlx::query!(
+            r#"
+            INSERT INTO auth.pending_session_revocations (session_id)
+            SELECT * FROM UNNEST($1::uuid[])
+            ON CONFLICT (session_id) DO NOTHING
+            "#,
+            session_ids
+        )
+        .execute(&self.pool)
+        .await?;
+        self.set_pending_revocations(true);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionRepository for PgSessionRepository {
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn create(&self, session: NewSession) -> Result<Session, AppError> {
+        // Generate a random token hash for the session
+        let token_hash = Uuid::new_v4().to_string();
+
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            INSERT INTO auth.sessions (user_id, token_hash, expires_at, user_agent, ip_address, impersonator_id)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, token_hash, expires_at, user_agent, ip_address, impersonator_id,
+                      created_at
+            "#,
+            session.user_id,
+            token_hash,
+            session.expires_at,
+            session.user_agent,
+            session.ip_address,
+            session.impersonator_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn get(&self, session_id: &str) -> Result<Session, AppError> {
+        let id = parse_id(session_id, "session")?;
+
+        let session = sqlx::query_as!(
+            Session,
+            r#"
+            SELECT id, user_id, token_hash, expires_at, user_agent, ip_address, impersonator_id,
+                   created_at
+            FROM auth.sessions
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(session)
+    }
+
+    /// Consults Redis before Postgres. Cache failures are logged and fall
+    /// back to the database so a Redis outage degrades latency rather than
+    /// availability.
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn get_active(&self, session_id: &str) -> Result<CachedSession, AppError> {
+        if self.cache_trusted().await {
+            match self.cache.get(session_id).await {
+                Ok(Some(cached)) if cached.expires_at > Utc::now() => return Ok(cached),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Session cache lookup failed: {}", e),
+            }
+        }
+
+        let session = self.get(session_id).await?;
+
+        if session.expires_at < Utc::now() {
+            return Err(AppError::Unauthorized);
+        }
+
+        if let Err(e) = self.cache.put(&session).await {
+            tracing::warn!("Failed to cache session {}: {}", session.id, e);
+        }
+
+        Ok(CachedSession::from(&session))
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        current: Option<&str>,
+    ) -> Result<Option<String>, AppError> {
+        let generation = generate_token();
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE auth.sessions SET token_hash = $2
+            WHERE id = $1 AND ($3::text IS NULL OR token_hash = $3)
+            "#,
+            session_id,
+            hash_token(&generation),
+            current
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok((result.rows_affected() > 0).then_some(generation))
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete(&self, session_id: &str) -> Result<(), AppError> {
+        let id = parse_id(session_id, "session")?;
+
+        sqlx::query!("DELETE FROM auth.sessions WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        self.revoke_cached(&[id]).await
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete_for_user(&self, user_id: Uuid) -> Result<u64, AppError> {
+        let session_ids: Vec<Uuid> = sqlx::query_scalar!(
+            "DELETE FROM auth.sessions WHERE user_id = $1 RETURNING id",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.revoke_cached(&session_ids).await?;
+
+        Ok(session_ids.len() as u64)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete_others(&self, user_id: Uuid, keep: Uuid) -> Result<u64, AppError> {
+        let session_ids: Vec<Uuid> = sqlx::query_scalar!(
+            "DELETE FROM auth.sessions WHERE user_id = $1 AND id <> $2 RETURNING id",
+            user_id,
+            keep
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        self.revoke_cached(&session_ids).await?;
+
+        Ok(session_ids.len() as u64)
+    }
+
+    /// Cached entries for expired sessions are left to their TTL; lookups
+    /// already reject them by `expires_at`.
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete_expired(&self) -> Result<u64, AppError> {
+        let result = sqlx::query!("DELETE FROM auth.sessions WHERE expires_at < CURRENT_TIMESTAMP")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn retry_revocations(&self) -> Result<u64, AppError> {
+        let session_ids: Vec<Uuid> = sqlx::query_scalar!(
+            "SELECT session_id FROM auth.pending_session_revocations ORDER BY created_at LIMIT $1",
+            REVOCATION_RETRY_BATCH_SIZE
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        if session_ids.is_empty() {
+            self.set_pending_revocations(false);
+            return Ok(0);
+        }
+
+        self.cache.invalidate_many(&session_ids).await?;
+
+        sqlx::query!(
+            "DELETE FROM auth.pending_session_revocations WHERE session_id = ANY($1)",
+            &session_ids
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(session_ids.len() as u64)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn seen_device(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        network: Option<&str>,
+        since: DateTime<Utc>,
+    ) -> Result<bool, AppError> {
+        let seen = sqlx::query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM auth.known_devices
+                WHERE user_id = $1 AND user_agent = $2 AND network = $3 AND last_seen > $4
+            ) as "exists!"
+            "#,
+            user_id,
+            user_agent.unwrap_or_default(),
+            network.unwrap_or_default(),
+            since
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(seen)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn record_device(
+        &self,
+        user_id: Uuid,
+        user_agent: Option<&str>,
+        network: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO auth.known_devices (user_id, user_agent, network)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, user_agent, network)
+            DO UPDATE SET last_seen = CURRENT_TIMESTAMP
+            "#,
+            user_id,
+            user_agent.unwrap_or_default(),
+            network.unwrap_or_default()
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete_devices_before(&self, before: DateTime<Utc>) -> Result<u64, AppError> {
+        let result = sqlx::query!(
+            "DELETE FROM auth.known_devices WHERE last_seen < $1",
+            before
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+#[derive(Clone)]
+pub struct PgApiKeyRepository {
+    pool: PgPool,
+}
+
+impl PgApiKeyRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PgApiKeyRepository {
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn list_for_user(&self, user_id: Uuid) -> Result<Vec<ApiKey>, AppError> {
+        let keys = sqlx::query_as!(
+            ApiKey,
+            "SELECT id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
+             FROM auth.api_keys WHERE user_id = $1 ORDER BY created_at DESC",
+            user_id
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(keys)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn create(
+        &self,
+        user_id: Uuid,
+        key_hash: &str,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, AppError> {
+        let key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            INSERT INTO auth.api_keys (user_id, key_hash, name, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
+            "#,
+            user_id,
+            key_hash,
+            name,
+            scopes,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn get(&self, key_id: Uuid, user_id: Uuid) -> Result<ApiKey, AppError> {
+        let key = sqlx::query_as!(
+            ApiKey,
+            "SELECT id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
+             FROM auth.api_keys WHERE id = $1 AND user_id = $2",
+            key_id,
+            user_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn update(
+        &self,
+        key_id: Uuid,
+        user_id: Uuid,
+        name: &str,
+        scopes: &[String],
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<ApiKey, AppError> {
+        let key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            UPDATE auth.api_keys
+            SET name = $3, scopes = $4, expires_at = $5
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
+            "#,
+            key_id,
+            user_id,
+            name,
+            scopes,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete(&self, key_id: Uuid, user_id: Uuid) -> Result<bool, AppError> {
+        let result = sqlx::query!(
+            "DELETE FROM auth.api_keys WHERE id = $1 AND user_id = $2",
+            key_id,
+            user_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>, AppError> {
+        let key = sqlx::query_as!(
+            ApiKey,
+            r#"
+            DELETE FROM auth.api_keys WHERE key_hash = $1
+            RETURNING id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
+            "#,
+            key_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(key)
+    }
+}
+
+#[derive(Clone)]
+pub struct PgTotpRepository {
+    pool: PgPool,
+}
+
+impl PgTotpRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl TotpRepository for PgTotpRepository {
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn begin(&self, user_id: Uuid, secret: &str) -> Result<bool, AppError> {
+        let stored = sqlx::query!(
+            r#"
+            INSERT INTO auth.totp_factors (user_id, secret)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE
+            SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = CURRENT_TIMESTAMP
+            WHERE auth.totp_factors.confirmed_at IS NULL
+            "#,
+            user_id,
+            secret
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(stored.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn secret(&self, user_id: Uuid, confirmed: bool) -> Result<Option<String>, AppError> {
+        let secret = sqlx::query_scalar!(
+            r#"
+            SELECT secret FROM auth.totp_factors
+            WHERE user_id = $1 AND (confirmed_at IS NOT NULL) = $2
+            "#,
+            user_id,
+            confirmed
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(secret)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn replace_secret(&self, user_id: Uuid, secret: &str) -> Result<(), AppError> {
+        sqlx::query!(
+            "UPDATE auth.totp_factors SET secret = $2 WHERE user_id = $1",
+            user_id,
+            secret
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn confirm(&self, user_id: Uuid, step: i64) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            UPDATE auth.totp_factors
+            SET confirmed_at = CURRENT_TIMESTAMP, last_used_step = $2
+            WHERE user_id = $1 AND confirmed_at IS NULL
+            "#,
+            user_id,
+            step
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn use_step(&self, user_id: Uuid, step: i64) -> Result<bool, AppError> {
+        let used = sqlx::query!(
+            r#"
+            UPDATE auth.totp_factors SET last_used_step = $2
+            WHERE user_id = $1 AND confirmed_at IS NOT NULL
+              AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            user_id,
+            step
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(used.rows_affected() == 1)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+    async fn delete(&self, user_id: Uuid) -> Result<bool, AppError> {
+        let deleted = sqlx::query!("DELETE FROM auth.totp_factors WHERE user_id = $1", user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+}
+
+#[derive(Clone)]
+pub struct PgAuditRepository {
+    pool: PgPool,
+}
+
+impl PgAuditRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl AuditRepository for PgAuditRepository {
+    async fn record(&self, client: &ClientContext, event: NewAuditEvent) -> Result<(), AppError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO auth.audit_events
+                (event_type, outcome, actor_id, target_id, ip_address, user_agent, details)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            event.event_type.as_str(),
+            event.outcome.as_str(),
+            event.actor_id,
+            event.target_id,
+            client.ip_address,
+            client.user_agent,
+            event.details
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
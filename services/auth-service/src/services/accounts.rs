@@ -0,0 +1,107 @@
+//! Account changes made by operators, shared by the admin API and the
+//! `bm-auth-admin` CLI. Callers record the audit event.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    repositories::SessionRepository,
+    services::outbox::{self, OutboxEvent},
+};
+
+/// Suspends a user and signs them out everywhere, returning how many sessions
+/// were revoked. Deleted accounts are `NotFound`.
+pub async fn suspend(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    user_id: Uuid,
+) -> Result<u64, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE auth.users SET status = 'suspended', updated_at = CURRENT_TIMESTAMP WHERE id = $1 AND status <> 'deleted'",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    outbox::enqueue(&mut tx, OutboxEvent::user_suspended(user_id)).await?;
+    tx.commit().await?;
+
+    sessions.delete_for_user(user_id).await
+}
+
+/// Lets a suspended user sign in again. Deleted accounts are `NotFound`.
+pub async fn activate(pool: &PgPool, user_id: Uuid) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query!(
+        "UPDATE auth.users SET status = 'active', updated_at = CURRENT_TIMESTAMP WHERE id = $1 AND status <> 'deleted'",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    outbox::enqueue(&mut tx, OutboxEvent::user_activated(user_id)).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Replaces a user's password and revokes their sessions, returning how many
+/// were revoked. Clears any forced reset, as a completed reset does.
+pub async fn set_password(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    user_id: Uuid,
+    password_hash: &str,
+) -> Result<u64, AppError> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE auth.users
+        SET password_hash = $2, password_reset_required = FALSE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND status <> 'deleted'
+        "#,
+        user_id,
+        password_hash
+    )
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    sessions.delete_for_user(user_id).await
+}
+
+/// Marks an email address as verified without a link, for accounts an
+/// operator vouches for.
+pub async fn mark_email_verified(
+    pool: &PgPool,
+    user_id: Uuid,
+    email: &str,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "UPDATE auth.users SET email_verified = TRUE, updated_at = CURRENT_TIMESTAMP WHERE id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    outbox::enqueue(&mut tx, OutboxEvent::user_email_verified(user_id, email)).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
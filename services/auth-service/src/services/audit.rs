@@ -0,0 +1,226 @@
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use ipnet::IpNet;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::{AuditEvent, AuditEventType, AuditOutcome},
+};
+
+/// Network details of the client that triggered an auth event.
+#[derive(Debug, Clone, Default)]
+pub struct ClientContext {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ClientContext {
+    /// Reads the address `middleware::client_address` resolved into
+    /// `x-real-ip`, and the user agent.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let user_agent = headers
+            .get("user-agent")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+
+        let ip_address = headers
+            .get("x-real-ip")
+            .and_then(|h| h.to_str().ok())
+            .map(|s| s.to_string());
+
+        Self {
+            ip_address,
+            user_agent,
+        }
+    }
+}
+
+/// The address a request came from. Forwarding headers are only believed
+/// when the connection comes from a trusted proxy, and then only as far back
+/// as the chain stays within trusted proxies: the client is the nearest
+/// untrusted hop in `X-Forwarded-For`.
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpNet]) -> Option<IpAddr> {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|net| net.contains(ip));
+
+    let peer = peer?;
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(','))
+        .filter_map(|hop| hop.trim().parse().ok())
+        .collect();
+
+    if let Some(client) = forwarded.iter().rev().find(|ip| !is_trusted(ip)) {
+        return Some(*client);
+    }
+    if let Some(first) = forwarded.first() {
+        return Some(*first);
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.trim().parse().ok())
+        .or(Some(peer))
+}
+
+/// An audit record about to be written.
+#[derive(Debug, Clone)]
+pub struct NewAuditEvent {
+    pub event_type: AuditEventType,
+    pub outcome: AuditOutcome,
+    pub actor_id: Option<Uuid>,
+    pub target_id: Option<Uuid>,
+    pub details: serde_json::Value,
+}
+
+impl NewAuditEvent {
+    pub fn new(event_type: AuditEventType, outcome: AuditOutcome) -> Self {
+        Self {
+            event_type,
+            outcome,
+            actor_id: None,
+            target_id: None,
+            details: serde_json::json!({}),
+        }
+    }
+
+    pub fn actor(mut self, actor_id: Uuid) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn target(mut self, target_id: Uuid) -> Self {
+        self.target_id = Some(target_id);
+        self
+    }
+
+    pub fn details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub event_type: Option<AuditEventType>,
+    pub outcome: Option<AuditOutcome>,
+    pub actor_id: Option<Uuid>,
+    pub target_id: Option<Uuid>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+pub async fn list_events(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AuditEvent>, AppError> {
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT id, event_type as "event_type: AuditEventType",
+               outcome as "outcome: AuditOutcome",
+               actor_id, target_id, ip_address, user_agent, details, created_at
+        FROM auth.audit_events
+        WHERE ($1::varchar IS NULL OR event_type = $1)
+          AND ($2::varchar IS NULL OR outcome = $2)
+          AND ($3::uuid IS NULL OR actor_id = $3)
+          AND ($4::uuid IS NULL OR target_id = $4)
+          AND ($5::timestamptz IS NULL OR created_at >= $5)
+          AND ($6::timestamptz IS NULL OR created_at < $6)
+        ORDER BY created_at DESC, id DESC
+        LIMIT $7 OFFSET $8
+        "#,
+        filter.event_type.map(|t| t.as_str()),
+        filter.outcome.map(|o| o.as_str()),
+        filter.actor_id,
+        filter.target_id,
+        filter.from,
+        filter.to,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+/// Matching events older than `before` in the same newest-first order, for
+/// walking the whole result set: unlike an offset, the position doesn't
+/// shift as new events are written.
+pub async fn list_events_before(
+    pool: &PgPool,
+    filter: &AuditFilter,
+    before: Option<(DateTime<Utc>, Uuid)>,
+    limit: i64,
+) -> Result<Vec<AuditEvent>, AppError> {
+    let (before_created_at, before_id) = before.unzip();
+
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT id, event_type as "event_type: AuditEventType",
+               outcome as "outcome: AuditOutcome",
+               actor_id, target_id, ip_address, user_agent, details, created_at
+        FROM auth.audit_events
+        WHERE ($1::varchar IS NULL OR event_type = $1)
+          AND ($2::varchar IS NULL OR outcome = $2)
+          AND ($3::uuid IS NULL OR actor_id = $3)
+          AND ($4::uuid IS NULL OR target_id = $4)
+          AND ($5::timestamptz IS NULL OR created_at >= $5)
+          AND ($6::timestamptz IS NULL OR created_at < $6)
+          AND ($7::timestamptz IS NULL OR (created_at, id) < ($7, $8::uuid))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $9
+        "#,
+        filter.event_type.map(|t| t.as_str()),
+        filter.outcome.map(|o| o.as_str()),
+        filter.actor_id,
+        filter.target_id,
+        filter.from,
+        filter.to,
+        before_created_at,
+        before_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(events)
+}
+
+pub async fn count_events(pool: &PgPool, filter: &AuditFilter) -> Result<i64, AppError> {
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) as "count!"
+        FROM auth.audit_events
+        WHERE ($1::varchar IS NULL OR event_type = $1)
+          AND ($2::varchar IS NULL OR outcome = $2)
+          AND ($3::uuid IS NULL OR actor_id = $3)
+          AND ($4::uuid IS NULL OR target_id = $4)
+          AND ($5::timestamptz IS NULL OR created_at >= $5)
+          AND ($6::timestamptz IS NULL OR created_at < $6)
+        "#,
+        filter.event_type.map(|t| t.as_str()),
+        filter.outcome.map(|o| o.as_str()),
+        filter.actor_id,
+        filter.target_id,
+        filter.from,
+        filter.to
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(total)
+}
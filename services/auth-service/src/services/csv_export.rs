@@ -0,0 +1,17 @@
+//! Cells for the CSV downloads admins open in spreadsheet software.
+
+use std::borrow::Cow;
+
+/// Characters that make a spreadsheet treat a cell as a formula.
+const FORMULA_TRIGGERS: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Neutralises a value a spreadsheet would otherwise evaluate, by prefixing
+/// it with `'` as OWASP recommends for CSV injection. Other values are
+/// written as they are.
+pub fn cell(value: &str) -> Cow<'_, str> {
+    if value.starts_with(FORMULA_TRIGGERS) {
+        Cow::Owned(format!("'{}", value))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
@@ -0,0 +1,229 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_token, hash_token},
+    config::Config,
+    errors::AppError,
+    models::User,
+    repositories::{SessionRepository, UserRepository},
+    services::mailer::EmailMessage,
+};
+
+/// A pending change and the raw tokens for its two links.
+pub struct IssuedEmailChange {
+    pub id: Uuid,
+    pub confirm_token: String,
+    pub cancel_token: String,
+}
+
+/// An email change that was confirmed and applied.
+pub struct AppliedEmailChange {
+    pub user_id: Uuid,
+    pub old_email: String,
+    pub new_email: String,
+    pub sessions_revoked: u64,
+}
+
+/// Starts a change of `user`'s email to `new_email`, replacing any pending
+/// one. Nothing changes on the account until the new address confirms.
+/// `session_id` is the session that asked, which survives the change.
+pub async fn request_change(
+    pool: &PgPool,
+    users: &dyn UserRepository,
+    user: &User,
+    new_email: &str,
+    session_id: Uuid,
+    config: &Config,
+) -> Result<IssuedEmailChange, AppError> {
+    if new_email.eq_ignore_ascii_case(&user.email) {
+        return Err(AppError::BadRequest(
+            "The new email address is the same as the current one".to_string(),
+        ));
+    }
+    if users.email_taken(new_email).await? {
+        return Err(AppError::Conflict(
+            "Email address is already in use".to_string(),
+        ));
+    }
+
+    let confirm_token = generate_token();
+    let cancel_token = generate_token();
+    let expires_at = Utc::now() + Duration::seconds(config.email_change_ttl);
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.email_change_requests SET cancelled_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1 AND confirmed_at IS NULL AND cancelled_at IS NULL
+        "#,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO auth.email_change_requests
+            (user_id, session_id, old_email, new_email, confirm_token_hash, cancel_token_hash,
+             expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id
+        "#,
+        user.id,
+        session_id,
+        user.email,
+        new_email,
+        hash_token(&confirm_token),
+        hash_token(&cancel_token),
+        expires_at
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(IssuedEmailChange {
+        id,
+        confirm_token,
+        cancel_token,
+    })
+}
+
+/// Applies a pending change from its confirmation link. Following the link
+/// proves control of the new address, so it is marked verified. Every session
+/// other than the one that asked for the change is revoked.
+pub async fn confirm_change(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    token: &str,
+) -> Result<AppliedEmailChange, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let change = sqlx::query!(
+        r#"
+        UPDATE auth.email_change_requests
+        SET confirmed_at = CURRENT_TIMESTAMP
+        WHERE confirm_token_hash = $1 AND confirmed_at IS NULL AND cancelled_at IS NULL
+          AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id, session_id, old_email, new_email
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired confirmation link".to_string()))?;
+
+    // The old address must still be current, or the request is stale
+    let updated = sqlx::query!(
+        r#"
+        UPDATE auth.users
+        SET email = $3, email_verified = TRUE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND email = $2
+        "#,
+        change.user_id,
+        change.old_email,
+        change.new_email
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            AppError::Conflict("Email address is already in use".to_string())
+        }
+        e => AppError::from(e),
+    })?;
+    if updated.rows_affected() == 0 {
+        return Err(AppError::BadRequest(
+            "Invalid or expired confirmation link".to_string(),
+        ));
+    }
+
+    tx.commit().await?;
+
+    let sessions_revoked = match change.session_id {
+        Some(keep) => sessions.delete_others(change.user_id, keep).await?,
+        None => sessions.delete_for_user(change.user_id).await?,
+    };
+
+    Ok(AppliedEmailChange {
+        user_id: change.user_id,
+        old_email: change.old_email,
+        new_email: change.new_email,
+        sessions_revoked,
+    })
+}
+
+/// Cancels a pending change from the link sent to the old address. Returns
+/// the user it belonged to.
+pub async fn cancel_change(pool: &PgPool, token: &str) -> Result<Uuid, AppError> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE auth.email_change_requests
+        SET cancelled_at = CURRENT_TIMESTAMP
+        WHERE cancel_token_hash = $1 AND confirmed_at IS NULL AND cancelled_at IS NULL
+          AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired cancellation link".to_string()))
+}
+
+/// Sent to the new address.
+pub fn confirmation_email(
+    user: &User,
+    new_email: &str,
+    token: &str,
+    config: &Config,
+) -> EmailMessage {
+    let link = format!(
+        "{}/auth/email-change/confirm?token={}",
+        config.frontend_url, token
+    );
+
+    EmailMessage {
+        to: new_email.to_string(),
+        subject: "Confirm your new BookMarket email address".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             Confirm that you want to use this address for your BookMarket account:\n\n\
+             {}\n\n\
+             The link expires in {} hours. Until then your account keeps using {}.\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            link,
+            config.email_change_ttl / 3600,
+            user.email
+        ),
+    }
+}
+
+/// Sent to the current address, so the owner can stop a change they didn't make.
+pub fn change_notice_email(
+    user: &User,
+    new_email: &str,
+    token: &str,
+    config: &Config,
+) -> EmailMessage {
+    let link = format!(
+        "{}/auth/email-change/cancel?token={}",
+        config.frontend_url, token
+    );
+
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "Your BookMarket email address is being changed".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             Someone asked to change the email address of your BookMarket account to {}.\n\n\
+             If this wasn't you, cancel the change and then change your password:\n\n\
+             {}\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            new_email,
+            link
+        ),
+    }
+}
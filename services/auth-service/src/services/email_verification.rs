@@ -0,0 +1,108 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_token, hash_token},
+    config::Config,
+    errors::AppError,
+    models::User,
+    repositories::UserRepository,
+    services::{
+        mailer::EmailMessage,
+        outbox::{self, OutboxEvent},
+    },
+};
+
+/// Creates a verification link token for a user and returns the raw token.
+/// Only the hash is stored.
+pub async fn issue_token(
+    users: &dyn UserRepository,
+    user_id: Uuid,
+    config: &Config,
+) -> Result<String, AppError> {
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::seconds(config.email_verification_ttl);
+
+    users
+        .add_verification_token(user_id, &hash_token(&token), expires_at)
+        .await?;
+
+    Ok(token)
+}
+
+/// Consumes a verification token and marks the user's email as verified.
+pub async fn verify_email(pool: &PgPool, token: &str) -> Result<Uuid, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE auth.email_verification_tokens
+        SET used_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired verification token".to_string()))?;
+
+    let email = sqlx::query_scalar!(
+        r#"
+        UPDATE auth.users
+        SET email_verified = TRUE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        RETURNING email
+        "#,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    outbox::enqueue(&mut tx, OutboxEvent::user_email_verified(user_id, &email)).await?;
+    tx.commit().await?;
+
+    Ok(user_id)
+}
+
+pub fn verification_email(user: &User, token: &str, config: &Config) -> EmailMessage {
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "Confirm your BookMarket email address".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             Welcome to BookMarket! Please confirm your email address:\n\n\
+             {}\n\n\
+             The link expires in {} hours. \
+             If you did not create an account you can ignore this email.\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            verification_link(token, config),
+            config.email_verification_ttl / 3600
+        ),
+    }
+}
+
+/// Sent once to accounts that still haven't confirmed their address a while
+/// after registering.
+pub fn reminder_email(user: &User, token: &str, config: &Config) -> EmailMessage {
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "Reminder: confirm your BookMarket email address".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             You haven't confirmed the email address of your BookMarket account yet. \
+             Confirming it lets you recover your account if you lose your password:\n\n\
+             {}\n\n\
+             The link expires in {} hours. \
+             If you did not create an account you can ignore this email.\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            verification_link(token, config),
+            config.email_verification_ttl / 3600
+        ),
+    }
+}
+
+fn verification_link(token: &str, config: &Config) -> String {
+    format!("{}/auth/verify-email?token={}", config.frontend_url, token)
+}
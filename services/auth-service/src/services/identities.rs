@@ -0,0 +1,390 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::generate_token,
+    errors::AppError,
+    models::{User, UserIdentity, UserRole},
+    repositories::{SessionRepository, UserRepository},
+    services::{oidc::VerifiedIdentity, password_hasher::PasswordHasher},
+};
+
+/// How an external identity was matched to a local account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IdentityResolution {
+    /// The identity was already linked.
+    Existing,
+    /// Linked to an existing account with the same verified email.
+    LinkedByEmail,
+    /// A new account was created for the identity.
+    Created,
+}
+
+impl IdentityResolution {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdentityResolution::Existing => "existing",
+            IdentityResolution::LinkedByEmail => "linked_by_email",
+            IdentityResolution::Created => "created",
+        }
+    }
+}
+
+/// Finds or creates the local user for someone signing in with an external
+/// identity.
+///
+/// Unknown identities are linked by email only when the provider asserts the
+/// address is verified. If the matching local account never verified its own
+/// email, it may have been registered by someone else ahead of the real owner,
+/// so its password is replaced with an unusable one and its sessions revoked
+/// before linking. The owner can set a password through the reset flow.
+pub async fn resolve_user(
+    pool: &PgPool,
+    users: &dyn UserRepository,
+    sessions: &dyn SessionRepository,
+    identity: &VerifiedIdentity,
+    hasher: &PasswordHasher,
+) -> Result<(User, IdentityResolution), AppError> {
+    if let Some(user_id) = find_linked_user(pool, &identity.provider, &identity.subject).await? {
+        let user = users.get_by_id(&user_id.to_string()).await?;
+        return Ok((user, IdentityResolution::Existing));
+    }
+
+    let email = match (&identity.email, identity.email_verified) {
+        (Some(email), true) => email.as_str(),
+        _ => {
+            return Err(AppError::BadRequest(format!(
+                "{} did not provide a verified email address",
+                identity.provider
+            )))
+        }
+    };
+
+    let resolution = match users.get_by_email(email).await {
+        Ok(user) => {
+            if !user.email_verified {
+                let password_hash = hasher.hash(&generate_token()).await?;
+                sqlx::query!(
+                    r#"
+                    UPDATE auth.users
+                    SET email_verified = TRUE, password_hash = $2,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE id = $1
+                    "#,
+                    user.id,
+                    password_hash
+                )
+                .execute(pool)
+                .await?;
+                sessions.delete_for_user(user.id).await?;
+            }
+            (user.id, IdentityResolution::LinkedByEmail)
+        }
+        Err(AppError::Database(sqlx::Error::RowNotFound)) => {
+            // No usable password until the user sets one through a reset link
+            let password_hash = hasher.hash(&generate_token()).await?;
+            let user = users
+                .create(
+                    email,
+                    &password_hash,
+                    identity.given_name.as_deref().unwrap_or(""),
+                    identity.family_name.as_deref().unwrap_or(""),
+                    None,
+                    UserRole::Customer,
+                )
+                .await?;
+            sqlx::query!(
+                "UPDATE auth.users SET email_verified = TRUE WHERE id = $1",
+                user.id
+            )
+            .execute(pool)
+            .await?;
+            (user.id, IdentityResolution::Created)
+        }
+        Err(e) => return Err(e),
+    };
+
+    link(
+        pool,
+        resolution.0,
+        &identity.provider,
+        &identity.subject,
+        Some(email),
+    )
+    .await?;
+    let user = users.get_by_id(&resolution.0.to_string()).await?;
+
+    Ok((user, resolution.1))
+}
+
+/// Returns the user an external account is linked to, if any, and records
+/// that the identity was just used.
+pub async fn find_linked_user(
+    pool: &PgPool,
+    provider: &str,
+    subject: &str,
+) -> Result<Option<Uuid>, AppError> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE auth.user_identities
+        SET last_used_at = CURRENT_TIMESTAMP
+        WHERE provider = $1 AND subject = $2
+        RETURNING user_id
+        "#,
+        provider,
+        subject
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user_id)
+}
+
+/// Links an external account to a user. A user can hold one identity per
+/// provider and an identity can belong to a single user.
+pub async fn link(
+    pool: &PgPool,
+    user_id: Uuid,
+    provider: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<UserIdentity, AppError> {
+    let identity = sqlx::query_as!(
+        UserIdentity,
+        r#"
+        INSERT INTO auth.user_identities (user_id, provider, subject, email, last_used_at)
+        VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP)
+        RETURNING id, user_id, provider, subject, email, last_used_at, created_at
+        "#,
+        user_id,
+        provider,
+        subject,
+        email
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => AppError::Conflict(format!(
+            "This {} account is already linked, or a {} account is already linked to this user",
+            provider, provider
+        )),
+        e => AppError::from(e),
+    })?;
+
+    Ok(identity)
+}
+
+pub async fn list_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<UserIdentity>, AppError> {
+    let identities = sqlx::query_as!(
+        UserIdentity,
+        r#"
+        SELECT id, user_id, provider, subject, email, last_used_at, created_at
+        FROM auth.user_identities
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(identities)
+}
+
+/// Removes a linked identity. Accounts created through a provider have no
+/// usable password, but their email is verified so the owner can always set
+/// one through the reset flow after unlinking.
+pub async fn unlink(
+    pool: &PgPool,
+    user_id: Uuid,
+    provider: &str,
+) -> Result<UserIdentity, AppError> {
+    let identity = sqlx::query_as!(
+        UserIdentity,
+        r#"
+        DELETE FROM auth.user_identities
+        WHERE user_id = $1 AND provider = $2
+        RETURNING id, user_id, provider, subject, email, last_used_at, created_at
+        "#,
+        user_id,
+        provider
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    Ok(identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        repositories::{memory::MemorySessionRepository, postgres::PgUserRepository, NewSession},
+    };
+    use chrono::{Duration, Utc};
+
+    const EMAIL: &str = "reader@example.com";
+
+    fn hasher() -> PasswordHasher {
+        std::env::set_var("JWT_SECRET", "identities-test-secret");
+        std::env::set_var("MFA_ENCRYPTION_KEY", "00".repeat(32));
+        PasswordHasher::from_config(&Config::from_env().unwrap()).unwrap()
+    }
+
+    fn identity(email_verified: bool) -> VerifiedIdentity {
+        VerifiedIdentity {
+            provider: "stub".to_string(),
+            subject: "stub-subject".to_string(),
+            email: Some(EMAIL.to_string()),
+            email_verified,
+            given_name: Some("Test".to_string()),
+            family_name: Some("Reader".to_string()),
+        }
+    }
+
+    async fn register(users: &PgUserRepository, hasher: &PasswordHasher) -> User {
+        let password_hash = hasher.hash("registered-password").await.unwrap();
+        users
+            .create(
+                EMAIL,
+                &password_hash,
+                "Test",
+                "Reader",
+                None,
+                UserRole::Customer,
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn sign_in(sessions: &MemorySessionRepository, user: &User) -> String {
+        let session = sessions
+            .create(NewSession {
+                user_id: user.id,
+                expires_at: Utc::now() + Duration::hours(1),
+                user_agent: None,
+                ip_address: None,
+                impersonator_id: None,
+            })
+            .await
+            .unwrap();
+        session.id.to_string()
+    }
+
+    #[sqlx::test(migrator = "crate::database::MIGRATOR")]
+    async fn unknown_identity_creates_a_verified_account(pool: PgPool) {
+        let users = PgUserRepository::new(pool.clone());
+        let sessions = MemorySessionRepository::new();
+        let hasher = hasher();
+
+        let (user, resolution) = resolve_user(&pool, &users, &sessions, &identity(true), &hasher)
+            .await
+            .unwrap();
+
+        assert_eq!(resolution, IdentityResolution::Created);
+        assert_eq!(user.email, EMAIL);
+        assert!(user.email_verified);
+        assert_eq!(user.role, UserRole::Customer);
+        assert_eq!(
+            find_linked_user(&pool, "stub", "stub-subject")
+                .await
+                .unwrap(),
+            Some(user.id)
+        );
+    }
+
+    #[sqlx::test(migrator = "crate::database::MIGRATOR")]
+    async fn linked_identity_signs_in_as_its_account(pool: PgPool) {
+        let users = PgUserRepository::new(pool.clone());
+        let sessions = MemorySessionRepository::new();
+        let hasher = hasher();
+
+        let (created, _) = resolve_user(&pool, &users, &sessions, &identity(true), &hasher)
+            .await
+            .unwrap();
+
+        // The provider's email no longer matters once the identity is linked
+        let mut identity = identity(false);
+        identity.email = Some("changed@example.com".to_string());
+        let (user, resolution) = resolve_user(&pool, &users, &sessions, &identity, &hasher)
+            .await
+            .unwrap();
+
+        assert_eq!(resolution, IdentityResolution::Existing);
+        assert_eq!(user.id, created.id);
+    }
+
+    #[sqlx::test(migrator = "crate::database::MIGRATOR")]
+    async fn verified_email_links_to_a_verified_account(pool: PgPool) {
+        let users = PgUserRepository::new(pool.clone());
+        let sessions = MemorySessionRepository::new();
+        let hasher = hasher();
+
+        let registered = register(&users, &hasher).await;
+        sqlx::query!(
+            "UPDATE auth.users SET email_verified = TRUE WHERE id = $1",
+            registered.id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let session_id = sign_in(&sessions, &registered).await;
+
+        let (user, resolution) = resolve_user(&pool, &users, &sessions, &identity(true), &hasher)
+            .await
+            .unwrap();
+
+        assert_eq!(resolution, IdentityResolution::LinkedByEmail);
+        assert_eq!(user.id, registered.id);
+        assert_eq!(user.password_hash, registered.password_hash);
+        assert!(sessions.get(&session_id).await.is_ok());
+    }
+
+    /// An unverified account may have been registered by someone else ahead
+    /// of the address's owner, who must not inherit their password or
+    /// sessions.
+    #[sqlx::test(migrator = "crate::database::MIGRATOR")]
+    async fn verified_email_takes_over_an_unverified_account(pool: PgPool) {
+        let users = PgUserRepository::new(pool.clone());
+        let sessions = MemorySessionRepository::new();
+        let hasher = hasher();
+
+        let registered = register(&users, &hasher).await;
+        let session_id = sign_in(&sessions, &registered).await;
+
+        let (user, resolution) = resolve_user(&pool, &users, &sessions, &identity(true), &hasher)
+            .await
+            .unwrap();
+
+        assert_eq!(resolution, IdentityResolution::LinkedByEmail);
+        assert_eq!(user.id, registered.id);
+        assert!(user.email_verified);
+        assert_ne!(user.password_hash, registered.password_hash);
+        assert!(!hasher
+            .verify("registered-password", &user.password_hash)
+            .await
+            .unwrap());
+        assert!(sessions.get(&session_id).await.is_err());
+    }
+
+    #[sqlx::test(migrator = "crate::database::MIGRATOR")]
+    async fn unverified_provider_email_is_refused(pool: PgPool) {
+        let users = PgUserRepository::new(pool.clone());
+        let sessions = MemorySessionRepository::new();
+        let hasher = hasher();
+
+        register(&users, &hasher).await;
+
+        let result = resolve_user(&pool, &users, &sessions, &identity(false), &hasher).await;
+
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert_eq!(
+            find_linked_user(&pool, "stub", "stub-subject")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
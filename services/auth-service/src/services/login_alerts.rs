@@ -0,0 +1,239 @@
+use chrono::{Duration, Utc};
+use ipnet::IpNet;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_token, hash_token},
+    config::Config,
+    errors::AppError,
+    models::{Session, User},
+    repositories::SessionRepository,
+    services::{audit::ClientContext, mailer::EmailMessage},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoginAlertReason {
+    NewDevice,
+    DormantAccount,
+}
+
+impl LoginAlertReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginAlertReason::NewDevice => "new_device",
+            LoginAlertReason::DormantAccount => "dormant_account",
+        }
+    }
+}
+
+/// A login alert that was just acted on through its "this wasn't me" link.
+#[derive(Debug, Clone)]
+pub struct DeniedLogin {
+    pub alert_id: Uuid,
+    pub user_id: Uuid,
+    pub session_id: Uuid,
+    pub sessions_revoked: u64,
+}
+
+/// Decides whether a successful login deserves a notice, based on the user's
+/// previous sign-ins. Must run before `remember_device` records this one.
+pub async fn assess(
+    sessions: &dyn SessionRepository,
+    user: &User,
+    client: &ClientContext,
+    config: &Config,
+) -> Result<Option<LoginAlertReason>, AppError> {
+    // Nothing to compare against on the very first sign-in
+    let last_login = match user.last_login {
+        Some(last_login) => last_login,
+        None => return Ok(None),
+    };
+
+    if last_login < Utc::now() - Duration::days(config.dormant_account_days) {
+        return Ok(Some(LoginAlertReason::DormantAccount));
+    }
+
+    let since = Utc::now() - Duration::days(config.known_device_window_days);
+    let known_device = sessions
+        .seen_device(
+            user.id,
+            client.user_agent.as_deref(),
+            device_network(client).as_deref(),
+            since,
+        )
+        .await?;
+
+    Ok((!known_device).then_some(LoginAlertReason::NewDevice))
+}
+
+/// Adds this sign-in to the user's known devices.
+pub async fn remember_device(
+    sessions: &dyn SessionRepository,
+    user: &User,
+    client: &ClientContext,
+) -> Result<(), AppError> {
+    sessions
+        .record_device(
+            user.id,
+            client.user_agent.as_deref(),
+            device_network(client).as_deref(),
+        )
+        .await
+}
+
+/// The network a device is recognised by: the /24 or /64 around its
+/// address, so a new lease from the same provider isn't a new device.
+fn device_network(client: &ClientContext) -> Option<String> {
+    let ip: IpAddr = client.ip_address.as_deref()?.parse().ok()?;
+    let prefix = match ip {
+        IpAddr::V4(_) => 24,
+        IpAddr::V6(_) => 64,
+    };
+
+    IpNet::new(ip, prefix)
+        .ok()
+        .map(|net| net.trunc().to_string())
+}
+
+/// Records an alert for a new session and returns the notice to send.
+pub async fn create_alert(
+    pool: &PgPool,
+    user: &User,
+    session: &Session,
+    reason: LoginAlertReason,
+    config: &Config,
+) -> Result<EmailMessage, AppError> {
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::seconds(config.login_alert_ttl);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO auth.login_alerts
+            (user_id, session_id, reason, ip_address, user_agent, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        user.id,
+        session.id,
+        reason.as_str(),
+        session.ip_address,
+        session.user_agent,
+        hash_token(&token),
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(alert_email(user, session, reason, &token, config))
+}
+
+/// Handles a "this wasn't me" click: revokes every session of the user,
+/// including the flagged one, and requires a password reset before the
+/// account can sign in again. Each alert link works only once.
+pub async fn deny(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    token: &str,
+) -> Result<DeniedLogin, AppError> {
+    let alert = sqlx::query!(
+        r#"
+        UPDATE auth.login_alerts
+        SET denied_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND denied_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING id, user_id, session_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired link".to_string()))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.users
+        SET password_reset_required = TRUE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        "#,
+        alert.user_id
+    )
+    .execute(pool)
+    .await?;
+
+    let sessions_revoked = sessions.delete_for_user(alert.user_id).await?;
+
+    Ok(DeniedLogin {
+        alert_id: alert.id,
+        user_id: alert.user_id,
+        session_id: alert.session_id,
+        sessions_revoked,
+    })
+}
+
+fn alert_email(
+    user: &User,
+    session: &Session,
+    reason: LoginAlertReason,
+    token: &str,
+    config: &Config,
+) -> EmailMessage {
+    let deny_link = format!(
+        "{}/auth/login-alerts/deny?token={}",
+        config.public_url, token
+    );
+    let intro = match reason {
+        LoginAlertReason::NewDevice => "We noticed a sign-in to your BookMarket account from a device or location we haven't seen recently.",
+        LoginAlertReason::DormantAccount => "Your BookMarket account was just signed into after a long period of inactivity.",
+    };
+
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "New sign-in to your BookMarket account".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             {}\n\n\
+             Time: {}\n\
+             IP address: {}\n\
+             Device: {}\n\n\
+             If this was you, no action is needed.\n\n\
+             If this wasn't you, open the link below. We will sign out every session \
+             on your account and ask you to choose a new password:\n\n\
+             {}\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            intro,
+            session.created_at.format("%Y-%m-%d %H:%M UTC"),
+            session.ip_address.as_deref().unwrap_or("unknown"),
+            session.user_agent.as_deref().unwrap_or("unknown"),
+            deny_link
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(ip_address: &str) -> Option<String> {
+        device_network(&ClientContext {
+            ip_address: Some(ip_address.to_string()),
+            user_agent: None,
+        })
+    }
+
+    #[test]
+    fn devices_are_recognised_by_network() {
+        assert_eq!(network("203.0.113.7").as_deref(), Some("203.0.113.0/24"));
+        assert_eq!(network("203.0.113.250"), network("203.0.113.7"));
+        assert_ne!(network("203.0.114.7"), network("203.0.113.7"));
+
+        assert_eq!(
+            network("2001:db8:0:1:abcd::42").as_deref(),
+            Some("2001:db8:0:1::/64")
+        );
+        assert_eq!(network("2001:db8:0:1::7"), network("2001:db8:0:1:abcd::42"));
+        assert_ne!(network("2001:db8:0:2::7"), network("2001:db8:0:1::7"));
+
+        assert_eq!(network("unknown"), None);
+        assert_eq!(device_network(&ClientContext::default()), None);
+    }
+}
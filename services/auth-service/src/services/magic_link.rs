@@ -0,0 +1,108 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::{create_magic_link_token, decode_magic_link_token, hash_token},
+    config::Config,
+    errors::AppError,
+    models::User,
+    services::mailer::EmailMessage,
+};
+
+/// Creates a sign-in link for `user` that can only be redeemed by the
+/// browser holding `nonce`, and returns the signed token for the link.
+/// Earlier links of the user that were not used yet stop working.
+pub async fn issue_link(
+    pool: &PgPool,
+    user: &User,
+    nonce: &str,
+    config: &Config,
+) -> Result<String, AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.magic_links
+        SET used_at = CURRENT_TIMESTAMP
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user.id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let expires_at = Utc::now() + Duration::seconds(config.magic_link_ttl);
+    let link_id = sqlx::query_scalar!(
+        r#"
+        INSERT INTO auth.magic_links (user_id, nonce_hash, expires_at)
+        VALUES ($1, $2, $3)
+        RETURNING id
+        "#,
+        user.id,
+        hash_token(nonce),
+        expires_at
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    create_magic_link_token(user.id, link_id, config.magic_link_ttl, &config.jwt_secret)
+}
+
+/// Consumes a sign-in link and returns the user it was issued to. The token
+/// signature, the requesting browser's nonce, expiry and single use are all
+/// checked; any failure reads the same to the caller.
+pub async fn redeem_link(
+    pool: &PgPool,
+    token: &str,
+    nonce: &str,
+    config: &Config,
+) -> Result<Uuid, AppError> {
+    let invalid = || AppError::BadRequest("Invalid or expired sign-in link".to_string());
+
+    let claims = decode_magic_link_token(token, &config.jwt_secrets()).map_err(|_| invalid())?;
+    let link_id = Uuid::parse_str(&claims.jti).map_err(|_| invalid())?;
+
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE auth.magic_links
+        SET used_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND nonce_hash = $2
+          AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id
+        "#,
+        link_id,
+        hash_token(nonce)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(invalid)?;
+
+    if user_id.to_string() != claims.sub {
+        return Err(invalid());
+    }
+
+    Ok(user_id)
+}
+
+pub fn magic_link_email(user: &User, token: &str, config: &Config) -> EmailMessage {
+    let link = format!("{}/auth/magic-link?token={}", config.frontend_url, token);
+    let minutes = config.magic_link_ttl / 60;
+
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "Your BookMarket sign-in link".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             Use the link below to sign in to BookMarket:\n\n\
+             {}\n\n\
+             The link expires in {} minutes, works only once and only in the browser \
+             where you asked for it. If you did not ask to sign in you can ignore this email.\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            link,
+            minutes
+        ),
+    }
+}
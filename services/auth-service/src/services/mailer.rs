@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use lettre::{message::Mailbox, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::Arc;
+
+use crate::{config::Config, errors::AppError};
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Outbound transactional email.
+#[async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, message: EmailMessage) -> Result<(), AppError>;
+}
+
+/// Writes emails to the log instead of delivering them. Used when no SMTP
+/// server is configured, which is the normal local development setup.
+pub struct LogEmailSender;
+
+#[async_trait]
+impl EmailSender for LogEmailSender {
+    async fn send(&self, message: EmailMessage) -> Result<(), AppError> {
+        tracing::info!(
+            to = %message.to,
+            subject = %message.subject,
+            "Email not delivered (no SMTP configured):\n{}",
+            message.body
+        );
+
+        Ok(())
+    }
+}
+
+pub struct SmtpEmailSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpEmailSender {
+    pub fn new(smtp_url: &str, from: &str) -> Result<Self, AppError> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)
+            .map_err(|e| AppError::InternalServerError(format!("Invalid SMTP URL: {}", e)))?
+            .build();
+        let from = from
+            .parse()
+            .map_err(|e| AppError::InternalServerError(format!("Invalid sender address: {}", e)))?;
+
+        Ok(Self { transport, from })
+    }
+}
+
+#[async_trait]
+impl EmailSender for SmtpEmailSender {
+    async fn send(&self, message: EmailMessage) -> Result<(), AppError> {
+        let to: Mailbox = message
+            .to
+            .parse()
+            .map_err(|e| AppError::BadRequest(format!("Invalid recipient address: {}", e)))?;
+
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(message.subject)
+            .body(message.body)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to build email: {}", e)))?;
+
+        self.transport
+            .send(email)
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("SMTP delivery failed: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+pub fn from_config(config: &Config) -> Result<Arc<dyn EmailSender>, AppError> {
+    match config.smtp_url.as_deref() {
+        Some(url) if !url.is_empty() => Ok(Arc::new(SmtpEmailSender::new(url, &config.mail_from)?)),
+        _ => Ok(Arc::new(LogEmailSender)),
+    }
+}
+
+/// Sends an email without holding up the request that triggered it.
+pub fn send_in_background(mailer: Arc<dyn EmailSender>, message: EmailMessage) {
+    tokio::spawn(async move {
+        let to = message.to.clone();
+        if let Err(e) = mailer.send(message).await {
+            tracing::error!("Failed to send email to {}: {}", to, e);
+        }
+    });
+}
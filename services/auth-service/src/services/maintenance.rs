@@ -0,0 +1,480 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::time::Duration;
+
+use crate::{
+    auth::generate_token,
+    config::Config,
+    errors::AppError,
+    handlers::metrics,
+    models::{AuditEventType, AuditOutcome},
+    repositories::{AuditRepository, SessionRepository},
+    services::{
+        audit::{ClientContext, NewAuditEvent},
+        email_verification, mailer, outbox, personal_data,
+        scheduler::{Job, Scheduler},
+        webhooks,
+    },
+    AppState,
+};
+
+/// Reminders sent per run, so a backlog is worked off gradually rather than
+/// in one burst to the mail server.
+const REMINDER_BATCH_SIZE: i64 = 100;
+
+/// Accounts anonymised per run.
+const DELETION_BATCH_SIZE: i64 = 100;
+
+/// Registers the auth-service maintenance jobs.
+pub fn scheduler(state: AppState, config: &Config) -> Result<Scheduler, AppError> {
+    let scheduler = Scheduler::new(state)
+        .with_job(PurgeExpiredSessions {
+            interval: Duration::from_secs(config.session_cleanup_interval),
+        })
+        .with_job(DisableExpiredApiKeys {
+            interval: Duration::from_secs(config.api_key_expiry_interval),
+        })
+        .with_job(PurgeStaleTokens {
+            interval: Duration::from_secs(config.token_cleanup_interval),
+            retention_secs: config.token_retention as f64,
+            known_device_window_days: config.known_device_window_days,
+        })
+        .with_job(SendVerificationReminders {
+            interval: Duration::from_secs(config.verification_reminder_interval),
+            delay_secs: config.verification_reminder_delay as f64,
+        })
+        .with_job(AnonymiseDeletedAccounts {
+            interval: Duration::from_secs(config.account_deletion_interval),
+        })
+        .with_job(RetrySessionRevocations {
+            interval: Duration::from_secs(config.session_revocation_retry_interval),
+        })
+        .with_job(RefreshSessionGauge {
+            interval: Duration::from_secs(config.session_gauge_interval),
+        })
+        .with_job(PublishOutboxEvents {
+            interval: Duration::from_secs(config.outbox_relay_interval),
+            stream: config.outbox_stream.clone(),
+            stream_max_len: config.outbox_stream_max_len,
+            retention_secs: config.outbox_retention as f64,
+        })
+        .with_job(DeliverWebhooks::new(config));
+
+    Ok(scheduler)
+}
+
+/// Deletes expired sessions. Impersonation sessions that ran out rather than
+/// being stopped are recorded as stopped first, so every start in the audit
+/// log has an end.
+pub struct PurgeExpiredSessions {
+    pub interval: Duration,
+}
+
+#[async_trait]
+impl Job for PurgeExpiredSessions {
+    fn name(&self) -> &'static str {
+        "purge_expired_sessions"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        purge_expired_sessions(&state.db, state.sessions.as_ref(), state.audit.as_ref()).await
+    }
+}
+
+/// Deletes expired sessions, first recording the end of any impersonation
+/// among them. Shared with `bm-auth-admin purge-sessions`.
+pub async fn purge_expired_sessions(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    audit: &dyn AuditRepository,
+) -> Result<u64, AppError> {
+    let expired = sqlx::query!(
+        r#"
+        DELETE FROM auth.sessions
+        WHERE impersonator_id IS NOT NULL AND expires_at < CURRENT_TIMESTAMP
+        RETURNING id, user_id, impersonator_id as "impersonator_id!", expires_at
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for session in &expired {
+        audit
+            .record(
+                &ClientContext::default(),
+                NewAuditEvent::new(AuditEventType::ImpersonationStopped, AuditOutcome::Success)
+                    .actor(session.impersonator_id)
+                    .target(session.user_id)
+                    .details(serde_json::json!({
+                        "session_id": session.id,
+                        "reason": "expired",
+                        "expired_at": session.expires_at,
+                    })),
+            )
+            .await?;
+    }
+
+    Ok(expired.len() as u64 + sessions.delete_expired().await?)
+}
+
+/// Delivers session revocations that couldn't reach Redis when the sessions
+/// were deleted. Cached sessions aren't trusted until this has caught up.
+pub struct RetrySessionRevocations {
+    pub interval: Duration,
+}
+
+#[async_trait]
+impl Job for RetrySessionRevocations {
+    fn name(&self) -> &'static str {
+        "retry_session_revocations"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        state.sessions.retry_revocations().await
+    }
+}
+
+/// Marks keys past their expiry as disabled and leaves an audit trail for
+/// their owners. Expired keys are already refused; this records when they
+/// stopped working.
+pub struct DisableExpiredApiKeys {
+    pub interval: Duration,
+}
+
+#[async_trait]
+impl Job for DisableExpiredApiKeys {
+    fn name(&self) -> &'static str {
+        "disable_expired_api_keys"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        let disabled = sqlx::query!(
+            r#"
+            UPDATE auth.api_keys
+            SET disabled_at = CURRENT_TIMESTAMP
+            WHERE disabled_at IS NULL AND expires_at <= CURRENT_TIMESTAMP
+            RETURNING id, user_id, name
+            "#
+        )
+        .fetch_all(&state.db)
+        .await?;
+
+        for key in &disabled {
+            state
+                .audit
+                .record(
+                    &ClientContext::default(),
+                    NewAuditEvent::new(AuditEventType::ApiKeyDisabled, AuditOutcome::Success)
+                        .target(key.user_id)
+                        .details(serde_json::json!({
+                            "key_id": key.id,
+                            "name": key.name,
+                            "reason": "expired",
+                        })),
+                )
+                .await?;
+        }
+
+        Ok(disabled.len() as u64)
+    }
+}
+
+/// Deletes single-use link tokens and email change requests once they have
+/// been expired or used for longer than the retention period, and forgets
+/// devices that would no longer count as known.
+pub struct PurgeStaleTokens {
+    pub interval: Duration,
+    pub retention_secs: f64,
+    pub known_device_window_days: i64,
+}
+
+#[async_trait]
+impl Job for PurgeStaleTokens {
+    fn name(&self) -> &'static str {
+        "purge_stale_tokens"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        let reset_tokens = sqlx::query!(
+            r#"
+            DELETE FROM auth.password_reset_tokens
+            WHERE LEAST(expires_at, used_at) < CURRENT_TIMESTAMP - make_interval(secs => $1)
+            "#,
+            self.retention_secs
+        )
+        .execute(&state.db)
+        .await?
+        .rows_affected();
+
+        let verification_tokens = sqlx::query!(
+            r#"
+            DELETE FROM auth.email_verification_tokens
+            WHERE LEAST(expires_at, used_at) < CURRENT_TIMESTAMP - make_interval(secs => $1)
+            "#,
+            self.retention_secs
+        )
+        .execute(&state.db)
+        .await?
+        .rows_affected();
+
+        let email_changes = sqlx::query!(
+            r#"
+            DELETE FROM auth.email_change_requests
+            WHERE LEAST(expires_at, confirmed_at, cancelled_at)
+                  < CURRENT_TIMESTAMP - make_interval(secs => $1)
+            "#,
+            self.retention_secs
+        )
+        .execute(&state.db)
+        .await?
+        .rows_affected();
+
+        let magic_links = sqlx::query!(
+            r#"
+            DELETE FROM auth.magic_links
+            WHERE LEAST(expires_at, used_at) < CURRENT_TIMESTAMP - make_interval(secs => $1)
+            "#,
+            self.retention_secs
+        )
+        .execute(&state.db)
+        .await?
+        .rows_affected();
+
+        let login_alerts = sqlx::query!(
+            r#"
+            DELETE FROM auth.login_alerts
+            WHERE expires_at < CURRENT_TIMESTAMP - make_interval(secs => $1)
+            "#,
+            self.retention_secs
+        )
+        .execute(&state.db)
+        .await?
+        .rows_affected();
+
+        let known_devices = state
+            .sessions
+            .delete_devices_before(
+                chrono::Utc::now() - chrono::Duration::days(self.known_device_window_days),
+            )
+            .await?;
+
+        Ok(reset_tokens
+            + verification_tokens
+            + email_changes
+            + magic_links
+            + login_alerts
+            + known_devices)
+    }
+}
+
+/// Sends one reminder, with a fresh link, to active accounts that still
+/// haven't verified their email some time after registering.
+pub struct SendVerificationReminders {
+    pub interval: Duration,
+    pub delay_secs: f64,
+}
+
+#[async_trait]
+impl Job for SendVerificationReminders {
+    fn name(&self) -> &'static str {
+        "send_verification_reminders"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        // Claiming the rows first means a failure below skips a reminder
+        // rather than sending it twice
+        let user_ids = sqlx::query_scalar!(
+            r#"
+            UPDATE auth.users
+            SET verification_reminder_sent_at = CURRENT_TIMESTAMP
+            WHERE id IN (
+                SELECT id FROM auth.users
+                WHERE NOT email_verified
+                  AND status = 'active'
+                  AND verification_reminder_sent_at IS NULL
+                  AND created_at < CURRENT_TIMESTAMP - make_interval(secs => $1)
+                ORDER BY created_at
+                LIMIT $2
+            )
+            RETURNING id
+            "#,
+            self.delay_secs,
+            REMINDER_BATCH_SIZE
+        )
+        .fetch_all(&state.db)
+        .await?;
+
+        for user_id in &user_ids {
+            let user = state.users.get_by_id(&user_id.to_string()).await?;
+            let token =
+                email_verification::issue_token(state.users.as_ref(), user.id, &state.config)
+                    .await?;
+            mailer::send_in_background(
+                state.mailer.clone(),
+                email_verification::reminder_email(&user, &token, &state.config),
+            );
+        }
+
+        Ok(user_ids.len() as u64)
+    }
+}
+
+/// Anonymises accounts whose deletion cooling-off period has ended.
+pub struct AnonymiseDeletedAccounts {
+    pub interval: Duration,
+}
+
+#[async_trait]
+impl Job for AnonymiseDeletedAccounts {
+    fn name(&self) -> &'static str {
+        "anonymise_deleted_accounts"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        let user_ids = personal_data::due_deletions(&state.db, DELETION_BATCH_SIZE).await?;
+
+        for user_id in &user_ids {
+            let unusable_password_hash = state.password_hasher.hash(&generate_token()).await?;
+            personal_data::anonymise(
+                &state.db,
+                state.sessions.as_ref(),
+                *user_id,
+                &unusable_password_hash,
+            )
+            .await?;
+
+            state
+                .audit
+                .record(
+                    &ClientContext::default(),
+                    NewAuditEvent::new(AuditEventType::AccountDeleted, AuditOutcome::Success)
+                        .target(*user_id),
+                )
+                .await?;
+        }
+
+        Ok(user_ids.len() as u64)
+    }
+}
+
+/// Counts unexpired sessions for the `auth_active_sessions` gauge, so a
+/// scrape doesn't have to. Every replica keeps its own gauge current.
+pub struct RefreshSessionGauge {
+    pub interval: Duration,
+}
+
+#[async_trait]
+impl Job for RefreshSessionGauge {
+    fn name(&self) -> &'static str {
+        "refresh_session_gauge"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn exclusive(&self) -> bool {
+        false
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        let active = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM auth.sessions WHERE expires_at > CURRENT_TIMESTAMP"#
+        )
+        .fetch_one(&state.db)
+        .await?;
+
+        metrics::set_active_sessions(active);
+
+        Ok(active as u64)
+    }
+}
+
+/// Relays user lifecycle events from the outbox to the Redis stream other
+/// services consume, then drops events published longer ago than the
+/// retention period. Exclusive, so events leave in the order they were written.
+pub struct PublishOutboxEvents {
+    pub interval: Duration,
+    pub stream: String,
+    pub stream_max_len: usize,
+    pub retention_secs: f64,
+}
+
+#[async_trait]
+impl Job for PublishOutboxEvents {
+    fn name(&self) -> &'static str {
+        "publish_outbox_events"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        let published =
+            outbox::publish_pending(&state.db, &state.redis, &self.stream, self.stream_max_len)
+                .await?;
+        outbox::prune_published(&state.db, self.retention_secs).await?;
+
+        Ok(published)
+    }
+}
+
+/// Sends due webhook deliveries. Exclusive, so replicas don't post the same
+/// delivery twice.
+pub struct DeliverWebhooks {
+    pub interval: Duration,
+    pub max_attempts: u32,
+    pub retry_base: Duration,
+    pub timeout: Duration,
+}
+
+impl DeliverWebhooks {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            interval: Duration::from_secs(config.webhook_delivery_interval),
+            max_attempts: config.webhook_max_attempts,
+            retry_base: Duration::from_secs(config.webhook_retry_base),
+            timeout: Duration::from_secs(config.webhook_timeout),
+        }
+    }
+}
+
+#[async_trait]
+impl Job for DeliverWebhooks {
+    fn name(&self) -> &'static str {
+        "deliver_webhooks"
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    async fn run(&self, state: &AppState) -> Result<u64, AppError> {
+        webhooks::deliver_due(&state.db, self.timeout, self.max_attempts, self.retry_base).await
+    }
+}
@@ -1 +1,28 @@
-pub mod user_service;
+pub mod accounts;
+pub mod audit;
+pub mod csv_export;
+pub mod email_change;
+pub mod email_verification;
+pub mod identities;
+pub mod login_alerts;
+pub mod magic_link;
+pub mod mailer;
+pub mod maintenance;
+pub mod oidc;
+pub mod organizations;
+pub mod outbox;
+pub mod password_hasher;
+pub mod password_policy;
+pub mod password_reset;
+pub mod password_strength;
+pub mod personal_data;
+pub mod phone;
+pub mod rate_limit;
+pub mod redis_connection;
+pub mod scheduler;
+pub mod session_cache;
+pub mod sms;
+pub mod tokens;
+pub mod totp;
+pub mod user_search;
+pub mod webhooks;
@@ -0,0 +1,456 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_token, hash_token},
+    config::Config,
+    errors::AppError,
+    models::{OrgContext, OrgInvitation, OrgMember, OrgMembership, OrgRole, Organization, User},
+    services::mailer::EmailMessage,
+};
+
+/// Creates an organisation with `owner` as its first owner.
+pub async fn create(pool: &PgPool, name: &str, owner: Uuid) -> Result<Organization, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+        INSERT INTO auth.organizations (name, created_by)
+        VALUES ($1, $2)
+        RETURNING id, name, created_by, created_at, updated_at
+        "#,
+        name,
+        owner
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO auth.organization_members (org_id, user_id, role) VALUES ($1, $2, 'owner')",
+        organization.id,
+        owner
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(organization)
+}
+
+pub async fn get(pool: &PgPool, org_id: Uuid) -> Result<Organization, AppError> {
+    sqlx::query_as!(
+        Organization,
+        "SELECT id, name, created_by, created_at, updated_at FROM auth.organizations WHERE id = $1",
+        org_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+pub async fn list_for_user(pool: &PgPool, user_id: Uuid) -> Result<Vec<OrgMembership>, AppError> {
+    let memberships = sqlx::query_as!(
+        OrgMembership,
+        r#"
+        SELECT m.org_id, o.name as org_name, m.role as "role: OrgRole", m.created_at as joined_at
+        FROM auth.organization_members m
+        JOIN auth.organizations o ON o.id = m.org_id
+        WHERE m.user_id = $1
+        ORDER BY o.name
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(memberships)
+}
+
+/// The user's role in an organisation, if they belong to it.
+pub async fn membership(
+    pool: &PgPool,
+    org_id: Uuid,
+    user_id: Uuid,
+) -> Result<Option<OrgContext>, AppError> {
+    let role = sqlx::query_scalar!(
+        r#"
+        SELECT role as "role: OrgRole" FROM auth.organization_members
+        WHERE org_id = $1 AND user_id = $2
+        "#,
+        org_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(role.map(|role| OrgContext { org_id, role }))
+}
+
+/// Fails with `Forbidden` unless the user belongs to the organisation, and
+/// with one of `roles` when any are given.
+pub async fn require_role(
+    pool: &PgPool,
+    org_id: Uuid,
+    user_id: Uuid,
+    roles: &[OrgRole],
+) -> Result<OrgContext, AppError> {
+    match membership(pool, org_id, user_id).await? {
+        Some(org) if roles.is_empty() || roles.contains(&org.role) => Ok(org),
+        _ => Err(AppError::Forbidden),
+    }
+}
+
+/// The organisation a fresh sign-in acts for: the one the user last switched
+/// to, or the first they joined.
+pub async fn default_for_user(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<OrgContext>, AppError> {
+    let org = sqlx::query!(
+        r#"
+        SELECT org_id, role as "role: OrgRole" FROM auth.organization_members
+        WHERE user_id = $1
+        ORDER BY last_selected_at DESC NULLS LAST, created_at
+        LIMIT 1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(org.map(|org| OrgContext {
+        org_id: org.org_id,
+        role: org.role,
+    }))
+}
+
+/// Remembers the organisation as the user's default for later sign-ins.
+pub async fn mark_selected(pool: &PgPool, org_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    sqlx::query!(
+        r#"
+        UPDATE auth.organization_members SET last_selected_at = CURRENT_TIMESTAMP
+        WHERE org_id = $1 AND user_id = $2
+        "#,
+        org_id,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list_members(pool: &PgPool, org_id: Uuid) -> Result<Vec<OrgMember>, AppError> {
+    let members = sqlx::query_as!(
+        OrgMember,
+        r#"
+        SELECT m.user_id, u.email, u.first_name, u.last_name, m.role as "role: OrgRole",
+               m.created_at as joined_at
+        FROM auth.organization_members m
+        JOIN auth.users u ON u.id = m.user_id
+        WHERE m.org_id = $1
+        ORDER BY m.created_at
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(members)
+}
+
+/// Changes a member's role. An organisation always keeps at least one owner.
+pub async fn update_member_role(
+    pool: &PgPool,
+    org_id: Uuid,
+    user_id: Uuid,
+    role: OrgRole,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+    ensure_other_owner(&mut tx, org_id, user_id, Some(role)).await?;
+
+    let result = sqlx::query!(
+        "UPDATE auth.organization_members SET role = $3 WHERE org_id = $1 AND user_id = $2",
+        org_id,
+        user_id,
+        role.as_str()
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Removes a member. The last owner can't leave; they must hand over first.
+pub async fn remove_member(pool: &PgPool, org_id: Uuid, user_id: Uuid) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+    ensure_other_owner(&mut tx, org_id, user_id, None).await?;
+
+    let result = sqlx::query!(
+        "DELETE FROM auth.organization_members WHERE org_id = $1 AND user_id = $2",
+        org_id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Refuses to leave the organisation without an owner when `user_id` stops
+/// being one. Locks the owner rows so concurrent changes can't both pass.
+async fn ensure_other_owner(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    org_id: Uuid,
+    user_id: Uuid,
+    new_role: Option<OrgRole>,
+) -> Result<(), AppError> {
+    if new_role == Some(OrgRole::Owner) {
+        return Ok(());
+    }
+
+    let owners = sqlx::query_scalar!(
+        r#"
+        SELECT user_id FROM auth.organization_members
+        WHERE org_id = $1 AND role = 'owner'
+        FOR UPDATE
+        "#,
+        org_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+
+    if owners == [user_id] {
+        return Err(AppError::BadRequest(
+            "An organisation needs at least one owner".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Invites an email address to join with `role` and returns the invitation
+/// with its raw token. A newer invitation to the same address replaces any
+/// pending one.
+pub async fn create_invitation(
+    pool: &PgPool,
+    org_id: Uuid,
+    email: &str,
+    role: OrgRole,
+    invited_by: Uuid,
+    config: &Config,
+) -> Result<(OrgInvitation, String), AppError> {
+    let already_member = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM auth.organization_members m
+            JOIN auth.users u ON u.id = m.user_id
+            WHERE m.org_id = $1 AND lower(u.email) = lower($2)
+        ) as "exists!"
+        "#,
+        org_id,
+        email
+    )
+    .fetch_one(pool)
+    .await?;
+    if already_member {
+        return Err(AppError::Conflict(
+            "This person is already a member of the organisation".to_string(),
+        ));
+    }
+
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::seconds(config.org_invitation_ttl);
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM auth.organization_invitations
+        WHERE org_id = $1 AND lower(email) = lower($2) AND responded_at IS NULL
+        "#,
+        org_id,
+        email
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let invitation = sqlx::query_as!(
+        OrgInvitation,
+        r#"
+        INSERT INTO auth.organization_invitations
+            (org_id, email, role, token_hash, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, org_id, email, role as "role: OrgRole", invited_by, expires_at, created_at
+        "#,
+        org_id,
+        email,
+        role.as_str(),
+        hash_token(&token),
+        invited_by,
+        expires_at
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((invitation, token))
+}
+
+pub async fn list_invitations(pool: &PgPool, org_id: Uuid) -> Result<Vec<OrgInvitation>, AppError> {
+    let invitations = sqlx::query_as!(
+        OrgInvitation,
+        r#"
+        SELECT id, org_id, email, role as "role: OrgRole", invited_by, expires_at, created_at
+        FROM auth.organization_invitations
+        WHERE org_id = $1 AND responded_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        ORDER BY created_at DESC
+        "#,
+        org_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(invitations)
+}
+
+pub async fn revoke_invitation(
+    pool: &PgPool,
+    org_id: Uuid,
+    invitation_id: Uuid,
+) -> Result<(), AppError> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM auth.organization_invitations
+        WHERE id = $1 AND org_id = $2 AND responded_at IS NULL
+        "#,
+        invitation_id,
+        org_id
+    )
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Accepts an invitation on behalf of `user`, whose email must be the one
+/// that was invited.
+pub async fn accept_invitation(
+    pool: &PgPool,
+    token: &str,
+    user: &User,
+) -> Result<OrgInvitation, AppError> {
+    let mut tx = pool.begin().await?;
+
+    let invitation = sqlx::query_as!(
+        OrgInvitation,
+        r#"
+        UPDATE auth.organization_invitations
+        SET responded_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND responded_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+          AND lower(email) = lower($2)
+        RETURNING id, org_id, email, role as "role: OrgRole", invited_by, expires_at, created_at
+        "#,
+        hash_token(token),
+        user.email
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired invitation".to_string()))?;
+
+    sqlx::query!(
+        "INSERT INTO auth.organization_members (org_id, user_id, role) VALUES ($1, $2, $3)",
+        invitation.org_id,
+        user.id,
+        invitation.role.as_str()
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+            AppError::Conflict("You are already a member of this organisation".to_string())
+        }
+        e => AppError::from(e),
+    })?;
+
+    tx.commit().await?;
+
+    Ok(invitation)
+}
+
+/// Declines an invitation. Holding the token is enough, so the link in the
+/// email works without signing in.
+pub async fn decline_invitation(pool: &PgPool, token: &str) -> Result<OrgInvitation, AppError> {
+    sqlx::query_as!(
+        OrgInvitation,
+        r#"
+        UPDATE auth.organization_invitations
+        SET responded_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND responded_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING id, org_id, email, role as "role: OrgRole", invited_by, expires_at, created_at
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired invitation".to_string()))
+}
+
+pub fn invitation_email(
+    organization: &Organization,
+    inviter: &User,
+    invitation: &OrgInvitation,
+    token: &str,
+    config: &Config,
+) -> EmailMessage {
+    let link = format!("{}/orgs/invitations?token={}", config.frontend_url, token);
+    let inviter_name = match (&inviter.first_name, &inviter.last_name) {
+        (Some(first), Some(last)) => format!("{} {}", first, last),
+        (Some(first), None) => first.clone(),
+        _ => inviter.email.clone(),
+    };
+
+    EmailMessage {
+        to: invitation.email.clone(),
+        subject: format!("You're invited to join {} on BookMarket", organization.name),
+        body: format!(
+            "Hello,\n\n\
+             {} invited you to help run {} on BookMarket as {}.\n\n\
+             Accept or decline the invitation here:\n\n\
+             {}\n\n\
+             You'll need to sign in, or create an account, with this email address to accept. \
+             The invitation expires in {} days.\n",
+            inviter_name,
+            organization.name,
+            role_label(invitation.role),
+            link,
+            config.org_invitation_ttl / 86400
+        ),
+    }
+}
+
+fn role_label(role: OrgRole) -> &'static str {
+    match role {
+        OrgRole::Owner => "an owner",
+        OrgRole::CatalogManager => "catalog manager",
+        OrgRole::Fulfilment => "fulfilment staff",
+        OrgRole::Accountant => "accountant",
+    }
+}
@@ -0,0 +1,242 @@
+//! Transactional outbox for user lifecycle events.
+//!
+//! Code that changes a user calls [`enqueue`] on the same transaction, so an
+//! event exists exactly when its change committed. [`publish_pending`] later
+//! copies unpublished rows, in order, to a Redis stream. A crash between the
+//! `XADD` and marking the row published sends the event again, so delivery is
+//! at least once and consumers deduplicate on the `id` field.
+//!
+//! Each stream entry has the fields `id`, `type`, `user_id`, `occurred_at`
+//! (RFC 3339) and `payload` (a JSON object).
+
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::{User, UserRole},
+};
+
+/// Events published per round trip to Redis.
+const PUBLISH_BATCH_SIZE: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventType {
+    UserRegistered,
+    UserEmailVerified,
+    UserSuspended,
+    UserActivated,
+    UserRoleChanged,
+    UserDeleted,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::UserRegistered => "user.registered",
+            EventType::UserEmailVerified => "user.email_verified",
+            EventType::UserSuspended => "user.suspended",
+            EventType::UserActivated => "user.activated",
+            EventType::UserRoleChanged => "user.role_changed",
+            EventType::UserDeleted => "user.deleted",
+        }
+    }
+}
+
+/// An event about to be written to the outbox.
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub event_type: EventType,
+    pub user_id: Uuid,
+    pub payload: serde_json::Value,
+}
+
+impl OutboxEvent {
+    fn new(event_type: EventType, user_id: Uuid, payload: serde_json::Value) -> Self {
+        Self {
+            event_type,
+            user_id,
+            payload,
+        }
+    }
+
+    pub fn user_registered(user: &User) -> Self {
+        Self::new(
+            EventType::UserRegistered,
+            user.id,
+            serde_json::json!({
+                "email": user.email,
+                "role": user.role.as_str(),
+            }),
+        )
+    }
+
+    pub fn user_email_verified(user_id: Uuid, email: &str) -> Self {
+        Self::new(
+            EventType::UserEmailVerified,
+            user_id,
+            serde_json::json!({ "email": email }),
+        )
+    }
+
+    pub fn user_suspended(user_id: Uuid) -> Self {
+        Self::new(EventType::UserSuspended, user_id, serde_json::json!({}))
+    }
+
+    pub fn user_activated(user_id: Uuid) -> Self {
+        Self::new(EventType::UserActivated, user_id, serde_json::json!({}))
+    }
+
+    pub fn user_role_changed(user_id: Uuid, from: &UserRole, to: &UserRole) -> Self {
+        Self::new(
+            EventType::UserRoleChanged,
+            user_id,
+            serde_json::json!({
+                "from": from.as_str(),
+                "to": to.as_str(),
+            }),
+        )
+    }
+
+    /// The account has been anonymised; consumers should drop what they hold
+    /// about the user.
+    pub fn user_deleted(user_id: Uuid) -> Self {
+        Self::new(EventType::UserDeleted, user_id, serde_json::json!({}))
+    }
+}
+
+/// Writes `event` on `conn`, which should be the transaction making the
+/// change the event describes.
+#[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+pub async fn enqueue(conn: &mut PgConnection, event: OutboxEvent) -> Result<(), AppError> {
+    sqlx::query!(
+        r#"
+        INSERT INTO auth.outbox_events (event_type, user_id, payload)
+        VALUES ($1, $2, $3)
+        "#,
+        event.event_type.as_str(),
+        event.user_id,
+        event.payload
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+struct PendingEvent {
+    id: Uuid,
+    event_type: String,
+    user_id: Uuid,
+    payload: serde_json::Value,
+    created_at: DateTime<Utc>,
+}
+
+/// Publishes unpublished events to `stream` in the order they were written,
+/// until none are left. Callers must not run this concurrently, or events
+/// could be published out of order.
+///
+/// `max_len` caps the stream approximately; consumers that fall further
+/// behind than that lose events.
+pub async fn publish_pending(
+    pool: &PgPool,
+    redis: &redis::Client,
+    stream: &str,
+    max_len: usize,
+) -> Result<u64, AppError> {
+    let mut conn = redis.get_async_connection().await?;
+    let mut published = 0;
+
+    loop {
+        let batch = sqlx::query_as!(
+            PendingEvent,
+            r#"
+            SELECT id, event_type, user_id, payload, created_at
+            FROM auth.outbox_events
+            WHERE published_at IS NULL
+            ORDER BY sequence
+            LIMIT $1
+            "#,
+            PUBLISH_BATCH_SIZE
+        )
+        .fetch_all(pool)
+        .await?;
+        if batch.is_empty() {
+            return Ok(published);
+        }
+
+        let mut pipe = redis::pipe();
+        for event in &batch {
+            pipe.cmd("XADD")
+                .arg(stream)
+                .arg("MAXLEN")
+                .arg("~")
+                .arg(max_len)
+                .arg("*")
+                .arg("id")
+                .arg(event.id.to_string())
+                .arg("type")
+                .arg(&event.event_type)
+                .arg("user_id")
+                .arg(event.user_id.to_string())
+                .arg("occurred_at")
+                .arg(event.created_at.to_rfc3339())
+                .arg("payload")
+                .arg(event.payload.to_string())
+                .ignore();
+        }
+
+        let ids: Vec<Uuid> = batch.iter().map(|event| event.id).collect();
+        if let Err(e) = pipe.query_async::<_, ()>(&mut conn).await {
+            // Entries before the failure may have been added; they are sent
+            // again next time, which consumers tolerate
+            sqlx::query!(
+                r#"
+                UPDATE auth.outbox_events
+                SET attempts = attempts + 1, last_error = $2
+                WHERE id = ANY($1)
+                "#,
+                &ids,
+                e.to_string()
+            )
+            .execute(pool)
+            .await?;
+
+            return Err(e.into());
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE auth.outbox_events
+            SET published_at = CURRENT_TIMESTAMP, attempts = attempts + 1, last_error = NULL
+            WHERE id = ANY($1)
+            "#,
+            &ids
+        )
+        .execute(pool)
+        .await?;
+
+        published += batch.len() as u64;
+        if (batch.len() as i64) < PUBLISH_BATCH_SIZE {
+            return Ok(published);
+        }
+    }
+}
+
+/// Deletes events published more than `retention_secs` ago.
+#[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
+pub async fn prune_published(pool: &PgPool, retention_secs: f64) -> Result<u64, AppError> {
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM auth.outbox_events
+        WHERE published_at < CURRENT_TIMESTAMP - make_interval(secs => $1)
+        "#,
+        retention_secs
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(deleted)
+}
@@ -0,0 +1,281 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use rand::RngCore;
+
+use crate::{config::Config, errors::AppError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Argon2id,
+    Bcrypt,
+}
+
+/// Hashes passwords as PHC strings and verifies anything we have ever stored.
+///
+/// New hashes use Argon2id unless bcrypt is configured. Verification picks
+/// the algorithm from the stored string, so accounts created before the
+/// switch keep working; `needs_rehash` tells the login path when a stored
+/// hash is weaker than the current settings and should be replaced.
+#[derive(Debug, Clone)]
+pub struct PasswordHasher {
+    algorithm: HashAlgorithm,
+    argon2_params: Params,
+    bcrypt_cost: u32,
+}
+
+impl PasswordHasher {
+    pub fn from_config(config: &Config) -> Result<Self, AppError> {
+        let algorithm = match config.password_hash_algorithm.as_str() {
+            "argon2id" => HashAlgorithm::Argon2id,
+            "bcrypt" => HashAlgorithm::Bcrypt,
+            other => {
+                return Err(AppError::InternalServerError(format!(
+                    "Unknown password hash algorithm: {}",
+                    other
+                )))
+            }
+        };
+        let argon2_params = Params::new(
+            config.argon2_memory_kib,
+            config.argon2_iterations,
+            config.argon2_parallelism,
+            None,
+        )
+        .map_err(|e| AppError::InternalServerError(format!("Invalid Argon2 parameters: {}", e)))?;
+
+        Ok(Self {
+            algorithm,
+            argon2_params,
+            bcrypt_cost: config.bcrypt_cost,
+        })
+    }
+
+    fn argon2(&self) -> Argon2<'static> {
+        Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            self.argon2_params.clone(),
+        )
+    }
+
+    /// Hashes on the blocking pool: a hash takes tens of milliseconds by design.
+    pub async fn hash(&self, password: &str) -> Result<String, AppError> {
+        let hasher = self.clone();
+        let password = password.to_string();
+
+        tokio::task::spawn_blocking(move || hasher.hash_blocking(&password))
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Hashing task failed: {}", e)))?
+    }
+
+    pub async fn verify(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+        let hasher = self.clone();
+        let password = password.to_string();
+        let hash = hash.to_string();
+
+        tokio::task::spawn_blocking(move || hasher.verify_blocking(&password, &hash))
+            .await
+            .map_err(|e| AppError::InternalServerError(format!("Hashing task failed: {}", e)))?
+    }
+
+    fn hash_blocking(&self, password: &str) -> Result<String, AppError> {
+        match self.algorithm {
+            HashAlgorithm::Bcrypt => {
+                bcrypt::hash(password, self.bcrypt_cost).map_err(AppError::from)
+            }
+            HashAlgorithm::Argon2id => {
+                let mut salt = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let salt = SaltString::encode_b64(&salt).map_err(argon2_error)?;
+
+                let hash = self
+                    .argon2()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map_err(argon2_error)?;
+
+                Ok(hash.to_string())
+            }
+        }
+    }
+
+    fn verify_blocking(&self, password: &str, hash: &str) -> Result<bool, AppError> {
+        if is_bcrypt(hash) {
+            return bcrypt::verify(password, hash).map_err(AppError::from);
+        }
+
+        let parsed = PasswordHash::new(hash).map_err(argon2_error)?;
+        // The stored string carries its own variant and parameters
+        match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(argon2_error(e)),
+        }
+    }
+
+    /// Whether `hash` should be replaced by a fresh hash under the current
+    /// settings: a different algorithm, or weaker parameters.
+    pub fn needs_rehash(&self, hash: &str) -> bool {
+        match self.algorithm {
+            HashAlgorithm::Bcrypt => match bcrypt_cost(hash) {
+                Some(cost) => cost < self.bcrypt_cost,
+                None => true,
+            },
+            HashAlgorithm::Argon2id => {
+                let parsed = match PasswordHash::new(hash) {
+                    Ok(parsed) if parsed.algorithm == Algorithm::Argon2id.ident() => parsed,
+                    _ => return true,
+                };
+                let params = match Params::try_from(&parsed) {
+                    Ok(params) => params,
+                    Err(_) => return true,
+                };
+
+                parsed.version != Some(Version::V0x13.into())
+                    || params.m_cost() < self.argon2_params.m_cost()
+                    || params.t_cost() < self.argon2_params.t_cost()
+                    || params.p_cost() < self.argon2_params.p_cost()
+            }
+        }
+    }
+}
+
+fn is_bcrypt(hash: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| hash.starts_with(prefix))
+}
+
+fn bcrypt_cost(hash: &str) -> Option<u32> {
+    if !is_bcrypt(hash) {
+        return None;
+    }
+    hash.get(4..6)?.parse().ok()
+}
+
+fn argon2_error(e: argon2::password_hash::Error) -> AppError {
+    AppError::InternalServerError(format!("Password hashing error: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "quiet-harbour-lantern-91";
+
+    fn argon2id(m_cost: u32, t_cost: u32, p_cost: u32) -> PasswordHasher {
+        PasswordHasher {
+            algorithm: HashAlgorithm::Argon2id,
+            argon2_params: Params::new(m_cost, t_cost, p_cost, None).unwrap(),
+            bcrypt_cost: 4,
+        }
+    }
+
+    fn bcrypt(cost: u32) -> PasswordHasher {
+        PasswordHasher {
+            algorithm: HashAlgorithm::Bcrypt,
+            argon2_params: Params::DEFAULT,
+            bcrypt_cost: cost,
+        }
+    }
+
+    #[tokio::test]
+    async fn argon2id_hashes_verify() {
+        let hasher = argon2id(1024, 2, 1);
+        let hash = hasher.hash(PASSWORD).await.unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=2,p=1$"));
+        assert!(hasher.verify(PASSWORD, &hash).await.unwrap());
+        assert!(!hasher
+            .verify("quiet-harbour-lantern-92", &hash)
+            .await
+            .unwrap());
+        assert!(!hasher.needs_rehash(&hash));
+    }
+
+    #[tokio::test]
+    async fn legacy_bcrypt_hashes_still_verify() {
+        let legacy = bcrypt::hash(PASSWORD, 4).unwrap();
+        let hasher = argon2id(1024, 2, 1);
+
+        assert!(hasher.verify(PASSWORD, &legacy).await.unwrap());
+        assert!(!hasher
+            .verify("quiet-harbour-lantern-92", &legacy)
+            .await
+            .unwrap());
+
+        // Every prefix bcrypt has used is recognised
+        for prefix in ["$2a$", "$2x$", "$2y$"] {
+            let variant = legacy.replacen("$2b$", prefix, 1);
+            assert!(
+                hasher.verify(PASSWORD, &variant).await.unwrap(),
+                "{}",
+                prefix
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn bcrypt_hashes_are_rehashed_under_argon2id() {
+        let legacy = bcrypt::hash(PASSWORD, 4).unwrap();
+
+        assert!(argon2id(1024, 2, 1).needs_rehash(&legacy));
+    }
+
+    #[tokio::test]
+    async fn weaker_argon2_parameters_are_rehashed() {
+        let current = argon2id(2048, 3, 2);
+        assert!(!current.needs_rehash(&current.hash(PASSWORD).await.unwrap()));
+
+        for (m_cost, t_cost, p_cost) in [(1024, 3, 2), (2048, 2, 2), (2048, 3, 1)] {
+            let weaker = argon2id(m_cost, t_cost, p_cost)
+                .hash(PASSWORD)
+                .await
+                .unwrap();
+            assert!(current.needs_rehash(&weaker), "{}", weaker);
+        }
+
+        // Stronger parameters are left alone
+        let stronger = argon2id(4096, 4, 2).hash(PASSWORD).await.unwrap();
+        assert!(!current.needs_rehash(&stronger));
+    }
+
+    #[test]
+    fn other_argon2_variants_and_versions_are_rehashed() {
+        let current = argon2id(1024, 2, 1);
+        let salt = SaltString::encode_b64(&[7u8; 16]).unwrap();
+        let params = Params::new(1024, 2, 1, None).unwrap();
+
+        for (algorithm, version) in [
+            (Algorithm::Argon2i, Version::V0x13),
+            (Algorithm::Argon2d, Version::V0x13),
+            (Algorithm::Argon2id, Version::V0x10),
+        ] {
+            let hash = Argon2::new(algorithm, version, params.clone())
+                .hash_password(PASSWORD.as_bytes(), &salt)
+                .unwrap()
+                .to_string();
+            assert!(current.needs_rehash(&hash), "{}", hash);
+        }
+
+        assert!(current.needs_rehash("not a hash"));
+    }
+
+    #[tokio::test]
+    async fn bcrypt_is_rehashed_below_the_configured_cost() {
+        let hasher = bcrypt(5);
+
+        let cheaper = bcrypt::hash(PASSWORD, 4).unwrap();
+        assert!(hasher.needs_rehash(&cheaper));
+        assert!(hasher.verify(PASSWORD, &cheaper).await.unwrap());
+
+        let current = hasher.hash(PASSWORD).await.unwrap();
+        assert!(!hasher.needs_rehash(&current));
+
+        // Moving back to bcrypt replaces Argon2 hashes
+        let argon2 = argon2id(1024, 2, 1).hash(PASSWORD).await.unwrap();
+        assert!(hasher.needs_rehash(&argon2));
+        assert!(hasher.verify(PASSWORD, &argon2).await.unwrap());
+    }
+}
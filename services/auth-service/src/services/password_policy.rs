@@ -0,0 +1,436 @@
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::path::PathBuf;
+use utoipa::ToSchema;
+
+use crate::{config::Config, errors::AppError, models::User, services::password_strength};
+
+/// What the password is checked against besides itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PasswordContext<'a> {
+    pub email: Option<&'a str>,
+    pub first_name: Option<&'a str>,
+    pub last_name: Option<&'a str>,
+}
+
+impl<'a> PasswordContext<'a> {
+    pub fn for_user(user: &'a User) -> Self {
+        Self {
+            email: Some(&user.email),
+            first_name: user.first_name.as_deref(),
+            last_name: user.last_name.as_deref(),
+        }
+    }
+}
+
+/// A rule the password breaks. `code` is stable for the frontend to key on.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PolicyViolation {
+    pub code: &'static str,
+    pub message: String,
+}
+
+/// Everything the register and change-password pages need to explain a
+/// decision: which rules failed, the 0-4 strength score, and hints.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PasswordFeedback {
+    pub acceptable: bool,
+    pub score: u8,
+    pub min_score: u8,
+    pub violations: Vec<PolicyViolation>,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// Screens passwords against a local copy of the Have I Been Pwned range
+/// files: one file per five-character SHA-1 prefix, named `<PREFIX>.txt`,
+/// each line holding the remaining 35 hex characters and a count
+/// (`SUFFIX:COUNT`). The same layout the range API serves, so the corpus can
+/// be built with the official downloader and no password ever leaves the host.
+#[derive(Debug, Clone)]
+pub struct BreachedPasswordCorpus {
+    dir: PathBuf,
+}
+
+impl BreachedPasswordCorpus {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Returns how many times the password appears in known breaches.
+    pub async fn occurrences(&self, password: &str) -> Result<u64, AppError> {
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(5);
+
+        let path = self.dir.join(format!("{}.txt", prefix));
+        let contents = match tokio::fs::read_to_string(&path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(AppError::InternalServerError(format!(
+                    "Failed to read breached password range {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        let count = contents
+            .lines()
+            .filter_map(|line| line.trim().split_once(':'))
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(suffix))
+            .map(|(_, count)| count.trim().parse().unwrap_or(1))
+            .unwrap_or(0);
+
+        Ok(count)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub min_score: u8,
+    pub breached: Option<BreachedPasswordCorpus>,
+}
+
+impl PasswordPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            min_length: config.password_min_length,
+            max_length: config.password_max_length,
+            require_lowercase: config.password_require_lowercase,
+            require_uppercase: config.password_require_uppercase,
+            require_digit: config.password_require_digit,
+            require_symbol: config.password_require_symbol,
+            min_score: config.password_min_score,
+            breached: config
+                .breached_passwords_dir
+                .as_deref()
+                .filter(|dir| !dir.is_empty())
+                .map(BreachedPasswordCorpus::new),
+        }
+    }
+
+    pub async fn evaluate(
+        &self,
+        password: &str,
+        context: PasswordContext<'_>,
+    ) -> Result<PasswordFeedback, AppError> {
+        let mut violations = Vec::new();
+        let mut violate = |code: &'static str, message: String| {
+            violations.push(PolicyViolation { code, message });
+        };
+
+        let length = password.chars().count();
+        if length < self.min_length {
+            violate(
+                "too_short",
+                format!("Use at least {} characters", self.min_length),
+            );
+        }
+        if length > self.max_length {
+            violate(
+                "too_long",
+                format!("Use at most {} characters", self.max_length),
+            );
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            violate("missing_lowercase", "Add a lowercase letter".to_string());
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            violate("missing_uppercase", "Add an uppercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violate("missing_digit", "Add a number".to_string());
+        }
+        if self.require_symbol && password.chars().all(|c| c.is_alphanumeric()) {
+            violate("missing_symbol", "Add a symbol such as ! or #".to_string());
+        }
+
+        let personal = personal_words(context);
+        let lower = password.to_lowercase();
+        if personal.iter().any(|word| lower.contains(word.as_str())) {
+            violate(
+                "contains_personal_info",
+                "Don't use your name or email address in your password".to_string(),
+            );
+        }
+
+        let inputs: Vec<&str> = personal.iter().map(String::as_str).collect();
+        let strength = password_strength::estimate(password, &inputs);
+        if strength.score < self.min_score {
+            violate(
+                "too_weak",
+                "Choose a password that is harder to guess".to_string(),
+            );
+        }
+
+        if let Some(corpus) = &self.breached {
+            if corpus.occurrences(password).await? > 0 {
+                violate(
+                    "breached",
+                    "This password has appeared in a data breach and can't be used".to_string(),
+                );
+            }
+        }
+
+        Ok(PasswordFeedback {
+            acceptable: violations.is_empty(),
+            score: strength.score,
+            min_score: self.min_score,
+            violations,
+            warning: strength.warning,
+            suggestions: strength.suggestions,
+        })
+    }
+
+    /// Evaluates the password and fails with the feedback if it is rejected.
+    pub async fn enforce(
+        &self,
+        password: &str,
+        context: PasswordContext<'_>,
+    ) -> Result<(), AppError> {
+        let feedback = self.evaluate(password, context).await?;
+        if !feedback.acceptable {
+            return Err(AppError::WeakPassword(Box::new(feedback)));
+        }
+
+        Ok(())
+    }
+}
+
+/// Lowercased name parts and email local-part pieces long enough to matter.
+fn personal_words(context: PasswordContext<'_>) -> Vec<String> {
+    let local_part = context.email.and_then(|email| email.split('@').next());
+    let email_pieces = local_part
+        .into_iter()
+        .flat_map(|local| local.split(|c: char| !c.is_alphanumeric()).chain([local]));
+
+    context
+        .first_name
+        .into_iter()
+        .chain(context.last_name)
+        .chain(email_pieces)
+        .map(|word| word.trim().to_lowercase())
+        .filter(|word| word.chars().count() >= 3)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRONG: &str = "quiet-harbour-lantern-91";
+
+    /// The defaults from `Config`.
+    fn policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 10,
+            max_length: 128,
+            require_lowercase: true,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            min_score: 3,
+            breached: None,
+        }
+    }
+
+    async fn violations(
+        policy: &PasswordPolicy,
+        password: &str,
+        context: PasswordContext<'_>,
+    ) -> Vec<&'static str> {
+        let feedback = policy.evaluate(password, context).await.unwrap();
+        assert_eq!(feedback.acceptable, feedback.violations.is_empty());
+
+        feedback.violations.iter().map(|v| v.code).collect()
+    }
+
+    #[tokio::test]
+    async fn strong_password_is_accepted() {
+        let feedback = policy()
+            .evaluate(STRONG, PasswordContext::default())
+            .await
+            .unwrap();
+
+        assert!(feedback.acceptable, "{:?}", feedback);
+        assert!(feedback.score >= 3);
+        assert_eq!(feedback.min_score, 3);
+    }
+
+    #[tokio::test]
+    async fn length_limits_are_inclusive() {
+        // Scoring is out of the way so only the length rules apply
+        let policy = PasswordPolicy {
+            min_score: 0,
+            ..policy()
+        };
+        let context = PasswordContext::default();
+
+        assert_eq!(
+            violations(&policy, "kqzvbmwtr", context).await,
+            ["too_short"]
+        );
+        assert!(violations(&policy, "kqzvbmwtrx", context).await.is_empty());
+
+        let longest = "k".repeat(128);
+        assert!(violations(&policy, &longest, context).await.is_empty());
+        let too_long = "k".repeat(129);
+        assert_eq!(violations(&policy, &too_long, context).await, ["too_long"]);
+
+        // Characters, not bytes, are counted
+        assert!(violations(&policy, "ééééééééééé", context).await.is_empty());
+        assert_eq!(
+            violations(&policy, "ééééééééé", context).await,
+            ["too_short"]
+        );
+    }
+
+    #[tokio::test]
+    async fn required_character_classes() {
+        let policy = PasswordPolicy {
+            require_uppercase: true,
+            require_digit: true,
+            require_symbol: true,
+            min_score: 0,
+            ..policy()
+        };
+        let context = PasswordContext::default();
+
+        assert_eq!(
+            violations(&policy, "KQZVBMWTRX", context).await,
+            ["missing_lowercase", "missing_digit", "missing_symbol"]
+        );
+        assert_eq!(
+            violations(&policy, "kqzvbmwtrx", context).await,
+            ["missing_uppercase", "missing_digit", "missing_symbol"]
+        );
+        assert!(violations(&policy, "Kqzvbm-wtr7", context).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn common_passwords_are_rejected() {
+        let policy = policy();
+
+        for password in [
+            "password123",
+            "Password1234",
+            "azertyuiop",
+            "1234567890",
+            "qwertyuiop",
+            "iloveyou123",
+            "bookmarket2024",
+            "p@ssw0rd123",
+        ] {
+            let feedback = policy
+                .evaluate(password, PasswordContext::default())
+                .await
+                .unwrap();
+            assert!(feedback.score < 3, "{} scored {}", password, feedback.score);
+            assert!(
+                feedback.violations.iter().any(|v| v.code == "too_weak"),
+                "{} was accepted",
+                password
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn minimum_score_is_inclusive() {
+        let score = policy()
+            .evaluate(STRONG, PasswordContext::default())
+            .await
+            .unwrap()
+            .score;
+
+        let at_score = PasswordPolicy {
+            min_score: score,
+            ..policy()
+        };
+        assert!(violations(&at_score, STRONG, PasswordContext::default())
+            .await
+            .is_empty());
+
+        if score < 4 {
+            let above_score = PasswordPolicy {
+                min_score: score + 1,
+                ..policy()
+            };
+            assert_eq!(
+                violations(&above_score, STRONG, PasswordContext::default()).await,
+                ["too_weak"]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn personal_details_are_rejected() {
+        let policy = policy();
+        let context = PasswordContext {
+            email: Some("amina.benali@example.com"),
+            first_name: Some("Youssef"),
+            last_name: Some("Al"),
+        };
+
+        for password in [
+            "youssef-harbour-lantern",   // first name
+            "harbour-YOUSSEF-lantern",   // in any case
+            "lantern-benali-harbour-91", // piece of the email
+            "quiet-amina.benali-91",     // whole local part
+        ] {
+            assert!(
+                violations(&policy, password, context)
+                    .await
+                    .contains(&"contains_personal_info"),
+                "{} was accepted",
+                password
+            );
+        }
+
+        // Name parts shorter than three characters would match too much
+        assert!(!violations(&policy, "quiet-harbour-always-91", context)
+            .await
+            .contains(&"contains_personal_info"));
+        // The domain is not personal
+        assert!(!violations(&policy, "example-harbour-lantern-91", context)
+            .await
+            .contains(&"contains_personal_info"));
+    }
+
+    #[tokio::test]
+    async fn breached_passwords_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("breached-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let digest = hex::encode_upper(Sha1::digest(STRONG.as_bytes()));
+        let (prefix, suffix) = digest.split_at(5);
+        std::fs::write(
+            dir.join(format!("{}.txt", prefix)),
+            format!(
+                "0000000000000000000000000000000000A:3\r\n{}:12\r\n",
+                suffix.to_lowercase()
+            ),
+        )
+        .unwrap();
+
+        let corpus = BreachedPasswordCorpus::new(&dir);
+        assert_eq!(corpus.occurrences(STRONG).await.unwrap(), 12);
+        assert_eq!(corpus.occurrences("not-in-the-corpus").await.unwrap(), 0);
+
+        let policy = PasswordPolicy {
+            breached: Some(corpus),
+            ..policy()
+        };
+        assert_eq!(
+            violations(&policy, STRONG, PasswordContext::default()).await,
+            ["breached"]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::{generate_token, hash_token},
+    config::Config,
+    errors::AppError,
+    models::User,
+    repositories::{SessionRepository, UserRepository},
+    services::mailer::EmailMessage,
+};
+
+/// Creates a reset link token for a user and returns the raw token.
+/// Only the hash is stored.
+pub async fn issue_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    config: &Config,
+) -> Result<String, AppError> {
+    let token = generate_token();
+    let expires_at = Utc::now() + Duration::seconds(config.password_reset_ttl);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO auth.password_reset_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        user_id,
+        hash_token(&token),
+        expires_at
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(token)
+}
+
+/// Returns the user a reset token was issued to, if it can still be used.
+pub async fn token_user(
+    pool: &PgPool,
+    users: &dyn UserRepository,
+    token: &str,
+) -> Result<User, AppError> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+        SELECT user_id FROM auth.password_reset_tokens
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired reset token".to_string()))?;
+
+    users.get_by_id(&user_id.to_string()).await
+}
+
+/// Sets a new password using a reset token. The token is consumed atomically,
+/// every session of the user is revoked and any forced-reset flag is cleared.
+pub async fn reset_password(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    token: &str,
+    new_password_hash: &str,
+) -> Result<Uuid, AppError> {
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE auth.password_reset_tokens
+        SET used_at = CURRENT_TIMESTAMP
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > CURRENT_TIMESTAMP
+        RETURNING user_id
+        "#,
+        hash_token(token)
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired reset token".to_string()))?;
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.users
+        SET password_hash = $1, password_reset_required = FALSE, updated_at = CURRENT_TIMESTAMP
+        WHERE id = $2
+        "#,
+        new_password_hash,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    sessions.delete_for_user(user_id).await?;
+
+    Ok(user_id)
+}
+
+pub fn reset_email(user: &User, token: &str, config: &Config) -> EmailMessage {
+    let link = format!(
+        "{}/auth/reset-password?token={}",
+        config.frontend_url, token
+    );
+    let minutes = config.password_reset_ttl / 60;
+
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "Reset your BookMarket password".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             Use the link below to choose a new password for your BookMarket account:\n\n\
+             {}\n\n\
+             The link expires in {} minutes and can only be used once. \
+             If you did not ask for a password reset you can ignore this email.\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            link,
+            minutes
+        ),
+    }
+}
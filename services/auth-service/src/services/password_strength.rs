@@ -0,0 +1,569 @@
+//! Password strength estimation in the style of zxcvbn.
+//!
+//! The password is split into the cheapest sequence of recognisable patterns
+//! (common passwords and words, the user's own details, keyboard rows,
+//! sequences, repeats and dates) with everything left over counted as brute
+//! force. The estimated number of guesses maps to a 0-4 score using the same
+//! thresholds as zxcvbn, so scores mean the same thing to the frontend.
+
+/// Common passwords and words, most frequent first. The position in the list
+/// is used as the number of guesses an attacker needs for that entry.
+const COMMON_WORDS: &[&str] = &[
+    "123456",
+    "password",
+    "12345678",
+    "qwerty",
+    "123456789",
+    "12345",
+    "1234",
+    "111111",
+    "1234567",
+    "azerty",
+    "123123",
+    "abc123",
+    "000000",
+    "iloveyou",
+    "1234567890",
+    "password1",
+    "admin",
+    "welcome",
+    "bookmarket",
+    "letmein",
+    "monkey",
+    "dragon",
+    "football",
+    "sunshine",
+    "princess",
+    "master",
+    "shadow",
+    "superman",
+    "starwars",
+    "qwertyuiop",
+    "azertyuiop",
+    "bonjour",
+    "soleil",
+    "doudou",
+    "chouchou",
+    "loulou",
+    "marseille",
+    "nicolas",
+    "julien",
+    "maroc",
+    "morocco",
+    "marocain",
+    "casablanca",
+    "rabat",
+    "marrakech",
+    "fes",
+    "tanger",
+    "agadir",
+    "meknes",
+    "oujda",
+    "tetouan",
+    "allah",
+    "bismillah",
+    "hamdoulah",
+    "inchallah",
+    "mohamed",
+    "mohammed",
+    "ahmed",
+    "youssef",
+    "fatima",
+    "amine",
+    "khadija",
+    "omar",
+    "hassan",
+    "rachid",
+    "karim",
+    "salma",
+    "yasmine",
+    "sara",
+    "wydad",
+    "raja",
+    "barca",
+    "barcelona",
+    "realmadrid",
+    "madrid",
+    "ronaldo",
+    "messi",
+    "baseball",
+    "hello",
+    "secret",
+    "love",
+    "lovely",
+    "freedom",
+    "whatever",
+    "trustno1",
+    "passw0rd",
+    "motdepasse",
+    "azerty123",
+    "qwerty123",
+    "login",
+    "access",
+    "flower",
+    "summer",
+    "winter",
+    "spring",
+    "autumn",
+    "charlie",
+    "michael",
+    "jordan",
+    "pokemon",
+    "naruto",
+    "computer",
+    "internet",
+    "samsung",
+    "iphone",
+    "google",
+    "facebook",
+    "instagram",
+    "book",
+    "books",
+    "library",
+    "reader",
+    "novel",
+    "market",
+    "shop",
+    "store",
+    "vendor",
+    "customer",
+    "user",
+    "test",
+    "demo",
+    "guest",
+    "root",
+    "changeme",
+    "default",
+];
+
+const KEYBOARD_ROWS: &[&str] = &[
+    "1234567890",
+    "qwertyuiop",
+    "asdfghjkl",
+    "zxcvbnm",
+    // French layout, common in Morocco
+    "azertyuiop",
+    "qsdfghjklm",
+    "wxcvbn",
+];
+
+const MIN_PATTERN_GUESSES: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PatternKind {
+    Dictionary {
+        common: bool,
+        capitalised: bool,
+        leet: bool,
+    },
+    Personal,
+    Keyboard,
+    Sequence,
+    Repeat,
+    Date,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pattern {
+    start: usize,
+    end: usize,
+    guesses: f64,
+    kind: PatternKind,
+}
+
+/// Result of a strength estimate.
+#[derive(Debug, Clone)]
+pub struct Strength {
+    /// 0 (too guessable) to 4 (very unguessable).
+    pub score: u8,
+    pub guesses_log10: f64,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+}
+
+/// Estimates how hard `password` is to guess. `user_inputs` are words an
+/// attacker targeting this user would try first, such as their name.
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+    let chars: Vec<char> = password.chars().collect();
+    if chars.is_empty() {
+        return Strength {
+            score: 0,
+            guesses_log10: 0.0,
+            warning: None,
+            suggestions: vec!["Use a few words, avoid common phrases".to_string()],
+        };
+    }
+
+    let patterns = find_patterns(&chars, user_inputs);
+    let (guesses_log10, used) = cheapest_cover(&chars, &patterns);
+    let (warning, suggestions) = feedback(&chars, &used);
+
+    Strength {
+        score: score(guesses_log10),
+        guesses_log10,
+        warning,
+        suggestions,
+    }
+}
+
+/// zxcvbn's thresholds: 10^3, 10^6, 10^8 and 10^10 guesses.
+fn score(guesses_log10: f64) -> u8 {
+    match guesses_log10 {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => 4,
+    }
+}
+
+fn brute_force_cardinality(chars: &[char]) -> f64 {
+    let mut cardinality = 0.0;
+    if chars.iter().any(|c| c.is_ascii_lowercase()) {
+        cardinality += 26.0;
+    }
+    if chars.iter().any(|c| c.is_ascii_uppercase()) {
+        cardinality += 26.0;
+    }
+    if chars.iter().any(|c| c.is_ascii_digit()) {
+        cardinality += 10.0;
+    }
+    if chars.iter().any(|c| c.is_ascii_punctuation() || *c == ' ') {
+        cardinality += 33.0;
+    }
+    if chars.iter().any(|c| !c.is_ascii()) {
+        cardinality += 100.0;
+    }
+    cardinality
+}
+
+/// Minimum-guesses segmentation of the password into patterns and brute
+/// forced characters. Returns log10(guesses) and the patterns used.
+fn cheapest_cover(chars: &[char], patterns: &[Pattern]) -> (f64, Vec<Pattern>) {
+    let n = chars.len();
+    let char_cost = brute_force_cardinality(chars).log10();
+
+    // best[i] = (log10 guesses to cover chars[..i], pattern ending at i if any)
+    let mut best: Vec<(f64, Option<Pattern>)> = vec![(f64::INFINITY, None); n + 1];
+    best[0] = (0.0, None);
+    for i in 1..=n {
+        best[i] = (best[i - 1].0 + char_cost, None);
+        for pattern in patterns.iter().filter(|p| p.end == i) {
+            let cost = best[pattern.start].0 + pattern.guesses.max(MIN_PATTERN_GUESSES).log10();
+            if cost < best[i].0 {
+                best[i] = (cost, Some(*pattern));
+            }
+        }
+    }
+
+    let mut used = Vec::new();
+    let mut i = n;
+    while i > 0 {
+        match best[i].1 {
+            Some(pattern) => {
+                used.push(pattern);
+                i = pattern.start;
+            }
+            None => i -= 1,
+        }
+    }
+    used.reverse();
+
+    (best[n].0, used)
+}
+
+fn find_patterns(chars: &[char], user_inputs: &[&str]) -> Vec<Pattern> {
+    let mut patterns = Vec::new();
+    dictionary_patterns(chars, user_inputs, &mut patterns);
+    keyboard_patterns(chars, &mut patterns);
+    sequence_patterns(chars, &mut patterns);
+    repeat_patterns(chars, &mut patterns);
+    date_patterns(chars, &mut patterns);
+    patterns
+}
+
+fn unleet(c: char) -> char {
+    match c {
+        '4' | '@' => 'a',
+        '3' => 'e',
+        '1' | '!' => 'i',
+        '0' => 'o',
+        '$' | '5' => 's',
+        '7' => 't',
+        other => other,
+    }
+}
+
+fn dictionary_patterns(chars: &[char], user_inputs: &[&str], patterns: &mut Vec<Pattern>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    let unleeted: Vec<char> = lower.iter().map(|c| unleet(*c)).collect();
+
+    // The user's own details are the very first thing a targeted attack tries
+    let personal = user_inputs
+        .iter()
+        .map(|word| word.to_lowercase())
+        .filter(|word| word.chars().count() >= 3)
+        .map(|word| (word, 0, true));
+    let common = COMMON_WORDS
+        .iter()
+        .enumerate()
+        .map(|(rank, word)| (word.to_string(), rank, false));
+
+    for (word, rank, is_personal) in personal.chain(common) {
+        let word: Vec<char> = word.chars().collect();
+        if word.len() > chars.len() {
+            continue;
+        }
+
+        for start in 0..=(chars.len() - word.len()) {
+            let end = start + word.len();
+            let plain = lower[start..end] == word[..];
+            if !plain && unleeted[start..end] != word[..] {
+                continue;
+            }
+
+            let capitalised = chars[start..end].iter().any(|c| c.is_ascii_uppercase());
+            let leet = !plain;
+            let mut guesses = (rank + 1) as f64;
+            if capitalised {
+                guesses *= uppercase_variations(&chars[start..end]);
+            }
+            if leet {
+                guesses *= 2.0;
+            }
+
+            let kind = if is_personal {
+                PatternKind::Personal
+            } else {
+                PatternKind::Dictionary {
+                    common: rank < 40,
+                    capitalised,
+                    leet,
+                }
+            };
+            patterns.push(Pattern {
+                start,
+                end,
+                guesses,
+                kind,
+            });
+        }
+    }
+}
+
+fn uppercase_variations(word: &[char]) -> f64 {
+    let upper = word.iter().filter(|c| c.is_ascii_uppercase()).count();
+    let all_upper = upper == word.len();
+    let first_only = upper == 1 && word[0].is_ascii_uppercase();
+    let last_only = upper == 1 && word[word.len() - 1].is_ascii_uppercase();
+
+    if all_upper || first_only || last_only {
+        2.0
+    } else {
+        // Choosing which letters to capitalise
+        (1..=upper)
+            .map(|k| binomial(word.len(), k))
+            .sum::<f64>()
+            .max(2.0)
+    }
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1.0, |acc, i| acc * (n - i) as f64 / (i + 1) as f64)
+}
+
+fn keyboard_patterns(chars: &[char], patterns: &mut Vec<Pattern>) {
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+
+    for row in KEYBOARD_ROWS {
+        let forward: Vec<char> = row.chars().collect();
+        let backward: Vec<char> = row.chars().rev().collect();
+
+        for (keys, reversed) in [(&forward, false), (&backward, true)] {
+            for start in 0..lower.len() {
+                let mut len = 0;
+                while start + len < lower.len() {
+                    let candidate = &lower[start..=start + len];
+                    if !contains_run(keys, candidate) {
+                        break;
+                    }
+                    len += 1;
+                }
+                if len >= 4 {
+                    // Starting key, direction and length
+                    let guesses = 40.0 * len as f64 * if reversed { 2.0 } else { 1.0 };
+                    patterns.push(Pattern {
+                        start,
+                        end: start + len,
+                        guesses,
+                        kind: PatternKind::Keyboard,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn contains_run(haystack: &[char], needle: &[char]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+fn sequence_patterns(chars: &[char], patterns: &mut Vec<Pattern>) {
+    let mut start = 0;
+    while start + 2 < chars.len() {
+        let delta = chars[start + 1] as i32 - chars[start] as i32;
+        if delta != 1 && delta != -1 {
+            start += 1;
+            continue;
+        }
+
+        let mut end = start + 2;
+        while end < chars.len() && chars[end] as i32 - chars[end - 1] as i32 == delta {
+            end += 1;
+        }
+
+        if end - start >= 3 {
+            let first = chars[start];
+            let base = if matches!(first, 'a' | 'A' | 'z' | 'Z' | '0' | '1' | '9') {
+                4.0
+            } else if first.is_ascii_digit() {
+                10.0
+            } else {
+                26.0
+            };
+            let direction = if delta < 0 { 2.0 } else { 1.0 };
+            patterns.push(Pattern {
+                start,
+                end,
+                guesses: base * (end - start) as f64 * direction,
+                kind: PatternKind::Sequence,
+            });
+        }
+        start = end - 1;
+    }
+}
+
+fn repeat_patterns(chars: &[char], patterns: &mut Vec<Pattern>) {
+    for start in 0..chars.len() {
+        for period in 1..=4 {
+            let mut end = start + period;
+            while end + period <= chars.len()
+                && chars[end..end + period] == chars[start..start + period]
+            {
+                end += period;
+            }
+
+            let repeats = (end - start) / period;
+            let long_enough = if period == 1 {
+                repeats >= 3
+            } else {
+                repeats >= 2
+            };
+            if long_enough {
+                let unit_guesses =
+                    brute_force_cardinality(&chars[start..start + period]).powi(period as i32);
+                patterns.push(Pattern {
+                    start,
+                    end,
+                    guesses: unit_guesses * repeats as f64,
+                    kind: PatternKind::Repeat,
+                });
+            }
+        }
+    }
+}
+
+fn date_patterns(chars: &[char], patterns: &mut Vec<Pattern>) {
+    let digit_run = |start: usize, len: usize| -> Option<u32> {
+        let slice = chars.get(start..start + len)?;
+        if slice.iter().all(|c| c.is_ascii_digit()) {
+            slice.iter().collect::<String>().parse().ok()
+        } else {
+            None
+        }
+    };
+    let is_year = |year: u32| (1900..=2099).contains(&year);
+    let is_day_month = |day: u32, month: u32| (1..=31).contains(&day) && (1..=12).contains(&month);
+
+    for start in 0..chars.len() {
+        if let Some(year) = digit_run(start, 4) {
+            if is_year(year) {
+                patterns.push(Pattern {
+                    start,
+                    end: start + 4,
+                    guesses: 200.0,
+                    kind: PatternKind::Date,
+                });
+            }
+        }
+
+        // ddmmyyyy or yyyymmdd
+        if let Some(value) = digit_run(start, 8) {
+            let ddmmyyyy =
+                is_day_month(value / 1_000_000, value / 10_000 % 100) && is_year(value % 10_000);
+            let yyyymmdd = is_year(value / 10_000) && is_day_month(value % 100, value / 100 % 100);
+            if ddmmyyyy || yyyymmdd {
+                patterns.push(Pattern {
+                    start,
+                    end: start + 8,
+                    guesses: 365.0 * 200.0,
+                    kind: PatternKind::Date,
+                });
+            }
+        }
+    }
+}
+
+fn feedback(chars: &[char], used: &[Pattern]) -> (Option<String>, Vec<String>) {
+    let mut suggestions = Vec::new();
+
+    // The longest pattern explains most of the weakness
+    let dominant = used.iter().max_by_key(|p| p.end - p.start);
+    let warning = dominant.map(|pattern| match pattern.kind {
+        PatternKind::Dictionary { common: true, .. }
+            if pattern.end - pattern.start == chars.len() =>
+        {
+            "This is a very common password".to_string()
+        }
+        PatternKind::Dictionary { .. } => "Common words and names are easy to guess".to_string(),
+        PatternKind::Personal => {
+            "Passwords based on your name or email are easy to guess".to_string()
+        }
+        PatternKind::Keyboard => {
+            "Straight rows of keys like \"azerty\" are easy to guess".to_string()
+        }
+        PatternKind::Sequence => "Sequences like \"abc\" or \"6543\" are easy to guess".to_string(),
+        PatternKind::Repeat => "Repeats like \"aaa\" or \"abcabc\" are easy to guess".to_string(),
+        PatternKind::Date => "Dates and years are often easy to guess".to_string(),
+    });
+
+    for pattern in used {
+        let suggestion = match pattern.kind {
+            PatternKind::Dictionary {
+                capitalised: true, ..
+            } => "Capitalisation doesn't help very much",
+            PatternKind::Dictionary { leet: true, .. } => {
+                "Predictable substitutions like \"@\" instead of \"a\" don't help very much"
+            }
+            PatternKind::Keyboard => "Use a longer keyboard pattern with more turns",
+            PatternKind::Sequence => "Avoid sequences",
+            PatternKind::Repeat => "Avoid repeated words and characters",
+            PatternKind::Date => "Avoid dates and years that are associated with you",
+            _ => continue,
+        };
+        if !suggestions.iter().any(|s| s == suggestion) {
+            suggestions.push(suggestion.to_string());
+        }
+    }
+
+    if !used.is_empty() {
+        suggestions.insert(
+            0,
+            "Add another word or two. Uncommon words are better".to_string(),
+        );
+    }
+
+    (warning, suggestions)
+}
@@ -0,0 +1,303 @@
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    errors::AppError,
+    models::{
+        AccountDeletion, ApiKey, AuditEvent, AuditEventType, AuditOutcome, KnownDevice,
+        PersonalDataExport, Session, User,
+    },
+    repositories::{SessionRepository, UserRepository},
+    services::{
+        identities,
+        mailer::EmailMessage,
+        outbox::{self, OutboxEvent},
+    },
+};
+
+/// Assembles everything auth-service stores about a user. Secrets (password,
+/// token and key hashes) are left out by the models' serialisation.
+pub async fn export(
+    pool: &PgPool,
+    users: &dyn UserRepository,
+    user_id: Uuid,
+) -> Result<PersonalDataExport, AppError> {
+    let profile = users.get_by_id(&user_id.to_string()).await?;
+
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, user_id, token_hash, expires_at, user_agent, ip_address, impersonator_id,
+               created_at
+        FROM auth.sessions
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let known_devices = sqlx::query_as!(
+        KnownDevice,
+        r#"
+        SELECT user_agent, network, first_seen, last_seen
+        FROM auth.known_devices
+        WHERE user_id = $1
+        ORDER BY last_seen DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let api_keys = sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT id, user_id, key_hash, name, scopes, last_used, expires_at, created_at
+        FROM auth.api_keys
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let identities = identities::list_for_user(pool, user_id).await?;
+
+    let audit_events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT id, event_type as "event_type: AuditEventType",
+               outcome as "outcome: AuditOutcome",
+               actor_id, target_id, ip_address, user_agent, details, created_at
+        FROM auth.audit_events
+        WHERE actor_id = $1 OR target_id = $1
+        ORDER BY created_at DESC, id DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let account_deletion = deletion_status(pool, user_id).await?;
+
+    Ok(PersonalDataExport {
+        generated_at: Utc::now(),
+        profile,
+        sessions,
+        known_devices,
+        api_keys,
+        identities,
+        audit_events,
+        account_deletion,
+    })
+}
+
+pub async fn deletion_status(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<AccountDeletion>, AppError> {
+    let deletion = sqlx::query_as!(
+        AccountDeletion,
+        r#"
+        SELECT requested_at, scheduled_for, completed_at
+        FROM auth.account_deletions
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(deletion)
+}
+
+/// Schedules the account for anonymisation after the cooling-off period.
+/// Asking again while a request is pending keeps the original date.
+pub async fn request_deletion(
+    pool: &PgPool,
+    user_id: Uuid,
+    config: &Config,
+) -> Result<AccountDeletion, AppError> {
+    let scheduled_for = Utc::now() + Duration::days(config.account_deletion_grace_days);
+
+    let deletion = sqlx::query_as!(
+        AccountDeletion,
+        r#"
+        INSERT INTO auth.account_deletions (user_id, scheduled_for)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET user_id = EXCLUDED.user_id
+        RETURNING requested_at, scheduled_for, completed_at
+        "#,
+        user_id,
+        scheduled_for
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(deletion)
+}
+
+/// Withdraws a pending deletion request. Fails with `NotFound` when there is
+/// nothing left to cancel.
+pub async fn cancel_deletion(pool: &PgPool, user_id: Uuid) -> Result<(), AppError> {
+    let result = sqlx::query!(
+        "DELETE FROM auth.account_deletions WHERE user_id = $1 AND completed_at IS NULL",
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Users whose cooling-off period has ended.
+pub async fn due_deletions(pool: &PgPool, limit: i64) -> Result<Vec<Uuid>, AppError> {
+    let user_ids = sqlx::query_scalar!(
+        r#"
+        SELECT user_id FROM auth.account_deletions
+        WHERE completed_at IS NULL AND scheduled_for <= CURRENT_TIMESTAMP
+        ORDER BY scheduled_for
+        LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(user_ids)
+}
+
+/// Strips a user of personal data while keeping the row, so orders, reviews
+/// and payouts that reference it stay valid. Credentials and everything tied
+/// to the person are deleted; the audit trail keeps its events but loses the
+/// network details and email addresses recorded in them.
+///
+/// `unusable_password_hash` should be a hash of a random secret so the row
+/// still holds a well-formed hash that nothing can match.
+pub async fn anonymise(
+    pool: &PgPool,
+    sessions: &dyn SessionRepository,
+    user_id: Uuid,
+    unusable_password_hash: &str,
+) -> Result<(), AppError> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.users
+        SET email = 'deleted-' || id || '@deleted.invalid',
+            password_hash = $2,
+            first_name = NULL,
+            last_name = NULL,
+            phone = NULL,
+            status = 'deleted',
+            email_verified = FALSE,
+            phone_verified = FALSE,
+            password_reset_required = FALSE,
+            updated_at = CURRENT_TIMESTAMP
+        WHERE id = $1
+        "#,
+        user_id,
+        unusable_password_hash
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM auth.api_keys WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM auth.user_identities WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM auth.totp_factors WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM auth.password_reset_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM auth.email_verification_tokens WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM auth.email_change_requests WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM auth.magic_links WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM auth.login_alerts WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!("DELETE FROM auth.known_devices WHERE user_id = $1", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.audit_events
+        SET ip_address = NULL, user_agent = NULL, details = details - 'email'
+        WHERE actor_id = $1 OR target_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE auth.account_deletions SET completed_at = CURRENT_TIMESTAMP WHERE user_id = $1",
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    outbox::enqueue(&mut tx, OutboxEvent::user_deleted(user_id)).await?;
+    tx.commit().await?;
+
+    // Through the repository so cached sessions are invalidated too
+    sessions.delete_for_user(user_id).await?;
+
+    Ok(())
+}
+
+pub fn deletion_scheduled_email(
+    user: &User,
+    deletion: &AccountDeletion,
+    config: &Config,
+) -> EmailMessage {
+    EmailMessage {
+        to: user.email.clone(),
+        subject: "Your BookMarket account will be deleted".to_string(),
+        body: format!(
+            "Hello {},\n\n\
+             We received a request to delete your BookMarket account. Your personal data \
+             will be permanently erased on {}.\n\n\
+             Changed your mind? Sign in before then and cancel the request from your \
+             account settings:\n\n\
+             {}/account/privacy\n\n\
+             If you did not ask for this, cancel the request and change your password.\n",
+            user.first_name.as_deref().unwrap_or("there"),
+            deletion.scheduled_for.format("%-d %B %Y"),
+            config.frontend_url
+        ),
+    }
+}
@@ -0,0 +1,290 @@
+use rand::Rng;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::ValidationError;
+
+use crate::{
+    auth::hash_token, config::Config, errors::AppError, services::redis_connection::LazyConnection,
+};
+
+/// Country code assumed for numbers written in national format. Most of our
+/// customers are in Morocco.
+pub const DEFAULT_COUNTRY_CODE: &str = "212";
+
+const OTP_KEY_PREFIX: &str = "auth:phone:otp:";
+const ATTEMPTS_KEY_PREFIX: &str = "auth:phone:attempts:";
+const COOLDOWN_KEY_PREFIX: &str = "auth:phone:cooldown:";
+const HOURLY_KEY_PREFIX: &str = "auth:phone:hourly:";
+
+/// Normalises a phone number to E.164 (`+` followed by up to 15 digits).
+///
+/// Moroccan numbers are accepted in every common spelling: `0612345678`,
+/// `06 12 34 56 78`, `212612345678`, `00212612345678` and `+212 6-12-34-56-78`
+/// all become `+212612345678`. Other countries must be written with their
+/// country code, either as `+` or `00`.
+pub fn normalize(input: &str) -> Result<String, AppError> {
+    let invalid = || AppError::Validation(format!("Invalid phone number: {}", input));
+
+    let trimmed = input.trim();
+    let (has_plus, rest) = match trimmed.strip_prefix('+') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed),
+    };
+
+    let mut digits = String::with_capacity(rest.len());
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => digits.push(c),
+            ' ' | '-' | '.' | '(' | ')' => {}
+            _ => return Err(invalid()),
+        }
+    }
+
+    let international = if has_plus {
+        digits
+    } else if let Some(international) = digits.strip_prefix("00") {
+        international.to_string()
+    } else if digits.len() == 12 && digits.starts_with(DEFAULT_COUNTRY_CODE) {
+        digits
+    } else if let Some(national) = digits.strip_prefix('0') {
+        format!("{}{}", DEFAULT_COUNTRY_CODE, national)
+    } else {
+        return Err(invalid());
+    };
+
+    if let Some(national) = international.strip_prefix(DEFAULT_COUNTRY_CODE) {
+        // "+212 06..." keeps the national trunk prefix by mistake
+        let national = match national.strip_prefix('0') {
+            Some(trimmed) if trimmed.len() == 9 => trimmed,
+            _ => national,
+        };
+
+        // Moroccan subscriber numbers are nine digits: 5 fixed, 6/7 mobile, 8 VoIP
+        let valid = national.len() == 9 && matches!(national.as_bytes()[0], b'5'..=b'8');
+        return if valid {
+            Ok(format!("+{}{}", DEFAULT_COUNTRY_CODE, national))
+        } else {
+            Err(invalid())
+        };
+    }
+
+    if !(8..=15).contains(&international.len()) || international.starts_with('0') {
+        return Err(invalid());
+    }
+
+    Ok(format!("+{}", international))
+}
+
+/// `validator` hook for phone fields on request DTOs.
+pub fn validate_phone(phone: &str) -> Result<(), ValidationError> {
+    normalize(phone)
+        .map(|_| ())
+        .map_err(|_| ValidationError::new("phone"))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingCode {
+    phone: String,
+    code_hash: String,
+}
+
+/// One-time codes for phone verification, kept in Redis.
+///
+/// Codes are stored hashed and keyed by user, so requesting a new code
+/// replaces the previous one. Sending is throttled per phone number, not per
+/// user, so one number can't be flooded from several accounts.
+#[derive(Clone)]
+pub struct PhoneOtpStore {
+    conn: LazyConnection,
+}
+
+impl PhoneOtpStore {
+    pub fn new(client: &redis::Client) -> Self {
+        Self {
+            conn: LazyConnection::new(client),
+        }
+    }
+
+    /// Creates a code for `user_id` to confirm `phone` and returns it for
+    /// delivery. Fails with `RateLimitExceeded` when the number was sent a
+    /// code too recently or too often.
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "redis"))]
+    pub async fn issue(
+        &self,
+        user_id: Uuid,
+        phone: &str,
+        config: &Config,
+    ) -> Result<String, AppError> {
+        let mut conn = self.conn.get().await?;
+
+        let cooldown_started: bool = redis::cmd("SET")
+            .arg(format!("{}{}", COOLDOWN_KEY_PREFIX, phone))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(config.phone_otp_cooldown)
+            .query_async::<_, Option<String>>(&mut conn)
+            .await?
+            .is_some();
+        if !cooldown_started {
+            return Err(AppError::RateLimitExceeded);
+        }
+
+        let hourly_key = format!("{}{}", HOURLY_KEY_PREFIX, phone);
+        let sent_this_hour: u64 = conn.incr(&hourly_key, 1).await?;
+        if sent_this_hour == 1 {
+            conn.expire::<_, ()>(&hourly_key, 3600).await?;
+        }
+        if sent_this_hour > config.phone_otp_hourly_limit {
+            return Err(AppError::RateLimitExceeded);
+        }
+
+        let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+        let pending = PendingCode {
+            phone: phone.to_string(),
+            code_hash: code_hash(user_id, phone, &code, config),
+        };
+        let payload = serde_json::to_string(&pending)
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
+        redis::pipe()
+            .atomic()
+            .set_ex(
+                format!("{}{}", OTP_KEY_PREFIX, user_id),
+                payload,
+                config.phone_otp_ttl,
+            )
+            .ignore()
+            .del(format!("{}{}", ATTEMPTS_KEY_PREFIX, user_id))
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(code)
+    }
+
+    /// Checks a code and returns the phone number it confirms. The pending
+    /// code is discarded on success and after too many wrong guesses.
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "redis"))]
+    pub async fn verify(
+        &self,
+        user_id: Uuid,
+        code: &str,
+        config: &Config,
+    ) -> Result<String, AppError> {
+        let mut conn = self.conn.get().await?;
+        let otp_key = format!("{}{}", OTP_KEY_PREFIX, user_id);
+        let attempts_key = format!("{}{}", ATTEMPTS_KEY_PREFIX, user_id);
+        let invalid = || AppError::BadRequest("Invalid or expired verification code".to_string());
+
+        let payload: Option<String> = conn.get(&otp_key).await?;
+        let pending: PendingCode = payload
+            .and_then(|p| serde_json::from_str(&p).ok())
+            .ok_or_else(invalid)?;
+
+        // Counted before comparing so concurrent guesses can't exceed the limit
+        let attempts: u64 = conn.incr(&attempts_key, 1).await?;
+        if attempts == 1 {
+            conn.expire::<_, ()>(&attempts_key, config.phone_otp_ttl as i64)
+                .await?;
+        }
+        if attempts > config.phone_otp_max_attempts {
+            conn.del::<_, ()>(&[&otp_key, &attempts_key]).await?;
+            return Err(invalid());
+        }
+
+        if code_hash(user_id, &pending.phone, code.trim(), config) != pending.code_hash {
+            return Err(invalid());
+        }
+
+        conn.del::<_, ()>(&[&otp_key, &attempts_key]).await?;
+
+        Ok(pending.phone)
+    }
+}
+
+/// Six-digit codes are easy to enumerate, so the hash is keyed with the
+/// server secret and bound to the user and number.
+fn code_hash(user_id: Uuid, phone: &str, code: &str, config: &Config) -> String {
+    hash_token(&format!(
+        "{}:{}:{}:{}",
+        config.jwt_secret, user_id, phone, code
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_moroccan_numbers() {
+        let cases = [
+            ("0612345678", "+212612345678"),
+            ("06 12 34 56 78", "+212612345678"),
+            ("06-12-34-56-78", "+212612345678"),
+            ("06.12.34.56.78", "+212612345678"),
+            ("0712345678", "+212712345678"),
+            ("0522123456", "+212522123456"),
+            ("212612345678", "+212612345678"),
+            ("00212612345678", "+212612345678"),
+            ("+212612345678", "+212612345678"),
+            ("+212 6-12-34-56-78", "+212612345678"),
+            ("+212 (0)6 12 34 56 78", "+212612345678"),
+            ("  +212 612 345 678  ", "+212612345678"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalize(input).unwrap(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn normalizes_international_numbers() {
+        let cases = [
+            ("+33 6 12 34 56 78", "+33612345678"),
+            ("0033612345678", "+33612345678"),
+            ("+1 (415) 555-2671", "+14155552671"),
+            ("+4915123456789", "+4915123456789"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(normalize(input).unwrap(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_numbers() {
+        let cases = [
+            "",
+            "+",
+            "612345678",             // no trunk prefix or country code
+            "061234567",             // too short
+            "06123456789",           // too long
+            "0912345678",            // not a Moroccan number range
+            "+212412345678",         // not a Moroccan number range
+            "+2126123456",           // too short with country code
+            "+212 06 12 34 56 7",    // trunk prefix and one digit short
+            "06 12 34 56 7a",        // letters
+            "+33 6 12 34 56 78 ext", // trailing text
+            "+1234567",              // shorter than any E.164 number
+            "+1234567890123456",     // longer than E.164 allows
+            "+0612345678",           // country codes don't start with 0
+            "06/12/34/56/78",        // unsupported separator
+        ];
+
+        for input in cases {
+            assert!(
+                matches!(normalize(input), Err(AppError::Validation(_))),
+                "{:?} was accepted",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn validator_hook_follows_normalize() {
+        assert!(validate_phone("06 12 34 56 78").is_ok());
+        assert_eq!(validate_phone("12345").unwrap_err().code, "phone");
+    }
+}
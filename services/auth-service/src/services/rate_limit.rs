@@ -0,0 +1,80 @@
+use redis::AsyncCommands;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{errors::AppError, services::redis_connection::LazyConnection};
+
+const KEY_PREFIX: &str = "auth:ratelimit:";
+
+#[derive(Clone)]
+enum Counters {
+    Redis(LazyConnection),
+    Memory(Arc<Mutex<HashMap<String, (u64, Instant)>>>),
+}
+
+/// Fixed-window request counters in Redis, shared by every replica.
+///
+/// Each limit is named by the caller, e.g. `magic_link:email` and the address
+/// it counts, so unrelated limits never share a counter.
+#[derive(Clone)]
+pub struct RateLimiter {
+    counters: Counters,
+}
+
+impl RateLimiter {
+    pub fn new(client: &redis::Client) -> Self {
+        Self {
+            counters: Counters::Redis(LazyConnection::new(client)),
+        }
+    }
+
+    /// Keeps the counters in this process, so each instance enforces its own
+    /// limits. For tests and single-instance development without Redis.
+    pub fn in_memory() -> Self {
+        Self {
+            counters: Counters::Memory(Arc::default()),
+        }
+    }
+
+    /// Counts one request against `limit` per `window_secs` and fails with
+    /// `RateLimitExceeded` once it is used up.
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "redis"))]
+    pub async fn check(
+        &self,
+        name: &str,
+        subject: &str,
+        limit: u64,
+        window_secs: i64,
+    ) -> Result<(), AppError> {
+        let key = format!("{}{}:{}", KEY_PREFIX, name, subject);
+
+        let count = match &self.counters {
+            Counters::Redis(conn) => {
+                let mut conn = conn.get().await?;
+                let count: u64 = conn.incr(&key, 1).await?;
+                if count == 1 {
+                    conn.expire::<_, ()>(&key, window_secs).await?;
+                }
+                count
+            }
+            Counters::Memory(counters) => {
+                let now = Instant::now();
+                let mut counters = counters.lock().unwrap();
+                counters.retain(|_, (_, resets_at)| *resets_at > now);
+                let (count, _) = counters
+                    .entry(key)
+                    .or_insert((0, now + Duration::from_secs(window_secs.max(0) as u64)));
+                *count += 1;
+                *count
+            }
+        };
+        if count > limit {
+            return Err(AppError::RateLimitExceeded);
+        }
+
+        Ok(())
+    }
+}
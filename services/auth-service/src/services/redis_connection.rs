@@ -0,0 +1,33 @@
+use redis::aio::ConnectionManager;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use crate::errors::AppError;
+
+/// A Redis connection opened on first use and shared by clones.
+///
+/// For stores only some routes touch, so the service can be assembled, as in
+/// tests, without a Redis server behind it.
+#[derive(Clone)]
+pub struct LazyConnection {
+    client: redis::Client,
+    conn: Arc<OnceCell<ConnectionManager>>,
+}
+
+impl LazyConnection {
+    pub fn new(client: &redis::Client) -> Self {
+        Self {
+            client: client.clone(),
+            conn: Arc::new(OnceCell::new()),
+        }
+    }
+
+    pub async fn get(&self) -> Result<ConnectionManager, AppError> {
+        let conn = self
+            .conn
+            .get_or_try_init(|| ConnectionManager::new(self.client.clone()))
+            .await?;
+
+        Ok(conn.clone())
+    }
+}
@@ -0,0 +1,178 @@
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use crate::{errors::AppError, handlers::metrics, AppState};
+
+/// How often each job checks whether it is due. Bounds how late a run can
+/// start after its interval has elapsed.
+const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A periodic maintenance task.
+#[async_trait]
+pub trait Job: Send + Sync {
+    /// Stable name, used for the lock, the `auth.job_runs` row and metrics.
+    fn name(&self) -> &'static str;
+
+    /// Minimum time between the starts of two runs across all replicas.
+    fn interval(&self) -> Duration;
+
+    /// Whether runs are coordinated across replicas. Jobs that only refresh
+    /// state held by this process return false and run on every replica,
+    /// once per interval, without the lock or the `auth.job_runs` row.
+    fn exclusive(&self) -> bool {
+        true
+    }
+
+    /// Does one pass and returns how many items it handled.
+    async fn run(&self, state: &AppState) -> Result<u64, AppError>;
+}
+
+/// Runs jobs in the background of every replica, while making sure each job
+/// runs at most once per interval across all of them.
+///
+/// A run holds a transaction-scoped Postgres advisory lock keyed on the job
+/// name, so concurrent attempts from other replicas skip instead of waiting;
+/// the start time recorded in `auth.job_runs` under that lock keeps replicas
+/// that poll just after a run has finished from repeating it. The lock is
+/// released with the transaction, including when a replica dies mid-run.
+pub struct Scheduler {
+    state: AppState,
+    jobs: Vec<Arc<dyn Job>>,
+}
+
+impl Scheduler {
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            jobs: Vec::new(),
+        }
+    }
+
+    pub fn with_job(mut self, job: impl Job + 'static) -> Self {
+        self.jobs.push(Arc::new(job));
+        self
+    }
+
+    /// Starts one task per job.
+    pub fn spawn(self) {
+        for job in self.jobs {
+            let state = self.state.clone();
+            tokio::spawn(async move {
+                let poll = if job.exclusive() {
+                    POLL_INTERVAL.min(job.interval())
+                } else {
+                    job.interval()
+                };
+                // Spread the first checks of freshly started replicas
+                let start = Instant::now() + poll.mul_f64(rand::random::<f64>());
+                let mut ticker = tokio::time::interval_at(start, poll);
+                ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+                loop {
+                    ticker.tick().await;
+                    if !job.exclusive() {
+                        run_and_record(&state, job.as_ref()).await;
+                    } else if let Err(e) = run_if_due(&state, job.as_ref()).await {
+                        tracing::error!(job = job.name(), "Failed to schedule job: {}", e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+/// Runs `job` if this replica wins the lock and the interval has elapsed.
+/// Errors from the job itself are recorded, not returned; the returned error
+/// is about the bookkeeping around it.
+async fn run_if_due(state: &AppState, job: &dyn Job) -> Result<(), AppError> {
+    let name = job.name();
+    let mut tx = state.db.begin().await?;
+
+    let locked: bool = sqlx::query_scalar!("SELECT pg_try_advisory_xact_lock($1)", lock_key(name))
+        .fetch_one(&mut *tx)
+        .await?
+        .unwrap_or(false);
+    if !locked {
+        tracing::debug!(job = name, "Job is running on another replica");
+        return Ok(());
+    }
+
+    let interval_secs = job.interval().as_secs_f64();
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO auth.job_runs (job_name, last_started_at)
+        VALUES ($1, CURRENT_TIMESTAMP)
+        ON CONFLICT (job_name) DO UPDATE
+        SET last_started_at = CURRENT_TIMESTAMP
+        WHERE auth.job_runs.last_started_at <= CURRENT_TIMESTAMP - make_interval(secs => $2)
+        "#,
+        name,
+        interval_secs
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if !claimed {
+        return Ok(());
+    }
+
+    let (items, error) = run_and_record(state, job).await;
+    let outcome = if error.is_none() {
+        "success"
+    } else {
+        "failure"
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE auth.job_runs
+        SET last_finished_at = CURRENT_TIMESTAMP, last_outcome = $2, last_error = $3,
+            items_processed = $4
+        WHERE job_name = $1
+        "#,
+        name,
+        outcome,
+        error,
+        items as i64
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Runs `job` once, logging and recording metrics for the outcome. Returns
+/// the items handled and the error, if it failed.
+async fn run_and_record(state: &AppState, job: &dyn Job) -> (u64, Option<String>) {
+    let name = job.name();
+    let started = Instant::now();
+    let result = job.run(state).await;
+    let duration = started.elapsed().as_secs_f64();
+
+    let (items, error) = match result {
+        Ok(items) => {
+            tracing::info!(job = name, items, duration, "Job finished");
+            (items, None)
+        }
+        Err(e) => {
+            tracing::error!(job = name, duration, "Job failed: {}", e);
+            (0, Some(e.to_string()))
+        }
+    };
+    metrics::record_job_run(name, error.is_none(), duration, items);
+
+    (items, error)
+}
+
+/// Advisory lock keys are a single bigint namespace shared by everything on
+/// the database, so derive one from a prefixed name instead of numbering jobs.
+fn lock_key(job_name: &str) -> i64 {
+    let digest = Sha256::digest(format!("auth-service:job:{}", job_name).as_bytes());
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    i64::from_be_bytes(bytes)
+}
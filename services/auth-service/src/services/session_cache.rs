@@ -0,0 +1,147 @@
+use bookmarket_auth::REVOKED_KEY_PREFIX;
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use redis::Script;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{errors::AppError, models::Session, services::redis_connection::LazyConnection};
+
+const KEY_PREFIX: &str = "auth:session:";
+
+lazy_static! {
+    /// Reads an entry unless the session has a revocation marker.
+    static ref GET_UNLESS_REVOKED: Script = Script::new(
+        r"
+        if redis.call('EXISTS', KEYS[2]) == 1 then
+            return false
+        end
+        return redis.call('GET', KEYS[1])
+        ",
+    );
+
+    /// Writes an entry unless the session has a revocation marker, so a
+    /// read that raced a revocation can't put the session back.
+    static ref PUT_UNLESS_REVOKED: Script = Script::new(
+        r"
+        if redis.call('EXISTS', KEYS[2]) == 1 then
+            return 0
+        end
+        redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[2])
+        return 1
+        ",
+    );
+}
+
+/// The subset of a session the auth middleware needs to accept a token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedSession {
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl From<&Session> for CachedSession {
+    fn from(session: &Session) -> Self {
+        Self {
+            user_id: session.user_id,
+            expires_at: session.expires_at,
+        }
+    }
+}
+
+/// Redis read-through cache of session validity.
+///
+/// Postgres stays the source of truth: entries are only written after a
+/// session has been read from the database, and every code path that deletes
+/// sessions must invalidate them here. A session with a revocation marker is
+/// neither served nor written, so a read-through that started before the
+/// revocation can't resurrect it.
+///
+/// Invalidation also leaves a revocation marker that other services check
+/// through `bookmarket-auth`. Markers outlive the session's refresh token, so
+/// no token for the session can still verify once its marker is gone.
+///
+/// The connection is opened on first use, so the service starts, and serves
+/// from Postgres, while Redis is down.
+#[derive(Clone)]
+pub struct SessionCache {
+    conn: LazyConnection,
+    ttl_seconds: u64,
+    revocation_ttl_seconds: u64,
+}
+
+impl SessionCache {
+    pub fn new(client: &redis::Client, ttl_seconds: u64, revocation_ttl_seconds: u64) -> Self {
+        Self {
+            conn: LazyConnection::new(client),
+            ttl_seconds,
+            revocation_ttl_seconds,
+        }
+    }
+
+    fn key(session_id: &str) -> String {
+        format!("{}{}", KEY_PREFIX, session_id)
+    }
+
+    fn revoked_key(session_id: &str) -> String {
+        format!("{}{}", REVOKED_KEY_PREFIX, session_id)
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "redis"))]
+    pub async fn get(&self, session_id: &str) -> Result<Option<CachedSession>, AppError> {
+        let mut conn = self.conn.get().await?;
+        let value: Option<String> = GET_UNLESS_REVOKED
+            .key(Self::key(session_id))
+            .key(Self::revoked_key(session_id))
+            .invoke_async(&mut conn)
+            .await?;
+
+        // A malformed entry is treated as a miss so it gets rewritten from Postgres
+        Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "redis"))]
+    pub async fn put(&self, session: &Session) -> Result<(), AppError> {
+        let remaining = (session.expires_at - Utc::now()).num_seconds();
+        if remaining <= 0 {
+            return Ok(());
+        }
+
+        let ttl = self.ttl_seconds.min(remaining as u64);
+        let value = serde_json::to_string(&CachedSession::from(session))
+            .map_err(|e| AppError::InternalServerError(e.to_string()))?;
+
+        let id = session.id.to_string();
+        let mut conn = self.conn.get().await?;
+        PUT_UNLESS_REVOKED
+            .key(Self::key(&id))
+            .key(Self::revoked_key(&id))
+            .arg(value)
+            .arg(ttl)
+            .invoke_async::<_, ()>(&mut conn)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "redis"))]
+    pub async fn invalidate_many(&self, session_ids: &[Uuid]) -> Result<(), AppError> {
+        if session_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut pipe = redis::pipe();
+        for id in session_ids {
+            let id = id.to_string();
+            pipe.del(Self::key(&id))
+                .ignore()
+                .set_ex(Self::revoked_key(&id), 1, self.revocation_ttl_seconds)
+                .ignore();
+        }
+
+        let mut conn = self.conn.get().await?;
+        pipe.query_async::<_, ()>(&mut conn).await?;
+
+        Ok(())
+    }
+}
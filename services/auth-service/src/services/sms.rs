@@ -0,0 +1,100 @@
+use async_trait::async_trait;
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+
+use crate::{config::Config, errors::AppError};
+
+#[derive(Debug, Clone)]
+pub struct SmsMessage {
+    /// Recipient in E.164 form.
+    pub to: String,
+    pub body: String,
+}
+
+/// Outbound text messages.
+#[async_trait]
+pub trait SmsSender: Send + Sync {
+    async fn send(&self, message: SmsMessage) -> Result<(), AppError>;
+}
+
+/// Writes text messages to the log instead of delivering them. Used when no
+/// SMS gateway is configured, which is the normal local development setup.
+pub struct LogSmsSender;
+
+#[async_trait]
+impl SmsSender for LogSmsSender {
+    async fn send(&self, message: SmsMessage) -> Result<(), AppError> {
+        tracing::info!(
+            to = %message.to,
+            "SMS not delivered (no gateway configured): {}",
+            message.body
+        );
+
+        Ok(())
+    }
+}
+
+/// Delivers text messages through an HTTP gateway that accepts
+/// `{"from", "to", "body"}` as JSON with a bearer API key.
+pub struct HttpSmsSender {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    sender_id: String,
+}
+
+#[derive(Serialize)]
+struct GatewayRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    body: &'a str,
+}
+
+impl HttpSmsSender {
+    pub fn new(url: &str, api_key: Option<String>, sender_id: &str) -> Result<Self, AppError> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(|e| AppError::InternalServerError(format!("HTTP client: {}", e)))?;
+
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            api_key,
+            sender_id: sender_id.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl SmsSender for HttpSmsSender {
+    async fn send(&self, message: SmsMessage) -> Result<(), AppError> {
+        let mut request = self.client.post(&self.url).json(&GatewayRequest {
+            from: &self.sender_id,
+            to: &message.to,
+            body: &message.body,
+        });
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::InternalServerError(format!("SMS delivery failed: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+pub fn from_config(config: &Config) -> Result<Arc<dyn SmsSender>, AppError> {
+    match config.sms_gateway_url.as_deref() {
+        Some(url) if !url.is_empty() => Ok(Arc::new(HttpSmsSender::new(
+            url,
+            config.sms_gateway_api_key.clone(),
+            &config.sms_sender_id,
+        )?)),
+        _ => Ok(Arc::new(LogSmsSender)),
+    }
+}
@@ -0,0 +1,246 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    auth::{
+        decode_jwt_token, decode_jwt_token_allow_expired, hash_api_key, refresh_generation_hash,
+    },
+    config::{Config, OAuthClientConfig},
+    errors::AppError,
+    handlers::metrics,
+    models::{IntrospectionResponse, TokenUse, UserRole, UserStatus},
+    repositories::{ApiKeyRepository, SessionRepository, UserRepository},
+};
+
+const API_KEY_PREFIX: &str = "bm_";
+
+/// What a revocation request ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RevokedToken {
+    /// The session behind an access or refresh token.
+    Session {
+        session_id: String,
+        user_id: Uuid,
+    },
+    ApiKey {
+        key_id: Uuid,
+        user_id: Uuid,
+    },
+}
+
+/// RFC 7662 token introspection for access tokens, refresh tokens and API
+/// keys. Anything that is malformed, expired, revoked or belongs to an
+/// account that is no longer active is reported as inactive, never as an
+/// error.
+pub async fn introspect(
+    pool: &PgPool,
+    users: &dyn UserRepository,
+    sessions: &dyn SessionRepository,
+    config: &Config,
+    token: &str,
+) -> Result<IntrospectionResponse, AppError> {
+    // API keys are recognisable by their prefix, so the type hint is not needed
+    if token.starts_with(API_KEY_PREFIX) {
+        let response = introspect_api_key(pool, token).await?;
+        metrics::record_api_key_authentication(response.active);
+        return Ok(response);
+    }
+
+    let claims = match decode_jwt_token(token, &config.jwt_secrets()) {
+        Ok(claims) => claims,
+        Err(_) => return Ok(IntrospectionResponse::default()),
+    };
+
+    let session = match sessions.get_active(&claims.jti).await {
+        Ok(session) if session.user_id.to_string() == claims.sub => session,
+        _ => return Ok(IntrospectionResponse::default()),
+    };
+
+    // A refresh token stops being active once it has been used, although
+    // its session lives on
+    if claims.token_use == Some(TokenUse::Refresh) {
+        let current = match sessions.get(&claims.jti).await {
+            Ok(session) => {
+                refresh_generation_hash(&claims, &session).as_deref()
+                    == Some(session.token_hash.as_str())
+            }
+            Err(_) => false,
+        };
+        if !current {
+            return Ok(IntrospectionResponse::default());
+        }
+    }
+
+    let user = users.get_by_id(&session.user_id.to_string()).await?;
+    if user.status != UserStatus::Active {
+        return Ok(IntrospectionResponse::default());
+    }
+
+    let token_type = match claims.token_use {
+        Some(TokenUse::Refresh) => "refresh_token",
+        _ => "access_token",
+    };
+
+    Ok(IntrospectionResponse {
+        active: true,
+        scope: Some(role_scope(&user.role).to_string()),
+        token_type: Some(token_type.to_string()),
+        sub: Some(claims.sub),
+        username: Some(user.email),
+        role: Some(user.role),
+        exp: Some(claims.exp),
+        iat: Some(claims.iat),
+        jti: Some(claims.jti),
+        act: claims.act,
+    })
+}
+
+async fn introspect_api_key(pool: &PgPool, key: &str) -> Result<IntrospectionResponse, AppError> {
+    // A lookup only: asking about a key is not using it, so last_used stays
+    let row = sqlx::query!(
+        r#"
+        SELECT k.id, k.user_id, k.scopes, k.expires_at, k.created_at,
+               u.email, u.role as "role: UserRole"
+        FROM auth.api_keys k
+        JOIN auth.users u ON u.id = k.user_id
+        WHERE k.key_hash = $1
+          AND u.status = 'active'
+          AND k.disabled_at IS NULL
+          AND (k.expires_at IS NULL OR k.expires_at > CURRENT_TIMESTAMP)
+        "#,
+        hash_api_key(key)
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(IntrospectionResponse::default()),
+    };
+
+    Ok(IntrospectionResponse {
+        active: true,
+        scope: Some(row.scopes.join(" ")),
+        token_type: Some("api_key".to_string()),
+        sub: Some(row.user_id.to_string()),
+        username: Some(row.email),
+        role: Some(row.role),
+        exp: row.expires_at.map(|at| at.timestamp()),
+        iat: Some(row.created_at.timestamp()),
+        jti: Some(row.id.to_string()),
+        act: None,
+    })
+}
+
+/// RFC 7009 revocation. Access and refresh tokens of one login share a
+/// session, so revoking either ends both. Returns `None` when there was
+/// nothing to revoke, which callers must not report as an error.
+///
+/// Sessions and API keys are issued to BookMarket's own apps, so other
+/// clients are refused with `Forbidden` before the token is looked at.
+pub async fn revoke(
+    api_keys: &dyn ApiKeyRepository,
+    sessions: &dyn SessionRepository,
+    config: &Config,
+    client: &OAuthClientConfig,
+    token: &str,
+) -> Result<Option<RevokedToken>, AppError> {
+    if !client.first_party {
+        return Err(AppError::Forbidden);
+    }
+
+    if token.starts_with(API_KEY_PREFIX) {
+        let revoked = api_keys.delete_by_hash(&hash_api_key(token)).await?;
+
+        return Ok(revoked.map(|key| RevokedToken::ApiKey {
+            key_id: key.id,
+            user_id: key.user_id,
+        }));
+    }
+
+    // Expired tokens are still revoked so a stale refresh token can be
+    // used to end its session
+    let claims = match decode_jwt_token_allow_expired(token, &config.jwt_secrets()) {
+        Ok(claims) => claims,
+        Err(_) => return Ok(None),
+    };
+    let user_id = match Uuid::parse_str(&claims.sub) {
+        Ok(user_id) => user_id,
+        Err(_) => return Ok(None),
+    };
+
+    let session = match sessions.get(&claims.jti).await {
+        Ok(session) if session.user_id == user_id => session,
+        _ => return Ok(None),
+    };
+    sessions.delete(&session.id.to_string()).await?;
+
+    Ok(Some(RevokedToken::Session {
+        session_id: claims.jti,
+        user_id,
+    }))
+}
+
+/// Space-separated scope string for a user token. User tokens carry a role
+/// rather than scopes; the role is exposed as a scope so resource servers can
+/// apply one kind of check to tokens and API keys alike.
+fn role_scope(role: &UserRole) -> &'static str {
+    match role {
+        UserRole::Customer => "customer",
+        UserRole::Vendor => "vendor",
+        UserRole::Admin => "admin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        auth::generate_api_key,
+        repositories::{
+            memory::MemorySessionRepository,
+            postgres::{PgApiKeyRepository, PgUserRepository},
+        },
+    };
+
+    #[sqlx::test(migrator = "crate::database::MIGRATOR")]
+    async fn introspecting_an_api_key_does_not_mark_it_used(pool: PgPool) {
+        std::env::set_var("JWT_SECRET", "tokens-test-secret");
+        std::env::set_var("MFA_ENCRYPTION_KEY", "00".repeat(32));
+        let config = Config::from_env().unwrap();
+        let users = PgUserRepository::new(pool.clone());
+        let api_keys = PgApiKeyRepository::new(pool.clone());
+
+        let user = users
+            .create(
+                "reader@example.com",
+                "unused-hash",
+                "Test",
+                "Reader",
+                None,
+                UserRole::Customer,
+            )
+            .await
+            .unwrap();
+        let key = generate_api_key();
+        let created = api_keys
+            .create(user.id, &hash_api_key(&key), "ci", &[], None)
+            .await
+            .unwrap();
+
+        let response = introspect(
+            &pool,
+            &users,
+            &MemorySessionRepository::new(),
+            &config,
+            &key,
+        )
+        .await
+        .unwrap();
+        assert!(response.active);
+        assert_eq!(response.token_type.as_deref(), Some("api_key"));
+
+        let key = api_keys.get(created.id, user.id).await.unwrap();
+        assert_eq!(key.last_used, None);
+    }
+}
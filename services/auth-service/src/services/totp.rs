@@ -0,0 +1,431 @@
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use reqwest::Url;
+use sha1::Sha1;
+use uuid::Uuid;
+
+use crate::{config::Config, errors::AppError, repositories::TotpRepository};
+
+/// Seconds each code is valid for, as assumed by authenticator apps.
+const STEP_SECS: i64 = 30;
+
+const DIGITS: u32 = 6;
+
+/// Codes from one step either side of the current one are accepted, to
+/// allow for clock drift between the server and the phone.
+const ALLOWED_DRIFT_STEPS: i64 = 1;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Marks a secret encrypted under `mfa_encryption_key`. Secrets stored
+/// before encryption was added are bare base32, which never contains `:`.
+const SEALED_PREFIX: &str = "v1:";
+
+const NONCE_LEN: usize = 12;
+
+/// Starts enrolling an authenticator app for `user_id`, replacing any
+/// enrolment that was never confirmed. Returns the base32 secret to show the
+/// user.
+pub async fn enroll(
+    factors: &dyn TotpRepository,
+    config: &Config,
+    user_id: Uuid,
+) -> Result<String, AppError> {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let secret = base32_encode(&secret);
+
+    if !factors
+        .begin(user_id, &seal(config, user_id, &secret)?)
+        .await?
+    {
+        return Err(AppError::Conflict("MFA is already enabled".to_string()));
+    }
+
+    Ok(secret)
+}
+
+/// Confirms a pending enrolment with a first code from the app.
+pub async fn confirm(
+    factors: &dyn TotpRepository,
+    config: &Config,
+    user_id: Uuid,
+    code: &str,
+) -> Result<(), AppError> {
+    let secret = load_secret(factors, config, user_id, false)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("No MFA setup is in progress".to_string()))?;
+
+    let step = matching_step(&secret, code, Utc::now().timestamp())
+        .ok_or_else(|| AppError::BadRequest("Invalid code".to_string()))?;
+
+    factors.confirm(user_id, step).await
+}
+
+/// Whether the user has a confirmed authenticator, and so must give a code
+/// to sign in.
+pub async fn is_enabled(factors: &dyn TotpRepository, user_id: Uuid) -> Result<bool, AppError> {
+    Ok(factors.secret(user_id, true).await?.is_some())
+}
+
+/// Checks a code against the user's confirmed authenticator. Each code is
+/// only accepted once, so one seen over someone's shoulder can't be replayed.
+pub async fn verify(
+    factors: &dyn TotpRepository,
+    config: &Config,
+    user_id: Uuid,
+    code: &str,
+) -> Result<bool, AppError> {
+    let secret = load_secret(factors, config, user_id, true)
+        .await?
+        .ok_or_else(|| AppError::BadRequest("MFA is not enabled".to_string()))?;
+
+    let Some(step) = matching_step(&secret, code, Utc::now().timestamp()) else {
+        return Ok(false);
+    };
+
+    factors.use_step(user_id, step).await
+}
+
+/// Removes the user's authenticator, confirmed or not.
+pub async fn disable(factors: &dyn TotpRepository, user_id: Uuid) -> Result<(), AppError> {
+    if !factors.delete(user_id).await? {
+        return Err(AppError::NotFound);
+    }
+
+    Ok(())
+}
+
+/// Reads and decrypts the user's secret. One stored before encryption was
+/// added is encrypted in place on first use.
+async fn load_secret(
+    factors: &dyn TotpRepository,
+    config: &Config,
+    user_id: Uuid,
+    confirmed: bool,
+) -> Result<Option<String>, AppError> {
+    let Some(stored) = factors.secret(user_id, confirmed).await? else {
+        return Ok(None);
+    };
+
+    match stored.strip_prefix(SEALED_PREFIX) {
+        Some(sealed) => open(config, user_id, sealed).map(Some),
+        None => {
+            factors
+                .replace_secret(user_id, &seal(config, user_id, &stored)?)
+                .await?;
+            Ok(Some(stored))
+        }
+    }
+}
+
+fn cipher(config: &Config) -> Result<Aes256Gcm, AppError> {
+    hex::decode(&config.mfa_encryption_key)
+        .ok()
+        .and_then(|key| Aes256Gcm::new_from_slice(&key).ok())
+        .ok_or_else(|| AppError::InternalServerError("Invalid MFA encryption key".to_string()))
+}
+
+/// Encrypts a secret for storage. The user ID is bound in as associated
+/// data, so a secret copied onto another account's row won't decrypt.
+fn seal(config: &Config, user_id: Uuid, secret: &str) -> Result<String, AppError> {
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ciphertext = cipher(config)?
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: secret.as_bytes(),
+                aad: user_id.as_bytes(),
+            },
+        )
+        .map_err(|_| AppError::InternalServerError("Failed to encrypt MFA secret".to_string()))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+
+    Ok(format!("{}{}", SEALED_PREFIX, STANDARD.encode(sealed)))
+}
+
+fn open(config: &Config, user_id: Uuid, sealed: &str) -> Result<String, AppError> {
+    let failed = || AppError::InternalServerError("Failed to decrypt MFA secret".to_string());
+
+    let sealed = STANDARD.decode(sealed).map_err(|_| failed())?;
+    if sealed.len() < NONCE_LEN {
+        return Err(failed());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+
+    let secret = cipher(config)?
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: user_id.as_bytes(),
+            },
+        )
+        .map_err(|_| failed())?;
+
+    String::from_utf8(secret).map_err(|_| failed())
+}
+
+/// The `otpauth://` URI authenticator apps scan from a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    let mut url = Url::parse("otpauth://totp").expect("valid base URI");
+    url.path_segments_mut()
+        .expect("otpauth URIs have a path")
+        .push(&format!("{}:{}", issuer, account));
+    url.query_pairs_mut()
+        .append_pair("secret", secret)
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &STEP_SECS.to_string());
+
+    url.to_string()
+}
+
+/// The time step `code` is valid for at `now`, if any.
+fn matching_step(secret: &str, code: &str, now: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let key = base32_decode(secret)?;
+    let current = now / STEP_SECS;
+
+    (current - ALLOWED_DRIFT_STEPS..=current + ALLOWED_DRIFT_STEPS)
+        .find(|&step| hotp(&key, step as u64) == code)
+}
+
+/// RFC 4226 HOTP value for `counter`, zero-padded.
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac =
+        <Hmac<Sha1> as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// RFC 4648 base32 without padding, as authenticator apps expect.
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+fn base32_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in input.bytes().filter(|&c| c != b'=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_uppercase())? as u32;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repositories::memory::MemoryTotpRepository;
+
+    /// The SHA-1 seed of RFC 6238 Appendix B, "12345678901234567890".
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn test_config() -> Config {
+        if std::env::var("DATABASE_URL").is_err() {
+            std::env::set_var("DATABASE_URL", "postgres://unused@localhost/unused");
+        }
+        std::env::set_var("JWT_SECRET", "totp-test-secret");
+        std::env::set_var("MFA_ENCRYPTION_KEY", "00".repeat(32));
+
+        Config::from_env().unwrap()
+    }
+
+    fn code_at_step(secret: &str, step: i64) -> String {
+        hotp(&base32_decode(secret).unwrap(), step as u64)
+    }
+
+    #[test]
+    fn rfc_6238_test_vectors() {
+        assert_eq!(base32_encode(b"12345678901234567890"), RFC_SECRET);
+        let key = base32_decode(RFC_SECRET).unwrap();
+
+        // The RFC lists 8-digit values; 6-digit codes are their last six digits
+        for (time, expected) in [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ] {
+            let step = time / STEP_SECS;
+            assert_eq!(hotp(&key, step as u64), expected[2..], "T = {}", time);
+            assert_eq!(
+                matching_step(RFC_SECRET, &expected[2..], time),
+                Some(step),
+                "T = {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn one_step_of_drift_is_allowed_either_way() {
+        let step = 1111111109 / STEP_SECS;
+
+        for now in [step * STEP_SECS, step * STEP_SECS + STEP_SECS - 1] {
+            for (offset, accepted) in [(-2, false), (-1, true), (0, true), (1, true), (2, false)] {
+                let code = code_at_step(RFC_SECRET, step + offset);
+                assert_eq!(
+                    matching_step(RFC_SECRET, &code, now),
+                    accepted.then_some(step + offset),
+                    "offset {} at {}",
+                    offset,
+                    now
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        let code = code_at_step(RFC_SECRET, 1111111109 / STEP_SECS);
+
+        assert_eq!(
+            matching_step(RFC_SECRET, &format!(" {} ", code), 1111111109),
+            Some(1111111109 / STEP_SECS)
+        );
+        for malformed in ["", "12345", "1234567", "12a456", "１２３４５６", &code[..5]] {
+            assert_eq!(matching_step(RFC_SECRET, malformed, 1111111109), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn each_step_is_accepted_once() {
+        let config = test_config();
+        let factors = MemoryTotpRepository::new();
+        let user_id = Uuid::new_v4();
+
+        let secret = enroll(&factors, &config, user_id).await.unwrap();
+        assert!(!is_enabled(&factors, user_id).await.unwrap());
+
+        // Steps either side of now are all valid; confirming uses the oldest
+        let now = Utc::now().timestamp() / STEP_SECS;
+        confirm(&factors, &config, user_id, &code_at_step(&secret, now - 1))
+            .await
+            .unwrap();
+        assert!(is_enabled(&factors, user_id).await.unwrap());
+
+        let current = code_at_step(&secret, now);
+        assert!(verify(&factors, &config, user_id, &current).await.unwrap());
+        assert!(!verify(&factors, &config, user_id, &current).await.unwrap());
+
+        // Nor is an earlier step once a later one has been used
+        let next = code_at_step(&secret, now + 1);
+        assert!(verify(&factors, &config, user_id, &next).await.unwrap());
+        assert!(
+            !verify(&factors, &config, user_id, &code_at_step(&secret, now))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn secrets_are_encrypted_at_rest() {
+        let config = test_config();
+        let factors = MemoryTotpRepository::new();
+        let user_id = Uuid::new_v4();
+
+        let secret = enroll(&factors, &config, user_id).await.unwrap();
+        let stored = factors.stored_secret(user_id).unwrap();
+
+        assert!(stored.starts_with(SEALED_PREFIX));
+        assert!(!stored.contains(&secret));
+        assert_eq!(
+            open(&config, user_id, &stored[SEALED_PREFIX.len()..]).unwrap(),
+            secret
+        );
+
+        // Bound to the account it was stored for
+        assert!(open(&config, Uuid::new_v4(), &stored[SEALED_PREFIX.len()..]).is_err());
+
+        // And to the key
+        let other_key = Config {
+            mfa_encryption_key: "11".repeat(32),
+            ..config
+        };
+        assert!(open(&other_key, user_id, &stored[SEALED_PREFIX.len()..]).is_err());
+    }
+
+    #[tokio::test]
+    async fn plaintext_secrets_are_encrypted_on_first_use() {
+        let config = test_config();
+        let factors = MemoryTotpRepository::new();
+        let user_id = Uuid::new_v4();
+
+        // As stored before secrets were encrypted
+        factors.begin(user_id, RFC_SECRET).await.unwrap();
+        factors.confirm(user_id, 0).await.unwrap();
+
+        let now = Utc::now().timestamp() / STEP_SECS;
+        assert!(
+            verify(&factors, &config, user_id, &code_at_step(RFC_SECRET, now))
+                .await
+                .unwrap()
+        );
+
+        let stored = factors.stored_secret(user_id).unwrap();
+        assert!(stored.starts_with(SEALED_PREFIX));
+        assert_eq!(
+            open(&config, user_id, &stored[SEALED_PREFIX.len()..]).unwrap(),
+            RFC_SECRET
+        );
+    }
+}
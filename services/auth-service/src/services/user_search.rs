@@ -0,0 +1,171 @@
+use async_stream::try_stream;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::{
+    errors::AppError,
+    models::{User, UserRole, UserStatus},
+};
+
+const USER_COLUMNS: &str = "id, email, password_hash, first_name, last_name, phone, role, status, \
+     email_verified, phone_verified, password_reset_required, last_login, created_at, updated_at";
+
+/// Haystack for free-text search. Must stay identical to the expression of
+/// `idx_users_search_trgm` for the trigram index to be used.
+const SEARCH_EXPR: &str =
+    "lower(coalesce(first_name, '') || ' ' || coalesce(last_name, '') || ' ' || email)";
+
+#[derive(Debug, Clone, Default)]
+pub struct UserSearch {
+    pub role: Option<UserRole>,
+    pub status: Option<UserStatus>,
+    pub email_verified: Option<bool>,
+    pub phone_verified: Option<bool>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    pub last_login_from: Option<DateTime<Utc>>,
+    pub last_login_to: Option<DateTime<Utc>>,
+    pub search: Option<String>,
+}
+
+/// Keyset position in the `created_at DESC, id DESC` ordering.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl UserCursor {
+    pub fn after(user: &User) -> Self {
+        Self {
+            created_at: user.created_at,
+            id: user.id,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}|{}", self.created_at.to_rfc3339(), self.id))
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::BadRequest("Invalid cursor".to_string());
+
+        let raw = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (created_at, id) = raw.split_once('|').ok_or_else(invalid)?;
+
+        Ok(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .map_err(|_| invalid())?
+                .with_timezone(&Utc),
+            id: Uuid::parse_str(id).map_err(|_| invalid())?,
+        })
+    }
+}
+
+fn push_filters(builder: &mut QueryBuilder<'_, Postgres>, filter: &UserSearch) {
+    builder.push(" WHERE TRUE");
+
+    if let Some(role) = &filter.role {
+        builder.push(" AND role = ").push_bind(role.clone());
+    }
+    if let Some(status) = &filter.status {
+        builder.push(" AND status = ").push_bind(status.clone());
+    }
+    if let Some(email_verified) = filter.email_verified {
+        builder
+            .push(" AND email_verified = ")
+            .push_bind(email_verified);
+    }
+    if let Some(phone_verified) = filter.phone_verified {
+        builder
+            .push(" AND phone_verified = ")
+            .push_bind(phone_verified);
+    }
+    if let Some(from) = filter.created_from {
+        builder.push(" AND created_at >= ").push_bind(from);
+    }
+    if let Some(to) = filter.created_to {
+        builder.push(" AND created_at < ").push_bind(to);
+    }
+    if let Some(from) = filter.last_login_from {
+        builder.push(" AND last_login >= ").push_bind(from);
+    }
+    if let Some(to) = filter.last_login_to {
+        builder.push(" AND last_login < ").push_bind(to);
+    }
+
+    let term = filter
+        .search
+        .as_deref()
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty());
+    if let Some(term) = term {
+        // Substring match for exact fragments, word similarity to tolerate typos;
+        // both operators are served by the trigram index
+        builder
+            .push(" AND (")
+            .push(SEARCH_EXPR)
+            .push(" LIKE ")
+            .push_bind(format!("%{}%", escape_like(&term)))
+            .push(" OR ")
+            .push_bind(term)
+            .push(" <% ")
+            .push(SEARCH_EXPR)
+            .push(")");
+    }
+}
+
+fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Returns one page of users newest first, starting after `cursor`.
+pub async fn search_users(
+    pool: &PgPool,
+    filter: &UserSearch,
+    cursor: Option<UserCursor>,
+    limit: i64,
+) -> Result<Vec<User>, AppError> {
+    let mut builder = QueryBuilder::new(format!("SELECT {} FROM auth.users", USER_COLUMNS));
+    push_filters(&mut builder, filter);
+
+    if let Some(cursor) = cursor {
+        builder
+            .push(" AND (created_at, id) < (")
+            .push_bind(cursor.created_at)
+            .push(", ")
+            .push_bind(cursor.id)
+            .push(")");
+    }
+
+    builder
+        .push(" ORDER BY created_at DESC, id DESC LIMIT ")
+        .push_bind(limit);
+
+    let users = builder.build_query_as::<User>().fetch_all(pool).await?;
+
+    Ok(users)
+}
+
+/// Streams every matching user without buffering the result set.
+pub fn stream_users(
+    pool: PgPool,
+    filter: UserSearch,
+) -> impl Stream<Item = Result<User, AppError>> + Send + 'static {
+    try_stream! {
+        let mut builder = QueryBuilder::new(format!("SELECT {} FROM auth.users", USER_COLUMNS));
+        push_filters(&mut builder, &filter);
+        builder.push(" ORDER BY created_at DESC, id DESC");
+
+        let mut rows = builder.build_query_as::<User>().fetch(&pool);
+        while let Some(user) = futures::TryStreamExt::try_next(&mut rows).await? {
+            yield user;
+        }
+    }
+}
@@ -0,0 +1,563 @@
+//! Signed outbound webhooks for account events.
+//!
+//! Handlers call [`notify_user`] or [`notify_org`] after a change, which
+//! queues one delivery per matching subscription. The delivery job posts them,
+//! retrying failures with exponential backoff until `webhook_max_attempts`,
+//! after which a delivery is dead until someone redelivers it.
+//!
+//! Each request body is `{"id", "type", "created_at", "data"}`, where `id` is
+//! shared by every delivery of the event. Requests carry
+//! `X-BookMarket-Signature: t=<unix time>,v1=<hex HMAC-SHA256>`, computed over
+//! `"<t>.<body>"` with the subscription secret. Receivers should recompute it
+//! and reject timestamps too far from their own clock.
+//!
+//! Receivers must resolve to public addresses only. The check is made when a
+//! subscription is created and again for every attempt, which connects to the
+//! addresses that were checked so the name can't be re-pointed in between.
+
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::generate_token,
+    errors::AppError,
+    models::{WebhookDelivery, WebhookDeliveryStatus, WebhookSubscription},
+};
+
+pub const SIGNATURE_HEADER: &str = "X-BookMarket-Signature";
+pub const EVENT_HEADER: &str = "X-BookMarket-Event";
+pub const DELIVERY_HEADER: &str = "X-BookMarket-Delivery";
+
+const SECRET_PREFIX: &str = "whsec_";
+
+/// Deliveries attempted per pass, and how many are in flight at once.
+const DELIVERY_BATCH_SIZE: i64 = 100;
+const DELIVERY_CONCURRENCY: usize = 10;
+
+/// Longest wait between two attempts, however many have failed.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(6 * 3600);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebhookEvent {
+    ApiKeyCreated,
+    ApiKeyRevoked,
+    OrgMemberJoined,
+    OrgMemberRoleChanged,
+    OrgMemberRemoved,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 5] = [
+        WebhookEvent::ApiKeyCreated,
+        WebhookEvent::ApiKeyRevoked,
+        WebhookEvent::OrgMemberJoined,
+        WebhookEvent::OrgMemberRoleChanged,
+        WebhookEvent::OrgMemberRemoved,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEvent::ApiKeyCreated => "api_key.created",
+            WebhookEvent::ApiKeyRevoked => "api_key.revoked",
+            WebhookEvent::OrgMemberJoined => "org.member_joined",
+            WebhookEvent::OrgMemberRoleChanged => "org.member_role_changed",
+            WebhookEvent::OrgMemberRemoved => "org.member_removed",
+        }
+    }
+
+    pub fn parse(event: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.as_str() == event)
+    }
+}
+
+fn envelope(event: WebhookEvent, event_id: Uuid, data: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "id": event_id,
+        "type": event.as_str(),
+        "created_at": Utc::now(),
+        "data": data,
+    })
+}
+
+/// Queues `event` for the user's own subscriptions and those of every
+/// organisation they belong to, since a vendor's staff act for the shop.
+///
+/// Callers have already made the change the event describes, so a failure to
+/// queue is logged rather than returned.
+pub async fn notify_user(
+    pool: &PgPool,
+    user_id: Uuid,
+    event: WebhookEvent,
+    data: serde_json::Value,
+) {
+    let event_id = Uuid::new_v4();
+
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO auth.webhook_deliveries (subscription_id, event_id, event_type, payload)
+        SELECT s.id, $2, $3::varchar, $4
+        FROM auth.webhook_subscriptions s
+        WHERE $3 = ANY(s.events)
+          AND (s.user_id = $1
+               OR s.org_id IN (SELECT org_id FROM auth.organization_members WHERE user_id = $1))
+        "#,
+        user_id,
+        event_id,
+        event.as_str(),
+        envelope(event, event_id, data)
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = queued {
+        tracing::error!(event = event.as_str(), %user_id, "Failed to queue webhooks: {}", e);
+    }
+}
+
+/// Queues `event` for the organisation's subscriptions. Failures are logged,
+/// as for [`notify_user`].
+pub async fn notify_org(pool: &PgPool, org_id: Uuid, event: WebhookEvent, data: serde_json::Value) {
+    let event_id = Uuid::new_v4();
+
+    let queued = sqlx::query!(
+        r#"
+        INSERT INTO auth.webhook_deliveries (subscription_id, event_id, event_type, payload)
+        SELECT id, $2, $3::varchar, $4
+        FROM auth.webhook_subscriptions
+        WHERE org_id = $1 AND $3 = ANY(events)
+        "#,
+        org_id,
+        event_id,
+        event.as_str(),
+        envelope(event, event_id, data)
+    )
+    .execute(pool)
+    .await;
+
+    if let Err(e) = queued {
+        tracing::error!(event = event.as_str(), %org_id, "Failed to queue webhooks: {}", e);
+    }
+}
+
+/// `t=<timestamp>,v1=<signature>` for a request body.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    format!(
+        "t={},v1={}",
+        timestamp,
+        hex::encode(mac.finalize().into_bytes())
+    )
+}
+
+/// Wait before the attempt after `attempts` failed ones: `base`, doubling
+/// each time, capped at six hours.
+pub fn retry_delay(base: Duration, attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    base.saturating_mul(factor).min(MAX_RETRY_DELAY)
+}
+
+/// Why a receiver can't be sent to. Shown to subscribers in the delivery
+/// log, so it never carries the underlying error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReceiverError {
+    Unresolvable,
+    NotPublic,
+}
+
+impl fmt::Display for ReceiverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReceiverError::Unresolvable => "Receiver host could not be resolved",
+            ReceiverError::NotPublic => "Receiver address is not public",
+        })
+    }
+}
+
+/// Receivers must use HTTPS, since the secret would otherwise travel in the
+/// clear alongside the signature it protects, and must resolve to public
+/// addresses, so subscriptions can't reach the internal network.
+pub async fn validate_url(url: &str) -> Result<(), AppError> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|_| AppError::Validation("Invalid webhook URL".to_string()))?;
+    if parsed.scheme() != "https" || parsed.host_str().is_none() {
+        return Err(AppError::Validation(
+            "Webhook URLs must use https".to_string(),
+        ));
+    }
+
+    match receiver_addresses(&parsed).await {
+        Ok(_) => Ok(()),
+        Err(ReceiverError::Unresolvable) => Err(AppError::Validation(
+            "Webhook URL host could not be resolved".to_string(),
+        )),
+        Err(ReceiverError::NotPublic) => Err(AppError::Validation(
+            "Webhook URLs must point to a public address".to_string(),
+        )),
+    }
+}
+
+/// Every address the URL's host resolves to, refused as a whole if any of
+/// them is not public.
+async fn receiver_addresses(url: &reqwest::Url) -> Result<Vec<SocketAddr>, ReceiverError> {
+    let host = url
+        .host_str()
+        .ok_or(ReceiverError::Unresolvable)?
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let port = url.port_or_known_default().unwrap_or(443);
+
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|_| ReceiverError::Unresolvable)?
+        .collect();
+    if addrs.is_empty() {
+        return Err(ReceiverError::Unresolvable);
+    }
+    if !addrs.iter().all(|addr| is_public(addr.ip())) {
+        return Err(ReceiverError::NotPublic);
+    }
+
+    Ok(addrs)
+}
+
+/// Whether an address is reachable on the public internet: not loopback,
+/// private, link-local (where cloud metadata services live), unique-local,
+/// shared, multicast or otherwise reserved.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)) // Carrier-grade NAT
+                || (a == 192 && b == 0 && c == 0) // IETF protocol assignments
+                || (a == 198 && (b == 18 || b == 19)) // Benchmarking
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_public(v4.into());
+            }
+            let segments = ip.segments();
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                // NAT64 reaches the embedded IPv4 address
+                let v4 = Ipv4Addr::from(((segments[6] as u32) << 16) | segments[7] as u32);
+                return is_public(v4.into());
+            }
+
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || (segments[0] & 0xfe00) == 0xfc00 // Unique local
+                || (segments[0] & 0xffc0) == 0xfe80 // Link local
+                || (segments[0] == 0x2001 && segments[1] == 0xdb8)) // Documentation
+        }
+    }
+}
+
+pub async fn create_subscription(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    org_id: Option<Uuid>,
+    url: &str,
+    events: &[WebhookEvent],
+    created_by: Uuid,
+) -> Result<WebhookSubscription, AppError> {
+    let secret = format!("{}{}", SECRET_PREFIX, generate_token());
+    let events: Vec<String> = events.iter().map(|e| e.as_str().to_string()).collect();
+
+    let subscription = sqlx::query_as!(
+        WebhookSubscription,
+        r#"
+        INSERT INTO auth.webhook_subscriptions (user_id, org_id, url, secret, events, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, user_id, org_id, url, secret, events, created_by, created_at
+        "#,
+        user_id,
+        org_id,
+        url,
+        secret,
+        &events,
+        created_by
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(subscription)
+}
+
+pub async fn get_subscription(
+    pool: &PgPool,
+    subscription_id: Uuid,
+) -> Result<WebhookSubscription, AppError> {
+    sqlx::query_as!(
+        WebhookSubscription,
+        r#"
+        SELECT id, user_id, org_id, url, secret, events, created_by, created_at
+        FROM auth.webhook_subscriptions
+        WHERE id = $1
+        "#,
+        subscription_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+/// Subscriptions of the organisation when `org_id` is given, otherwise the
+/// user's own.
+pub async fn list_subscriptions(
+    pool: &PgPool,
+    user_id: Uuid,
+    org_id: Option<Uuid>,
+) -> Result<Vec<WebhookSubscription>, AppError> {
+    let subscriptions = sqlx::query_as!(
+        WebhookSubscription,
+        r#"
+        SELECT id, user_id, org_id, url, secret, events, created_by, created_at
+        FROM auth.webhook_subscriptions
+        WHERE CASE WHEN $2::uuid IS NULL THEN user_id = $1 ELSE org_id = $2 END
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+        org_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(subscriptions)
+}
+
+/// Deletes the subscription along with its delivery log.
+pub async fn delete_subscription(pool: &PgPool, subscription_id: Uuid) -> Result<(), AppError> {
+    sqlx::query!(
+        "DELETE FROM auth.webhook_subscriptions WHERE id = $1",
+        subscription_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Most recent deliveries first.
+pub async fn list_deliveries(
+    pool: &PgPool,
+    subscription_id: Uuid,
+    status: Option<WebhookDeliveryStatus>,
+    limit: i64,
+) -> Result<Vec<WebhookDelivery>, AppError> {
+    let deliveries = sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        SELECT id, subscription_id, event_id, event_type, payload,
+               status as "status: WebhookDeliveryStatus", attempts, next_attempt_at,
+               last_attempt_at, response_status, last_error, delivered_at, created_at
+        FROM auth.webhook_deliveries
+        WHERE subscription_id = $1 AND ($2::varchar IS NULL OR status = $2)
+        ORDER BY created_at DESC
+        LIMIT $3
+        "#,
+        subscription_id,
+        status as Option<WebhookDeliveryStatus>,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(deliveries)
+}
+
+/// Queues a delivery to be sent again straight away with a fresh set of
+/// retries, whatever its status. The body, and so the event id, is unchanged.
+pub async fn redeliver(
+    pool: &PgPool,
+    subscription_id: Uuid,
+    delivery_id: Uuid,
+) -> Result<WebhookDelivery, AppError> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        UPDATE auth.webhook_deliveries
+        SET status = 'pending', attempts = 0, next_attempt_at = CURRENT_TIMESTAMP
+        WHERE id = $1 AND subscription_id = $2
+        RETURNING id, subscription_id, event_id, event_type, payload,
+                  status as "status: WebhookDeliveryStatus", attempts, next_attempt_at,
+                  last_attempt_at, response_status, last_error, delivered_at, created_at
+        "#,
+        delivery_id,
+        subscription_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+struct DueDelivery {
+    id: Uuid,
+    event_type: String,
+    payload: serde_json::Value,
+    attempts: i32,
+    url: String,
+    secret: String,
+}
+
+/// Outcome of one attempt: the response status, if one came back, and the
+/// error when the attempt failed.
+type Attempt = (Option<i32>, Option<String>);
+
+/// Sends deliveries that are due and records each outcome. Returns how many
+/// were attempted.
+pub async fn deliver_due(
+    pool: &PgPool,
+    timeout: Duration,
+    max_attempts: u32,
+    retry_base: Duration,
+) -> Result<u64, AppError> {
+    let due = sqlx::query_as!(
+        DueDelivery,
+        r#"
+        SELECT d.id, d.event_type, d.payload, d.attempts, s.url, s.secret
+        FROM auth.webhook_deliveries d
+        JOIN auth.webhook_subscriptions s ON s.id = d.subscription_id
+        WHERE d.status = 'pending' AND d.next_attempt_at <= CURRENT_TIMESTAMP
+        ORDER BY d.next_attempt_at
+        LIMIT $1
+        "#,
+        DELIVERY_BATCH_SIZE
+    )
+    .fetch_all(pool)
+    .await?;
+    let attempted = due.len() as u64;
+
+    let results: Vec<(DueDelivery, Attempt)> = stream::iter(due)
+        .map(|delivery| async move {
+            let attempt = send(timeout, &delivery).await;
+            (delivery, attempt)
+        })
+        .buffer_unordered(DELIVERY_CONCURRENCY)
+        .collect()
+        .await;
+
+    for (delivery, (response_status, error)) in results {
+        let attempts = delivery.attempts + 1;
+        let (status, next_attempt_at) = match &error {
+            None => (WebhookDeliveryStatus::Delivered, Utc::now()),
+            Some(_) if attempts >= max_attempts as i32 => {
+                tracing::warn!(delivery_id = %delivery.id, attempts, "Webhook delivery is dead");
+                (WebhookDeliveryStatus::Dead, Utc::now())
+            }
+            Some(_) => (
+                WebhookDeliveryStatus::Pending,
+                next_attempt(retry_base, attempts as u32),
+            ),
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE auth.webhook_deliveries
+            SET status = $2, attempts = $3, next_attempt_at = $4,
+                last_attempt_at = CURRENT_TIMESTAMP, response_status = $5, last_error = $6,
+                delivered_at = CASE WHEN $2::varchar = 'delivered' THEN CURRENT_TIMESTAMP END
+            WHERE id = $1
+            "#,
+            delivery.id,
+            status as WebhookDeliveryStatus,
+            attempts,
+            next_attempt_at,
+            response_status,
+            error
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(attempted)
+}
+
+fn next_attempt(base: Duration, attempts: u32) -> DateTime<Utc> {
+    let delay = chrono::Duration::from_std(retry_delay(base, attempts))
+        .unwrap_or_else(|_| chrono::Duration::hours(6));
+    Utc::now() + delay
+}
+
+async fn send(timeout: Duration, delivery: &DueDelivery) -> Attempt {
+    let url = match reqwest::Url::parse(&delivery.url) {
+        Ok(url) => url,
+        Err(_) => return (None, Some("Receiver URL is invalid".to_string())),
+    };
+    let addrs = match receiver_addresses(&url).await {
+        Ok(addrs) => addrs,
+        Err(e) => return (None, Some(e.to_string())),
+    };
+
+    // Redirects are not followed, since wherever they point was not checked,
+    // and proxies are bypassed so the connection goes to `addrs`
+    let mut http = reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .no_proxy();
+    if let Some(host) = url.domain() {
+        http = http.resolve_to_addrs(host, &addrs);
+    }
+    let http = match http.build() {
+        Ok(http) => http,
+        Err(e) => {
+            tracing::error!(delivery_id = %delivery.id, "Failed to build webhook client: {}", e);
+            return (None, Some("Request to the receiver failed".to_string()));
+        }
+    };
+
+    let body = delivery.payload.to_string();
+    let signature = sign(&delivery.secret, Utc::now().timestamp(), &body);
+
+    let response = http
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(SIGNATURE_HEADER, signature)
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .body(body)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i32), None)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i32),
+            Some(format!("Receiver answered {}", response.status())),
+        ),
+        // The log is shown to subscribers; the cause stays in ours
+        Err(e) => {
+            tracing::warn!(delivery_id = %delivery.id, "Webhook delivery failed: {}", e);
+            let error = if e.is_timeout() {
+                "Receiver did not answer in time"
+            } else if e.is_connect() {
+                "Could not connect to the receiver"
+            } else {
+                "Request to the receiver failed"
+            };
+            (None, Some(error.to_string()))
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use axum::{extract::Request, http::HeaderMap};
+use opentelemetry::{global, propagation::Extractor, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::Config;
+
+const SERVICE_NAME: &str = "auth-service";
+
+const DEFAULT_LOG_FILTER: &str = "auth_service=debug,tower_http=debug";
+
+/// Installs the global tracing subscriber: logs to stdout and, when
+/// `otel_exporter_endpoint` is set, spans to an OTLP collector over gRPC.
+/// A collector that can't be set up is logged and skipped rather than
+/// stopping the service.
+pub fn init(config: &Config) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let (otel_layer, otel_error) = match config.otel_exporter_endpoint.as_deref() {
+        Some(endpoint) => match otlp_tracer(endpoint) {
+            Ok(tracer) => (
+                Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+                None,
+            ),
+            Err(e) => (None, Some(e)),
+        },
+        None => (None, None),
+    };
+
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    match (&config.otel_exporter_endpoint, otel_error) {
+        (Some(endpoint), None) => tracing::info!("Exporting traces to {}", endpoint),
+        (Some(endpoint), Some(e)) => {
+            tracing::warn!("Trace export to {} disabled: {}", endpoint, e)
+        }
+        (None, _) => {}
+    }
+}
+
+/// Flushes spans still buffered for export. Call before the process exits.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+fn otlp_tracer(endpoint: &str) -> Result<trace::Tracer, opentelemetry::trace::TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                SERVICE_NAME,
+            )])),
+        )
+        .install_batch(runtime::Tokio)
+}
+
+/// Span for an incoming request, continuing the caller's trace when the
+/// request carries a W3C `traceparent` header.
+pub fn request_span(request: &Request) -> Span {
+    let span = tracing::info_span!(
+        "request",
+        otel.kind = "server",
+        http.method = %request.method(),
+        http.target = %request.uri().path(),
+    );
+
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    span
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
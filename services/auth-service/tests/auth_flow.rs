@@ -0,0 +1,1030 @@
+//! The sign-in lifecycle over HTTP, on the in-memory repositories and rate
+//! limiter. Postgres and Redis are configured but never reached, so these run
+//! anywhere.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::ConnectInfo,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use sqlx::postgres::PgPoolOptions;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+use auth_service::{
+    auth::decode_jwt_token,
+    config::Config,
+    create_app,
+    models::{AuditEventType, AuditOutcome, AuthMethod, UserRole, UserStatus},
+    repositories::memory::{
+        MemoryApiKeyRepository, MemoryAuditRepository, MemorySessionRepository,
+        MemoryTotpRepository, MemoryUserRepository,
+    },
+    services::{
+        mailer, oidc::OidcClient, outbox::EventType, password_hasher::PasswordHasher,
+        password_policy::PasswordPolicy, phone::PhoneOtpStore, rate_limit::RateLimiter, sms,
+    },
+    AppState,
+};
+use bookmarket_auth::{secret_key_id, AuthError, Verifier};
+
+const EMAIL: &str = "reader@bookmarket.test";
+const PASSWORD: &str = "quiet-harbour-lantern-91";
+const JWT_SECRET: &str = "auth-flow-test-secret";
+
+fn test_config() -> Config {
+    std::env::set_var("DATABASE_URL", "postgres://unused@localhost/unused");
+    std::env::set_var("REDIS_URL", "redis://localhost:6379");
+    std::env::set_var("JWT_SECRET", JWT_SECRET);
+    std::env::set_var("MFA_ENCRYPTION_KEY", "00".repeat(32));
+    // Cheapest hashes the service accepts; strength is not under test
+    std::env::set_var("AUTH_PASSWORD_HASH_ALGORITHM", "bcrypt");
+    std::env::set_var("AUTH_BCRYPT_COST", "4");
+    std::env::set_var("AUTH_JOBS_ENABLED", "false");
+    std::env::set_var(
+        "OAUTH_CLIENTS",
+        r#"[
+            {"client_id": "order-service", "client_secret": "order-secret"},
+            {"client_id": "storefront", "client_secret": "storefront-secret", "first_party": true}
+        ]"#,
+    );
+    std::env::set_var(
+        "OIDC_PROVIDERS",
+        r#"[{"name": "mock", "issuer": "http://127.0.0.1:9", "client_id": "bookmarket", "client_secret": "secret"}]"#,
+    );
+
+    Config::from_env().expect("test configuration")
+}
+
+fn test_app() -> (
+    Router,
+    Arc<MemoryAuditRepository>,
+    Arc<MemoryUserRepository>,
+) {
+    let config = Arc::new(test_config());
+    let redis = redis::Client::open(config.redis_url.as_str()).unwrap();
+    let audit = Arc::new(MemoryAuditRepository::new());
+    let users = Arc::new(MemoryUserRepository::new());
+
+    let state = AppState {
+        // Only reached by best-effort side effects such as webhooks, which
+        // should give up quickly
+        db: PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy(&config.database_url)
+            .unwrap(),
+        users: users.clone(),
+        sessions: Arc::new(MemorySessionRepository::new()),
+        api_keys: Arc::new(MemoryApiKeyRepository::new()),
+        totp: Arc::new(MemoryTotpRepository::new()),
+        audit: audit.clone(),
+        mailer: mailer::from_config(&config).unwrap(),
+        sms: sms::from_config(&config).unwrap(),
+        phone_otp: PhoneOtpStore::new(&redis),
+        rate_limiter: RateLimiter::in_memory(),
+        oidc: OidcClient::new(&redis, &config).unwrap(),
+        password_policy: Arc::new(PasswordPolicy::from_config(&config)),
+        password_hasher: PasswordHasher::from_config(&config).unwrap(),
+        redis,
+        config,
+    };
+
+    (create_app(state), audit, users)
+}
+
+async fn post(app: &Router, path: &str, bearer: Option<&str>, body: Value) -> (StatusCode, Value) {
+    send(app, Method::POST, path, bearer, body).await
+}
+
+async fn send(
+    app: &Router,
+    method: Method,
+    path: &str,
+    bearer: Option<&str>,
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(path)
+        .header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = bearer {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+
+    (status, body)
+}
+
+/// Asks `/oauth/introspect` about `token` as a backend service would.
+async fn introspect(app: &Router, token: &str) -> Value {
+    let request = Request::post("/oauth/introspect")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+            "token={}&client_id=order-service&client_secret=order-secret",
+            token
+        )))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+/// Asks `/oauth/revoke` to revoke `token` as the given client.
+async fn revoke(app: &Router, token: &str, client_id: &str, client_secret: &str) -> StatusCode {
+    let request = Request::post("/oauth/revoke")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!(
+            "token={}&client_id={}&client_secret={}",
+            token, client_id, client_secret
+        )))
+        .unwrap();
+
+    app.clone().oneshot(request).await.unwrap().status()
+}
+
+/// Asks for a sign-in link from `peer`, returning the status and the cookie
+/// with its random nonce blanked out.
+async fn request_magic_link(app: &Router, peer: [u8; 4], email: &str) -> (StatusCode, String) {
+    let mut request = Request::post("/auth/magic-link")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(json!({ "email": email }).to_string()))
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((peer, 40000))));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let cookie = response
+        .headers()
+        .get(header::SET_COOKIE)
+        .map(|value| {
+            let (name, rest) = value.to_str().unwrap().split_once('=').unwrap();
+            let (_, attributes) = rest.split_once(';').unwrap();
+            format!("{}=<nonce>;{}", name, attributes)
+        })
+        .unwrap_or_default();
+
+    (status, cookie)
+}
+
+/// The same access token re-signed as if its user had signed in an hour ago,
+/// past the window for sensitive changes.
+fn stale_token(access_token: &str) -> String {
+    let mut claims = decode_jwt_token(access_token, &[JWT_SECRET]).unwrap();
+    claims.auth_time = Some(chrono::Utc::now().timestamp() - 3600);
+
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+async fn register(app: &Router) -> (StatusCode, Value) {
+    post(
+        app,
+        "/auth/register",
+        None,
+        json!({
+            "email": EMAIL,
+            "password": PASSWORD,
+            "first_name": "Test",
+            "last_name": "Reader",
+        }),
+    )
+    .await
+}
+
+async fn login(app: &Router, password: &str) -> (StatusCode, Value) {
+    post(
+        app,
+        "/auth/login",
+        None,
+        json!({ "email": EMAIL, "password": password }),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn register_login_refresh_logout() {
+    let (app, audit, _) = test_app();
+
+    let (status, user) = register(&app).await;
+    assert_eq!(status, StatusCode::OK, "{}", user);
+    assert_eq!(user["email"], EMAIL);
+
+    let (status, tokens) = login(&app, PASSWORD).await;
+    assert_eq!(status, StatusCode::OK, "{}", tokens);
+    let refresh_token = tokens["refresh_token"].as_str().unwrap().to_string();
+
+    // Refreshing needs only the refresh token, since the access token may
+    // already have expired
+    let (status, refreshed) = post(
+        &app,
+        "/auth/refresh",
+        None,
+        json!({ "refresh_token": refresh_token }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", refreshed);
+    assert_eq!(refreshed["user"]["email"], EMAIL);
+    let access_token = refreshed["access_token"].as_str().unwrap().to_string();
+    let refresh_token = refreshed["refresh_token"].as_str().unwrap().to_string();
+
+    let (status, body) = post(&app, "/auth/logout", Some(&access_token), json!({})).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{}", body);
+
+    // The session is gone, so neither token is honoured any more
+    let (status, _) = post(
+        &app,
+        "/auth/refresh",
+        None,
+        json!({ "refresh_token": refresh_token }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let events: Vec<_> = audit
+        .events()
+        .into_iter()
+        .map(|e| (e.event_type, e.outcome))
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            (AuditEventType::Login, AuditOutcome::Success),
+            (AuditEventType::Logout, AuditOutcome::Success),
+        ]
+    );
+}
+
+/// The code an authenticator app shows for `secret` at `step`, per RFC 6238.
+fn totp_code(secret: &str, step: i64) -> String {
+    use hmac::{Hmac, Mac};
+
+    let mut key = Vec::new();
+    let (mut buffer, mut bits) = (0u64, 0);
+    for c in secret.bytes() {
+        let value = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567"
+            .iter()
+            .position(|&a| a == c)
+            .unwrap() as u64;
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            key.push((buffer >> bits) as u8);
+        }
+    }
+
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(&key).unwrap();
+    mac.update(&(step as u64).to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[19] & 0xf) as usize;
+    let value = u32::from_be_bytes(hash[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+
+    format!("{:06}", value % 1_000_000)
+}
+
+/// The current TOTP step, first waiting out the end of one that is about to
+/// roll over so the steps a test derives from it stay valid.
+async fn current_step() -> i64 {
+    let now = chrono::Utc::now().timestamp();
+    if now % 30 >= 25 {
+        tokio::time::sleep(Duration::from_secs((30 - now % 30) as u64)).await;
+    }
+
+    chrono::Utc::now().timestamp() / 30
+}
+
+#[tokio::test]
+async fn sign_in_asks_for_the_authenticator_code() {
+    let (app, audit, _) = test_app();
+
+    register(&app).await;
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap().to_string();
+
+    let (status, setup) = post(
+        &app,
+        "/auth/mfa/setup",
+        Some(&access_token),
+        json!({ "method": "totp" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", setup);
+    let secret = setup["secret"].as_str().unwrap().to_string();
+
+    let step = current_step().await;
+    let (status, body) = post(
+        &app,
+        "/auth/mfa/verify",
+        Some(&access_token),
+        json!({ "code": totp_code(&secret, step - 1) }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    // The password alone no longer issues tokens
+    let (status, body) = login(&app, PASSWORD).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "MFA_REQUIRED");
+    assert!(body.get("access_token").is_none());
+    let mfa_token = body["error"]["details"]["mfa_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let wrong = format!(
+        "{:06}",
+        (totp_code(&secret, step).parse::<u32>().unwrap() + 1) % 1_000_000
+    );
+    let (status, _) = post(
+        &app,
+        "/auth/mfa/challenge",
+        None,
+        json!({ "mfa_token": mfa_token, "code": wrong }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let failure = audit.events().pop().unwrap();
+    assert_eq!(failure.outcome, AuditOutcome::Failure);
+    assert_eq!(failure.details["reason"], "invalid_mfa_code");
+
+    let (status, tokens) = post(
+        &app,
+        "/auth/mfa/challenge",
+        None,
+        json!({ "mfa_token": mfa_token, "code": totp_code(&secret, step) }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", tokens);
+    let claims = decode_jwt_token(tokens["access_token"].as_str().unwrap(), &[JWT_SECRET]).unwrap();
+    assert_eq!(claims.amr, [AuthMethod::Pwd, AuthMethod::Otp]);
+
+    // A challenge finishes one sign-in only, even with a fresh code
+    let (status, _) = post(
+        &app,
+        "/auth/mfa/challenge",
+        None,
+        json!({ "mfa_token": mfa_token, "code": totp_code(&secret, step + 1) }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn self_registered_accounts_cannot_impersonate() {
+    let (app, _, _) = test_app();
+
+    for role in ["Admin", "Vendor"] {
+        let (status, body) = post(
+            &app,
+            "/auth/register",
+            None,
+            json!({
+                "email": EMAIL,
+                "password": PASSWORD,
+                "first_name": "Test",
+                "last_name": "Reader",
+                "role": role,
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+    }
+
+    let (status, user) = register(&app).await;
+    assert_eq!(status, StatusCode::OK, "{}", user);
+    assert_eq!(user["role"], "Customer");
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap().to_string();
+
+    let (status, other) = post(
+        &app,
+        "/auth/register",
+        None,
+        json!({
+            "email": "other@bookmarket.test",
+            "password": PASSWORD,
+            "first_name": "Other",
+            "last_name": "Reader",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", other);
+
+    let (status, body) = post(
+        &app,
+        &format!("/admin/users/{}/impersonate", other["id"].as_str().unwrap()),
+        Some(&access_token),
+        json!({ "reason": "support ticket" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{}", body);
+    assert!(body.get("access_token").is_none());
+}
+
+#[tokio::test]
+async fn login_refused_while_password_reset_required() {
+    let (app, audit, users) = test_app();
+
+    let (_, user) = register(&app).await;
+    let user_id = user["id"].as_str().unwrap().parse().unwrap();
+    users.require_password_reset(user_id).unwrap();
+
+    let (status, body) = login(&app, PASSWORD).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"]["code"], "PASSWORD_RESET_REQUIRED");
+
+    let failure = audit.events().pop().unwrap();
+    assert_eq!(failure.outcome, AuditOutcome::Failure);
+    assert_eq!(failure.details["reason"], "password_reset_required");
+}
+
+/// Mail scanners open links, so following the "this wasn't me" link must not
+/// end any sessions by itself.
+#[tokio::test]
+async fn login_alert_link_only_asks_for_confirmation() {
+    let (app, _, _) = test_app();
+
+    let token = "ab".repeat(32);
+    let response = app
+        .clone()
+        .oneshot(
+            Request::get(format!("/auth/login-alerts/deny?token={}", token))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let page = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let page = String::from_utf8(page.to_vec()).unwrap();
+    assert!(page.contains(r#"<form method="post" action="/auth/login-alerts/deny">"#));
+    assert!(page.contains(&format!(r#"value="{}""#, token)));
+
+    let response = app
+        .oneshot(
+            Request::get("/auth/login-alerts/deny?token=%22%3E%3Cscript%3E")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// A provider redirect only completes in the browser that started the
+/// request, so a link or sign-in started by someone else can't be finished
+/// in a victim's session.
+#[tokio::test]
+async fn oidc_callback_requires_the_state_cookie() {
+    let (app, _, _) = test_app();
+
+    for cookie in [None, Some("bm_oidc_state=0123abcd")] {
+        let mut request = Request::get("/auth/oidc/mock/callback?code=code&state=attacker-state");
+        if let Some(cookie) = cookie {
+            request = request.header(header::COOKIE, cookie);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "http://localhost:3000/auth/callback?error=sign_in_failed"
+        );
+        let set_cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(set_cookie.starts_with("bm_oidc_state=; Path=/auth/oidc; Max-Age=0"));
+    }
+}
+
+#[tokio::test]
+async fn refresh_token_is_not_a_bearer_token() {
+    let (app, _, _) = test_app();
+
+    assert_eq!(register(&app).await.0, StatusCode::OK);
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+
+    let (status, _) = post(&app, "/auth/logout", Some(refresh_token), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn reused_refresh_token_ends_the_session() {
+    let (app, audit, _) = test_app();
+
+    assert_eq!(register(&app).await.0, StatusCode::OK);
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let spent = tokens["refresh_token"].as_str().unwrap();
+
+    let (status, refreshed) = post(
+        &app,
+        "/auth/refresh",
+        None,
+        json!({ "refresh_token": spent }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", refreshed);
+    let latest = refreshed["refresh_token"].as_str().unwrap();
+
+    let (status, _) = post(
+        &app,
+        "/auth/refresh",
+        None,
+        json!({ "refresh_token": spent }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // The latest token goes with the session
+    let (status, _) = post(
+        &app,
+        "/auth/refresh",
+        None,
+        json!({ "refresh_token": latest }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let reuse = audit
+        .events()
+        .into_iter()
+        .find(|e| e.event_type == AuditEventType::RefreshTokenReused)
+        .unwrap();
+    assert_eq!(reuse.outcome, AuditOutcome::Failure);
+}
+
+#[tokio::test]
+async fn spent_refresh_token_is_not_active() {
+    let (app, _, _) = test_app();
+
+    assert_eq!(register(&app).await.0, StatusCode::OK);
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let spent = tokens["refresh_token"].as_str().unwrap();
+
+    let introspection = introspect(&app, spent).await;
+    assert_eq!(introspection["active"], true, "{}", introspection);
+    assert_eq!(introspection["token_type"], "refresh_token");
+
+    let (status, refreshed) = post(
+        &app,
+        "/auth/refresh",
+        None,
+        json!({ "refresh_token": spent }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", refreshed);
+
+    assert_eq!(introspect(&app, spent).await, json!({ "active": false }));
+    let latest = refreshed["refresh_token"].as_str().unwrap();
+    assert_eq!(introspect(&app, latest).await["active"], true);
+}
+
+#[tokio::test]
+async fn password_change_is_limited_to_the_callers_account() {
+    let (app, _, _) = test_app();
+
+    let (_, user) = register(&app).await;
+    let (status, other) = post(
+        &app,
+        "/auth/register",
+        None,
+        json!({
+            "email": "other@bookmarket.test",
+            "password": PASSWORD,
+            "first_name": "Other",
+            "last_name": "Reader",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", other);
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &format!("/users/{}/password", other["id"].as_str().unwrap()),
+        Some(access_token),
+        json!({
+            "current_password": PASSWORD,
+            "new_password": "copper-meadow-whistle-27",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // On their own account the caller gets as far as the password check
+    let (status, body) = send(
+        &app,
+        Method::PUT,
+        &format!("/users/{}/password", user["id"].as_str().unwrap()),
+        Some(access_token),
+        json!({
+            "current_password": "not-my-password-at-all",
+            "new_password": "copper-meadow-whistle-27",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+}
+
+#[tokio::test]
+async fn profiles_are_limited_to_their_owner() {
+    let (app, _, _) = test_app();
+
+    let (_, user) = register(&app).await;
+    let (_, other) = post(
+        &app,
+        "/auth/register",
+        None,
+        json!({
+            "email": "other@bookmarket.test",
+            "password": PASSWORD,
+            "first_name": "Other",
+            "last_name": "Reader",
+        }),
+    )
+    .await;
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let other_path = format!("/users/{}", other["id"].as_str().unwrap());
+
+    let (status, body) = send(
+        &app,
+        Method::GET,
+        &other_path,
+        Some(access_token),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body.get("email").is_none());
+
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &other_path,
+        Some(access_token),
+        json!({ "first_name": "Mallory" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let own_path = format!("/users/{}", user["id"].as_str().unwrap());
+    let (status, body) = send(
+        &app,
+        Method::PUT,
+        &own_path,
+        Some(access_token),
+        json!({ "first_name": "Alice" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["first_name"], "Alice");
+    assert_eq!(body["last_name"], "Reader");
+
+    let (status, body) = send(
+        &app,
+        Method::GET,
+        &own_path,
+        Some(access_token),
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["email"], EMAIL);
+    assert_eq!(body["first_name"], "Alice");
+}
+
+/// Admins can set a new password on a locked-out customer's account without
+/// knowing the old one, but not on another admin's.
+#[tokio::test]
+async fn admins_reset_passwords_without_the_current_one() {
+    let (app, audit, users) = test_app();
+
+    let (_, user) = register(&app).await;
+    let mut admins = Vec::new();
+    for email in ["admin@bookmarket.test", "admin-2@bookmarket.test"] {
+        let (_, admin) = post(
+            &app,
+            "/auth/register",
+            None,
+            json!({
+                "email": email,
+                "password": PASSWORD,
+                "first_name": "Support",
+                "last_name": "Desk",
+            }),
+        )
+        .await;
+        let admin_id = admin["id"].as_str().unwrap().to_string();
+        users
+            .set_role(admin_id.parse().unwrap(), UserRole::Admin)
+            .unwrap();
+        admins.push(admin_id);
+    }
+    let (status, tokens) = post(
+        &app,
+        "/auth/login",
+        None,
+        json!({ "email": "admin@bookmarket.test", "password": PASSWORD }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", tokens);
+    let admin_token = tokens["access_token"].as_str().unwrap();
+    let new_password = "copper-meadow-whistle-27";
+
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &format!("/users/{}/password", admins[1]),
+        Some(admin_token),
+        json!({ "new_password": new_password }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = send(
+        &app,
+        Method::PUT,
+        &format!("/users/{}/password", user["id"].as_str().unwrap()),
+        Some(admin_token),
+        json!({ "new_password": new_password }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{}", body);
+
+    let reset = audit.events().pop().unwrap();
+    assert_eq!(reset.event_type, AuditEventType::PasswordReset);
+    assert_eq!(reset.outcome, AuditOutcome::Success);
+    assert_eq!(reset.actor_id.unwrap().to_string(), admins[0]);
+    assert_eq!(reset.target_id.unwrap().to_string(), user["id"]);
+
+    assert_eq!(login(&app, PASSWORD).await.0, StatusCode::UNAUTHORIZED);
+    assert_eq!(login(&app, new_password).await.0, StatusCode::OK);
+
+    // Callers changing their own password still need the current one
+    let (_, tokens) = login(&app, new_password).await;
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &format!("/users/{}/password", user["id"].as_str().unwrap()),
+        tokens["access_token"].as_str(),
+        json!({ "new_password": "amber-lantern-orchard-64" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn api_key_update_requires_a_recent_sign_in() {
+    let (app, _, _) = test_app();
+
+    assert_eq!(register(&app).await.0, StatusCode::OK);
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let (status, key) = post(
+        &app,
+        "/api-keys",
+        Some(access_token),
+        json!({ "name": "catalog sync", "scopes": ["catalog:read"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", key);
+    let path = format!("/api-keys/{}", key["id"].as_str().unwrap());
+    let widened = json!({ "name": "catalog sync", "scopes": ["catalog:read", "orders:write"] });
+
+    let (status, body) = send(
+        &app,
+        Method::PUT,
+        &path,
+        Some(&stale_token(access_token)),
+        widened.clone(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "REAUTHENTICATION_REQUIRED");
+
+    let (status, updated) = send(&app, Method::PUT, &path, Some(access_token), widened).await;
+    assert_eq!(status, StatusCode::OK, "{}", updated);
+    assert_eq!(updated["scopes"], json!(["catalog:read", "orders:write"]));
+}
+
+#[tokio::test]
+async fn magic_links_are_throttled_per_email() {
+    let (app, _, _) = test_app();
+
+    for _ in 0..5 {
+        let (status, _) = request_magic_link(&app, [10, 0, 0, 1], "someone@bookmarket.test").await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    // Case doesn't matter, and a new client address doesn't help
+    let (status, _) = request_magic_link(&app, [10, 0, 0, 2], "Someone@Bookmarket.test").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let (status, _) = request_magic_link(&app, [10, 0, 0, 1], "else@bookmarket.test").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
+
+#[tokio::test]
+async fn magic_links_are_throttled_per_client_address() {
+    let (app, _, _) = test_app();
+
+    for i in 0..20 {
+        let email = format!("reader{}@bookmarket.test", i);
+        let (status, _) = request_magic_link(&app, [10, 0, 0, 1], &email).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    let (status, _) = request_magic_link(&app, [10, 0, 0, 1], "reader20@bookmarket.test").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    let (status, _) = request_magic_link(&app, [10, 0, 0, 2], "reader20@bookmarket.test").await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
+
+/// Unknown addresses get the same response and are throttled the same way as
+/// accounts, so neither reveals which emails are registered.
+#[tokio::test]
+async fn magic_link_responses_do_not_reveal_accounts() {
+    let (app, audit, users) = test_app();
+
+    // A suspended account is never sent a link, which keeps Postgres out of
+    // the test while still going through the account lookup
+    let (_, user) = register(&app).await;
+    let user_id = user["id"].as_str().unwrap().parse().unwrap();
+    users.set_status(user_id, UserStatus::Suspended).unwrap();
+
+    for _ in 0..6 {
+        let known = request_magic_link(&app, [10, 0, 0, 1], EMAIL).await;
+        let unknown = request_magic_link(&app, [10, 0, 0, 2], "nobody@bookmarket.test").await;
+        assert_eq!(known, unknown);
+    }
+
+    let (status, cookie) = request_magic_link(&app, [10, 0, 0, 3], "nobody@bookmarket.test").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(cookie.is_empty());
+
+    assert!(!audit
+        .events()
+        .iter()
+        .any(|event| event.event_type == AuditEventType::MagicLinkRequested));
+}
+
+#[tokio::test]
+async fn registration_is_published_to_other_services() {
+    let (app, _, users) = test_app();
+
+    let (status, user) = register(&app).await;
+    assert_eq!(status, StatusCode::OK, "{}", user);
+
+    let events = users.outbox_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].event_type, EventType::UserRegistered);
+    assert_eq!(events[0].user_id.to_string(), user["id"]);
+    assert_eq!(
+        events[0].payload,
+        json!({ "email": EMAIL, "role": "customer" })
+    );
+}
+
+#[tokio::test]
+async fn register_rejects_taken_email() {
+    let (app, _, _) = test_app();
+
+    assert_eq!(register(&app).await.0, StatusCode::OK);
+
+    let (status, body) = register(&app).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"]["code"], "CONFLICT");
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_password() {
+    let (app, audit, _) = test_app();
+
+    assert_eq!(register(&app).await.0, StatusCode::OK);
+
+    let (status, _) = login(&app, "not-the-password").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let failure = audit.events().pop().unwrap();
+    assert_eq!(failure.event_type, AuditEventType::Login);
+    assert_eq!(failure.outcome, AuditOutcome::Failure);
+    assert_eq!(failure.details["reason"], "invalid_password");
+}
+
+/// Other services verify these tokens with `bookmarket-auth`, so its claims
+/// type must keep up with what login issues.
+#[tokio::test]
+async fn issued_tokens_verify_with_shared_client() {
+    let (app, _, _) = test_app();
+
+    let (_, user) = register(&app).await;
+    let (status, tokens) = login(&app, PASSWORD).await;
+    assert_eq!(status, StatusCode::OK, "{}", tokens);
+
+    let verifier = Verifier::with_secret(JWT_SECRET);
+
+    let claims = verifier
+        .verify(tokens["access_token"].as_str().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(claims.user_id().unwrap().to_string(), user["id"]);
+    assert_eq!(claims.email, EMAIL);
+    assert!(!claims.is_admin());
+    assert!(claims.authenticated_within(60));
+
+    let refresh = verifier
+        .verify(tokens["refresh_token"].as_str().unwrap())
+        .await;
+    assert!(matches!(refresh, Err(AuthError::WrongToken)));
+}
+
+/// After a rotation the new secret signs and the replaced one is kept for
+/// verification, so tokens issued before the switch keep working here and in
+/// the services that check them.
+#[tokio::test]
+async fn tokens_signed_with_the_previous_secret_verify_during_a_rotation() {
+    const NEW_SECRET: &str = "auth-flow-rotated-secret";
+    let (app, _, _) = test_app();
+
+    register(&app).await;
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let header = jsonwebtoken::decode_header(access_token).unwrap();
+    assert_eq!(header.kid, Some(secret_key_id(JWT_SECRET)));
+
+    let mut rotated = test_config();
+    rotated.jwt_secret = NEW_SECRET.to_string();
+    rotated.jwt_previous_secret = Some(JWT_SECRET.to_string());
+    let claims = decode_jwt_token(access_token, &rotated.jwt_secrets()).unwrap();
+    assert_eq!(claims.email, EMAIL);
+    assert!(Verifier::with_secrets(&[NEW_SECRET, JWT_SECRET])
+        .verify(access_token)
+        .await
+        .is_ok());
+
+    // Once the previous secret is retired, its tokens stop verifying
+    rotated.jwt_previous_secret = None;
+    assert!(decode_jwt_token(access_token, &rotated.jwt_secrets()).is_err());
+    assert!(Verifier::with_secret(NEW_SECRET)
+        .verify(access_token)
+        .await
+        .is_err());
+}
+
+/// Sessions are issued to BookMarket's own apps, so a backend service that
+/// merely holds a user's token cannot end their session.
+#[tokio::test]
+async fn only_first_party_clients_revoke_tokens() {
+    let (app, audit, _) = test_app();
+
+    register(&app).await;
+    let (_, tokens) = login(&app, PASSWORD).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    assert_eq!(
+        revoke(&app, access_token, "order-service", "order-secret").await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(introspect(&app, access_token).await["active"], true);
+
+    assert_eq!(
+        revoke(&app, access_token, "storefront", "storefront-secret").await,
+        StatusCode::OK
+    );
+    assert_eq!(introspect(&app, access_token).await["active"], false);
+
+    let revoked = audit.events().pop().unwrap();
+    assert_eq!(revoked.event_type, AuditEventType::TokenRevoked);
+    assert_eq!(revoked.details["client_id"], "storefront");
+}
@@ -0,0 +1,60 @@
+//! Which address audit records and throttles attribute a request to.
+
+use axum::http::{HeaderMap, HeaderValue};
+use ipnet::IpNet;
+use std::net::IpAddr;
+
+use auth_service::services::audit::client_ip;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn forwarded(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+fn proxies() -> Vec<IpNet> {
+    vec!["10.0.0.0/8".parse().unwrap()]
+}
+
+#[test]
+fn forwarding_headers_from_clients_are_ignored() {
+    let mut headers = forwarded("1.2.3.4");
+    headers.insert("x-real-ip", HeaderValue::from_static("5.6.7.8"));
+
+    assert_eq!(
+        client_ip(Some(ip("203.0.113.9")), &headers, &proxies()),
+        Some(ip("203.0.113.9"))
+    );
+    assert_eq!(
+        client_ip(Some(ip("203.0.113.9")), &headers, &[]),
+        Some(ip("203.0.113.9"))
+    );
+}
+
+#[test]
+fn trusted_proxies_name_the_nearest_untrusted_hop() {
+    // The leftmost entry was supplied by the client and can't be believed
+    let headers = forwarded("1.2.3.4, 198.51.100.7, 10.0.0.3");
+
+    assert_eq!(
+        client_ip(Some(ip("10.0.0.2")), &headers, &proxies()),
+        Some(ip("198.51.100.7"))
+    );
+}
+
+#[test]
+fn trusted_proxy_without_forwarding_headers_is_the_client() {
+    assert_eq!(
+        client_ip(Some(ip("10.0.0.2")), &HeaderMap::new(), &proxies()),
+        Some(ip("10.0.0.2"))
+    );
+}
+
+#[test]
+fn unknown_peer_has_no_address() {
+    assert_eq!(client_ip(None, &forwarded("1.2.3.4"), &proxies()), None);
+}
@@ -0,0 +1,24 @@
+//! Exported cells can't run as spreadsheet formulas.
+
+use auth_service::services::csv_export::cell;
+
+#[test]
+fn formula_prefixes_are_neutralised() {
+    for value in [
+        "=HYPERLINK(\"http://evil\")",
+        "+1+1",
+        "-2+3",
+        "@SUM(A1)",
+        "\t=1",
+        "\r=1",
+    ] {
+        assert_eq!(cell(value), format!("'{}", value));
+    }
+}
+
+#[test]
+fn ordinary_values_are_unchanged() {
+    for value in ["reader@bookmarket.test", "Mozilla/5.0", "198.51.100.7", ""] {
+        assert_eq!(cell(value), value);
+    }
+}
@@ -0,0 +1,177 @@
+//! Starting an email change over HTTP. The change is recorded in Postgres,
+//! so unlike `auth_flow` these run against the database in `DATABASE_URL`;
+//! sessions and the audit log stay in memory and Redis is never reached.
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+use auth_service::{
+    auth::decode_jwt_token,
+    config::Config,
+    create_app,
+    models::{AuditEventType, AuditOutcome},
+    repositories::{
+        memory::{
+            MemoryApiKeyRepository, MemoryAuditRepository, MemorySessionRepository,
+            MemoryTotpRepository,
+        },
+        postgres::PgUserRepository,
+    },
+    services::{
+        mailer, oidc::OidcClient, password_hasher::PasswordHasher, password_policy::PasswordPolicy,
+        phone::PhoneOtpStore, rate_limit::RateLimiter, sms,
+    },
+    AppState,
+};
+
+const EMAIL: &str = "reader@bookmarket.test";
+const NEW_EMAIL: &str = "new-reader@bookmarket.test";
+const PASSWORD: &str = "quiet-harbour-lantern-91";
+const JWT_SECRET: &str = "email-change-test-secret";
+
+fn test_app(pool: PgPool) -> (Router, Arc<MemoryAuditRepository>) {
+    std::env::set_var("REDIS_URL", "redis://localhost:6379");
+    std::env::set_var("JWT_SECRET", JWT_SECRET);
+    std::env::set_var("MFA_ENCRYPTION_KEY", "00".repeat(32));
+    std::env::set_var("AUTH_PASSWORD_HASH_ALGORITHM", "bcrypt");
+    std::env::set_var("AUTH_BCRYPT_COST", "4");
+    std::env::set_var("AUTH_JOBS_ENABLED", "false");
+
+    let config = Arc::new(Config::from_env().expect("test configuration"));
+    let redis = redis::Client::open(config.redis_url.as_str()).unwrap();
+    let audit = Arc::new(MemoryAuditRepository::new());
+
+    let state = AppState {
+        users: Arc::new(PgUserRepository::new(pool.clone())),
+        db: pool,
+        sessions: Arc::new(MemorySessionRepository::new()),
+        api_keys: Arc::new(MemoryApiKeyRepository::new()),
+        totp: Arc::new(MemoryTotpRepository::new()),
+        audit: audit.clone(),
+        mailer: mailer::from_config(&config).unwrap(),
+        sms: sms::from_config(&config).unwrap(),
+        phone_otp: PhoneOtpStore::new(&redis),
+        rate_limiter: RateLimiter::in_memory(),
+        oidc: OidcClient::in_memory(&config).unwrap(),
+        password_policy: Arc::new(PasswordPolicy::from_config(&config)),
+        password_hasher: PasswordHasher::from_config(&config).unwrap(),
+        redis,
+        config,
+    };
+
+    (create_app(state), audit)
+}
+
+async fn post(app: &Router, path: &str, bearer: Option<&str>, body: Value) -> (StatusCode, Value) {
+    let mut request = Request::post(path).header(header::CONTENT_TYPE, "application/json");
+    if let Some(token) = bearer {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+
+    let response = app
+        .clone()
+        .oneshot(request.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+
+    (status, body)
+}
+
+/// Registers and signs in, returning a fresh access token.
+async fn sign_in(app: &Router) -> String {
+    let (status, user) = post(
+        app,
+        "/auth/register",
+        None,
+        json!({
+            "email": EMAIL,
+            "password": PASSWORD,
+            "first_name": "Test",
+            "last_name": "Reader",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", user);
+
+    let (status, tokens) = post(
+        app,
+        "/auth/login",
+        None,
+        json!({ "email": EMAIL, "password": PASSWORD }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", tokens);
+
+    tokens["access_token"].as_str().unwrap().to_string()
+}
+
+/// The same access token re-signed as if its user had signed in an hour ago,
+/// past the window for sensitive changes.
+fn stale_token(access_token: &str) -> String {
+    let mut claims = decode_jwt_token(access_token, &[JWT_SECRET]).unwrap();
+    claims.auth_time = Some(chrono::Utc::now().timestamp() - 3600);
+
+    jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &jsonwebtoken::EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .unwrap()
+}
+
+async fn pending_changes(pool: &PgPool) -> Vec<String> {
+    sqlx::query_scalar(
+        "SELECT new_email FROM auth.email_change_requests \
+         WHERE confirmed_at IS NULL AND cancelled_at IS NULL",
+    )
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test(migrator = "auth_service::database::MIGRATOR")]
+async fn email_change_requires_a_recent_sign_in(pool: PgPool) {
+    let (app, audit) = test_app(pool.clone());
+    let access_token = sign_in(&app).await;
+
+    let (status, body) = post(
+        &app,
+        "/users/me/email",
+        Some(&stale_token(&access_token)),
+        json!({ "new_email": NEW_EMAIL }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"]["code"], "REAUTHENTICATION_REQUIRED");
+    assert!(pending_changes(&pool).await.is_empty());
+
+    let (status, body) = post(
+        &app,
+        "/users/me/email",
+        Some(&access_token),
+        json!({ "new_email": NEW_EMAIL }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", body);
+    assert_eq!(pending_changes(&pool).await, [NEW_EMAIL]);
+
+    let requested = audit.events().pop().unwrap();
+    assert_eq!(requested.event_type, AuditEventType::EmailChangeRequested);
+    assert_eq!(requested.outcome, AuditOutcome::Success);
+    assert_eq!(requested.details["new_email"], NEW_EMAIL);
+}
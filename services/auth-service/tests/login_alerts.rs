@@ -0,0 +1,219 @@
+//! New-device login alerts. Alerts are recorded in Postgres, so like
+//! `email_change` these run against the database in `DATABASE_URL`; over
+//! HTTP, sessions and the audit log stay in memory and Redis is never
+//! reached.
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::ConnectInfo,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::{net::SocketAddr, sync::Arc};
+use tower::ServiceExt;
+
+use auth_service::{
+    config::Config,
+    create_app,
+    repositories::{
+        memory::{
+            MemoryApiKeyRepository, MemoryAuditRepository, MemorySessionRepository,
+            MemoryTotpRepository,
+        },
+        postgres::{PgSessionRepository, PgUserRepository},
+        SessionRepository,
+    },
+    services::{
+        mailer, oidc::OidcClient, password_hasher::PasswordHasher, password_policy::PasswordPolicy,
+        phone::PhoneOtpStore, rate_limit::RateLimiter, session_cache::SessionCache, sms,
+    },
+    AppState,
+};
+
+const EMAIL: &str = "reader@bookmarket.test";
+const PASSWORD: &str = "quiet-harbour-lantern-91";
+const BROWSER: &str = "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0";
+
+fn test_app(pool: PgPool) -> Router {
+    std::env::set_var("REDIS_URL", "redis://localhost:6379");
+    std::env::set_var("JWT_SECRET", "login-alerts-test-secret");
+    std::env::set_var("MFA_ENCRYPTION_KEY", "00".repeat(32));
+    std::env::set_var("AUTH_PASSWORD_HASH_ALGORITHM", "bcrypt");
+    std::env::set_var("AUTH_BCRYPT_COST", "4");
+    std::env::set_var("AUTH_JOBS_ENABLED", "false");
+
+    let config = Arc::new(Config::from_env().expect("test configuration"));
+    let redis = redis::Client::open(config.redis_url.as_str()).unwrap();
+
+    let state = AppState {
+        users: Arc::new(PgUserRepository::new(pool.clone())),
+        db: pool,
+        sessions: Arc::new(MemorySessionRepository::new()),
+        api_keys: Arc::new(MemoryApiKeyRepository::new()),
+        totp: Arc::new(MemoryTotpRepository::new()),
+        audit: Arc::new(MemoryAuditRepository::new()),
+        mailer: mailer::from_config(&config).unwrap(),
+        sms: sms::from_config(&config).unwrap(),
+        phone_otp: PhoneOtpStore::new(&redis),
+        rate_limiter: RateLimiter::in_memory(),
+        oidc: OidcClient::in_memory(&config).unwrap(),
+        password_policy: Arc::new(PasswordPolicy::from_config(&config)),
+        password_hasher: PasswordHasher::from_config(&config).unwrap(),
+        redis,
+        config,
+    };
+
+    create_app(state)
+}
+
+/// Posts `body` as a browser at `peer` would.
+async fn post(
+    app: &Router,
+    path: &str,
+    peer: [u8; 4],
+    bearer: Option<&str>,
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut request = Request::post(path)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::USER_AGENT, BROWSER);
+    if let Some(token) = bearer {
+        request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+    }
+    let mut request = request.body(Body::from(body.to_string())).unwrap();
+    request
+        .extensions_mut()
+        .insert(ConnectInfo(SocketAddr::from((peer, 40000))));
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = if bytes.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&bytes).unwrap()
+    };
+
+    (status, body)
+}
+
+/// Signs in from `peer`, returning the access token.
+async fn login(app: &Router, peer: [u8; 4]) -> String {
+    let (status, tokens) = post(
+        app,
+        "/auth/login",
+        peer,
+        None,
+        json!({ "email": EMAIL, "password": PASSWORD }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", tokens);
+
+    tokens["access_token"].as_str().unwrap().to_string()
+}
+
+async fn alert_reasons(pool: &PgPool) -> Vec<String> {
+    sqlx::query_scalar("SELECT reason FROM auth.login_alerts ORDER BY created_at")
+        .fetch_all(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrator = "auth_service::database::MIGRATOR")]
+async fn signing_out_does_not_forget_the_device(pool: PgPool) {
+    let app = test_app(pool.clone());
+
+    let (status, user) = post(
+        &app,
+        "/auth/register",
+        [203, 0, 113, 7],
+        None,
+        json!({
+            "email": EMAIL,
+            "password": PASSWORD,
+            "first_name": "Test",
+            "last_name": "Reader",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{}", user);
+
+    let access_token = login(&app, [203, 0, 113, 7]).await;
+    let (status, _) = post(
+        &app,
+        "/auth/logout",
+        [203, 0, 113, 7],
+        Some(&access_token),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Same browser, new address from the same provider
+    login(&app, [203, 0, 113, 58]).await;
+    assert!(alert_reasons(&pool).await.is_empty());
+
+    login(&app, [198, 51, 100, 7]).await;
+    assert_eq!(alert_reasons(&pool).await, ["new_device"]);
+}
+
+/// Remembering devices never touches the session cache, so this needs no
+/// Redis either.
+#[sqlx::test(migrator = "auth_service::database::MIGRATOR")]
+async fn known_devices_are_kept_in_postgres(pool: PgPool) {
+    let redis = redis::Client::open("redis://localhost:6379").unwrap();
+    let sessions = PgSessionRepository::new(pool.clone(), SessionCache::new(&redis, 60, 60));
+    let user_id: uuid::Uuid = sqlx::query_scalar(
+        "INSERT INTO auth.users (email, password_hash) VALUES ($1, 'x') RETURNING id",
+    )
+    .bind(EMAIL)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    let network = Some("203.0.113.0/24");
+    let an_hour_ago = chrono::Utc::now() - chrono::Duration::hours(1);
+
+    assert!(!sessions
+        .seen_device(user_id, Some(BROWSER), network, an_hour_ago)
+        .await
+        .unwrap());
+
+    sessions
+        .record_device(user_id, Some(BROWSER), network)
+        .await
+        .unwrap();
+    sessions.record_device(user_id, None, None).await.unwrap();
+    assert!(sessions
+        .seen_device(user_id, Some(BROWSER), network, an_hour_ago)
+        .await
+        .unwrap());
+    assert!(sessions
+        .seen_device(user_id, None, None, an_hour_ago)
+        .await
+        .unwrap());
+    assert!(!sessions
+        .seen_device(user_id, Some(BROWSER), None, an_hour_ago)
+        .await
+        .unwrap());
+
+    // Seeing a device again refreshes it rather than adding a row
+    sessions
+        .record_device(user_id, Some(BROWSER), network)
+        .await
+        .unwrap();
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM auth.known_devices")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, 2);
+
+    assert_eq!(
+        sessions
+            .delete_devices_before(chrono::Utc::now() + chrono::Duration::hours(1))
+            .await
+            .unwrap(),
+        2
+    );
+}
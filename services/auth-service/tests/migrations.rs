@@ -0,0 +1,30 @@
+//! The shipped schema migrations, as `bm-auth-admin migrate-revert` relies on them.
+
+use auth_service::database::MIGRATOR;
+
+#[test]
+fn every_migration_can_be_reverted() {
+    let (down, up): (Vec<_>, Vec<_>) = MIGRATOR
+        .iter()
+        .partition(|m| m.migration_type.is_down_migration());
+
+    assert!(!up.is_empty());
+    for migration in &up {
+        assert!(
+            down.iter().any(|d| d.version == migration.version),
+            "migration {} has no down script",
+            migration.version
+        );
+    }
+}
+
+#[test]
+fn versions_are_sequential() {
+    let versions: Vec<i64> = MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| m.version)
+        .collect();
+
+    assert_eq!(versions, (1..=versions.len() as i64).collect::<Vec<_>>());
+}
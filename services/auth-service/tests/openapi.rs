@@ -0,0 +1,97 @@
+use auth_service::openapi::ApiDoc;
+use utoipa::OpenApi;
+
+/// Source of the router, scanned for the routes `create_app` registers.
+const LIB_RS: &str = include_str!("../src/lib.rs");
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+/// Every `(method, path)` registered in `create_app`, with axum's `:param`
+/// segments rewritten to OpenAPI's `{param}`.
+fn registered_routes() -> Vec<(String, String)> {
+    let start = LIB_RS
+        .find("pub fn create_app")
+        .expect("lib.rs defines create_app");
+    let body = &LIB_RS[start..];
+    let body = &body[..body.find("\n}\n").expect("create_app has a closing brace")];
+
+    let mut routes = Vec::new();
+    let mut rest = body;
+    while let Some(at) = rest.find(".route(") {
+        rest = &rest[at + ".route(".len()..];
+        let call = &rest[..closing_paren(rest)];
+
+        let path = call
+            .split('"')
+            .nth(1)
+            .expect("route path is a string literal");
+        let path = path
+            .split('/')
+            .map(|segment| match segment.strip_prefix(':') {
+                Some(param) => format!("{{{}}}", param),
+                None => segment.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let handlers = &call[call.find(',').expect("route has a method router")..];
+        let mut found = false;
+        for method in METHODS {
+            let needle = format!("{}(", method);
+            for (i, _) in handlers.match_indices(&needle) {
+                let preceding = handlers[..i].chars().next_back();
+                if matches!(preceding, Some(c) if c.is_alphanumeric() || c == '_' || c == ':') {
+                    continue;
+                }
+                routes.push((method.to_string(), path.clone()));
+                found = true;
+            }
+        }
+        assert!(found, "no method router recognised for {}", path);
+    }
+
+    assert!(!routes.is_empty(), "no routes found in create_app");
+    routes
+}
+
+/// Index of the parenthesis closing a call whose opening one precedes `s`.
+fn closing_paren(s: &str) -> usize {
+    let mut depth = 1;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+    panic!("unbalanced parentheses in create_app");
+}
+
+#[test]
+fn every_route_is_documented() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    let missing: Vec<String> = registered_routes()
+        .into_iter()
+        .filter(|(method, path)| spec["paths"][path][method].is_null())
+        .map(|(method, path)| format!("{} {}", method.to_uppercase(), path))
+        .collect();
+
+    assert!(
+        missing.is_empty(),
+        "routes missing from the OpenAPI document: {:?}",
+        missing
+    );
+}
+
+#[test]
+fn document_is_openapi_3_1() {
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3.1"));
+}
@@ -0,0 +1,153 @@
+//! Webhook signatures, retry timing and receiver checks, as receivers and
+//! operators see them. Only the delivery log needs the database in
+//! `DATABASE_URL`; no test resolves a name other than `localhost` or opens a
+//! connection.
+
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use auth_service::{
+    models::WebhookDeliveryStatus,
+    services::webhooks::{self, WebhookEvent},
+};
+
+#[test]
+fn signature_is_hmac_of_timestamp_and_body() {
+    // Computed independently with `openssl dgst -sha256 -hmac`
+    let signature = webhooks::sign("whsec_test", 1700000000, r#"{"id":"1"}"#);
+
+    assert_eq!(
+        signature,
+        "t=1700000000,v1=11bf4466ea17c3df3fd743af0b435368e16b7a05eb8eced85e8c4670767bdec5"
+    );
+}
+
+#[test]
+fn signature_depends_on_secret_timestamp_and_body() {
+    let body = r#"{"type":"api_key.created"}"#;
+    let signature = webhooks::sign("whsec_a", 1700000000, body);
+
+    assert_ne!(signature, webhooks::sign("whsec_b", 1700000000, body));
+    assert_ne!(signature, webhooks::sign("whsec_a", 1700000001, body));
+    assert_ne!(signature, webhooks::sign("whsec_a", 1700000000, "{}"));
+}
+
+#[test]
+fn retries_back_off_exponentially_up_to_a_cap() {
+    let base = Duration::from_secs(30);
+
+    assert_eq!(webhooks::retry_delay(base, 1), Duration::from_secs(30));
+    assert_eq!(webhooks::retry_delay(base, 2), Duration::from_secs(60));
+    assert_eq!(webhooks::retry_delay(base, 5), Duration::from_secs(480));
+    assert_eq!(
+        webhooks::retry_delay(base, 40),
+        Duration::from_secs(6 * 3600)
+    );
+}
+
+#[tokio::test]
+async fn only_https_receivers_and_known_events_are_accepted() {
+    assert!(webhooks::validate_url("https://93.184.215.14/hooks")
+        .await
+        .is_ok());
+    assert!(webhooks::validate_url("http://93.184.215.14/hooks")
+        .await
+        .is_err());
+    assert!(webhooks::validate_url("not a url").await.is_err());
+
+    for event in WebhookEvent::ALL {
+        assert_eq!(WebhookEvent::parse(event.as_str()), Some(event));
+    }
+    assert_eq!(WebhookEvent::parse("user.registered"), None);
+}
+
+#[tokio::test]
+async fn receivers_must_be_public() {
+    for url in [
+        "https://[2606:4700:4700::1111]/hooks",
+        "https://8.8.8.8:8443/hooks",
+    ] {
+        assert!(webhooks::validate_url(url).await.is_ok(), "{}", url);
+    }
+
+    for url in [
+        "https://localhost/hooks",
+        "https://127.0.0.1/hooks",
+        "https://0.0.0.0/hooks",
+        "https://10.1.2.3/hooks",
+        "https://172.16.0.1/hooks",
+        "https://192.168.1.1/hooks",
+        "https://100.64.0.1/hooks",
+        "https://169.254.169.254/latest/meta-data",
+        "https://[::1]/hooks",
+        "https://[fd00:ec2::254]/hooks",
+        "https://[fe80::1]/hooks",
+        "https://[::ffff:10.0.0.1]/hooks",
+        "https://[64:ff9b::a9fe:a9fe]/hooks",
+    ] {
+        let refused = webhooks::validate_url(url).await.unwrap_err();
+        assert_eq!(
+            refused.to_string(),
+            "Validation error: Webhook URLs must point to a public address",
+            "{}",
+            url
+        );
+    }
+}
+
+/// Addresses are checked again when sending, since a subscription's name
+/// may have been re-pointed since it was created, and the log shown to
+/// subscribers says why without the underlying error.
+#[sqlx::test(migrator = "auth_service::database::MIGRATOR")]
+async fn deliveries_to_internal_addresses_are_refused(pool: PgPool) {
+    let user_id: Uuid = sqlx::query_scalar(
+        "INSERT INTO auth.users (email, password_hash) VALUES ('vendor@bookmarket.test', 'x') \
+         RETURNING id",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+
+    let mut subscriptions = Vec::new();
+    for url in ["https://localhost:9/hooks", "https://127.0.0.1:9/hooks"] {
+        let subscription = webhooks::create_subscription(
+            &pool,
+            Some(user_id),
+            None,
+            url,
+            &[WebhookEvent::ApiKeyCreated],
+            user_id,
+        )
+        .await
+        .unwrap();
+        subscriptions.push(subscription.id);
+    }
+    webhooks::notify_user(
+        &pool,
+        user_id,
+        WebhookEvent::ApiKeyCreated,
+        serde_json::json!({}),
+    )
+    .await;
+
+    let attempted =
+        webhooks::deliver_due(&pool, Duration::from_secs(1), 8, Duration::from_secs(30))
+            .await
+            .unwrap();
+    assert_eq!(attempted, 2);
+
+    for subscription_id in subscriptions {
+        let deliveries = webhooks::list_deliveries(&pool, subscription_id, None, 10)
+            .await
+            .unwrap();
+        let delivery = &deliveries[0];
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.response_status, None);
+        assert_eq!(
+            delivery.last_error.as_deref(),
+            Some("Receiver address is not public")
+        );
+    }
+}
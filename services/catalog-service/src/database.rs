@@ -19,6 +19,7 @@ impl Database {
         Ok(Database { pool })
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn create_product(&self, vendor_id: Uuid, product: &CreateProductRequest) -> Result<Product> {
         let id = Uuid::new_v4();
         let now = Utc::now();
@@ -80,6 +81,7 @@ impl Database {
         })
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn get_product(&self, id: Uuid) -> Result<Option<Product>> {
         let row = sqlx::query_as!(
             Product,
@@ -100,6 +102,7 @@ impl Database {
         Ok(row)
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn update_product(&self, id: Uuid, vendor_id: Uuid, update: &UpdateProductRequest) -> Result<Option<Product>> {
         let now = Utc::now();
         
@@ -153,6 +156,7 @@ impl Database {
         Ok(row)
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn list_products(&self, vendor_id: Option<Uuid>, category_id: Option<Uuid>, page: i32, limit: i32) -> Result<(Vec<Product>, i64)> {
         let offset = (page - 1) * limit;
         
@@ -196,6 +200,7 @@ impl Database {
         Ok((products, total.count.unwrap_or(0)))
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn delete_product(&self, id: Uuid, vendor_id: Uuid) -> Result<bool> {
         let result = sqlx::query!(
             "DELETE FROM marketplace.products WHERE id = $1 AND vendor_id = $2",
@@ -209,6 +214,7 @@ impl Database {
     }
 
     // Category methods
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn create_category(&self, category: &CreateCategoryRequest) -> Result<Category> {
         let id = Uuid::new_v4();
         let now = Utc::now();
@@ -236,6 +242,7 @@ impl Database {
         Ok(row)
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn get_categories(&self) -> Result<Vec<Category>> {
         let categories = sqlx::query_as!(
             Category,
@@ -248,6 +255,7 @@ impl Database {
     }
 
     // Review methods
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn create_review(&self, product_id: Uuid, user_id: Uuid, review: &CreateReviewRequest) -> Result<Review> {
         let id = Uuid::new_v4();
         let now = Utc::now();
@@ -274,6 +282,7 @@ impl Database {
         Ok(row)
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "postgresql"))]
     pub async fn get_product_reviews(&self, product_id: Uuid, page: i32, limit: i32) -> Result<(Vec<Review>, i64)> {
         let offset = (page - 1) * limit;
         
@@ -4,3 +4,15 @@ pub mod search;
 pub mod reviews;
 pub mod health;
 pub mod metrics;
+
+use bookmarket_auth::Claims;
+use uuid::Uuid;
+
+use crate::errors::AppError;
+
+/// The user a verified token was issued to.
+pub fn caller_id(claims: &Claims) -> Result<Uuid, AppError> {
+    claims
+        .user_id()
+        .ok_or_else(|| AppError::Auth("Invalid token subject".to_string()))
+}
@@ -1,7 +1,8 @@
 use actix_web::{web, HttpResponse, Result};
+use bookmarket_auth::Claims;
 use uuid::Uuid;
 use validator::Validate;
-use crate::{AppState, models::*, errors::AppError};
+use crate::{AppState, models::*, errors::AppError, handlers::caller_id};
 
 pub fn configure() -> actix_web::Scope {
     web::scope("/products")
@@ -18,11 +19,11 @@ pub fn configure() -> actix_web::Scope {
 async fn create_product(
     state: web::Data<AppState>,
     req: web::Json<CreateProductRequest>,
-    user_id: web::ReqData<Uuid>,
+    claims: Claims,
 ) -> Result<HttpResponse, AppError> {
     req.validate()?;
     
-    let vendor_id = *user_id.into_inner();
+    let vendor_id = caller_id(&claims)?;
     let product = state.database.create_product(vendor_id, &req).await?;
     
     // Index in search engine
@@ -73,12 +74,12 @@ async fn update_product(
     state: web::Data<AppState>,
     path: web::Path<Uuid>,
     req: web::Json<UpdateProductRequest>,
-    user_id: web::ReqData<Uuid>,
+    claims: Claims,
 ) -> Result<HttpResponse, AppError> {
     req.validate()?;
     
     let product_id = path.into_inner();
-    let vendor_id = *user_id.into_inner();
+    let vendor_id = caller_id(&claims)?;
     
     match state.database.update_product(product_id, vendor_id, &req).await? {
         Some(product) => {
@@ -95,10 +96,10 @@ async fn update_product(
 async fn delete_product(
     state: web::Data<AppState>,
     path: web::Path<Uuid>,
-    user_id: web::ReqData<Uuid>,
+    claims: Claims,
 ) -> Result<HttpResponse, AppError> {
     let product_id = path.into_inner();
-    let vendor_id = *user_id.into_inner();
+    let vendor_id = caller_id(&claims)?;
     
     let deleted = state.database.delete_product(product_id, vendor_id).await?;
     
@@ -117,10 +118,10 @@ async fn upload_images(
     state: web::Data<AppState>,
     path: web::Path<Uuid>,
     payload: actix_web::web::Payload,
-    user_id: web::ReqData<Uuid>,
+    claims: Claims,
 ) -> Result<HttpResponse, AppError> {
     let product_id = path.into_inner();
-    let vendor_id = *user_id.into_inner();
+    let vendor_id = caller_id(&claims)?;
     
     // Verify product ownership
     match state.database.get_product(product_id).await? {
@@ -166,12 +167,12 @@ async fn create_review(
     state: web::Data<AppState>,
     path: web::Path<Uuid>,
     req: web::Json<CreateReviewRequest>,
-    user_id: web::ReqData<Uuid>,
+    claims: Claims,
 ) -> Result<HttpResponse, AppError> {
     req.validate()?;
     
     let product_id = path.into_inner();
-    let user_id = *user_id.into_inner();
+    let user_id = caller_id(&claims)?;
     
     let review = state.database.create_review(product_id, user_id, &req).await?;
     
@@ -1,5 +1,6 @@
 use actix_web::{web, App, HttpServer, middleware::Logger};
 use actix_cors::Cors;
+use bookmarket_auth::{IntrospectionCheck, RedisCheck, RequireAuth, Verifier};
 use tracing_actix_web::TracingLogger;
 use std::env;
 
@@ -10,24 +11,22 @@ mod services;
 mod database;
 mod search;
 mod storage;
-mod middleware;
 mod errors;
+mod telemetry;
 
-use config::Config;
+use config::{Config, RevocationCheck};
 use database::Database;
 use search::SearchEngine;
 use storage::StorageService;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
-
     // Load configuration
     let config = Config::from_env().expect("Failed to load configuration");
-    
+
+    // Initialize tracing, exporting spans when a collector is configured
+    telemetry::init(&config);
+
     tracing::info!("Starting BookMarket Catalog Service");
 
     // Initialize database
@@ -38,10 +37,17 @@ async fn main() -> std::io::Result<()> {
     // Initialize Redis
     let redis_client = redis::Client::open(config.redis_url.as_str())
         .expect("Failed to create Redis client");
-    let redis_manager = redis::aio::ConnectionManager::new(redis_client)
+    let redis_manager = redis::aio::ConnectionManager::new(redis_client.clone())
         .await
         .expect("Failed to create Redis connection manager");
 
+    // Bearer token verification, shared by every authenticated handler
+    let verifier = web::Data::new(
+        auth_verifier(&config, redis_client)
+            .await
+            .expect("Failed to initialize token verification"),
+    );
+
     // Initialize search engine
     let search_engine = SearchEngine::new(&config.elasticsearch_url)
         .await
@@ -63,9 +69,10 @@ async fn main() -> std::io::Result<()> {
     let bind_address = format!("0.0.0.0:{}", config.port);
     tracing::info!("Catalog service listening on {}", bind_address);
 
-    HttpServer::new(move || {
+    let served = HttpServer::new(move || {
         App::new()
             .app_data(app_state.clone())
+            .app_data(verifier.clone())
             .wrap(TracingLogger::default())
             .wrap(Logger::default())
             .wrap(
@@ -74,9 +81,11 @@ async fn main() -> std::io::Result<()> {
                     .allow_any_method()
                     .allow_any_header()
             )
-            .wrap(middleware::auth::Auth)
             .service(
+                // Every catalog route needs a signed-in caller; health and
+                // metrics stay open
                 web::scope("/api/v1")
+                    .wrap(RequireAuth)
                     .service(handlers::products::configure())
                     .service(handlers::categories::configure())
                     .service(handlers::search::configure())
@@ -87,7 +96,40 @@ async fn main() -> std::io::Result<()> {
     })
     .bind(&bind_address)?
     .run()
-    .await
+    .await;
+
+    telemetry::shutdown();
+    served
+}
+
+async fn auth_verifier(
+    config: &Config,
+    redis_client: redis::Client,
+) -> Result<Verifier, bookmarket_auth::AuthError> {
+    let verifier = match (&config.auth_jwks_url, &config.jwt_secret) {
+        (Some(url), _) => Verifier::with_jwks(url.clone())?,
+        (None, Some(secret)) => match &config.jwt_previous_secret {
+            Some(previous) => Verifier::with_secrets(&[secret, previous]),
+            None => Verifier::with_secret(secret),
+        },
+        (None, None) => unreachable!("Config requires JWT_SECRET or AUTH_JWKS_URL"),
+    };
+
+    Ok(match config.auth_revocation_check {
+        RevocationCheck::Redis => verifier.with_revocation(RedisCheck::new(redis_client).await?),
+        RevocationCheck::Introspection => verifier.with_revocation(
+            IntrospectionCheck::new(
+                &config.auth_service_url,
+                config.auth_client_id.clone(),
+                config.auth_client_secret.clone(),
+            )?
+            .with_headers(telemetry::inject_context),
+        ),
+        RevocationCheck::None => {
+            tracing::warn!("Token revocation is not checked; logged-out tokens stay valid until they expire");
+            verifier
+        }
+    })
 }
 
 pub struct AppState {
@@ -25,6 +25,7 @@ impl SearchEngine {
         Ok(search_engine)
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "elasticsearch"))]
     async fn create_index(&self) -> Result<()> {
         let mapping = json!({
             "mappings": {
@@ -74,6 +75,7 @@ impl SearchEngine {
         Ok(())
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "elasticsearch"))]
     pub async fn index_product(&self, product: &Product) -> Result<()> {
         let doc = json!({
             "id": product.id,
@@ -102,6 +104,7 @@ impl SearchEngine {
         Ok(())
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "elasticsearch"))]
     pub async fn delete_product(&self, product_id: Uuid) -> Result<()> {
         self.client
             .delete(elasticsearch::DeleteParts::IndexId(&self.index_name, &product_id.to_string()))
@@ -111,6 +114,7 @@ impl SearchEngine {
         Ok(())
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", db.system = "elasticsearch"))]
     pub async fn search(&self, request: &SearchRequest) -> Result<SearchResponse> {
         let mut query = json!({
             "bool": {
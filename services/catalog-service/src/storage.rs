@@ -27,6 +27,7 @@ impl StorageService {
         })
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", aws.s3.bucket = %self.bucket))]
     pub async fn upload_product_images(
         &self,
         product_id: Uuid,
@@ -66,6 +67,7 @@ impl StorageService {
         Ok(image_urls)
     }
 
+    #[tracing::instrument(skip_all, fields(otel.kind = "client", aws.s3.bucket = %self.bucket))]
     pub async fn delete_product_images(&self, product_id: Uuid) -> Result<(), AppError> {
         let prefix = format!("products/{}/images/", product_id);
         
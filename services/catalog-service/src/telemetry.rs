@@ -0,0 +1,85 @@
+use opentelemetry::{global, propagation::Injector, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+use crate::config::Config;
+
+const SERVICE_NAME: &str = "catalog-service";
+
+/// Installs the global tracing subscriber: logs to stdout and, when
+/// `otel_exporter_endpoint` is set, spans to an OTLP collector over gRPC.
+/// A collector that can't be set up is logged and skipped rather than
+/// stopping the service.
+///
+/// Incoming `traceparent` headers are picked up by `TracingLogger` through
+/// the propagator registered here.
+pub fn init(config: &Config) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let (otel_layer, otel_error) = match config.otel_exporter_endpoint.as_deref() {
+        Some(endpoint) => match otlp_tracer(endpoint) {
+            Ok(tracer) => (Some(tracing_opentelemetry::layer().with_tracer(tracer)), None),
+            Err(e) => (None, Some(e)),
+        },
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::from_default_env())
+        .with(tracing_subscriber::fmt::layer())
+        .with(otel_layer)
+        .init();
+
+    match (&config.otel_exporter_endpoint, otel_error) {
+        (Some(endpoint), None) => tracing::info!("Exporting traces to {}", endpoint),
+        (Some(endpoint), Some(e)) => {
+            tracing::warn!("Trace export to {} disabled: {}", endpoint, e)
+        }
+        (None, _) => {}
+    }
+}
+
+/// Flushes spans still buffered for export. Call before the process exits.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Adds the current span's trace context to the headers of a request to
+/// another service, so its spans join the same trace.
+pub fn inject_context(headers: &mut HeaderMap) {
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
+
+fn otlp_tracer(endpoint: &str) -> Result<trace::Tracer, opentelemetry::trace::TraceError> {
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_resource(Resource::new(vec![KeyValue::new("service.name", SERVICE_NAME)])),
+        )
+        .install_batch(runtime::Tokio)
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}